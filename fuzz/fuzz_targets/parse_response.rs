@@ -0,0 +1,19 @@
+#![no_main]
+
+use std::io::BufReader;
+
+use bored_browser::parser;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Must never panic or hang, whatever the server sends.
+    let _ = parser::parse_response(data);
+
+    let mut reader = BufReader::new(data);
+    if let Ok(head) = parser::parse_head(&mut reader) {
+        let _ = parser::read_body(&mut reader, &head);
+    }
+
+    let first_line = data.split(|byte| *byte == b'\n').next().unwrap_or(&[]);
+    let _ = parser::parse_status_line(first_line);
+});
@@ -0,0 +1,634 @@
+use crate::uri::percent_decode;
+
+use openssl::base64;
+use openssl::hash::{self, MessageDigest};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+const BASIC: &str = "Basic";
+const DIGEST: &str = "Digest";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl Credentials {
+    pub fn new(username: &str, password: &str) -> Self {
+        Self {
+            username: String::from(username),
+            password: String::from(password),
+        }
+    }
+
+    /// Credentials from percent-encoded `user:password` userinfo.
+    pub fn from_userinfo(userinfo: &str) -> Self {
+        let (username, password) = userinfo.split_once(':').unwrap_or((userinfo, ""));
+
+        Self::new(&percent_decode(username), &percent_decode(password))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Challenge {
+    pub scheme: String,
+    pub token68: Option<String>,
+    pub params: Vec<(String, String)>,
+}
+
+impl Challenge {
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn realm(&self) -> &str {
+        self.param("realm").unwrap_or("")
+    }
+
+    fn is(&self, scheme: &str) -> bool {
+        self.scheme.eq_ignore_ascii_case(scheme)
+    }
+}
+
+// Splits a comma-separated header list, leaving commas inside quoted strings alone.
+fn split_list(value: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut in_quotes = false;
+    let mut escaped = false;
+    let mut start = 0;
+
+    for (index, character) in value.char_indices() {
+        match character {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => {
+                items.push(value[start..index].trim());
+                start = index + 1;
+            }
+            _ => (),
+        }
+    }
+    items.push(value[start..].trim());
+
+    items.into_iter().filter(|item| !item.is_empty()).collect()
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+
+    match value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    {
+        Some(quoted) => {
+            let mut output = String::new();
+            let mut characters = quoted.chars();
+
+            while let Some(character) = characters.next() {
+                match character {
+                    '\\' => output.extend(characters.next()),
+                    character => output.push(character),
+                }
+            }
+
+            output
+        }
+        None => String::from(value),
+    }
+}
+
+fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte))
+}
+
+// `name=value` auth-params, as opposed to token68 values which may end in '='.
+fn parse_param(item: &str) -> Option<(String, String)> {
+    let (name, value) = item.split_once('=')?;
+    let name = name.trim();
+
+    if !is_token(name) || value.trim().is_empty() || value.starts_with('=') {
+        return None;
+    }
+
+    Some((name.to_ascii_lowercase(), unquote(value)))
+}
+
+/// Parses WWW-Authenticate or Proxy-Authenticate values (RFC 9110 section 11.6.1), which may
+/// hold several challenges each followed by its parameters.
+pub fn parse_challenges(value: &str) -> Vec<Challenge> {
+    let mut challenges: Vec<Challenge> = Vec::new();
+
+    for item in split_list(value) {
+        if let (Some(param), Some(challenge)) = (parse_param(item), challenges.last_mut()) {
+            challenge.params.push(param);
+            continue;
+        }
+
+        let (scheme, rest) = item.split_once(' ').unwrap_or((item, ""));
+        let rest = rest.trim();
+
+        let mut challenge = Challenge {
+            scheme: String::from(scheme),
+            token68: None,
+            params: Vec::new(),
+        };
+
+        if !rest.is_empty() {
+            match parse_param(rest) {
+                Some(param) => challenge.params.push(param),
+                None => challenge.token68 = Some(String::from(rest)),
+            }
+        }
+
+        challenges.push(challenge);
+    }
+
+    challenges
+}
+
+fn digest_algorithm(challenge: &Challenge) -> Option<(MessageDigest, bool)> {
+    let algorithm = challenge.param("algorithm").unwrap_or("MD5");
+
+    match algorithm.to_ascii_uppercase().as_str() {
+        "MD5" => Some((MessageDigest::md5(), false)),
+        "MD5-SESS" => Some((MessageDigest::md5(), true)),
+        "SHA-256" => Some((MessageDigest::sha256(), false)),
+        "SHA-256-SESS" => Some((MessageDigest::sha256(), true)),
+        _ => None,
+    }
+}
+
+fn supports_qop_auth(challenge: &Challenge) -> bool {
+    // Without qop this is RFC 2069 digest, which we still answer.
+    challenge.param("qop").is_none_or(|qop| {
+        qop.split(',')
+            .any(|option| option.trim().eq_ignore_ascii_case("auth"))
+    })
+}
+
+fn is_supported(challenge: &Challenge) -> bool {
+    if challenge.is(BASIC) {
+        return true;
+    }
+
+    challenge.is(DIGEST)
+        && challenge.param("nonce").is_some()
+        && digest_algorithm(challenge).is_some()
+        && supports_qop_auth(challenge)
+}
+
+/// Picks the strongest challenge we can answer: Digest SHA-256, then Digest MD5, then Basic.
+pub fn select_challenge(challenges: &[Challenge]) -> Option<&Challenge> {
+    challenges
+        .iter()
+        .filter(|challenge| is_supported(challenge))
+        .max_by_key(|challenge| {
+            if challenge.is(BASIC) {
+                0
+            } else if digest_algorithm(challenge)
+                .is_some_and(|(digest, _)| digest.type_() == MessageDigest::sha256().type_())
+            {
+                2
+            } else {
+                1
+            }
+        })
+}
+
+pub fn basic_authorization(credentials: &Credentials) -> String {
+    let pair = format!("{}:{}", credentials.username, credentials.password);
+
+    format!("{BASIC} {}", base64::encode_block(pair.as_bytes()))
+}
+
+fn hex_digest(digest: MessageDigest, data: &str) -> io::Result<String> {
+    let bytes = hash::hash(digest, data.as_bytes())?;
+
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Answers a Digest challenge (RFC 7616) for `method` on the request target `uri`.
+pub fn digest_authorization(
+    challenge: &Challenge,
+    credentials: &Credentials,
+    method: &str,
+    uri: &str,
+    cnonce: &str,
+    nonce_count: u32,
+) -> io::Result<String> {
+    let unsupported = || io::Error::new(io::ErrorKind::Unsupported, "Unsupported Digest challenge");
+
+    let (digest, session) = digest_algorithm(challenge).ok_or_else(unsupported)?;
+    let nonce = challenge.param("nonce").ok_or_else(unsupported)?;
+    let realm = challenge.realm();
+    let qop = challenge.param("qop").map(|_| "auth");
+    let nonce_count = format!("{nonce_count:08x}");
+
+    let mut ha1 = hex_digest(
+        digest,
+        &format!("{}:{realm}:{}", credentials.username, credentials.password),
+    )?;
+    if session {
+        ha1 = hex_digest(digest, &format!("{ha1}:{nonce}:{cnonce}"))?;
+    }
+    let ha2 = hex_digest(digest, &format!("{method}:{uri}"))?;
+
+    let response = match qop {
+        Some(qop) => hex_digest(
+            digest,
+            &format!("{ha1}:{nonce}:{nonce_count}:{cnonce}:{qop}:{ha2}"),
+        )?,
+        None => hex_digest(digest, &format!("{ha1}:{nonce}:{ha2}"))?,
+    };
+
+    let mut fields = vec![
+        format!("username={}", quote(&credentials.username)),
+        format!("realm={}", quote(realm)),
+        format!("nonce={}", quote(nonce)),
+        format!("uri={}", quote(uri)),
+        format!("response={}", quote(&response)),
+    ];
+
+    if let Some(algorithm) = challenge.param("algorithm") {
+        fields.push(format!("algorithm={algorithm}"));
+    }
+    if let Some(qop) = qop {
+        fields.push(format!("qop={qop}"));
+        fields.push(format!("nc={nonce_count}"));
+        fields.push(format!("cnonce={}", quote(cnonce)));
+    }
+    if let Some(opaque) = challenge.param("opaque") {
+        fields.push(format!("opaque={}", quote(opaque)));
+    }
+
+    Ok(format!("{DIGEST} {}", fields.join(", ")))
+}
+
+/// Builds the Authorization (or Proxy-Authorization) value answering `challenge`.
+pub fn authorization(
+    challenge: &Challenge,
+    credentials: &Credentials,
+    method: &str,
+    uri: &str,
+) -> io::Result<String> {
+    if challenge.is(BASIC) {
+        return Ok(basic_authorization(credentials));
+    }
+
+    let mut cnonce = [0u8; 16];
+    openssl::rand::rand_bytes(&mut cnonce)?;
+    let cnonce: String = cnonce.iter().map(|byte| format!("{byte:02x}")).collect();
+
+    digest_authorization(challenge, credentials, method, uri, &cnonce, 1)
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Netrc {
+    machines: Vec<(String, Credentials)>,
+    default: Option<Credentials>,
+}
+
+impl Netrc {
+    /// Parses the ftp(1) `.netrc` format: `machine`, `login`, `password` and `default`
+    /// tokens; `account` and `macdef` entries are skipped.
+    pub fn parse(contents: &str) -> Netrc {
+        let mut netrc = Netrc::default();
+        // The machine being described, None for `default`.
+        let mut current: Option<(Option<String>, Credentials)> = None;
+
+        let mut finish = |entry: Option<(Option<String>, Credentials)>| match entry {
+            Some((Some(machine), credentials)) => netrc.machines.push((machine, credentials)),
+            Some((None, credentials)) => netrc.default = Some(credentials),
+            None => (),
+        };
+
+        let mut lines = contents.lines();
+        while let Some(line) = lines.next() {
+            let mut tokens = line.split_whitespace();
+
+            while let Some(token) = tokens.next() {
+                match token {
+                    "machine" => {
+                        finish(current.take());
+                        current = tokens.next().map(|machine| {
+                            (Some(machine.to_ascii_lowercase()), Credentials::new("", ""))
+                        });
+                    }
+                    "default" => {
+                        finish(current.take());
+                        current = Some((None, Credentials::new("", "")));
+                    }
+                    "login" => {
+                        if let (Some((_, credentials)), Some(login)) =
+                            (current.as_mut(), tokens.next())
+                        {
+                            credentials.username = String::from(login);
+                        }
+                    }
+                    "password" => {
+                        if let (Some((_, credentials)), Some(password)) =
+                            (current.as_mut(), tokens.next())
+                        {
+                            credentials.password = String::from(password);
+                        }
+                    }
+                    "account" => {
+                        tokens.next();
+                    }
+                    "macdef" => {
+                        // Macro definitions run until the next blank line.
+                        for line in lines.by_ref() {
+                            if line.trim().is_empty() {
+                                break;
+                            }
+                        }
+                        break;
+                    }
+                    _ => (),
+                }
+            }
+        }
+        finish(current);
+
+        netrc
+    }
+
+    pub fn load(path: &Path) -> io::Result<Netrc> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// `$NETRC`, or `.netrc` in the home directory.
+    pub fn default_path() -> Option<PathBuf> {
+        env::var_os("NETRC")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".netrc")))
+    }
+
+    pub fn lookup(&self, host: &str) -> Option<&Credentials> {
+        self.machines
+            .iter()
+            .find(|(machine, _)| machine.eq_ignore_ascii_case(host))
+            .map(|(_, credentials)| credentials)
+            .or(self.default.as_ref())
+    }
+}
+
+/// Asks for credentials, given the host and the realm of the challenge.
+pub type Prompt = fn(&str, &str) -> Option<Credentials>;
+
+fn set_terminal_echo(enabled: bool) {
+    let _ = Command::new("stty")
+        .arg(if enabled { "echo" } else { "-echo" })
+        .stdin(Stdio::inherit())
+        .status();
+}
+
+/// Prompts on the terminal, hiding the password where `stty` is available.
+pub fn prompt_terminal(host: &str, realm: &str) -> Option<Credentials> {
+    if !io::stdin().is_terminal() {
+        return None;
+    }
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut stderr = io::stderr();
+
+    let _ = write!(stderr, "Username for {host} ({realm}): ");
+    let _ = stderr.flush();
+    let username = lines.next()?.ok()?;
+
+    let _ = write!(stderr, "Password: ");
+    let _ = stderr.flush();
+    set_terminal_echo(false);
+    let password = lines.next();
+    set_terminal_echo(true);
+    let _ = writeln!(stderr);
+
+    Some(Credentials::new(username.trim(), &password?.ok()?))
+}
+
+/// Where credentials come from, in order: URL userinfo, `.netrc`, then the prompt.
+#[derive(Clone, Debug, Default)]
+pub struct CredentialStore {
+    pub netrc: Option<Netrc>,
+    pub prompt: Option<Prompt>,
+}
+
+impl CredentialStore {
+    /// Credentials for `host`, starting with those `known` from the URL. On `retry` the
+    /// earlier credentials were rejected, so only the prompt can offer new ones.
+    pub fn lookup(
+        &self,
+        known: Option<Credentials>,
+        host: &str,
+        realm: &str,
+        retry: bool,
+    ) -> Option<Credentials> {
+        if !retry {
+            let known = known.or_else(|| {
+                self.netrc
+                    .as_ref()
+                    .and_then(|netrc| netrc.lookup(host).cloned())
+            });
+
+            if known.is_some() {
+                return known;
+            }
+        }
+
+        self.prompt.and_then(|prompt| prompt(host, realm))
+    }
+}
+
+#[cfg(test)]
+mod challenge_tests {
+    use super::{parse_challenges, select_challenge, Challenge};
+
+    #[test]
+    fn parses_multiple_challenges() {
+        let challenges = parse_challenges(
+            "Newauth realm=\"apps\", type=1, title=\"Login to \\\"apps\\\"\", Basic realm=\"simple\", Bearer abc123==",
+        );
+
+        assert_eq!(challenges.len(), 3);
+        assert_eq!(challenges[0].scheme, "Newauth");
+        assert_eq!(challenges[0].realm(), "apps");
+        assert_eq!(challenges[0].param("TYPE"), Some("1"));
+        assert_eq!(challenges[0].param("title"), Some("Login to \"apps\""));
+        assert_eq!(challenges[1].scheme, "Basic");
+        assert_eq!(challenges[1].realm(), "simple");
+        assert_eq!(challenges[2].token68.as_deref(), Some("abc123=="));
+    }
+
+    #[test]
+    fn keeps_commas_inside_quotes() {
+        let challenges = parse_challenges(
+            "Digest realm=\"a, b\", qop=\"auth,auth-int\", nonce=\"xyz\", algorithm=SHA-256",
+        );
+
+        assert_eq!(challenges.len(), 1);
+        assert_eq!(challenges[0].realm(), "a, b");
+        assert_eq!(challenges[0].param("qop"), Some("auth,auth-int"));
+    }
+
+    #[test]
+    fn prefers_the_strongest_supported_challenge() {
+        let challenges = parse_challenges(
+            "Basic realm=\"x\", Digest realm=\"x\", nonce=\"1\", Digest realm=\"x\", nonce=\"2\", algorithm=SHA-256, Digest realm=\"x\", nonce=\"3\", algorithm=SHA-512-256",
+        );
+
+        let selected: &Challenge = select_challenge(&challenges).unwrap();
+        assert_eq!(selected.param("nonce"), Some("2"));
+
+        let only_auth_int = parse_challenges("Digest realm=\"x\", nonce=\"1\", qop=\"auth-int\"");
+        assert_eq!(select_challenge(&only_auth_int), None);
+    }
+}
+
+#[cfg(test)]
+mod authorization_tests {
+    use super::{basic_authorization, digest_authorization, parse_challenges, Credentials};
+
+    #[test]
+    fn builds_basic_credentials() {
+        assert_eq!(
+            basic_authorization(&Credentials::new("Aladdin", "open sesame")),
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+    }
+
+    #[test]
+    fn matches_rfc_7616_digest_examples() {
+        let credentials = Credentials::new("Mufasa", "Circle of Life");
+        let header = "Digest realm=\"http-auth@example.org\", qop=\"auth, auth-int\", \
+            nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", \
+            opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\"";
+        let cnonce = "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ";
+
+        for (algorithm, response) in [
+            ("MD5", "8ca523f5e9506fed4657c9700eebdbec"),
+            (
+                "SHA-256",
+                "753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1",
+            ),
+        ] {
+            let challenges = parse_challenges(&format!("{header}, algorithm={algorithm}"));
+            let authorization = digest_authorization(
+                &challenges[0],
+                &credentials,
+                "GET",
+                "/dir/index.html",
+                cnonce,
+                1,
+            )
+            .unwrap();
+
+            assert!(
+                authorization.contains(&format!("response=\"{response}\"")),
+                "{authorization}"
+            );
+            assert!(authorization.contains("qop=auth, nc=00000001"));
+            assert!(
+                authorization.contains("opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\"")
+            );
+        }
+    }
+
+    #[test]
+    fn matches_rfc_2617_digest_example() {
+        let challenges = parse_challenges(
+            "Digest realm=\"testrealm@host.com\", qop=\"auth,auth-int\", nonce=\"dcd98b7102dd2f0e8b11d0f600bfb0c093\"",
+        );
+
+        let authorization = digest_authorization(
+            &challenges[0],
+            &Credentials::new("Mufasa", "Circle Of Life"),
+            "GET",
+            "/dir/index.html",
+            "0a4f113b",
+            1,
+        )
+        .unwrap();
+
+        assert!(authorization.contains("response=\"6629fae49393a05397450978507c4ef1\""));
+    }
+}
+
+#[cfg(test)]
+mod credential_tests {
+    use super::{CredentialStore, Credentials, Netrc};
+
+    #[test]
+    fn parses_netrc_files() {
+        let netrc = Netrc::parse(
+            "machine intranet.example login alice password s3cret\n\
+             machine other.example\n  login bob\n  account x\n  password hunter2\n\
+             macdef init\ncd /pub\n\n\
+             default login anonymous password guest\n",
+        );
+
+        assert_eq!(
+            netrc.lookup("Intranet.example"),
+            Some(&Credentials::new("alice", "s3cret"))
+        );
+        assert_eq!(
+            netrc.lookup("other.example"),
+            Some(&Credentials::new("bob", "hunter2"))
+        );
+        assert_eq!(
+            netrc.lookup("elsewhere.example"),
+            Some(&Credentials::new("anonymous", "guest"))
+        );
+    }
+
+    #[test]
+    fn prefers_userinfo_then_netrc_then_prompt() {
+        fn prompt(_host: &str, realm: &str) -> Option<Credentials> {
+            Some(Credentials::new("prompted", realm))
+        }
+
+        let store = CredentialStore {
+            netrc: Some(Netrc::parse(
+                "machine intranet.example login alice password s3cret",
+            )),
+            prompt: Some(prompt),
+        };
+
+        assert_eq!(
+            store.lookup(
+                Some(Credentials::from_userinfo("carol:p%40ss")),
+                "intranet.example",
+                "realm",
+                false
+            ),
+            Some(Credentials::new("carol", "p@ss"))
+        );
+        assert_eq!(
+            store.lookup(None, "intranet.example", "realm", false),
+            Some(Credentials::new("alice", "s3cret"))
+        );
+        assert_eq!(
+            store.lookup(None, "intranet.example", "realm", true),
+            Some(Credentials::new("prompted", "realm"))
+        );
+        assert_eq!(
+            CredentialStore::default().lookup(None, "intranet.example", "realm", false),
+            None
+        );
+    }
+}
@@ -0,0 +1,762 @@
+use crate::auth::{self, CredentialStore, Netrc};
+use crate::cache::Cache;
+use crate::css;
+use crate::download::Download;
+use crate::har::HarLog;
+use crate::header::Header;
+use crate::hsts::HstsStore;
+use crate::html::view_source;
+use crate::html::{self, Token, Tokenizer};
+use crate::mime::{self, Category, MimeType};
+use crate::parallel::{self, Limits};
+use crate::proxy::ProxyConfig;
+use crate::redirect::RedirectPolicy;
+use crate::request::{Request, RequestOptions, Timeouts};
+use crate::resolver::Resolver;
+use crate::retry::RetryPolicy;
+use crate::sse;
+use crate::tls::TlsOptions;
+use crate::transport::TransportMode;
+use crate::uri::{Scheme, URI};
+use crate::websocket;
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+const CACHE_DIRECTORY: &str = ".cache/";
+
+// Kept apart from the cache so that clearing it doesn't forget which hosts require HTTPS.
+const HSTS_FILE: &str = ".hsts";
+
+pub struct Options {
+    pub debug: bool,
+    pub url: String,
+    pub cache_directory: PathBuf,
+    pub clear_cache: bool,
+    pub show_tls: bool,
+    pub timeouts: Timeouts,
+    pub retry: RetryPolicy,
+    pub redirect: RedirectPolicy,
+    pub proxies: ProxyConfig,
+    pub tls: TlsOptions,
+    pub transport: TransportMode,
+    pub http2: bool,
+    pub hsts_file: PathBuf,
+    pub hsts_preload: Option<PathBuf>,
+    pub https_first: bool,
+    pub netrc: Option<PathBuf>,
+    pub har: Option<PathBuf>,
+    // Save the response body to this file instead of showing it, resuming partial downloads.
+    pub save: Option<PathBuf>,
+    // Treat the URL as an event stream, printing events as they arrive.
+    pub follow: bool,
+    // Pages to load concurrently instead of `url`, shown in this order.
+    pub urls: Vec<String>,
+    pub parallel: Limits,
+    pub resolver: Resolver,
+    // Print the document tree of HTML pages instead of their text.
+    pub dump_dom: bool,
+    // Print the style sheets and `style` attributes of HTML pages, fetching linked ones.
+    pub dump_css: bool,
+    // How `view-source:` pages are written.
+    pub source_format: view_source::Format,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            debug: false,
+            url: String::new(),
+            cache_directory: PathBuf::from(CACHE_DIRECTORY),
+            clear_cache: false,
+            show_tls: false,
+            timeouts: Timeouts::default(),
+            retry: RetryPolicy::default(),
+            redirect: RedirectPolicy::default(),
+            proxies: ProxyConfig::default(),
+            tls: TlsOptions::default(),
+            transport: TransportMode::Network,
+            http2: true,
+            hsts_file: PathBuf::from(HSTS_FILE),
+            hsts_preload: None,
+            https_first: false,
+            netrc: None,
+            har: None,
+            save: None,
+            follow: false,
+            urls: Vec::new(),
+            parallel: Limits::default(),
+            resolver: Resolver::new(),
+            dump_dom: false,
+            dump_css: false,
+            source_format: view_source::Format::Plain,
+        }
+    }
+}
+
+// Writes the text of an HTML document, leaving out markup, scripts and styles. With
+// `only_body`, text outside `<body>` is left out too.
+fn show(output: &mut impl Write, source: &str, only_body: bool) -> io::Result<()> {
+    let mut in_body = false;
+    let mut in_script = false;
+
+    for token in Tokenizer::new(source) {
+        match token {
+            Token::StartTag(tag) if tag.name == "body" => in_body = true,
+            Token::EndTag(tag) if tag.name == "body" => in_body = false,
+            Token::StartTag(tag) if tag.name == "script" || tag.name == "style" => in_script = true,
+            Token::EndTag(tag) if tag.name == "script" || tag.name == "style" => in_script = false,
+            Token::Character(text) if !in_script && (!only_body || in_body) => {
+                output.write_all(text.as_bytes())?
+            }
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+// Re-indents JSON with two spaces per level, leaving strings untouched. Invalid JSON comes
+// out re-indented all the same, as there's nothing better to show.
+fn format_json(text: &str) -> String {
+    let mut output = String::new();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    // An opening bracket was just written, so an empty object or array stays on one line.
+    let mut opened = false;
+
+    let newline = |output: &mut String, depth: usize| {
+        output.push('\n');
+        output.push_str(&"  ".repeat(depth));
+    };
+
+    for character in text.chars() {
+        if in_string {
+            output.push(character);
+            match character {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => (),
+            }
+            continue;
+        }
+
+        if character.is_whitespace() {
+            continue;
+        }
+
+        let closing = matches!(character, '}' | ']');
+        if opened && !closing {
+            newline(&mut output, depth);
+        }
+
+        match character {
+            '{' | '[' => {
+                output.push(character);
+                depth += 1;
+                opened = true;
+                continue;
+            }
+            '}' | ']' => {
+                depth = depth.saturating_sub(1);
+                if !opened {
+                    newline(&mut output, depth);
+                }
+                output.push(character);
+            }
+            ',' => {
+                output.push(',');
+                newline(&mut output, depth);
+            }
+            ':' => output.push_str(": "),
+            '"' => {
+                output.push('"');
+                in_string = true;
+            }
+            character => output.push(character),
+        }
+
+        opened = false;
+    }
+
+    output.push('\n');
+    output
+}
+
+// Shows markup found at `uri` as its highlighted source, its styles, its document tree or its
+// text. `request` fetches the style sheets it links to.
+fn show_html(
+    output: &mut impl Write,
+    source: &str,
+    only_body: bool,
+    uri: &URI,
+    request: &Request,
+    options: &Options,
+) -> io::Result<()> {
+    if uri.flags.contains_key("view-source") {
+        view_source::render(output, source, options.source_format)
+    } else if options.dump_css {
+        write!(
+            output,
+            "{}",
+            css::collect(&html::parse(source), uri, request)
+        )
+    } else if options.dump_dom {
+        write!(output, "{}", html::parse(source))
+    } else {
+        show(output, source, only_body)
+    }
+}
+
+// Shows a body the way its MIME type calls for: HTML rendered as text, other text as it is,
+// and a summary of anything that isn't text.
+fn render(
+    output: &mut impl Write,
+    mime_type: &MimeType,
+    body: &[u8],
+    uri: &URI,
+    request: &Request,
+    options: &Options,
+) -> io::Result<()> {
+    let text = String::from_utf8_lossy(body);
+    let view_source = uri.flags.contains_key("view-source");
+
+    match mime_type.category() {
+        Category::Html => show_html(output, &text, true, uri, request, options),
+        Category::Json if !view_source => output.write_all(format_json(&text).as_bytes()),
+        Category::Json | Category::PlainText | Category::Xml => output.write_all(text.as_bytes()),
+        Category::Image => writeln!(
+            output,
+            "[{} image, {} bytes]",
+            mime_type.essence(),
+            body.len()
+        ),
+        Category::Download => writeln!(
+            output,
+            "[{} content, {} bytes: use --save to download it]",
+            mime_type.essence(),
+            body.len()
+        ),
+    }
+}
+
+/// Loads a page and renders it as text to `output`.
+pub struct Browser<W: Write> {
+    options: Options,
+    request: Request,
+    output: W,
+}
+
+impl<W: Write> Browser<W> {
+    pub fn new(options: Options, output: W) -> Self {
+        let cache = Cache::initialize_at(&options.cache_directory, options.clear_cache);
+
+        let mut hsts = HstsStore::load(&options.hsts_file).expect("Couldn't read HSTS policies");
+        if let Some(preload) = &options.hsts_preload {
+            hsts.load_preload_list(preload)
+                .expect("Couldn't read HSTS preload list");
+        }
+
+        let netrc = options
+            .netrc
+            .as_ref()
+            .map(|path| Netrc::load(path).expect("Couldn't read netrc file"));
+
+        let requester = Request::init(RequestOptions {
+            cache,
+            timeouts: options.timeouts.clone(),
+            retry: options.retry.clone(),
+            redirect: options.redirect.clone(),
+            proxies: options.proxies.clone(),
+            tls: options.tls.clone(),
+            transport: options.transport.clone(),
+            http2: options.http2,
+            hsts,
+            https_first: options.https_first,
+            auth: CredentialStore {
+                netrc,
+                prompt: Some(auth::prompt_terminal),
+            },
+            har: options.har.is_some(),
+            resolver: options.resolver.clone(),
+        });
+        Self {
+            options,
+            request: requester,
+            output,
+        }
+    }
+
+    // Fetches `uri` and shows it as text, for the schemes whose pages are read whole.
+    fn display(
+        request: &Request,
+        options: &Options,
+        uri: &URI,
+        output: &mut impl Write,
+    ) -> io::Result<()> {
+        match uri.scheme {
+            Scheme::HTTPS | Scheme::HTTP => {
+                let response = request.send(uri)?;
+
+                if options.show_tls {
+                    return match response.tls {
+                        Some(info) => write!(output, "{info}"),
+                        None if uri.scheme == Scheme::HTTP => {
+                            writeln!(output, "No TLS: plain HTTP")
+                        }
+                        // Cache hits never touch the network.
+                        None => writeln!(output, "No TLS info: response served from cache"),
+                    };
+                }
+
+                let mime_type = mime::sniff(
+                    response
+                        .headers
+                        .get(Header::ContentType.as_str())
+                        .map(String::as_str),
+                    mime::is_no_sniff(&response.headers),
+                    &response.body,
+                );
+                log::debug!("Showing {} as {mime_type}", uri.as_str());
+
+                render(output, &mime_type, &response.body, uri, request, options)
+            }
+            Scheme::Data => {
+                // _ is the content_type
+                let (_, path_data) = uri.path.split_once(',').unwrap_or((&uri.path, ""));
+
+                // Writing end-of-file.
+                let data = String::new() + path_data + "\r\n";
+                show_html(output, &data, false, uri, request, options)
+            }
+            Scheme::File => {
+                let data = fs::read_to_string(&uri.path)?;
+                show_html(output, &data, false, uri, request, options)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Can't display {} as a page", uri.as_str()),
+            )),
+        }
+    }
+
+    pub fn load(&mut self) -> io::Result<()> {
+        let uri = URI::parse(&self.options.url);
+
+        match uri.scheme {
+            Scheme::HTTPS | Scheme::HTTP if self.options.follow => {
+                sse::follow(&self.request, &uri, &mut self.output, None)
+            }
+            Scheme::HTTPS | Scheme::HTTP if self.options.save.is_some() => {
+                let destination = self.options.save.as_ref().expect("No destination");
+                let length = Download::new(destination).run(&self.request, &uri)?;
+
+                writeln!(
+                    self.output,
+                    "Saved {length} bytes to {}",
+                    destination.display()
+                )
+            }
+            Scheme::WS | Scheme::WSS => {
+                let mut socket = self.request.websocket(&uri)?;
+                websocket::pipe(&mut socket, io::stdin(), &mut self.output)
+            }
+            Scheme::VIEWSOURCE => panic!("Unexpected view-source scheme provided to browser."),
+            _ => Self::display(&self.request, &self.options, &uri, &mut self.output),
+        }
+    }
+
+    /// Loads every page in `urls` concurrently, within the `parallel` limits, showing each
+    /// under a `==> url <==` heading in the order given. A page that fails to load shows
+    /// the error instead, without stopping the others.
+    pub fn load_all(&mut self) -> io::Result<()> {
+        let uris: Vec<Option<URI>> = self
+            .options
+            .urls
+            .iter()
+            .map(|url| URI::try_parse(url))
+            .collect();
+        let (request, options, output) = (&self.request, &self.options, &mut self.output);
+        let mut result = Ok(());
+
+        parallel::run_ordered(
+            &uris,
+            &options.parallel,
+            |uri| {
+                uri.as_ref()?
+                    .authority
+                    .as_ref()
+                    .map(|authority| authority.host.clone())
+            },
+            |uri| {
+                let uri = uri
+                    .as_ref()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid URL"))?;
+                let mut page = Vec::new();
+                Self::display(request, options, uri, &mut page).map(|()| page)
+            },
+            |index, page| {
+                let url = &options.urls[index];
+
+                let written = match page {
+                    Ok(page) => writeln!(output, "==> {url} <==")
+                        .and_then(|()| output.write_all(&page))
+                        .and_then(|()| writeln!(output)),
+                    Err(e) => {
+                        log::warn!("Couldn't load {url}: {e}");
+                        writeln!(output, "==> {url} <==\nCouldn't load the page: {e}\n")
+                    }
+                };
+
+                // Keep the first write error; later pages are still fetched but not shown.
+                if result.is_ok() {
+                    result = written.and_then(|()| output.flush());
+                }
+            },
+        );
+
+        result
+    }
+
+    /// The HAR log of the exchanges so far, when `har` is set.
+    pub fn har(&self) -> Option<HarLog> {
+        self.request.har()
+    }
+
+    pub fn run(&mut self) {
+        if self.options.urls.is_empty() {
+            self.load().expect("Couldn't load the page");
+        } else {
+            self.load_all().expect("Couldn't show the pages");
+        }
+
+        if let (Some(path), Some(har)) = (&self.options.har, self.request.har()) {
+            har.write(path).expect("Couldn't write HAR file");
+        }
+    }
+}
+
+#[cfg(test)]
+mod browser_tests {
+    use super::{Browser, Options};
+    use crate::hsts::HstsStore;
+    use crate::testing::temporary_directory;
+    use crate::transport::{Fixtures, TransportMode};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    // Replays `responses` (URL, raw response) with a fresh cache, returning the fixtures and
+    // cache directories.
+    fn fixtures(name: &str, responses: &[(&str, &[u8])]) -> (PathBuf, PathBuf) {
+        let directory = temporary_directory(&format!("browser-{name}"));
+        let fixtures = Fixtures::new(&directory.join("fixtures"));
+
+        for (url, response) in responses {
+            fixtures.save("GET", url, 0, response).unwrap();
+        }
+
+        (directory.join("fixtures"), directory.join("cache"))
+    }
+
+    fn load(url: &str, replay: &Path, cache: &Path) -> String {
+        let options = Options {
+            url: String::from(url),
+            cache_directory: cache.to_path_buf(),
+            hsts_file: cache.with_file_name("hsts"),
+            transport: TransportMode::Replay(replay.to_path_buf()),
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        Browser::new(options, &mut output).load().unwrap();
+
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn follows_redirects() {
+        let (replay, cache) = fixtures(
+            "redirects",
+            &[
+                (
+                    "http://example.org/old",
+                    b"HTTP/1.1 301 Moved Permanently\r\nLocation: http://example.org/new\r\nContent-Length: 0\r\n\r\n",
+                ),
+                (
+                    "http://example.org/new",
+                    b"HTTP/1.1 200 OK\r\nContent-Length: 31\r\n\r\n<html><body>Moved</body></html>",
+                ),
+            ],
+        );
+
+        assert_eq!(load("http://example.org/old", &replay, &cache), "Moved");
+    }
+
+    #[test]
+    fn decodes_chunked_bodies() {
+        let (replay, cache) = fixtures(
+            "chunked",
+            &[(
+                "http://example.org/",
+                b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n6\r\n<body>\r\nE\r\nChunked &amp; \r\n10\r\ndone&lt;</body>\r\n0\r\n\r\n",
+            )],
+        );
+
+        assert_eq!(
+            load("http://example.org/", &replay, &cache),
+            "Chunked & done<"
+        );
+    }
+
+    #[test]
+    fn shows_only_the_text_of_pages() {
+        let body = "<!DOCTYPE html><title>T</title><body><!-- a > b --><script>if (a < b) {}</script><a title=\"x > y\">Link</a> &amp; text</body>";
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        let (replay, cache) = fixtures("markup", &[("http://example.org/", response.as_bytes())]);
+
+        assert_eq!(load("http://example.org/", &replay, &cache), "Link & text");
+    }
+
+    #[test]
+    fn dumps_the_dom_of_pages() {
+        let (replay, cache) = fixtures(
+            "dump-dom",
+            &[(
+                "http://example.org/",
+                b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 20\r\n\r\n<p class=a>One<p>Two",
+            )],
+        );
+        let options = Options {
+            url: String::from("http://example.org/"),
+            hsts_file: cache.with_file_name("hsts"),
+            cache_directory: cache,
+            transport: TransportMode::Replay(replay),
+            dump_dom: true,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        Browser::new(options, &mut output).load().unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<html>\n  <head>\n  <body>\n    <p class=\"a\">\n      \"One\"\n    <p>\n      \"Two\"\n"
+        );
+    }
+
+    #[test]
+    fn dumps_the_styles_of_pages() {
+        let body = "<link rel=stylesheet href=\"css/site.css\"><link rel=stylesheet href=/gone.css><style>p{margin:0 auto}</style><p style=\"color: red; width:\">Hi";
+        let page = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        let (replay, cache) = fixtures(
+            "dump-css",
+            &[
+                ("http://example.org/docs/index.html", page.as_bytes()),
+                (
+                    "http://example.org/docs/css/site.css",
+                    b"HTTP/1.1 200 OK\r\nContent-Type: text/css\r\nContent-Length: 30\r\n\r\n@media print{a{color:#000;}}\n\n",
+                ),
+                (
+                    "http://example.org/gone.css",
+                    b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n",
+                ),
+            ],
+        );
+        let options = Options {
+            url: String::from("http://example.org/docs/index.html"),
+            hsts_file: cache.with_file_name("hsts"),
+            cache_directory: cache,
+            transport: TransportMode::Replay(replay),
+            dump_css: true,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        Browser::new(options, &mut output).load().unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "/* http://example.org/docs/css/site.css */\n\
+             @media print {\n  a {\n    color: #000;\n  }\n}\n\
+             /* <style> */\n\
+             p {\n  margin-top: 0;\n  margin-right: auto;\n  margin-bottom: 0;\n  margin-left: auto;\n}\n\
+             /* style attribute of node 8 */\n\
+             color: red;\n"
+        );
+    }
+
+    #[test]
+    fn shows_source_as_it_is() {
+        let (replay, cache) = fixtures(
+            "view-source",
+            &[(
+                "http://example.org/",
+                b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 21\r\n\r\n<p>&amp;lt;</p>\n<br>\n",
+            )],
+        );
+
+        assert_eq!(
+            load("view-source:http://example.org/", &replay, &cache),
+            "1  <p>&amp;lt;</p>\n2  <br>\n"
+        );
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn decompresses_gzip_bodies() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"<body>Compressed</body>").unwrap();
+        let body = encoder.finish().unwrap();
+
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+        response.extend_from_slice(&body);
+
+        let (replay, cache) = fixtures("gzip", &[("http://example.org/", &response)]);
+
+        assert_eq!(load("http://example.org/", &replay, &cache), "Compressed");
+    }
+
+    #[test]
+    fn serves_repeat_visits_from_the_cache() {
+        let (replay, cache) = fixtures(
+            "cache",
+            &[(
+                "http://example.org/",
+                b"HTTP/1.1 200 OK\r\nContent-Length: 19\r\n\r\n<body>Cached</body>",
+            )],
+        );
+
+        assert_eq!(load("http://example.org/", &replay, &cache), "Cached");
+
+        // Nothing left to replay: the second visit can only come from the cache.
+        let empty = replay.with_file_name("empty");
+        assert_eq!(load("http://example.org/", &empty, &cache), "Cached");
+    }
+
+    #[test]
+    fn keeps_hsts_policies_when_clearing_the_cache() {
+        let (replay, cache) = fixtures(
+            "hsts",
+            &[(
+                "https://example.org/",
+                b"HTTP/1.1 200 OK\r\nContent-Length: 19\r\n\r\n<body>Secure</body>",
+            )],
+        );
+        let hsts_file = cache.with_file_name("hsts");
+        let expires = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 3600;
+        fs::write(&hsts_file, format!("example.org;{expires};0")).unwrap();
+
+        let options = Options {
+            url: String::from("http://example.org/"),
+            cache_directory: cache,
+            clear_cache: true,
+            hsts_file: hsts_file.clone(),
+            transport: TransportMode::Replay(replay),
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        Browser::new(options, &mut output).load().unwrap();
+
+        // Only the https:// page was recorded, so the policy upgraded the request.
+        assert_eq!(String::from_utf8(output).unwrap(), "Secure");
+        assert!(HstsStore::load(&hsts_file)
+            .unwrap()
+            .is_known_host("example.org"));
+    }
+
+    #[test]
+    fn shows_plain_text_as_it_is() {
+        let (replay, cache) = fixtures(
+            "plain-text",
+            &[(
+                "http://example.org/notes.txt",
+                b"HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: 22\r\n\r\nif a < b && c > d &lt;",
+            )],
+        );
+
+        assert_eq!(
+            load("http://example.org/notes.txt", &replay, &cache),
+            "if a < b && c > d &lt;"
+        );
+    }
+
+    #[test]
+    fn summarizes_images_instead_of_decoding_them() {
+        let (replay, cache) = fixtures(
+            "image",
+            &[(
+                "http://example.org/logo",
+                // Served as text/plain by a server with Apache's old default.
+                b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 12\r\n\r\n\x89PNG\r\n\x1A\n\x00\x00\xFF\xFE",
+            ),
+            (
+                "http://example.org/photo",
+                b"HTTP/1.1 200 OK\r\nContent-Type: image/jpeg\r\nContent-Length: 4\r\n\r\n\xFF\xD8\xFF\xE0",
+            )],
+        );
+
+        assert_eq!(
+            load("http://example.org/logo", &replay, &cache),
+            "[application/octet-stream content, 12 bytes: use --save to download it]\n"
+        );
+        assert_eq!(
+            load("http://example.org/photo", &replay, &cache),
+            "[image/jpeg image, 4 bytes]\n"
+        );
+    }
+
+    #[test]
+    fn honours_nosniff() {
+        let (replay, cache) = fixtures(
+            "nosniff",
+            &[(
+                "http://example.org/upload",
+                b"HTTP/1.1 200 OK\r\nX-Content-Type-Options: nosniff\r\nContent-Length: 19\r\n\r\n<body>Upload</body>",
+            )],
+        );
+
+        assert_eq!(
+            load("http://example.org/upload", &replay, &cache),
+            "<body>Upload</body>"
+        );
+    }
+
+    #[test]
+    fn indents_json() {
+        let (replay, cache) = fixtures(
+            "json",
+            &[(
+                "http://example.org/api",
+                b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 42\r\n\r\n{\"a\":[1,2],\"b\":{},\"c\":\"x, \\\"{y}\\\"\",\"d\":[]}",
+            )],
+        );
+
+        assert_eq!(
+            load("http://example.org/api", &replay, &cache),
+            "{\n  \"a\": [\n    1,\n    2\n  ],\n  \"b\": {},\n  \"c\": \"x, \\\"{y}\\\"\",\n  \"d\": []\n}\n"
+        );
+    }
+}
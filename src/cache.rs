@@ -19,6 +19,7 @@ struct Item {
 
 pub struct Cache {
     items: Vec<Item>,
+    path: PathBuf,
 }
 
 fn extract_item_from(string_vec: Vec<&str>) -> Item {
@@ -38,45 +39,40 @@ fn hash_from(request: HTTPRequest) -> String {
 
     let value = hasher.finalize();
 
-    return format!("{:X}", value);
+    format!("{:X}", value)
 }
 
 impl Cache {
     const BASE_PATH: &str = CACHE_PATH;
     const CONTROL_FILE: &str = ".control";
 
-    fn get_cache_path() -> &'static Path {
-        Path::new(Self::BASE_PATH)
+    fn create_cache_control_file(cache_path: &Path) -> io::Result<File> {
+        File::create(cache_path.join(Self::CONTROL_FILE))
     }
 
-    fn create_cache_control_file() -> io::Result<File> {
-        File::create(Self::get_cache_path().join(Self::CONTROL_FILE))
-    }
-
-    fn initialize_cache_dir() {
-        let cache_path = Self::get_cache_path();
-
-        match fs::create_dir(cache_path) {
+    fn initialize_cache_dir(cache_path: &Path) {
+        match fs::create_dir_all(cache_path) {
             Ok(..) => (),
             Err(e) => panic!("{e}"),
         }
 
-        Self::create_cache_control_file().unwrap();
+        Self::create_cache_control_file(cache_path).unwrap();
     }
 
-    fn clear() {
-        fs::remove_dir_all(Self::get_cache_path()).expect("Cannot remove directory");
+    fn clear(cache_path: &Path) {
+        fs::remove_dir_all(cache_path).expect("Cannot remove directory");
 
-        Self::initialize_cache_dir()
+        Self::initialize_cache_dir(cache_path)
     }
 
-    fn read_cache_control() -> Vec<Item> {
-        let control_file = Self::get_cache_path().join(Self::CONTROL_FILE);
+    fn read_cache_control(cache_path: &Path) -> Vec<Item> {
+        let control_file = cache_path.join(Self::CONTROL_FILE);
 
         let mut file = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
+            .truncate(false)
             .open(control_file)
             .expect("File cannot be opened");
 
@@ -97,10 +93,11 @@ impl Cache {
 
     fn write_to_cache_control(&self) {
         // Function to write to cache control
-        let control_file = Self::get_cache_path().join(Self::CONTROL_FILE);
+        let control_file = self.path.join(Self::CONTROL_FILE);
         let mut file = OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .open(control_file)
             .unwrap();
         let items: Vec<String> = self
@@ -127,22 +124,20 @@ impl Cache {
                 for item in &self.items {
                     if item.path_string == hash_from(request.clone()) {
                         // TODO: implement expiry check
-                        let file_path = Self::get_cache_path().join(&item.path_string);
+                        let file_path = self.path.join(&item.path_string);
                         return Ok(Self::read_file(file_path));
                     }
                 }
 
-                return Err(Error::new(
+                Err(Error::new(
                     ErrorKind::NotFound,
-                    format!("Value not found in cache"),
-                ));
-            }
-            _ => {
-                return Err(Error::new(
-                    ErrorKind::InvalidInput,
-                    format!("Method not supported"),
-                ));
+                    "Value not found in cache".to_string(),
+                ))
             }
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Method not supported".to_string(),
+            )),
         }
     }
 
@@ -151,16 +146,17 @@ impl Cache {
         let mut file = OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .open(path)
             .unwrap();
 
-        file.write_all(&data.as_slice()).unwrap();
+        file.write_all(data.as_slice()).unwrap();
     }
 
     pub fn insert(&mut self, request: &HTTPRequest, response: Vec<u8>, expiry: u64) {
         let file_name_hash = hash_from(request.clone());
 
-        let file_path = Self::get_cache_path().join(&file_name_hash);
+        let file_path = self.path.join(&file_name_hash);
 
         Self::write_file(file_path, response);
 
@@ -169,24 +165,33 @@ impl Cache {
             path_string: file_name_hash,
         });
 
-        Self::write_to_cache_control(&self)
+        Self::write_to_cache_control(self)
     }
 
     pub fn initialize(clear_cache: bool) -> Cache {
-        let cache_path = Self::get_cache_path();
+        Self::initialize_at(Path::new(Self::BASE_PATH), clear_cache)
+    }
+
+    /// Opens (or creates) the cache kept in `cache_path` rather than `.cache/`.
+    pub fn initialize_at(cache_path: &Path, clear_cache: bool) -> Cache {
+        let path = cache_path.to_path_buf();
 
         if !cache_path.is_dir() {
-            Self::initialize_cache_dir();
+            Self::initialize_cache_dir(cache_path);
 
-            return Self { items: vec![] };
+            Self {
+                items: vec![],
+                path,
+            }
         } else {
             if clear_cache {
-                Self::clear()
+                Self::clear(cache_path)
             }
 
-            return Self {
-                items: Self::read_cache_control(),
-            };
+            Self {
+                items: Self::read_cache_control(cache_path),
+                path,
+            }
         }
     }
 }
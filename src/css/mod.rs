@@ -0,0 +1,14 @@
+//! Reading CSS, following CSS Syntax Module Level 3.
+
+pub mod page;
+pub mod parser;
+pub mod selector;
+pub mod shorthand;
+pub mod tokenizer;
+
+pub use page::{collect, PageStyles};
+pub use parser::{
+    parse, parse_declarations, ComponentValue, Declaration, MediaRule, Rule, StyleRule, Stylesheet,
+};
+pub use selector::Selector;
+pub use tokenizer::{Token, Tokenizer};
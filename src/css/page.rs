@@ -0,0 +1,202 @@
+//! Gathering the styles a page uses: its `<style>` elements, linked style sheets and
+//! `style` attributes.
+
+use super::parser::{self, Declaration, Stylesheet};
+use crate::html::dom::ROOT;
+use crate::html::{Document, NodeData, NodeId};
+use crate::request::{HTTPResponse, Request};
+use crate::uri::{self, Scheme, URI};
+
+use std::fmt;
+use std::fs;
+use std::io;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PageStyles {
+    // Style sheets in document order, each with where it came from: `<style>` or its URL.
+    pub stylesheets: Vec<(String, Stylesheet)>,
+    pub inline: Vec<(NodeId, Vec<Declaration>)>,
+}
+
+fn is_stylesheet_link(rel: &str) -> bool {
+    let has = |keyword: &str| {
+        rel.split_ascii_whitespace()
+            .any(|word| word.eq_ignore_ascii_case(keyword))
+    };
+
+    // Alternate style sheets are only used once picked.
+    has("stylesheet") && !has("alternate")
+}
+
+fn is_css(kind: Option<&str>) -> bool {
+    kind.is_none_or(|kind| kind.is_empty() || kind.trim().eq_ignore_ascii_case("text/css"))
+}
+
+fn text(document: &Document, id: NodeId) -> String {
+    document
+        .children(id)
+        .iter()
+        .filter_map(|&child| match &document.node(child).data {
+            NodeData::Text(text) => Some(text.data.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn successful(response: io::Result<HTTPResponse>) -> io::Result<String> {
+    let response = response?;
+
+    if !(200..300).contains(&response.status_code) {
+        return Err(io::Error::other(format!(
+            "{} {}",
+            response.status_code, response.status_message
+        )));
+    }
+
+    Ok(response.data)
+}
+
+// Loads a style sheet that isn't fetched over HTTP for a page found at `base`. Only local
+// pages may use local files, so a page from the web can't read them.
+fn load(base: &URI, url: &URI) -> io::Result<String> {
+    match url.scheme {
+        Scheme::File if base.scheme == Scheme::File => fs::read_to_string(&url.path),
+        Scheme::File => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "Only local pages can use local style sheets",
+        )),
+        Scheme::Data => {
+            let (_, data) = url.path.split_once(',').unwrap_or((&url.path, ""));
+            Ok(uri::percent_decode(data))
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Style sheets can't be loaded from here",
+        )),
+    }
+}
+
+fn is_http(url: &URI) -> bool {
+    matches!(url.scheme, Scheme::HTTP | Scheme::HTTPS)
+}
+
+enum Source {
+    Style(String),
+    Sheet(URI),
+}
+
+/// Parses every style sheet and `style` attribute of a page found at `base`, fetching linked
+/// style sheets together with `request`. Those that fail to load are left out, as browsers do.
+pub fn collect(document: &Document, base: &URI, request: &Request) -> PageStyles {
+    let mut sources = Vec::new();
+    let mut styles = PageStyles::default();
+
+    for id in document.descendants(ROOT) {
+        let Some(element) = document.element(id) else {
+            continue;
+        };
+
+        if element.name == "style" && is_css(element.attribute("type")) {
+            sources.push(Source::Style(text(document, id)));
+        }
+
+        if element.name == "link" && element.attribute("rel").is_some_and(is_stylesheet_link) {
+            let href = element.attribute("href").unwrap_or("");
+
+            match base.resolve(href) {
+                Some(url) => sources.push(Source::Sheet(url)),
+                None => log::warn!("Couldn't resolve style sheet {href}"),
+            }
+        }
+
+        if let Some(style) = element.attribute("style") {
+            styles.inline.push((id, parser::parse_declarations(style)));
+        }
+    }
+
+    let urls: Vec<URI> = sources
+        .iter()
+        .filter_map(|source| match source {
+            Source::Sheet(url) if is_http(url) => Some(url.clone()),
+            _ => None,
+        })
+        .collect();
+    let mut responses = request.send_all(&urls).into_iter();
+
+    for source in sources {
+        match source {
+            Source::Style(css) => styles
+                .stylesheets
+                .push((String::from("<style>"), parser::parse(&css))),
+            Source::Sheet(url) => {
+                let css = if is_http(&url) {
+                    successful(responses.next().expect("A response for every URL"))
+                } else {
+                    load(base, &url)
+                };
+
+                match css {
+                    Ok(css) => styles.stylesheets.push((url.as_str(), parser::parse(&css))),
+                    Err(error) => log::warn!("Couldn't load style sheet {}: {error}", url.as_str()),
+                }
+            }
+        }
+    }
+
+    styles
+}
+
+impl fmt::Display for PageStyles {
+    /// Each style sheet under a comment saying where it came from, then the declarations of
+    /// each `style` attribute.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (source, stylesheet) in &self.stylesheets {
+            writeln!(f, "/* {source} */")?;
+            write!(f, "{stylesheet}")?;
+        }
+
+        for (id, declarations) in &self.inline {
+            writeln!(f, "/* style attribute of node {id} */")?;
+            for declaration in declarations {
+                writeln!(f, "{declaration};")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod page_tests {
+    use super::{is_css, is_stylesheet_link, load};
+    use crate::uri::URI;
+    use std::io;
+
+    #[test]
+    fn picks_style_sheet_links() {
+        assert!(is_stylesheet_link("StyleSheet"));
+        assert!(is_stylesheet_link(" preload  stylesheet "));
+        assert!(!is_stylesheet_link("alternate stylesheet"));
+        assert!(!is_stylesheet_link("icon"));
+        assert!(is_css(None));
+        assert!(is_css(Some(" TEXT/CSS")));
+        assert!(!is_css(Some("text/less")));
+    }
+
+    #[test]
+    fn loads_local_style_sheets_only_for_local_pages() {
+        let sheet = URI::parse(&format!("file://{}/Cargo.toml", env!("CARGO_MANIFEST_DIR")));
+
+        let local = URI::parse("file:///home/me/page.html");
+        assert!(load(&local, &sheet).unwrap().contains("[package]"));
+
+        for page in [
+            "http://example.org/",
+            "https://example.org/",
+            "data:text/html,<p>",
+        ] {
+            let error = load(&URI::parse(page), &sheet).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::PermissionDenied, "{page}");
+        }
+    }
+}
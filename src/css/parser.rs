@@ -0,0 +1,616 @@
+//! Turning CSS tokens into style sheets (CSS Syntax Module Level 3, section 5).
+//!
+//! Tokens are first grouped into component values, so blocks and functions nest, and rules
+//! and declarations are then read from those. Anything malformed is skipped up to the next
+//! `;` or the end of its block, the way browsers recover, and the rest still applies.
+
+use super::selector::{self, Selector};
+use super::shorthand;
+use super::tokenizer::{Token, Tokenizer};
+
+use std::fmt;
+
+// Deeper blocks are emptied, so hostile style sheets can't exhaust the stack.
+const MAX_NESTING: usize = 128;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bracket {
+    Paren,
+    Square,
+    Curly,
+}
+
+impl Bracket {
+    fn delimiters(self) -> (&'static str, &'static str) {
+        match self {
+            Bracket::Paren => ("(", ")"),
+            Bracket::Square => ("[", "]"),
+            Bracket::Curly => ("{", "}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ComponentValue {
+    Token(Token),
+    Function {
+        name: String,
+        value: Vec<ComponentValue>,
+    },
+    Block {
+        bracket: Bracket,
+        value: Vec<ComponentValue>,
+    },
+}
+
+impl ComponentValue {
+    pub fn is_token(&self, token: &Token) -> bool {
+        matches!(self, ComponentValue::Token(own) if own == token)
+    }
+
+    pub fn is_whitespace(&self) -> bool {
+        self.is_token(&Token::Whitespace)
+    }
+
+    pub fn ident(&self) -> Option<&str> {
+        match self {
+            ComponentValue::Token(Token::Ident(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    // Whether this could never be part of a valid declaration.
+    fn is_invalid(&self) -> bool {
+        match self {
+            ComponentValue::Token(token) => matches!(
+                token,
+                Token::BadString
+                    | Token::BadUrl
+                    | Token::CloseParen
+                    | Token::CloseSquare
+                    | Token::CloseCurly
+            ),
+            ComponentValue::Function { value, .. } | ComponentValue::Block { value, .. } => {
+                value.iter().any(ComponentValue::is_invalid)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ComponentValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ComponentValue::Token(token) => write!(f, "{token}"),
+            ComponentValue::Function { name, value } => {
+                write!(f, "{name}(")?;
+                write_values(f, value)?;
+                f.write_str(")")
+            }
+            ComponentValue::Block { bracket, value } => {
+                let (open, close) = bracket.delimiters();
+                f.write_str(open)?;
+                write_values(f, value)?;
+                f.write_str(close)
+            }
+        }
+    }
+}
+
+fn write_values(f: &mut fmt::Formatter<'_>, values: &[ComponentValue]) -> fmt::Result {
+    values.iter().try_for_each(|value| write!(f, "{value}"))
+}
+
+/// Writes component values back out as CSS.
+pub fn serialize(values: &[ComponentValue]) -> String {
+    values.iter().map(ToString::to_string).collect()
+}
+
+/// `values` without whitespace at either end.
+pub fn trim(values: &[ComponentValue]) -> &[ComponentValue] {
+    let start = values
+        .iter()
+        .position(|value| !value.is_whitespace())
+        .unwrap_or(values.len());
+    let end = values
+        .iter()
+        .rposition(|value| !value.is_whitespace())
+        .map_or(start, |index| index + 1);
+
+    &values[start..end]
+}
+
+fn consume_component_value(
+    token: Token,
+    tokens: &mut impl Iterator<Item = Token>,
+    depth: usize,
+) -> ComponentValue {
+    let (bracket, ending) = match token {
+        Token::OpenParen => (Bracket::Paren, Token::CloseParen),
+        Token::OpenSquare => (Bracket::Square, Token::CloseSquare),
+        Token::OpenCurly => (Bracket::Curly, Token::CloseCurly),
+        Token::Function(name) => {
+            return ComponentValue::Function {
+                name,
+                value: consume_until(tokens, &Token::CloseParen, depth + 1),
+            }
+        }
+        token => return ComponentValue::Token(token),
+    };
+
+    ComponentValue::Block {
+        bracket,
+        value: consume_until(tokens, &ending, depth + 1),
+    }
+}
+
+fn consume_until(
+    tokens: &mut impl Iterator<Item = Token>,
+    ending: &Token,
+    depth: usize,
+) -> Vec<ComponentValue> {
+    let mut values = Vec::new();
+    // Nested blocks past the limit are still skipped whole, just not kept.
+    let mut skipped = 0;
+
+    while let Some(token) = tokens.next() {
+        if depth >= MAX_NESTING {
+            match token {
+                Token::OpenParen | Token::OpenSquare | Token::OpenCurly | Token::Function(_) => {
+                    skipped += 1
+                }
+                Token::CloseParen | Token::CloseSquare | Token::CloseCurly if skipped > 0 => {
+                    skipped -= 1
+                }
+                token if token == *ending => break,
+                _ => (),
+            }
+        } else if token == *ending {
+            break;
+        } else {
+            values.push(consume_component_value(token, tokens, depth));
+        }
+    }
+
+    values
+}
+
+/// Groups the tokens of `input` into component values.
+pub fn parse_component_values(input: &str) -> Vec<ComponentValue> {
+    let mut tokens = Tokenizer::new(input);
+    let mut values = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        values.push(consume_component_value(token, &mut tokens, 0));
+    }
+
+    values
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Declaration {
+    pub name: String,
+    pub value: Vec<ComponentValue>,
+    pub important: bool,
+}
+
+impl fmt::Display for Declaration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.name)?;
+        write_values(f, &self.value)?;
+        if self.important {
+            f.write_str(" !important")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StyleRule {
+    pub selectors: Vec<Selector>,
+    // Shorthands are expanded into the longhands they set.
+    pub declarations: Vec<Declaration>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MediaRule {
+    // The media query list, like `screen and (min-width: 40em)`.
+    pub media: String,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportRule {
+    pub url: String,
+    pub media: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Rule {
+    Style(StyleRule),
+    Media(MediaRule),
+    Import(ImportRule),
+    // The descriptors of an `@font-face` rule.
+    FontFace(Vec<Declaration>),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stylesheet {
+    pub rules: Vec<Rule>,
+}
+
+fn write_declarations(
+    f: &mut fmt::Formatter<'_>,
+    declarations: &[Declaration],
+    depth: usize,
+) -> fmt::Result {
+    writeln!(f, "{{")?;
+    for declaration in declarations {
+        writeln!(f, "{:indent$}{declaration};", "", indent = (depth + 1) * 2)?;
+    }
+    writeln!(f, "{:indent$}}}", "", indent = depth * 2)
+}
+
+fn write_rules(f: &mut fmt::Formatter<'_>, rules: &[Rule], depth: usize) -> fmt::Result {
+    for rule in rules {
+        write!(f, "{:indent$}", "", indent = depth * 2)?;
+
+        match rule {
+            Rule::Style(style) => {
+                let selectors: Vec<String> =
+                    style.selectors.iter().map(ToString::to_string).collect();
+                write!(f, "{} ", selectors.join(", "))?;
+                write_declarations(f, &style.declarations, depth)?;
+            }
+            Rule::Media(media) => {
+                writeln!(f, "@media {} {{", media.media)?;
+                write_rules(f, &media.rules, depth + 1)?;
+                writeln!(f, "{:indent$}}}", "", indent = depth * 2)?;
+            }
+            Rule::Import(import) if import.media.is_empty() => {
+                writeln!(f, "@import url({:?});", import.url)?
+            }
+            Rule::Import(import) => writeln!(f, "@import url({:?}) {};", import.url, import.media)?,
+            Rule::FontFace(declarations) => {
+                write!(f, "@font-face ")?;
+                write_declarations(f, declarations, depth)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl fmt::Display for Stylesheet {
+    /// The rules as CSS, one declaration per line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_rules(f, &self.rules, 0)
+    }
+}
+
+// A rule before its prelude and block are made sense of.
+enum RawRule {
+    At {
+        name: String,
+        prelude: Vec<ComponentValue>,
+        block: Option<Vec<ComponentValue>>,
+    },
+    Qualified {
+        prelude: Vec<ComponentValue>,
+        block: Vec<ComponentValue>,
+    },
+}
+
+fn curly_block(value: &ComponentValue) -> Option<&[ComponentValue]> {
+    match value {
+        ComponentValue::Block {
+            bracket: Bracket::Curly,
+            value,
+        } => Some(value),
+        _ => None,
+    }
+}
+
+fn consume_rules(values: &[ComponentValue], top_level: bool) -> Vec<RawRule> {
+    let mut rules = Vec::new();
+    let mut values = values.iter();
+
+    while let Some(value) = values.next() {
+        match value {
+            ComponentValue::Token(Token::Whitespace) => (),
+            ComponentValue::Token(Token::Cdo | Token::Cdc) if top_level => (),
+            ComponentValue::Token(Token::AtKeyword(name)) => {
+                let mut prelude = Vec::new();
+                let mut block = None;
+
+                for value in values.by_ref() {
+                    if value.is_token(&Token::Semicolon) {
+                        break;
+                    }
+                    if let Some(value) = curly_block(value) {
+                        block = Some(value.to_vec());
+                        break;
+                    }
+                    prelude.push(value.clone());
+                }
+
+                rules.push(RawRule::At {
+                    name: name.to_ascii_lowercase(),
+                    prelude,
+                    block,
+                });
+            }
+            value => {
+                let mut prelude = vec![value.clone()];
+                let mut block = curly_block(value);
+
+                while block.is_none() {
+                    // A rule cut off by the end of the style sheet is dropped.
+                    let Some(value) = values.next() else {
+                        return rules;
+                    };
+                    block = curly_block(value);
+                    prelude.push(value.clone());
+                }
+
+                prelude.pop();
+                rules.push(RawRule::Qualified {
+                    prelude,
+                    block: block.unwrap_or_default().to_vec(),
+                });
+            }
+        }
+    }
+
+    rules
+}
+
+fn consume_declaration(values: &[ComponentValue]) -> Option<Declaration> {
+    let name = values.first()?.ident()?;
+    let rest = trim(&values[1..]);
+    if !rest.first()?.is_token(&Token::Colon) {
+        return None;
+    }
+
+    let mut value = trim(&rest[1..]);
+    let mut important = false;
+
+    let words: Vec<usize> = (0..value.len())
+        .filter(|&index| !value[index].is_whitespace())
+        .collect();
+    if let [.., bang, last] = words[..] {
+        if value[bang].is_token(&Token::Delim('!'))
+            && value[last]
+                .ident()
+                .is_some_and(|ident| ident.eq_ignore_ascii_case("important"))
+        {
+            important = true;
+            value = trim(&value[..bang]);
+        }
+    }
+
+    // Custom properties keep their name as written, and may even be empty.
+    let custom = name.starts_with("--");
+    if (value.is_empty() && !custom) || value.iter().any(ComponentValue::is_invalid) {
+        return None;
+    }
+
+    Some(Declaration {
+        name: if custom {
+            String::from(name)
+        } else {
+            name.to_ascii_lowercase()
+        },
+        value: value.to_vec(),
+        important,
+    })
+}
+
+// The declarations in a block or `style` attribute, as written.
+fn consume_declarations(values: &[ComponentValue]) -> Vec<Declaration> {
+    let mut declarations = Vec::new();
+    let mut index = 0;
+
+    while let Some(value) = values.get(index) {
+        match value {
+            ComponentValue::Token(Token::Whitespace | Token::Semicolon) => index += 1,
+            // Nothing takes at-rules inside declarations yet, so they are skipped whole.
+            ComponentValue::Token(Token::AtKeyword(_)) => {
+                index += 1;
+                while let Some(value) = values.get(index) {
+                    index += 1;
+                    if value.is_token(&Token::Semicolon) || curly_block(value).is_some() {
+                        break;
+                    }
+                }
+            }
+            _ => {
+                let end = values[index..]
+                    .iter()
+                    .position(|value| value.is_token(&Token::Semicolon))
+                    .map_or(values.len(), |offset| index + offset);
+
+                match consume_declaration(&values[index..end]) {
+                    Some(declaration) => declarations.push(declaration),
+                    None => log::debug!(
+                        "Skipping invalid declaration {}",
+                        serialize(&values[index..end])
+                    ),
+                }
+                index = end;
+            }
+        }
+    }
+
+    declarations
+}
+
+fn longhands(declarations: Vec<Declaration>) -> Vec<Declaration> {
+    declarations
+        .into_iter()
+        .flat_map(shorthand::expand)
+        .collect()
+}
+
+fn import(prelude: &[ComponentValue]) -> Option<ImportRule> {
+    let prelude = trim(prelude);
+
+    let url = match prelude.first()? {
+        ComponentValue::Token(Token::String(url) | Token::Url(url)) => url.clone(),
+        ComponentValue::Function { name, value } if name.eq_ignore_ascii_case("url") => {
+            match trim(value) {
+                [ComponentValue::Token(Token::String(url))] => url.clone(),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+
+    Some(ImportRule {
+        url,
+        media: serialize(trim(&prelude[1..])),
+    })
+}
+
+fn interpret(raw_rules: Vec<RawRule>, top_level: bool) -> Vec<Rule> {
+    let mut rules = Vec::new();
+
+    for raw in raw_rules {
+        match raw {
+            RawRule::Qualified { prelude, block } => match selector::parse_list(&prelude) {
+                Some(selectors) => rules.push(Rule::Style(StyleRule {
+                    selectors,
+                    declarations: longhands(consume_declarations(&block)),
+                })),
+                None => log::debug!(
+                    "Skipping rule with invalid selector {}",
+                    serialize(&prelude)
+                ),
+            },
+            RawRule::At {
+                name,
+                prelude,
+                block: Some(block),
+            } if name == "media" => rules.push(Rule::Media(MediaRule {
+                media: serialize(trim(&prelude)),
+                rules: interpret(consume_rules(&block, false), false),
+            })),
+            RawRule::At {
+                name,
+                prelude,
+                block: None,
+            } if name == "import" => {
+                // Imports only count before every other rule.
+                let leading = top_level && rules.iter().all(|rule| matches!(rule, Rule::Import(_)));
+                match import(&prelude) {
+                    Some(import) if leading => rules.push(Rule::Import(import)),
+                    _ => log::debug!("Skipping invalid @import {}", serialize(&prelude)),
+                }
+            }
+            RawRule::At {
+                name,
+                prelude,
+                block: Some(block),
+            } if name == "font-face" && trim(&prelude).is_empty() => {
+                rules.push(Rule::FontFace(consume_declarations(&block)))
+            }
+            // `@charset` only matters to decoding, and other at-rules aren't supported.
+            RawRule::At { name, .. } => log::debug!("Skipping @{name} rule"),
+        }
+    }
+
+    rules
+}
+
+/// Parses a style sheet, like the contents of a `<style>` element.
+pub fn parse(input: &str) -> Stylesheet {
+    Stylesheet {
+        rules: interpret(consume_rules(&parse_component_values(input), true), true),
+    }
+}
+
+/// Parses a list of declarations, like a `style` attribute, with shorthands expanded.
+pub fn parse_declarations(input: &str) -> Vec<Declaration> {
+    longhands(consume_declarations(&parse_component_values(input)))
+}
+
+#[cfg(test)]
+mod parser_tests {
+    use super::{parse, parse_declarations, Rule};
+
+    fn css(input: &str) -> String {
+        parse(input).to_string()
+    }
+
+    #[test]
+    fn parses_rules() {
+        assert_eq!(
+            css("h1,p  >  a{color:red;BACKGROUND-COLOR : #fff !IMPORTANT}"),
+            "h1, p > a {\n  color: red;\n  background-color: #fff !important;\n}\n"
+        );
+    }
+
+    #[test]
+    fn skips_invalid_declarations() {
+        assert_eq!(
+            css("p { color red; width: ; margin-top: 1px; @apply x; background: url(a b); height: 2px }"),
+            "p {\n  margin-top: 1px;\n  height: 2px;\n}\n"
+        );
+    }
+
+    #[test]
+    fn skips_invalid_rules() {
+        // A stray `}` is part of the next rule's selector, which makes it invalid too.
+        assert_eq!(
+            css("p > { color: red } a { color: blue } } b { x: 1 } @unknown x { y } c { x: 2"),
+            "a {\n  color: blue;\n}\nc {\n  x: 2;\n}\n"
+        );
+    }
+
+    #[test]
+    fn parses_at_rules() {
+        let stylesheet = parse(
+            "@charset \"utf-8\"; @import url(a.css); @import \"b.css\" print; \
+             @media screen and (min-width: 40em) { p { margin: 0 } } \
+             @font-face { font-family: \"Sans\"; src: url(sans.woff) } \
+             @import \"late.css\";",
+        );
+
+        assert_eq!(stylesheet.rules.len(), 4);
+        assert!(matches!(&stylesheet.rules[0], Rule::Import(import) if import.url == "a.css"));
+        assert_eq!(
+            stylesheet.to_string(),
+            "@import url(\"a.css\");\n\
+             @import url(\"b.css\") print;\n\
+             @media screen and (min-width: 40em) {\n  \
+               p {\n    margin-top: 0;\n    margin-right: 0;\n    margin-bottom: 0;\n    margin-left: 0;\n  }\n\
+             }\n\
+             @font-face {\n  font-family: \"Sans\";\n  src: url(\"sans.woff\");\n}\n"
+        );
+    }
+
+    #[test]
+    fn parses_style_attributes() {
+        let declarations = parse_declarations("color: red; padding: 1px 2px !important; --Gap:4px");
+        let declarations: Vec<String> = declarations.iter().map(ToString::to_string).collect();
+
+        assert_eq!(
+            declarations,
+            [
+                "color: red",
+                "padding-top: 1px !important",
+                "padding-right: 2px !important",
+                "padding-bottom: 1px !important",
+                "padding-left: 2px !important",
+                "--Gap: 4px",
+            ]
+        );
+    }
+
+    #[test]
+    fn survives_deep_nesting() {
+        let input = format!("a {{ b: {} }}", "(".repeat(100_000));
+
+        assert!(css(&input).starts_with("a {\n  b: (((("));
+    }
+}
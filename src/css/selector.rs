@@ -0,0 +1,423 @@
+//! Selectors (Selectors Level 4), which pick the elements a style rule applies to.
+
+use super::parser::{self, Bracket, ComponentValue};
+use super::tokenizer::Token;
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Combinator {
+    // `a b`
+    Descendant,
+    // `a > b`
+    Child,
+    // `a + b`
+    NextSibling,
+    // `a ~ b`
+    SubsequentSibling,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttributeOperator {
+    // `=`
+    Equals,
+    // `~=`, one of the whitespace separated words.
+    Includes,
+    // `|=`, the value or the value followed by `-`.
+    DashMatch,
+    // `^=`
+    Prefix,
+    // `$=`
+    Suffix,
+    // `*=`
+    Substring,
+}
+
+impl AttributeOperator {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AttributeOperator::Equals => "=",
+            AttributeOperator::Includes => "~=",
+            AttributeOperator::DashMatch => "|=",
+            AttributeOperator::Prefix => "^=",
+            AttributeOperator::Suffix => "$=",
+            AttributeOperator::Substring => "*=",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SimpleSelector {
+    Type(String),
+    Universal,
+    Id(String),
+    Class(String),
+    Attribute {
+        name: String,
+        matcher: Option<(AttributeOperator, String)>,
+        // Set by a trailing `i`.
+        case_insensitive: bool,
+    },
+    PseudoClass {
+        name: String,
+        // What's between the parentheses of functional ones like `:not(p)`, as CSS.
+        arguments: Option<String>,
+    },
+    PseudoElement(String),
+}
+
+/// Simple selectors that must all match the same element, like `p.note:hover`.
+pub type Compound = Vec<SimpleSelector>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Selector {
+    pub compounds: Vec<Compound>,
+    // `combinators[i]` joins `compounds[i]` to `compounds[i + 1]`.
+    pub combinators: Vec<Combinator>,
+}
+
+impl Selector {
+    /// How many IDs, classes (with attributes and pseudo-classes) and types (with
+    /// pseudo-elements) the selector has, which decides between conflicting rules.
+    pub fn specificity(&self) -> (u32, u32, u32) {
+        let mut specificity = (0, 0, 0);
+
+        for simple in self.compounds.iter().flatten() {
+            match simple {
+                SimpleSelector::Id(_) => specificity.0 += 1,
+                SimpleSelector::Class(_) | SimpleSelector::Attribute { .. } => specificity.1 += 1,
+                SimpleSelector::PseudoClass { name, .. } if name == "where" => (),
+                // These count as their most specific argument.
+                SimpleSelector::PseudoClass {
+                    name,
+                    arguments: Some(arguments),
+                } if matches!(name.as_str(), "is" | "not" | "has" | "matches") => {
+                    let most = parse_list(&parser::parse_component_values(arguments))
+                        .unwrap_or_default()
+                        .iter()
+                        .map(Selector::specificity)
+                        .max()
+                        .unwrap_or_default();
+                    specificity.0 += most.0;
+                    specificity.1 += most.1;
+                    specificity.2 += most.2;
+                }
+                SimpleSelector::PseudoClass { .. } => specificity.1 += 1,
+                SimpleSelector::Type(_) | SimpleSelector::PseudoElement(_) => specificity.2 += 1,
+                SimpleSelector::Universal => (),
+            }
+        }
+
+        specificity
+    }
+}
+
+impl fmt::Display for SimpleSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimpleSelector::Type(name) => f.write_str(name),
+            SimpleSelector::Universal => f.write_str("*"),
+            SimpleSelector::Id(id) => write!(f, "#{id}"),
+            SimpleSelector::Class(class) => write!(f, ".{class}"),
+            SimpleSelector::Attribute {
+                name,
+                matcher,
+                case_insensitive,
+            } => {
+                write!(f, "[{name}")?;
+                if let Some((operator, value)) = matcher {
+                    write!(f, "{}{value:?}", operator.as_str())?;
+                }
+                if *case_insensitive {
+                    f.write_str(" i")?;
+                }
+                f.write_str("]")
+            }
+            SimpleSelector::PseudoClass {
+                name,
+                arguments: None,
+            } => write!(f, ":{name}"),
+            SimpleSelector::PseudoClass {
+                name,
+                arguments: Some(arguments),
+            } => write!(f, ":{name}({arguments})"),
+            SimpleSelector::PseudoElement(name) => write!(f, "::{name}"),
+        }
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, compound) in self.compounds.iter().enumerate() {
+            if index > 0 {
+                f.write_str(match self.combinators[index - 1] {
+                    Combinator::Descendant => " ",
+                    Combinator::Child => " > ",
+                    Combinator::NextSibling => " + ",
+                    Combinator::SubsequentSibling => " ~ ",
+                })?;
+            }
+            for simple in compound {
+                write!(f, "{simple}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn attribute(values: &[ComponentValue]) -> Option<SimpleSelector> {
+    let mut values = values
+        .iter()
+        .filter(|value| !value.is_whitespace())
+        .peekable();
+    let name = values.next()?.ident()?.to_ascii_lowercase();
+
+    let Some(first) = values.next() else {
+        return Some(SimpleSelector::Attribute {
+            name,
+            matcher: None,
+            case_insensitive: false,
+        });
+    };
+
+    let operator = match first {
+        ComponentValue::Token(Token::Delim('=')) => AttributeOperator::Equals,
+        ComponentValue::Token(Token::Delim(character)) => {
+            let operator = match character {
+                '~' => AttributeOperator::Includes,
+                '|' => AttributeOperator::DashMatch,
+                '^' => AttributeOperator::Prefix,
+                '$' => AttributeOperator::Suffix,
+                '*' => AttributeOperator::Substring,
+                _ => return None,
+            };
+            // The `=` must follow right away, which the whitespace filter can't tell.
+            if !values.next()?.is_token(&Token::Delim('=')) {
+                return None;
+            }
+            operator
+        }
+        _ => return None,
+    };
+
+    let value = match values.next()? {
+        ComponentValue::Token(Token::Ident(value) | Token::String(value)) => value.clone(),
+        _ => return None,
+    };
+
+    let case_insensitive = match values.next() {
+        None => false,
+        Some(flag) => match flag.ident()? {
+            flag if flag.eq_ignore_ascii_case("i") => true,
+            flag if flag.eq_ignore_ascii_case("s") => false,
+            _ => return None,
+        },
+    };
+
+    if values.next().is_some() {
+        return None;
+    }
+
+    Some(SimpleSelector::Attribute {
+        name,
+        matcher: Some((operator, value)),
+        case_insensitive,
+    })
+}
+
+fn pseudo(value: &ComponentValue, element: bool) -> Option<SimpleSelector> {
+    match value {
+        ComponentValue::Token(Token::Ident(name)) => {
+            let name = name.to_ascii_lowercase();
+            // CSS 2 wrote these with one colon, which still works.
+            let legacy = matches!(
+                name.as_str(),
+                "before" | "after" | "first-line" | "first-letter"
+            );
+
+            Some(if element || legacy {
+                SimpleSelector::PseudoElement(name)
+            } else {
+                SimpleSelector::PseudoClass {
+                    name,
+                    arguments: None,
+                }
+            })
+        }
+        ComponentValue::Function { name, value } if !element => Some(SimpleSelector::PseudoClass {
+            name: name.to_ascii_lowercase(),
+            arguments: Some(parser::serialize(parser::trim(value))),
+        }),
+        _ => None,
+    }
+}
+
+fn complex(values: &[ComponentValue]) -> Option<Selector> {
+    let mut selector = Selector {
+        compounds: Vec::new(),
+        combinators: Vec::new(),
+    };
+    let mut compound = Compound::new();
+    let mut combinator = None;
+    let mut values = parser::trim(values).iter().peekable();
+
+    while let Some(value) = values.next() {
+        let simple = match value {
+            ComponentValue::Token(Token::Whitespace) => {
+                if !compound.is_empty() {
+                    selector.compounds.push(std::mem::take(&mut compound));
+                    combinator = Some(Combinator::Descendant);
+                }
+                continue;
+            }
+            ComponentValue::Token(Token::Delim(character @ ('>' | '+' | '~'))) => {
+                if !compound.is_empty() {
+                    selector.compounds.push(std::mem::take(&mut compound));
+                } else if combinator != Some(Combinator::Descendant) {
+                    // Leading or doubled combinators.
+                    return None;
+                }
+                combinator = Some(match character {
+                    '>' => Combinator::Child,
+                    '+' => Combinator::NextSibling,
+                    _ => Combinator::SubsequentSibling,
+                });
+                continue;
+            }
+            ComponentValue::Token(Token::Ident(name)) if compound.is_empty() => {
+                SimpleSelector::Type(name.to_ascii_lowercase())
+            }
+            ComponentValue::Token(Token::Delim('*')) if compound.is_empty() => {
+                SimpleSelector::Universal
+            }
+            ComponentValue::Token(Token::Hash { value, is_id: true }) => {
+                SimpleSelector::Id(value.clone())
+            }
+            ComponentValue::Token(Token::Delim('.')) => {
+                SimpleSelector::Class(String::from(values.next()?.ident()?))
+            }
+            ComponentValue::Block {
+                bracket: Bracket::Square,
+                value,
+            } => attribute(value)?,
+            ComponentValue::Token(Token::Colon) => {
+                let element = values
+                    .next_if(|value| value.is_token(&Token::Colon))
+                    .is_some();
+                pseudo(values.next()?, element)?
+            }
+            _ => return None,
+        };
+
+        if let Some(combinator) = combinator.take() {
+            selector.combinators.push(combinator);
+        }
+        compound.push(simple);
+    }
+
+    // Empty selectors and trailing combinators.
+    if compound.is_empty() {
+        return None;
+    }
+    selector.compounds.push(compound);
+
+    Some(selector)
+}
+
+/// Parses a comma separated selector list, the prelude of a style rule. A single invalid
+/// selector makes the whole list invalid.
+pub fn parse_list(values: &[ComponentValue]) -> Option<Vec<Selector>> {
+    values
+        .split(|value| value.is_token(&Token::Comma))
+        .map(complex)
+        .collect()
+}
+
+/// Parses selectors written as text, like those passed to `querySelector`.
+pub fn parse(input: &str) -> Option<Vec<Selector>> {
+    parse_list(&parser::parse_component_values(input))
+}
+
+#[cfg(test)]
+mod selector_tests {
+    use super::{parse, AttributeOperator, Combinator, SimpleSelector};
+
+    fn selectors(input: &str) -> Option<Vec<String>> {
+        parse(input).map(|list| list.iter().map(ToString::to_string).collect())
+    }
+
+    #[test]
+    fn parses_compound_selectors() {
+        let list = parse("DIV#main.a.b[data-x|=\"en\" i]:Hover::before").unwrap();
+
+        assert_eq!(
+            list[0].compounds,
+            [vec![
+                SimpleSelector::Type(String::from("div")),
+                SimpleSelector::Id(String::from("main")),
+                SimpleSelector::Class(String::from("a")),
+                SimpleSelector::Class(String::from("b")),
+                SimpleSelector::Attribute {
+                    name: String::from("data-x"),
+                    matcher: Some((AttributeOperator::DashMatch, String::from("en"))),
+                    case_insensitive: true,
+                },
+                SimpleSelector::PseudoClass {
+                    name: String::from("hover"),
+                    arguments: None,
+                },
+                SimpleSelector::PseudoElement(String::from("before")),
+            ]]
+        );
+    }
+
+    #[test]
+    fn parses_combinators() {
+        let list = parse("ul  li>a + b~ c , *:not( .x )").unwrap();
+
+        assert_eq!(
+            list[0].combinators,
+            [
+                Combinator::Descendant,
+                Combinator::Child,
+                Combinator::NextSibling,
+                Combinator::SubsequentSibling
+            ]
+        );
+        assert_eq!(
+            selectors("ul  li>a + b~ c , *:not( .x ), a:after").unwrap(),
+            ["ul li > a + b ~ c", "*:not(.x)", "a::after"]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_selectors() {
+        for input in [
+            "",
+            "a,",
+            "> a",
+            "a >",
+            "a > > b",
+            "a.b c#1",
+            "[x=]",
+            "[x y]",
+            "p::hover(a)",
+            "a b|c",
+            "p.",
+            "a $ b",
+        ] {
+            assert_eq!(selectors(input), None, "{input}");
+        }
+    }
+
+    #[test]
+    fn computes_specificity() {
+        let specificity = |input: &str| parse(input).unwrap()[0].specificity();
+
+        assert_eq!(specificity("*"), (0, 0, 0));
+        assert_eq!(specificity("li::before"), (0, 0, 2));
+        assert_eq!(specificity("ul li.a[href]:hover"), (0, 3, 2));
+        assert_eq!(specificity("#a:not(#b, .c) :where(#d)"), (2, 0, 0));
+    }
+}
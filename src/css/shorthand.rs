@@ -0,0 +1,655 @@
+//! Expanding shorthand properties like `margin` into the longhands they set, so rules
+//! written either way cascade against each other.
+
+use super::parser::{self, ComponentValue, Declaration};
+use super::tokenizer::Token;
+
+const SIDES: [&str; 4] = ["top", "right", "bottom", "left"];
+
+const CORNERS: [&str; 4] = ["top-left", "top-right", "bottom-right", "bottom-left"];
+
+const CSS_WIDE_KEYWORDS: [&str; 5] = ["inherit", "initial", "unset", "revert", "revert-layer"];
+
+const BORDER_STYLES: [&str; 10] = [
+    "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset",
+];
+
+const FONT_SIZES: [&str; 10] = [
+    "xx-small",
+    "x-small",
+    "small",
+    "medium",
+    "large",
+    "x-large",
+    "xx-large",
+    "xxx-large",
+    "larger",
+    "smaller",
+];
+
+const FONT_STRETCHES: [&str; 8] = [
+    "ultra-condensed",
+    "extra-condensed",
+    "condensed",
+    "semi-condensed",
+    "semi-expanded",
+    "expanded",
+    "extra-expanded",
+    "ultra-expanded",
+];
+
+// `font: menu` and friends take the font of a part of the system's interface.
+const SYSTEM_FONTS: [&str; 6] = [
+    "caption",
+    "icon",
+    "menu",
+    "message-box",
+    "small-caption",
+    "status-bar",
+];
+
+// A group of longhands set to one value each, in the order `longhands` lists them.
+type Values = Vec<Vec<ComponentValue>>;
+
+// The longhands a shorthand sets, or `None` for longhands.
+fn longhands(name: &str) -> Option<Vec<String>> {
+    let sides = |format: &dyn Fn(&str) -> String| SIDES.iter().map(|side| format(side)).collect();
+
+    let names = match name {
+        "margin" | "padding" => sides(&|side| format!("{name}-{side}")),
+        "inset" => sides(&|side| String::from(side)),
+        "border-width" | "border-style" | "border-color" => {
+            let kind = &name["border-".len()..];
+            sides(&|side| format!("border-{side}-{kind}"))
+        }
+        "border-radius" => CORNERS
+            .iter()
+            .map(|corner| format!("border-{corner}-radius"))
+            .collect(),
+        "border-top" | "border-right" | "border-bottom" | "border-left" | "outline" => {
+            ["width", "style", "color"]
+                .iter()
+                .map(|kind| format!("{name}-{kind}"))
+                .collect()
+        }
+        "border" => SIDES
+            .iter()
+            .flat_map(|side| {
+                ["width", "style", "color"]
+                    .iter()
+                    .map(move |kind| format!("border-{side}-{kind}"))
+            })
+            .collect(),
+        "overflow" => vec![String::from("overflow-x"), String::from("overflow-y")],
+        "gap" => vec![String::from("row-gap"), String::from("column-gap")],
+        "list-style" => ["type", "position", "image"]
+            .iter()
+            .map(|kind| format!("list-style-{kind}"))
+            .collect(),
+        "flex" => ["grow", "shrink", "basis"]
+            .iter()
+            .map(|kind| format!("flex-{kind}"))
+            .collect(),
+        "font" => [
+            "font-style",
+            "font-variant",
+            "font-weight",
+            "font-stretch",
+            "font-size",
+            "line-height",
+            "font-family",
+        ]
+        .map(String::from)
+        .to_vec(),
+        "background" => [
+            "color",
+            "image",
+            "repeat",
+            "attachment",
+            "position",
+            "size",
+            "origin",
+            "clip",
+        ]
+        .iter()
+        .map(|kind| format!("background-{kind}"))
+        .collect(),
+        _ => return None,
+    };
+
+    Some(names)
+}
+
+fn is_keyword(value: &ComponentValue, keywords: &[&str]) -> bool {
+    value.ident().is_some_and(|ident| {
+        keywords
+            .iter()
+            .any(|keyword| ident.eq_ignore_ascii_case(keyword))
+    })
+}
+
+fn is_length(value: &ComponentValue) -> bool {
+    match value {
+        ComponentValue::Token(Token::Dimension { .. } | Token::Percentage(_)) => true,
+        ComponentValue::Token(Token::Number { value, .. }) => *value == 0.0,
+        ComponentValue::Function { name, .. } => {
+            matches!(
+                name.to_ascii_lowercase().as_str(),
+                "calc" | "min" | "max" | "clamp"
+            )
+        }
+        _ => false,
+    }
+}
+
+// Named colors are told apart from other keywords by the caller.
+fn is_color(value: &ComponentValue) -> bool {
+    match value {
+        ComponentValue::Token(Token::Hash { .. } | Token::Ident(_)) => true,
+        ComponentValue::Function { name, .. } => matches!(
+            name.to_ascii_lowercase().as_str(),
+            "rgb"
+                | "rgba"
+                | "hsl"
+                | "hsla"
+                | "hwb"
+                | "lab"
+                | "lch"
+                | "oklab"
+                | "oklch"
+                | "color"
+                | "color-mix"
+        ),
+        _ => false,
+    }
+}
+
+fn is_image(value: &ComponentValue) -> bool {
+    match value {
+        ComponentValue::Token(Token::Url(_)) => true,
+        ComponentValue::Function { name, .. } => {
+            let name = name.to_ascii_lowercase();
+            name == "url" || name == "image-set" || name.ends_with("gradient")
+        }
+        _ => false,
+    }
+}
+
+fn keyword(name: &str) -> Vec<ComponentValue> {
+    vec![ComponentValue::Token(Token::Ident(String::from(name)))]
+}
+
+// Space separated words as one value.
+fn join(words: &[&ComponentValue]) -> Vec<ComponentValue> {
+    let mut value = Vec::new();
+
+    for (index, &word) in words.iter().enumerate() {
+        if index > 0 {
+            value.push(ComponentValue::Token(Token::Whitespace));
+        }
+        value.push(word.clone());
+    }
+
+    value
+}
+
+// One to four values for the top, right, bottom and left, the missing ones copied from
+// the opposite side.
+fn box_sides(words: &[&ComponentValue]) -> Option<Values> {
+    let indices = match words.len() {
+        1 => [0, 0, 0, 0],
+        2 => [0, 1, 0, 1],
+        3 => [0, 1, 2, 1],
+        4 => [0, 1, 2, 3],
+        _ => return None,
+    };
+
+    Some(
+        indices
+            .iter()
+            .map(|&index| join(&words[index..=index]))
+            .collect(),
+    )
+}
+
+fn pair(words: &[&ComponentValue]) -> Option<Values> {
+    match words {
+        [both] => Some(vec![join(&[*both]), join(&[*both])]),
+        [first, second] => Some(vec![join(&[*first]), join(&[*second])]),
+        _ => None,
+    }
+}
+
+// A width, style and color in any order, as `border` and `outline` take them.
+fn line(words: &[&ComponentValue]) -> Option<Values> {
+    let (mut width, mut style, mut color) = (None, None, None);
+
+    for &word in words {
+        if style.is_none() && is_keyword(word, &BORDER_STYLES) {
+            style = Some(word);
+        } else if width.is_none()
+            && (is_length(word) || is_keyword(word, &["thin", "medium", "thick"]))
+        {
+            width = Some(word);
+        } else if color.is_none() && is_color(word) && !is_keyword(word, &BORDER_STYLES) {
+            color = Some(word);
+        } else {
+            return None;
+        }
+    }
+
+    Some(vec![
+        width.map_or_else(|| keyword("medium"), |width| join(&[width])),
+        style.map_or_else(|| keyword("none"), |style| join(&[style])),
+        color.map_or_else(|| keyword("currentcolor"), |color| join(&[color])),
+    ])
+}
+
+fn list_style(words: &[&ComponentValue]) -> Option<Values> {
+    let (mut kind, mut position, mut image) = (None, None, None);
+    let mut nones = 0;
+
+    for &word in words {
+        if is_keyword(word, &["none"]) {
+            nones += 1;
+        } else if position.is_none() && is_keyword(word, &["inside", "outside"]) {
+            position = Some(join(&[word]));
+        } else if image.is_none() && is_image(word) {
+            image = Some(join(&[word]));
+        } else if kind.is_none()
+            && matches!(
+                word,
+                ComponentValue::Token(Token::Ident(_) | Token::String(_))
+            )
+        {
+            kind = Some(join(&[word]));
+        } else {
+            return None;
+        }
+    }
+
+    // `none` goes to the type first, then to the image.
+    for _ in 0..nones {
+        if kind.is_none() {
+            kind = Some(keyword("none"));
+        } else if image.is_none() {
+            image = Some(keyword("none"));
+        } else {
+            return None;
+        }
+    }
+
+    Some(vec![
+        kind.unwrap_or_else(|| keyword("disc")),
+        position.unwrap_or_else(|| keyword("outside")),
+        image.unwrap_or_else(|| keyword("none")),
+    ])
+}
+
+fn number(value: f64) -> Vec<ComponentValue> {
+    vec![ComponentValue::Token(Token::Number {
+        value,
+        integer: true,
+    })]
+}
+
+fn flex(words: &[&ComponentValue]) -> Option<Values> {
+    match words {
+        [word] if is_keyword(word, &["none"]) => {
+            return Some(vec![number(0.0), number(0.0), keyword("auto")])
+        }
+        [word] if is_keyword(word, &["auto"]) => {
+            return Some(vec![number(1.0), number(1.0), keyword("auto")])
+        }
+        [] | [_, _, _, _, ..] => return None,
+        _ => (),
+    }
+
+    let is_number = |word: &ComponentValue| matches!(word, ComponentValue::Token(Token::Number { value, .. }) if *value >= 0.0);
+    let (mut grow, mut shrink, mut basis) = (None, None, None);
+    let mut after_grow = false;
+
+    for &word in words {
+        let follows_grow = std::mem::take(&mut after_grow);
+
+        if grow.is_none() && is_number(word) {
+            grow = Some(word);
+            after_grow = true;
+        } else if shrink.is_none() && follows_grow && is_number(word) {
+            shrink = Some(word);
+        } else if basis.is_none() && (is_length(word) || is_keyword(word, &["auto", "content"])) {
+            basis = Some(word);
+        } else {
+            return None;
+        }
+    }
+
+    Some(vec![
+        grow.map_or_else(|| number(1.0), |grow| join(&[grow])),
+        shrink.map_or_else(|| number(1.0), |shrink| join(&[shrink])),
+        // `flex: 1` shares out all the space, not just what's left over.
+        basis.map_or_else(
+            || vec![ComponentValue::Token(Token::Percentage(0.0))],
+            |basis| join(&[basis]),
+        ),
+    ])
+}
+
+// `[style || variant || weight || stretch]? size [/ line-height]? family`.
+fn font(value: &[ComponentValue]) -> Option<Values> {
+    let words: Vec<usize> = (0..value.len())
+        .filter(|&index| !value[index].is_whitespace())
+        .collect();
+    let (mut style, mut variant, mut weight, mut stretch) = (None, None, None, None);
+    let mut position = 0;
+
+    while position < words.len().min(4) {
+        let word = &value[words[position]];
+
+        if is_keyword(word, &["normal"]) {
+            // Leaves whichever of them it stands for as it is.
+        } else if style.is_none() && is_keyword(word, &["italic", "oblique"]) {
+            style = Some(word);
+        } else if variant.is_none() && is_keyword(word, &["small-caps"]) {
+            variant = Some(word);
+        } else if weight.is_none()
+            && (is_keyword(word, &["bold", "bolder", "lighter"])
+                || matches!(word, ComponentValue::Token(Token::Number { value, .. }) if (1.0..=1000.0).contains(value)))
+        {
+            weight = Some(word);
+        } else if stretch.is_none() && is_keyword(word, &FONT_STRETCHES) {
+            stretch = Some(word);
+        } else {
+            break;
+        }
+        position += 1;
+    }
+
+    let size = &value[*words.get(position)?];
+    if !is_length(size) && !is_keyword(size, &FONT_SIZES) {
+        return None;
+    }
+    position += 1;
+
+    let mut line_height = None;
+    if words
+        .get(position)
+        .is_some_and(|&index| value[index].is_token(&Token::Delim('/')))
+    {
+        line_height = Some(&value[*words.get(position + 1)?]);
+        position += 2;
+    }
+
+    let family = parser::trim(&value[*words.get(position)?..]);
+    let is_family = |word: &ComponentValue| {
+        matches!(
+            word,
+            ComponentValue::Token(
+                Token::Ident(_) | Token::String(_) | Token::Comma | Token::Whitespace
+            )
+        )
+    };
+    if !family.iter().all(is_family) {
+        return None;
+    }
+
+    let normal = |word: Option<&ComponentValue>| {
+        word.map_or_else(|| keyword("normal"), |word| join(&[word]))
+    };
+    Some(vec![
+        normal(style),
+        normal(variant),
+        normal(weight),
+        normal(stretch),
+        join(&[size]),
+        normal(line_height),
+        family.to_vec(),
+    ])
+}
+
+// A single background layer. Several layers are left as they are.
+fn background(words: &[&ComponentValue]) -> Option<Values> {
+    let (mut color, mut image, mut attachment) = (None, None, None);
+    let (mut repeat, mut position, mut size, mut boxes) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    let is_position = |word: &ComponentValue| {
+        is_length(word) || is_keyword(word, &["left", "right", "top", "bottom", "center"])
+    };
+    let mut words = words.iter().copied().peekable();
+
+    while let Some(word) = words.next() {
+        if image.is_none() && (is_image(word) || is_keyword(word, &["none"])) {
+            image = Some(word);
+        } else if repeat.len() < 2
+            && is_keyword(
+                word,
+                &[
+                    "repeat",
+                    "repeat-x",
+                    "repeat-y",
+                    "no-repeat",
+                    "space",
+                    "round",
+                ],
+            )
+        {
+            repeat.push(word);
+        } else if attachment.is_none() && is_keyword(word, &["scroll", "fixed", "local"]) {
+            attachment = Some(word);
+        } else if boxes.len() < 2 && is_keyword(word, &["border-box", "padding-box", "content-box"])
+        {
+            boxes.push(word);
+        } else if position.is_empty() && is_position(word) {
+            position.push(word);
+            while let Some(word) = words.next_if(|&word| is_position(word)) {
+                position.push(word);
+            }
+
+            // The size can only follow the position, after a `/`.
+            if words
+                .next_if(|word| word.is_token(&Token::Delim('/')))
+                .is_some()
+            {
+                while let Some(word) = words.next_if(|&word| {
+                    is_length(word) || is_keyword(word, &["auto", "cover", "contain"])
+                }) {
+                    size.push(word);
+                }
+                if size.is_empty() {
+                    return None;
+                }
+            }
+        } else if color.is_none() && is_color(word) {
+            color = Some(word);
+        } else {
+            return None;
+        }
+    }
+
+    let or = |words: &[&ComponentValue], initial: &str| {
+        if words.is_empty() {
+            parser::parse_component_values(initial)
+        } else {
+            join(words)
+        }
+    };
+    let origin = boxes.first().copied();
+    let clip = boxes.last().copied();
+
+    Some(vec![
+        or(color.as_slice(), "transparent"),
+        or(image.as_slice(), "none"),
+        or(&repeat, "repeat"),
+        or(attachment.as_slice(), "scroll"),
+        or(&position, "0% 0%"),
+        or(&size, "auto"),
+        or(origin.as_slice(), "padding-box"),
+        or(clip.as_slice(), "border-box"),
+    ])
+}
+
+/// The longhands `declaration` sets: itself if it isn't a shorthand, and nothing if its
+/// value isn't valid for the shorthand.
+pub fn expand(declaration: Declaration) -> Vec<Declaration> {
+    let Some(names) = longhands(&declaration.name) else {
+        return vec![declaration];
+    };
+
+    let value = &declaration.value;
+    let words: Vec<&ComponentValue> = value.iter().filter(|word| !word.is_whitespace()).collect();
+    let has = |token: Token| words.iter().any(|word| word.is_token(&token));
+    let uses_variables = |value: &ComponentValue| matches!(value, ComponentValue::Function { name, .. } if name.eq_ignore_ascii_case("var"));
+
+    // What these expand to is only known once the page is laid out, or isn't supported.
+    if value.iter().any(uses_variables)
+        || (declaration.name == "border-radius" && has(Token::Delim('/')))
+        || (declaration.name == "background" && has(Token::Comma))
+        || (declaration.name == "font"
+            && matches!(words[..], [word] if is_keyword(word, &SYSTEM_FONTS)))
+    {
+        return vec![declaration];
+    }
+
+    let values = match (declaration.name.as_str(), &words[..]) {
+        (_, [word]) if is_keyword(word, &CSS_WIDE_KEYWORDS) => {
+            Some(vec![join(&[*word]); names.len()])
+        }
+        (
+            "margin" | "padding" | "inset" | "border-width" | "border-style" | "border-color"
+            | "border-radius",
+            words,
+        ) => box_sides(words),
+        ("border", words) => {
+            line(words).map(|line| SIDES.iter().flat_map(|_| line.clone()).collect())
+        }
+        ("border-top" | "border-right" | "border-bottom" | "border-left" | "outline", words) => {
+            line(words)
+        }
+        ("overflow" | "gap", words) => pair(words),
+        ("list-style", words) => list_style(words),
+        ("flex", words) => flex(words),
+        ("font", _) => font(value),
+        ("background", words) => background(words),
+        _ => None,
+    };
+
+    let Some(values) = values else {
+        log::debug!("Skipping invalid {declaration}");
+        return Vec::new();
+    };
+
+    names
+        .into_iter()
+        .zip(values)
+        .map(|(name, value)| Declaration {
+            name,
+            value,
+            important: declaration.important,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod shorthand_tests {
+    use crate::css::parser::parse_declarations;
+
+    fn longhands(input: &str) -> Vec<String> {
+        parse_declarations(input)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn expands_box_sides() {
+        assert_eq!(
+            longhands("margin: 1px 2px 3px"),
+            [
+                "margin-top: 1px",
+                "margin-right: 2px",
+                "margin-bottom: 3px",
+                "margin-left: 2px"
+            ]
+        );
+        assert_eq!(
+            longhands("border-color: red blue"),
+            [
+                "border-top-color: red",
+                "border-right-color: blue",
+                "border-bottom-color: red",
+                "border-left-color: blue"
+            ]
+        );
+        assert!(longhands("padding: 1px 2px 3px 4px 5px").is_empty());
+    }
+
+    #[test]
+    fn expands_borders() {
+        assert_eq!(
+            longhands("outline: dashed #00f"),
+            [
+                "outline-width: medium",
+                "outline-style: dashed",
+                "outline-color: #00f"
+            ]
+        );
+        assert_eq!(longhands("border: 1px solid black").len(), 12);
+        assert!(longhands("border: solid dotted").is_empty());
+    }
+
+    #[test]
+    fn expands_fonts() {
+        assert_eq!(
+            longhands("font: italic bold 12px/1.5 \"Open Sans\", sans-serif !important"),
+            [
+                "font-style: italic !important",
+                "font-variant: normal !important",
+                "font-weight: bold !important",
+                "font-stretch: normal !important",
+                "font-size: 12px !important",
+                "line-height: 1.5 !important",
+                "font-family: \"Open Sans\", sans-serif !important"
+            ]
+        );
+        assert_eq!(longhands("font: menu"), ["font: menu"]);
+        assert!(longhands("font: bold serif").is_empty());
+    }
+
+    #[test]
+    fn expands_backgrounds() {
+        assert_eq!(
+            longhands("background: url(a.png) no-repeat center / cover #fff"),
+            [
+                "background-color: #fff",
+                "background-image: url(\"a.png\")",
+                "background-repeat: no-repeat",
+                "background-attachment: scroll",
+                "background-position: center",
+                "background-size: cover",
+                "background-origin: padding-box",
+                "background-clip: border-box"
+            ]
+        );
+        assert_eq!(longhands("background: url(a.png), red").len(), 1);
+    }
+
+    #[test]
+    fn expands_other_shorthands() {
+        assert_eq!(
+            longhands("flex: 2; overflow: hidden auto; list-style: none; gap: inherit"),
+            [
+                "flex-grow: 2",
+                "flex-shrink: 1",
+                "flex-basis: 0%",
+                "overflow-x: hidden",
+                "overflow-y: auto",
+                "list-style-type: none",
+                "list-style-position: outside",
+                "list-style-image: none",
+                "row-gap: inherit",
+                "column-gap: inherit"
+            ]
+        );
+        assert_eq!(longhands("margin: var(--space)"), ["margin: var(--space)"]);
+    }
+}
@@ -0,0 +1,587 @@
+//! The CSS tokenizer (CSS Syntax Module Level 3, section 4).
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Ident(String),
+    // A name followed by `(`, like `rgb(`.
+    Function(String),
+    AtKeyword(String),
+    Hash {
+        value: String,
+        // Whether the value is an identifier, as `#id` selectors need.
+        is_id: bool,
+    },
+    String(String),
+    // A string broken by a newline.
+    BadString,
+    Url(String),
+    BadUrl,
+    Delim(char),
+    Number {
+        value: f64,
+        integer: bool,
+    },
+    Percentage(f64),
+    Dimension {
+        value: f64,
+        integer: bool,
+        unit: String,
+    },
+    Whitespace,
+    // `<!--` and `-->`, allowed around style sheets for old browsers.
+    Cdo,
+    Cdc,
+    Colon,
+    Semicolon,
+    Comma,
+    OpenSquare,
+    CloseSquare,
+    OpenParen,
+    CloseParen,
+    OpenCurly,
+    CloseCurly,
+}
+
+fn is_ident_start(character: char) -> bool {
+    character.is_ascii_alphabetic() || character == '_' || !character.is_ascii()
+}
+
+fn is_ident(character: char) -> bool {
+    is_ident_start(character) || character.is_ascii_digit() || character == '-'
+}
+
+fn is_whitespace(character: char) -> bool {
+    matches!(character, '\n' | '\t' | ' ')
+}
+
+fn is_valid_escape(first: Option<char>, second: Option<char>) -> bool {
+    first == Some('\\') && second != Some('\n')
+}
+
+fn would_start_ident(first: Option<char>, second: Option<char>, third: Option<char>) -> bool {
+    match first {
+        Some('-') => {
+            second.is_some_and(|second| is_ident_start(second) || second == '-')
+                || is_valid_escape(second, third)
+        }
+        Some('\\') => is_valid_escape(first, second),
+        Some(first) => is_ident_start(first),
+        None => false,
+    }
+}
+
+fn would_start_number(first: Option<char>, second: Option<char>, third: Option<char>) -> bool {
+    let is_digit = |character: Option<char>| character.is_some_and(|c| c.is_ascii_digit());
+
+    match first {
+        Some('+' | '-') => is_digit(second) || (second == Some('.') && is_digit(third)),
+        Some('.') => is_digit(second),
+        first => is_digit(first),
+    }
+}
+
+// Numbers are written the shortest way that reads back the same.
+fn write_number(f: &mut fmt::Formatter<'_>, value: f64, integer: bool) -> fmt::Result {
+    if integer {
+        write!(f, "{}", value as i64)
+    } else {
+        write!(f, "{value}")
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for character in value.chars() {
+        match character {
+            '"' | '\\' => write!(f, "\\{character}")?,
+            '\n' => f.write_str("\\a ")?,
+            character => write!(f, "{character}")?,
+        }
+    }
+    f.write_str("\"")
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(name) => f.write_str(name),
+            Token::Function(name) => write!(f, "{name}("),
+            Token::AtKeyword(name) => write!(f, "@{name}"),
+            Token::Hash { value, .. } => write!(f, "#{value}"),
+            Token::String(value) => write_string(f, value),
+            Token::BadString => f.write_str("\"\""),
+            Token::Url(value) => {
+                f.write_str("url(")?;
+                write_string(f, value)?;
+                f.write_str(")")
+            }
+            Token::BadUrl => f.write_str("url()"),
+            Token::Delim(character) => write!(f, "{character}"),
+            Token::Number { value, integer } => write_number(f, *value, *integer),
+            Token::Percentage(value) => {
+                write_number(f, *value, value.fract() == 0.0)?;
+                f.write_str("%")
+            }
+            Token::Dimension {
+                value,
+                integer,
+                unit,
+            } => {
+                write_number(f, *value, *integer)?;
+                f.write_str(unit)
+            }
+            Token::Whitespace => f.write_str(" "),
+            Token::Cdo => f.write_str("<!--"),
+            Token::Cdc => f.write_str("-->"),
+            Token::Colon => f.write_str(":"),
+            Token::Semicolon => f.write_str(";"),
+            Token::Comma => f.write_str(","),
+            Token::OpenSquare => f.write_str("["),
+            Token::CloseSquare => f.write_str("]"),
+            Token::OpenParen => f.write_str("("),
+            Token::CloseParen => f.write_str(")"),
+            Token::OpenCurly => f.write_str("{"),
+            Token::CloseCurly => f.write_str("}"),
+        }
+    }
+}
+
+/// Splits CSS into tokens, dropping comments.
+pub struct Tokenizer {
+    input: Vec<char>,
+    position: usize,
+}
+
+impl Tokenizer {
+    pub fn new(input: &str) -> Self {
+        // Preprocessing (section 3.3).
+        let input = input
+            .replace("\r\n", "\n")
+            .replace(['\r', '\x0C'], "\n")
+            .replace('\0', "\u{FFFD}");
+
+        Self {
+            input: input.chars().collect(),
+            position: 0,
+        }
+    }
+
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.input.get(self.position + offset).copied()
+    }
+
+    fn consume(&mut self) -> Option<char> {
+        let character = self.peek(0);
+        self.position += 1;
+
+        character
+    }
+
+    fn skip_comments(&mut self) {
+        while self.peek(0) == Some('/') && self.peek(1) == Some('*') {
+            self.position += 2;
+
+            while self.position < self.input.len()
+                && !(self.peek(0) == Some('*') && self.peek(1) == Some('/'))
+            {
+                self.position += 1;
+            }
+
+            self.position = (self.position + 2).min(self.input.len());
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek(0).is_some_and(is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    // After a `\`, known to be a valid escape (section 4.3.7).
+    fn consume_escape(&mut self) -> char {
+        let Some(character) = self.consume() else {
+            return char::REPLACEMENT_CHARACTER;
+        };
+
+        if !character.is_ascii_hexdigit() {
+            return character;
+        }
+
+        let mut digits = String::from(character);
+        while digits.len() < 6 && self.peek(0).is_some_and(|c| c.is_ascii_hexdigit()) {
+            digits.push(self.input[self.position]);
+            self.position += 1;
+        }
+
+        if self.peek(0).is_some_and(is_whitespace) {
+            self.position += 1;
+        }
+
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .filter(|&value| value != 0)
+            .and_then(char::from_u32)
+            .unwrap_or(char::REPLACEMENT_CHARACTER)
+    }
+
+    fn consume_ident_sequence(&mut self) -> String {
+        let mut name = String::new();
+
+        loop {
+            match self.peek(0) {
+                Some(character) if is_ident(character) => {
+                    name.push(character);
+                    self.position += 1;
+                }
+                first if is_valid_escape(first, self.peek(1)) => {
+                    self.position += 1;
+                    name.push(self.consume_escape());
+                }
+                _ => return name,
+            }
+        }
+    }
+
+    fn consume_number(&mut self) -> (f64, bool) {
+        let start = self.position;
+        let mut integer = true;
+        let is_digit = |character: Option<char>| character.is_some_and(|c| c.is_ascii_digit());
+
+        if matches!(self.peek(0), Some('+' | '-')) {
+            self.position += 1;
+        }
+        while is_digit(self.peek(0)) {
+            self.position += 1;
+        }
+
+        if self.peek(0) == Some('.') && is_digit(self.peek(1)) {
+            integer = false;
+            self.position += 1;
+            while is_digit(self.peek(0)) {
+                self.position += 1;
+            }
+        }
+
+        if matches!(self.peek(0), Some('e' | 'E')) {
+            let signed = matches!(self.peek(1), Some('+' | '-'));
+            if is_digit(self.peek(1)) || (signed && is_digit(self.peek(2))) {
+                integer = false;
+                self.position += if signed { 2 } else { 1 };
+                while is_digit(self.peek(0)) {
+                    self.position += 1;
+                }
+            }
+        }
+
+        let representation: String = self.input[start..self.position].iter().collect();
+
+        (representation.parse().unwrap_or(0.0), integer)
+    }
+
+    fn consume_numeric(&mut self) -> Token {
+        let (value, integer) = self.consume_number();
+
+        if would_start_ident(self.peek(0), self.peek(1), self.peek(2)) {
+            Token::Dimension {
+                value,
+                integer,
+                unit: self.consume_ident_sequence(),
+            }
+        } else if self.peek(0) == Some('%') {
+            self.position += 1;
+            Token::Percentage(value)
+        } else {
+            Token::Number { value, integer }
+        }
+    }
+
+    fn consume_string(&mut self, ending: char) -> Token {
+        let mut value = String::new();
+
+        loop {
+            match self.consume() {
+                None => return Token::String(value),
+                Some(character) if character == ending => return Token::String(value),
+                Some('\n') => {
+                    self.position -= 1;
+                    return Token::BadString;
+                }
+                Some('\\') => match self.peek(0) {
+                    None => (),
+                    Some('\n') => self.position += 1,
+                    Some(_) => value.push(self.consume_escape()),
+                },
+                Some(character) => value.push(character),
+            }
+        }
+    }
+
+    // Skips the rest of a URL that turned out to be invalid.
+    fn consume_bad_url(&mut self) -> Token {
+        loop {
+            match self.peek(0) {
+                None => return Token::BadUrl,
+                Some(')') => {
+                    self.position += 1;
+                    return Token::BadUrl;
+                }
+                first if is_valid_escape(first, self.peek(1)) => {
+                    self.position += 1;
+                    self.consume_escape();
+                }
+                Some(_) => self.position += 1,
+            }
+        }
+    }
+
+    // An unquoted `url(...)`, after its `(` (section 4.3.6).
+    fn consume_url(&mut self) -> Token {
+        let mut value = String::new();
+        self.skip_whitespace();
+
+        loop {
+            match self.consume() {
+                None | Some(')') => return Token::Url(value),
+                Some(character) if is_whitespace(character) => {
+                    self.skip_whitespace();
+                    return match self.peek(0) {
+                        None => Token::Url(value),
+                        Some(')') => {
+                            self.position += 1;
+                            Token::Url(value)
+                        }
+                        Some(_) => self.consume_bad_url(),
+                    };
+                }
+                Some('"' | '\'' | '(') => return self.consume_bad_url(),
+                Some(character) if character.is_control() && !is_whitespace(character) => {
+                    return self.consume_bad_url()
+                }
+                Some('\\') if is_valid_escape(Some('\\'), self.peek(0)) => {
+                    value.push(self.consume_escape())
+                }
+                Some('\\') => return self.consume_bad_url(),
+                Some(character) => value.push(character),
+            }
+        }
+    }
+
+    fn consume_ident_like(&mut self) -> Token {
+        let name = self.consume_ident_sequence();
+
+        if self.peek(0) != Some('(') {
+            return Token::Ident(name);
+        }
+        self.position += 1;
+
+        if !name.eq_ignore_ascii_case("url") {
+            return Token::Function(name);
+        }
+
+        // `url("...")` is a function taking a string, only bare URLs are URL tokens.
+        while self.peek(0).is_some_and(is_whitespace) && self.peek(1).is_some_and(is_whitespace) {
+            self.position += 1;
+        }
+
+        let quoted = |character: Option<char>| matches!(character, Some('"' | '\''));
+        if quoted(self.peek(0)) || (self.peek(0).is_some_and(is_whitespace) && quoted(self.peek(1)))
+        {
+            Token::Function(name)
+        } else {
+            self.consume_url()
+        }
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        self.skip_comments();
+
+        let character = self.consume()?;
+        let (next, after) = (self.peek(0), self.peek(1));
+
+        let token = match character {
+            character if is_whitespace(character) => {
+                self.skip_whitespace();
+                Token::Whitespace
+            }
+            '"' | '\'' => self.consume_string(character),
+            '#' if next.is_some_and(is_ident) || is_valid_escape(next, after) => {
+                let is_id = would_start_ident(next, after, self.peek(2));
+                Token::Hash {
+                    value: self.consume_ident_sequence(),
+                    is_id,
+                }
+            }
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            '[' => Token::OpenSquare,
+            ']' => Token::CloseSquare,
+            '{' => Token::OpenCurly,
+            '}' => Token::CloseCurly,
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            ';' => Token::Semicolon,
+            '+' | '.' if would_start_number(Some(character), next, after) => {
+                self.position -= 1;
+                self.consume_numeric()
+            }
+            '-' if would_start_number(Some(character), next, after) => {
+                self.position -= 1;
+                self.consume_numeric()
+            }
+            '-' if next == Some('-') && after == Some('>') => {
+                self.position += 2;
+                Token::Cdc
+            }
+            '-' if would_start_ident(Some(character), next, after) => {
+                self.position -= 1;
+                self.consume_ident_like()
+            }
+            '<' if next == Some('!') && after == Some('-') && self.peek(2) == Some('-') => {
+                self.position += 3;
+                Token::Cdo
+            }
+            '@' if would_start_ident(next, after, self.peek(2)) => {
+                Token::AtKeyword(self.consume_ident_sequence())
+            }
+            '\\' if is_valid_escape(Some(character), next) => {
+                self.position -= 1;
+                self.consume_ident_like()
+            }
+            character if character.is_ascii_digit() => {
+                self.position -= 1;
+                self.consume_numeric()
+            }
+            character if is_ident_start(character) => {
+                self.position -= 1;
+                self.consume_ident_like()
+            }
+            character => Token::Delim(character),
+        };
+
+        Some(token)
+    }
+}
+
+impl Iterator for Tokenizer {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.next_token()
+    }
+}
+
+#[cfg(test)]
+mod tokenizer_tests {
+    use super::{Token, Tokenizer};
+
+    fn tokens(input: &str) -> Vec<Token> {
+        Tokenizer::new(input).collect()
+    }
+
+    fn ident(name: &str) -> Token {
+        Token::Ident(String::from(name))
+    }
+
+    #[test]
+    fn reads_rules() {
+        assert_eq!(
+            tokens("a#b.c/* comment */{color:red!important}"),
+            vec![
+                ident("a"),
+                Token::Hash {
+                    value: String::from("b"),
+                    is_id: true
+                },
+                Token::Delim('.'),
+                ident("c"),
+                Token::OpenCurly,
+                ident("color"),
+                Token::Colon,
+                ident("red"),
+                Token::Delim('!'),
+                ident("important"),
+                Token::CloseCurly,
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_numbers() {
+        assert_eq!(
+            tokens("12 -3.5em +.5% 1e3 10e 2-x"),
+            vec![
+                Token::Number {
+                    value: 12.0,
+                    integer: true
+                },
+                Token::Whitespace,
+                Token::Dimension {
+                    value: -3.5,
+                    integer: false,
+                    unit: String::from("em")
+                },
+                Token::Whitespace,
+                Token::Percentage(0.5),
+                Token::Whitespace,
+                Token::Number {
+                    value: 1000.0,
+                    integer: false
+                },
+                Token::Whitespace,
+                Token::Dimension {
+                    value: 10.0,
+                    integer: true,
+                    unit: String::from("e")
+                },
+                Token::Whitespace,
+                Token::Dimension {
+                    value: 2.0,
+                    integer: true,
+                    unit: String::from("-x")
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_strings_urls_and_escapes() {
+        assert_eq!(
+            tokens("'a\\'b' url( img.png ) url(\"q.png\") url(a b) \\31 23 @media --x --> \"x\ny"),
+            vec![
+                Token::String(String::from("a'b")),
+                Token::Whitespace,
+                Token::Url(String::from("img.png")),
+                Token::Whitespace,
+                Token::Function(String::from("url")),
+                Token::String(String::from("q.png")),
+                Token::CloseParen,
+                Token::Whitespace,
+                Token::BadUrl,
+                Token::Whitespace,
+                ident("123"),
+                Token::Whitespace,
+                Token::AtKeyword(String::from("media")),
+                Token::Whitespace,
+                ident("--x"),
+                Token::Whitespace,
+                Token::Cdc,
+                Token::Whitespace,
+                // A newline ends the string, and what follows is read afresh.
+                Token::BadString,
+                Token::Whitespace,
+                ident("y"),
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_tokens_back_as_css() {
+        let css: String = Tokenizer::new("rgb(0 , 1.50) \"a\\\"b\" 50% #fff url(x)")
+            .map(|token| token.to_string())
+            .collect();
+
+        assert_eq!(css, "rgb(0 , 1.5) \"a\\\"b\" 50% #fff url(\"x\")");
+    }
+}
@@ -0,0 +1,187 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+
+// Days between 1970-01-01 and the given proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month as i64 + 9) % 12) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = if days >= 0 { days } else { days - 146096 } / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+fn parse_month(value: &str) -> Option<u32> {
+    MONTHS
+        .iter()
+        .position(|month| month.eq_ignore_ascii_case(value))
+        .map(|index| index as u32 + 1)
+}
+
+fn parse_time(value: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = value.split(':').map(|part| part.parse::<u64>().ok());
+
+    let hours = parts.next()??;
+    let minutes = parts.next()??;
+    let seconds = parts.next()??;
+
+    if parts.next().is_some() || hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+
+    Some((hours, minutes, seconds))
+}
+
+fn to_system_time(year: i64, month: u32, day: u32, time: (u64, u64, u64)) -> Option<SystemTime> {
+    if !(1..=31).contains(&day) || year < 1970 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day) as u64;
+    let seconds = days * 86400 + time.0 * 3600 + time.1 * 60 + time.2;
+
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// Parses an HTTP-date in any of the three formats allowed by RFC 9110 section 5.6.7.
+pub fn parse_http_date(value: &str) -> Option<SystemTime> {
+    let parts: Vec<&str> = value
+        .split([' ', ','])
+        .filter(|part| !part.is_empty())
+        .collect();
+
+    match parts.as_slice() {
+        // IMF-fixdate: Sun, 06 Nov 1994 08:49:37 GMT
+        [_, day, month, year, time, "GMT"] => to_system_time(
+            year.parse().ok()?,
+            parse_month(month)?,
+            day.parse().ok()?,
+            parse_time(time)?,
+        ),
+        // RFC 850: Sunday, 06-Nov-94 08:49:37 GMT
+        [_, date, time, "GMT"] => {
+            let mut date_parts = date.split('-');
+            let day = date_parts.next()?.parse().ok()?;
+            let month = parse_month(date_parts.next()?)?;
+            let year: i64 = date_parts.next()?.parse().ok()?;
+            let year = if year < 70 { 2000 + year } else { 1900 + year };
+
+            to_system_time(year, month, day, parse_time(time)?)
+        }
+        // asctime: Sun Nov  6 08:49:37 1994
+        [_, month, day, time, year] => to_system_time(
+            year.parse().ok()?,
+            parse_month(month)?,
+            day.parse().ok()?,
+            parse_time(time)?,
+        ),
+        _ => None,
+    }
+}
+
+/// Formats a time as an IMF-fixdate.
+pub fn format_http_date(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs();
+    let days = (seconds / 86400) as i64;
+    let (year, month, day) = civil_from_days(days);
+    let seconds_of_day = seconds % 86400;
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[(days % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60
+    )
+}
+
+/// Formats a time as ISO 8601 in UTC with milliseconds, e.g. `1994-11-06T08:49:37.000Z`.
+pub fn format_iso8601(time: SystemTime) -> String {
+    let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO);
+    let seconds = elapsed.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let seconds_of_day = seconds % 86400;
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60,
+        elapsed.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod http_date_tests {
+    use super::{format_http_date, format_iso8601, parse_http_date};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    // Sun, 06 Nov 1994 08:49:37 GMT
+    const EXAMPLE_SECONDS: u64 = 784111777;
+
+    #[test]
+    fn parses_all_three_formats() {
+        let expected = Some(UNIX_EPOCH + Duration::from_secs(EXAMPLE_SECONDS));
+
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), expected);
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), expected);
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), expected);
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert_eq!(parse_http_date("tomorrow"), None);
+        assert_eq!(parse_http_date("Sun, 06 Foo 1994 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 25:49:37 GMT"), None);
+    }
+
+    #[test]
+    fn formats_imf_fixdate() {
+        assert_eq!(
+            format_http_date(UNIX_EPOCH + Duration::from_secs(EXAMPLE_SECONDS)),
+            "Sun, 06 Nov 1994 08:49:37 GMT"
+        );
+        assert_eq!(
+            format_http_date(UNIX_EPOCH),
+            "Thu, 01 Jan 1970 00:00:00 GMT"
+        );
+    }
+
+    #[test]
+    fn formats_iso8601() {
+        assert_eq!(
+            format_iso8601(UNIX_EPOCH + Duration::from_millis(EXAMPLE_SECONDS * 1000 + 42)),
+            "1994-11-06T08:49:37.042Z"
+        );
+    }
+}
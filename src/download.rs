@@ -0,0 +1,187 @@
+use crate::header::Header;
+use crate::range::{self, ByteRange, ContentRange};
+use crate::request::Request;
+use crate::uri::URI;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// Bytes asked for per request, so an interrupted download keeps what it already got.
+const CHUNK_SIZE: u64 = 1 << 20;
+
+/// Saves a URL to a file, resumably: bytes are appended to `<destination>.part` one range
+/// at a time and the file only takes its final name once its length checks out. Running
+/// it again after an interruption picks up where the `.part` file ends, provided the
+/// server still has the same representation.
+pub struct Download {
+    pub destination: PathBuf,
+    pub chunk_size: u64,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(suffix);
+
+    PathBuf::from(name)
+}
+
+impl Download {
+    pub fn new(destination: &Path) -> Self {
+        Self {
+            destination: destination.to_path_buf(),
+            chunk_size: CHUNK_SIZE,
+        }
+    }
+
+    /// Where the bytes downloaded so far are kept.
+    pub fn part_path(&self) -> PathBuf {
+        with_suffix(&self.destination, ".part")
+    }
+
+    // The ETag or Last-Modified the `.part` file was downloaded with, sent as If-Range.
+    fn validator_path(&self) -> PathBuf {
+        with_suffix(&self.destination, ".part.validator")
+    }
+
+    fn append(&self, bytes: &[u8]) -> io::Result<()> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.part_path())?
+            .write_all(bytes)
+    }
+
+    fn restart(&self) -> io::Result<()> {
+        File::create(self.part_path())?;
+
+        match fs::remove_file(self.validator_path()) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Downloads `url`, returning the length of the saved file.
+    pub fn run(&self, request: &Request, url: &URI) -> io::Result<u64> {
+        let mut offset = fs::metadata(self.part_path()).map_or(0, |metadata| metadata.len());
+        let mut validator = fs::read_to_string(self.validator_path()).ok();
+
+        if offset > 0 && validator.is_none() {
+            // Without a validator there's no telling whether the bytes are still current.
+            log::info!("Can't resume {}, starting over", url.as_str());
+            offset = 0;
+        }
+
+        if offset == 0 {
+            self.restart()?;
+            validator = None;
+        } else {
+            log::info!("Resuming {} from byte {offset}", url.as_str());
+        }
+
+        let mut complete_length;
+        let mut restarted = false;
+
+        loop {
+            let range = ByteRange::new(offset, offset + self.chunk_size - 1);
+            let response = request.send_range(url, &range, validator.as_deref())?;
+
+            let content_range = response
+                .headers
+                .get(Header::ContentRange.as_str())
+                .map(|value| ContentRange::parse(value))
+                .transpose()?;
+
+            match response.status_code {
+                206 => {
+                    let (start, end) = content_range
+                        .and_then(|content_range| content_range.range)
+                        .ok_or_else(|| invalid(String::from("206 without a Content-Range")))?;
+
+                    if start != offset || response.body.len() as u64 != end - start + 1 {
+                        return Err(invalid(format!(
+                            "Asked for {range}, got bytes {start}-{end} ({} bytes)",
+                            response.body.len()
+                        )));
+                    }
+
+                    if validator.is_none() {
+                        validator = range::validator(&response.headers);
+                        if let Some(validator) = &validator {
+                            fs::write(self.validator_path(), validator)?;
+                        }
+                    }
+
+                    self.append(&response.body)?;
+                    offset = end + 1;
+                    complete_length = content_range.and_then(|range| range.complete_length);
+
+                    let short = end - start + 1 < self.chunk_size;
+                    if complete_length.map_or(short, |length| offset >= length) {
+                        break;
+                    }
+                }
+                200 => {
+                    // The server ignored the range, or the representation changed since the
+                    // `.part` file was started: either way this is the whole thing.
+                    if offset > 0 {
+                        log::info!("{} changed, starting over", url.as_str());
+                    }
+                    self.restart()?;
+                    self.append(&response.body)?;
+                    complete_length = Some(response.body.len() as u64);
+                    break;
+                }
+                416 => {
+                    let length = content_range.and_then(|range| range.complete_length);
+
+                    // The `.part` file already holds everything.
+                    if length == Some(offset) {
+                        complete_length = length;
+                        break;
+                    }
+
+                    if restarted {
+                        return Err(invalid(format!(
+                            "Server refused {range} for {}",
+                            url.as_str()
+                        )));
+                    }
+
+                    log::info!("{} is shorter than expected, starting over", url.as_str());
+                    self.restart()?;
+                    offset = 0;
+                    validator = None;
+                    restarted = true;
+                }
+                status => {
+                    return Err(io::Error::other(format!(
+                        "Couldn't download {}: {status} {}",
+                        url.as_str(),
+                        response.status_message
+                    )))
+                }
+            }
+        }
+
+        let length = fs::metadata(self.part_path())?.len();
+
+        if let Some(expected) = complete_length {
+            if length != expected {
+                return Err(invalid(format!(
+                    "Downloaded {length} bytes of {}, expected {expected}",
+                    url.as_str()
+                )));
+            }
+        }
+
+        fs::rename(self.part_path(), &self.destination)?;
+        let _ = fs::remove_file(self.validator_path());
+
+        Ok(length)
+    }
+}
@@ -0,0 +1,270 @@
+use std::io::{self, Error, ErrorKind};
+use std::str::FromStr;
+
+#[cfg(feature = "gzip")]
+use flate2::read::MultiGzDecoder;
+#[cfg(feature = "deflate")]
+use flate2::read::{DeflateDecoder, ZlibDecoder};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContentCoding {
+    Brotli,
+    Deflate,
+    Gzip,
+    Identity,
+    Zstd,
+}
+
+const BROTLI_CODING: &str = "br";
+const DEFLATE_CODING: &str = "deflate";
+const GZIP_CODING: &str = "gzip";
+const IDENTITY_CODING: &str = "identity";
+const ZSTD_CODING: &str = "zstd";
+
+// Preference order used when advertising codings to servers.
+const ADVERTISED_CODINGS: [ContentCoding; 4] = [
+    ContentCoding::Zstd,
+    ContentCoding::Brotli,
+    ContentCoding::Gzip,
+    ContentCoding::Deflate,
+];
+
+impl FromStr for ContentCoding {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<ContentCoding, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            BROTLI_CODING => Ok(ContentCoding::Brotli),
+            DEFLATE_CODING => Ok(ContentCoding::Deflate),
+            // x-gzip is the legacy alias from RFC 2616
+            GZIP_CODING | "x-gzip" => Ok(ContentCoding::Gzip),
+            IDENTITY_CODING => Ok(ContentCoding::Identity),
+            ZSTD_CODING => Ok(ContentCoding::Zstd),
+            _other => Err("Unknown content coding."),
+        }
+    }
+}
+
+impl ContentCoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentCoding::Brotli => BROTLI_CODING,
+            ContentCoding::Deflate => DEFLATE_CODING,
+            ContentCoding::Gzip => GZIP_CODING,
+            ContentCoding::Identity => IDENTITY_CODING,
+            ContentCoding::Zstd => ZSTD_CODING,
+        }
+    }
+
+    /// Whether a decoder for this coding was compiled in.
+    pub fn is_supported(&self) -> bool {
+        match self {
+            ContentCoding::Brotli => cfg!(feature = "brotli"),
+            ContentCoding::Deflate => cfg!(feature = "deflate"),
+            ContentCoding::Gzip => cfg!(feature = "gzip"),
+            ContentCoding::Identity => true,
+            ContentCoding::Zstd => cfg!(feature = "zstd"),
+        }
+    }
+
+    fn decode(&self, data: Vec<u8>) -> io::Result<Vec<u8>> {
+        match self {
+            ContentCoding::Identity => Ok(data),
+            #[cfg(feature = "brotli")]
+            ContentCoding::Brotli => read_all(brotli::Decompressor::new(data.as_slice(), 4096)),
+            #[cfg(feature = "deflate")]
+            ContentCoding::Deflate => {
+                // "deflate" is meant to be zlib-wrapped, but some servers send raw deflate.
+                if has_zlib_header(&data) {
+                    read_all(ZlibDecoder::new(data.as_slice()))
+                } else {
+                    read_all(DeflateDecoder::new(data.as_slice()))
+                }
+            }
+            #[cfg(feature = "gzip")]
+            ContentCoding::Gzip => read_all(MultiGzDecoder::new(data.as_slice())),
+            #[cfg(feature = "zstd")]
+            ContentCoding::Zstd => zstd::stream::decode_all(data.as_slice()),
+            #[allow(unreachable_patterns)]
+            _ => Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("Content coding {} not compiled in", self.as_str()),
+            )),
+        }
+    }
+}
+
+#[cfg(any(feature = "brotli", feature = "deflate", feature = "gzip"))]
+fn read_all(mut reader: impl io::Read) -> io::Result<Vec<u8>> {
+    let mut output = Vec::new();
+    reader.read_to_end(&mut output)?;
+
+    Ok(output)
+}
+
+#[cfg(feature = "deflate")]
+fn has_zlib_header(data: &[u8]) -> bool {
+    if data.len() < 2 {
+        return false;
+    }
+
+    let cmf = data[0] as u16;
+    let flg = data[1] as u16;
+
+    cmf & 0x0f == 8 && (cmf << 8 | flg).is_multiple_of(31)
+}
+
+/// Codings we can decode, in the order we prefer to receive them.
+pub fn supported_codings() -> Vec<ContentCoding> {
+    ADVERTISED_CODINGS
+        .into_iter()
+        .filter(|coding| coding.is_supported())
+        .collect()
+}
+
+/// Value for the Accept-Encoding request header.
+pub fn accept_encoding() -> String {
+    let codings: Vec<&str> = supported_codings()
+        .iter()
+        .map(|coding| coding.as_str())
+        .collect();
+
+    if codings.is_empty() {
+        String::from(IDENTITY_CODING)
+    } else {
+        codings.join(", ")
+    }
+}
+
+/// Parses a Content-Encoding header value into the list of codings, in the order they were applied.
+pub fn parse_content_encoding(value: &str) -> io::Result<Vec<ContentCoding>> {
+    value
+        .split(',')
+        .filter(|coding| !coding.trim().is_empty())
+        .map(|coding| {
+            ContentCoding::from_str(coding).map_err(|_| {
+                Error::new(
+                    ErrorKind::Unsupported,
+                    format!("Unknown content coding {}", coding.trim()),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Undoes every coding listed in a Content-Encoding header value.
+pub fn decode(data: Vec<u8>, content_encoding: &str) -> io::Result<Vec<u8>> {
+    let codings = parse_content_encoding(content_encoding)?;
+
+    // Codings are listed in the order they were applied, so undo them back to front.
+    codings
+        .iter()
+        .rev()
+        .try_fold(data, |data, coding| coding.decode(data))
+}
+
+#[cfg(test)]
+mod content_coding_tests {
+    use super::ContentCoding;
+    #[cfg(any(feature = "brotli", feature = "deflate", feature = "gzip"))]
+    use std::io::Write;
+    use std::str::FromStr;
+
+    const PAYLOAD: &[u8] = b"<html><body>Hello, compressed world!</body></html>";
+
+    #[cfg(feature = "gzip")]
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn parses_codings_case_insensitively() {
+        assert_eq!(ContentCoding::from_str("GZip"), Ok(ContentCoding::Gzip));
+        assert_eq!(ContentCoding::from_str(" br "), Ok(ContentCoding::Brotli));
+        assert_eq!(ContentCoding::from_str("x-gzip"), Ok(ContentCoding::Gzip));
+        assert!(ContentCoding::from_str("compress").is_err());
+    }
+
+    #[test]
+    fn identity_is_a_no_op() {
+        let decoded = super::decode(PAYLOAD.to_vec(), "identity").unwrap();
+
+        assert_eq!(decoded, PAYLOAD);
+    }
+
+    #[test]
+    fn unknown_coding_is_an_error() {
+        assert!(super::decode(PAYLOAD.to_vec(), "compress").is_err());
+    }
+
+    #[test]
+    fn accept_encoding_lists_only_supported_codings() {
+        let accept_encoding = super::accept_encoding();
+
+        for coding in super::supported_codings() {
+            assert!(coding.is_supported());
+            assert!(accept_encoding.contains(coding.as_str()));
+        }
+        assert!(!accept_encoding.is_empty());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn decodes_gzip() {
+        let decoded = super::decode(gzip(PAYLOAD), "gzip").unwrap();
+
+        assert_eq!(decoded, PAYLOAD);
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn decodes_zlib_and_raw_deflate() {
+        let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        zlib.write_all(PAYLOAD).unwrap();
+        let mut raw =
+            flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        raw.write_all(PAYLOAD).unwrap();
+
+        assert_eq!(
+            super::decode(zlib.finish().unwrap(), "deflate").unwrap(),
+            PAYLOAD
+        );
+        assert_eq!(
+            super::decode(raw.finish().unwrap(), "deflate").unwrap(),
+            PAYLOAD
+        );
+    }
+
+    #[cfg(feature = "brotli")]
+    #[test]
+    fn decodes_brotli() {
+        let mut encoded = Vec::new();
+        {
+            let mut encoder = brotli::CompressorWriter::new(&mut encoded, 4096, 5, 22);
+            encoder.write_all(PAYLOAD).unwrap();
+        }
+
+        assert_eq!(super::decode(encoded, "br").unwrap(), PAYLOAD);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn decodes_zstd() {
+        let encoded = zstd::stream::encode_all(PAYLOAD, 3).unwrap();
+
+        assert_eq!(super::decode(encoded, "zstd").unwrap(), PAYLOAD);
+    }
+
+    #[cfg(all(feature = "gzip", feature = "zstd"))]
+    #[test]
+    fn decodes_stacked_codings_in_reverse_order() {
+        let encoded = zstd::stream::encode_all(gzip(PAYLOAD).as_slice(), 3).unwrap();
+
+        assert_eq!(
+            super::decode(encoded, "gzip, identity, zstd").unwrap(),
+            PAYLOAD
+        );
+    }
+}
@@ -0,0 +1,433 @@
+//! HTTP Archive (HAR 1.2) export of the requests made by `Request`.
+
+use crate::date;
+use crate::header::{Header, HeaderMap};
+use crate::request::{HTTPRequest, HTTPResponse};
+
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+const HAR_VERSION: &str = "1.2";
+const HEADER_END: &[u8] = b"\r\n\r\n";
+
+/// Time spent in each phase of a request. Phases that didn't happen, such as DNS for a
+/// reused address or TLS for plain HTTP, are None.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Timings {
+    pub dns: Option<Duration>,
+    // Includes the TLS handshake, as in HAR.
+    pub connect: Option<Duration>,
+    pub ssl: Option<Duration>,
+    pub send: Duration,
+    pub wait: Duration,
+    pub receive: Duration,
+}
+
+impl Timings {
+    pub fn total(&self) -> Duration {
+        self.dns.unwrap_or_default()
+            + self.connect.unwrap_or_default()
+            + self.send
+            + self.wait
+            + self.receive
+    }
+}
+
+// The subset of JSON HAR needs, serialized with two-space indentation.
+enum Json {
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+
+fn string(value: &str) -> Json {
+    Json::String(String::from(value))
+}
+
+fn milliseconds(duration: Option<Duration>) -> Json {
+    Json::Number(duration.map_or(-1.0, |duration| duration.as_secs_f64() * 1000.0))
+}
+
+fn escape(value: &str, output: &mut String) {
+    output.push('"');
+
+    for character in value.chars() {
+        match character {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            character if character < ' ' => {
+                let _ = write!(output, "\\u{:04x}", character as u32);
+            }
+            character => output.push(character),
+        }
+    }
+
+    output.push('"');
+}
+
+impl Json {
+    fn write(&self, output: &mut String, indent: usize) {
+        let padding = "  ".repeat(indent + 1);
+        let closing = "  ".repeat(indent);
+
+        match self {
+            Json::Number(number) if number.fract() == 0.0 && number.abs() < 1e15 => {
+                let _ = write!(output, "{}", *number as i64);
+            }
+            Json::Number(number) => {
+                let _ = write!(output, "{number:.3}");
+            }
+            Json::String(value) => escape(value, output),
+            Json::Array(items) if items.is_empty() => output.push_str("[]"),
+            Json::Array(items) => {
+                output.push_str("[\n");
+                for (index, item) in items.iter().enumerate() {
+                    output.push_str(&padding);
+                    item.write(output, indent + 1);
+                    output.push_str(if index + 1 < items.len() { ",\n" } else { "\n" });
+                }
+                output.push_str(&closing);
+                output.push(']');
+            }
+            Json::Object(fields) if fields.is_empty() => output.push_str("{}"),
+            Json::Object(fields) => {
+                output.push_str("{\n");
+                for (index, (name, value)) in fields.iter().enumerate() {
+                    output.push_str(&padding);
+                    escape(name, output);
+                    output.push_str(": ");
+                    value.write(output, indent + 1);
+                    output.push_str(if index + 1 < fields.len() {
+                        ",\n"
+                    } else {
+                        "\n"
+                    });
+                }
+                output.push_str(&closing);
+                output.push('}');
+            }
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut output = String::new();
+        self.write(&mut output, 0);
+
+        f.write_str(&output)
+    }
+}
+
+fn headers_json(headers: &HeaderMap) -> Json {
+    Json::Array(
+        headers
+            .iter()
+            .map(|(name, value)| {
+                Json::Object(vec![("name", string(name)), ("value", string(value))])
+            })
+            .collect(),
+    )
+}
+
+fn query_json(url: &str) -> Json {
+    let query = url.split_once('?').map_or("", |(_, query)| query);
+    let query = query.split('#').next().unwrap_or("");
+
+    Json::Array(
+        query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                Json::Object(vec![("name", string(name)), ("value", string(value))])
+            })
+            .collect(),
+    )
+}
+
+fn http_version(version: &str) -> String {
+    format!("HTTP/{version}")
+}
+
+#[derive(Clone, Debug)]
+pub struct HarEntry {
+    pub started: SystemTime,
+    pub request: HTTPRequest,
+    pub response: HTTPResponse,
+    // Bytes of the response head and (possibly compressed or chunked) body as received.
+    pub headers_size: usize,
+    pub body_size: usize,
+    pub from_cache: bool,
+}
+
+impl HarEntry {
+    /// An entry for `response` to `request`, given the raw bytes it was parsed from.
+    pub fn new(
+        started: SystemTime,
+        request: &HTTPRequest,
+        raw: &[u8],
+        response: &HTTPResponse,
+        from_cache: bool,
+    ) -> HarEntry {
+        let headers_size = raw
+            .windows(HEADER_END.len())
+            .position(|window| window == HEADER_END)
+            .map_or(raw.len(), |index| index + HEADER_END.len());
+
+        HarEntry {
+            started,
+            request: request.clone(),
+            response: response.clone(),
+            headers_size,
+            body_size: raw.len() - headers_size,
+            from_cache,
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        let url = self.request.url.as_str();
+        // Requests go out in whatever version the response came back in.
+        let version = http_version(&self.response.http_version);
+        let timings = self.response.timings.clone().unwrap_or_default();
+
+        let mut request = vec![
+            ("method", string(self.request.method.as_str())),
+            ("url", string(&url)),
+            ("httpVersion", string(&version)),
+            ("cookies", Json::Array(Vec::new())),
+            ("headers", headers_json(&self.request.headers)),
+            ("queryString", query_json(&url)),
+        ];
+
+        if !self.request.data.is_empty() {
+            let mime_type = self
+                .request
+                .headers
+                .get(Header::ContentType.as_str())
+                .map_or("", |value| value.as_str());
+
+            request.push((
+                "postData",
+                Json::Object(vec![
+                    ("mimeType", string(mime_type)),
+                    ("text", string(&self.request.data)),
+                ]),
+            ));
+        }
+
+        request.push(("headersSize", Json::Number(-1.0)));
+        request.push(("bodySize", Json::Number(self.request.data.len() as f64)));
+
+        let headers = &self.response.headers;
+        let content_type = headers
+            .get(Header::ContentType.as_str())
+            .map_or("", |value| value.as_str());
+        let content_size = self.response.body.len();
+
+        let response = Json::Object(vec![
+            ("status", Json::Number(self.response.status_code as f64)),
+            ("statusText", string(&self.response.status_message)),
+            ("httpVersion", string(&version)),
+            ("cookies", Json::Array(Vec::new())),
+            ("headers", headers_json(headers)),
+            (
+                "content",
+                Json::Object(vec![
+                    ("size", Json::Number(content_size as f64)),
+                    (
+                        "compression",
+                        Json::Number(content_size as f64 - self.body_size as f64),
+                    ),
+                    ("mimeType", string(content_type)),
+                    ("text", string(&self.response.data)),
+                ]),
+            ),
+            (
+                "redirectURL",
+                string(
+                    headers
+                        .get(Header::Location.as_str())
+                        .map_or("", |value| value.as_str()),
+                ),
+            ),
+            ("headersSize", Json::Number(self.headers_size as f64)),
+            ("bodySize", Json::Number(self.body_size as f64)),
+        ]);
+
+        let mut entry = vec![
+            (
+                "startedDateTime",
+                string(&date::format_iso8601(self.started)),
+            ),
+            ("time", milliseconds(Some(timings.total()))),
+            ("request", Json::Object(request)),
+            ("response", response),
+            ("cache", Json::Object(Vec::new())),
+            (
+                "timings",
+                Json::Object(vec![
+                    ("blocked", Json::Number(-1.0)),
+                    ("dns", milliseconds(timings.dns)),
+                    ("connect", milliseconds(timings.connect)),
+                    ("send", milliseconds(Some(timings.send))),
+                    ("wait", milliseconds(Some(timings.wait))),
+                    ("receive", milliseconds(Some(timings.receive))),
+                    ("ssl", milliseconds(timings.ssl)),
+                ]),
+            ),
+        ];
+
+        if self.from_cache {
+            entry.push(("comment", string("Served from the local cache")));
+        }
+
+        Json::Object(entry)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct HarLog {
+    entries: Vec<HarEntry>,
+}
+
+impl HarLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, entry: HarEntry) {
+        self.entries.push(entry);
+    }
+
+    pub fn entries(&self) -> &[HarEntry] {
+        &self.entries
+    }
+
+    pub fn to_json(&self) -> String {
+        let log = Json::Object(vec![(
+            "log",
+            Json::Object(vec![
+                ("version", string(HAR_VERSION)),
+                (
+                    "creator",
+                    Json::Object(vec![
+                        ("name", string(env!("CARGO_PKG_NAME"))),
+                        ("version", string(env!("CARGO_PKG_VERSION"))),
+                    ]),
+                ),
+                ("pages", Json::Array(Vec::new())),
+                (
+                    "entries",
+                    Json::Array(self.entries.iter().map(HarEntry::to_json).collect()),
+                ),
+            ]),
+        )]);
+
+        format!("{log}\n")
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+}
+
+#[cfg(test)]
+mod har_tests {
+    use super::{HarEntry, HarLog, Timings};
+    use crate::header::{Header, HeaderMap};
+    use crate::request::{HTTPMethod, HTTPRequest, HTTPResponse};
+    use crate::uri::URI;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn exchange() -> (HTTPRequest, HTTPResponse) {
+        let mut request_headers = HeaderMap::new();
+        request_headers
+            .insert(Header::Host.as_str(), "example.org")
+            .unwrap();
+
+        let request = HTTPRequest {
+            url: URI::parse("http://example.org/search?q=a%20b&page=2"),
+            http_version: String::from("1.1"),
+            method: HTTPMethod::GET,
+            headers: request_headers,
+            data: String::new(),
+        };
+
+        let mut response_headers = HeaderMap::new();
+        response_headers
+            .insert(Header::ContentType.as_str(), "text/html")
+            .unwrap();
+
+        let response = HTTPResponse {
+            http_version: String::from("1.1"),
+            status_code: 200,
+            status_message: String::from("OK"),
+            headers: response_headers,
+            data: String::from("<p>\"quoted\"\n</p>"),
+            body: b"<p>\"quoted\"\n</p>".to_vec(),
+            tls: None,
+            redirect_chain: Vec::new(),
+            timings: Some(Timings {
+                dns: Some(Duration::from_millis(2)),
+                connect: Some(Duration::from_millis(5)),
+                ssl: None,
+                send: Duration::from_millis(1),
+                wait: Duration::from_millis(20),
+                receive: Duration::from_micros(1500),
+            }),
+        };
+
+        (request, response)
+    }
+
+    #[test]
+    fn serializes_entries() {
+        let (request, response) = exchange();
+        let raw = b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<p>\"quoted\"\n</p>";
+
+        let mut log = HarLog::new();
+        log.add(HarEntry::new(
+            UNIX_EPOCH + Duration::from_secs(1),
+            &request,
+            raw,
+            &response,
+            false,
+        ));
+
+        let json = log.to_json();
+
+        assert!(json.starts_with("{\n  \"log\": {\n    \"version\": \"1.2\""));
+        assert!(json.contains("\"startedDateTime\": \"1970-01-01T00:00:01.000Z\""));
+        assert!(json.contains("\"time\": 29.500"));
+        assert!(json.contains("\"url\": \"http://example.org/search?q=a%20b&page=2\""));
+        assert!(json.contains("\"name\": \"page\",\n"));
+        assert!(json.contains("\"text\": \"<p>\\\"quoted\\\"\\n</p>\""));
+        assert!(json.contains("\"headersSize\": 44"));
+        assert!(json.contains("\"bodySize\": 16"));
+        assert!(json.contains("\"ssl\": -1"));
+        assert!(!json.contains("comment"));
+    }
+
+    #[test]
+    fn marks_cache_hits() {
+        let (request, mut response) = exchange();
+        response.timings = None;
+
+        let mut log = HarLog::new();
+        log.add(HarEntry::new(UNIX_EPOCH, &request, b"", &response, true));
+
+        let json = log.to_json();
+
+        assert!(json.contains("\"comment\": \"Served from the local cache\""));
+        assert!(json.contains("\"dns\": -1"));
+        assert!(json.contains("\"time\": 0"));
+    }
+}
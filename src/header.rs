@@ -0,0 +1,505 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Header {
+    Accept,
+    AcceptCharset,
+    AcceptEncoding,
+    AcceptLanguage,
+    AcceptRanges,
+    Age,
+    Allow,
+    Authorization,
+    CacheControl,
+    Connection,
+    ContentDisposition,
+    ContentEncoding,
+    ContentLanguage,
+    ContentLength,
+    ContentLocation,
+    ContentRange,
+    ContentType,
+    Cookie,
+    Date,
+    ETag,
+    Expect,
+    Expires,
+    Forwarded,
+    From,
+    Host,
+    IfMatch,
+    IfModifiedSince,
+    IfNoneMatch,
+    IfRange,
+    IfUnmodifiedSince,
+    KeepAlive,
+    LastModified,
+    Link,
+    Location,
+    MaxForwards,
+    Origin,
+    Pragma,
+    ProxyAuthenticate,
+    ProxyAuthorization,
+    Range,
+    Referer,
+    RetryAfter,
+    SecWebSocketAccept,
+    SecWebSocketKey,
+    SecWebSocketProtocol,
+    SecWebSocketVersion,
+    Server,
+    SetCookie,
+    StrictTransportSecurity,
+    TE,
+    Trailer,
+    TransferEncoding,
+    Upgrade,
+    UserAgent,
+    Vary,
+    Via,
+    Warning,
+    WWWAuthenticate,
+    XContentTypeOptions,
+}
+
+const ALL_HEADERS: [Header; 59] = [
+    Header::Accept,
+    Header::AcceptCharset,
+    Header::AcceptEncoding,
+    Header::AcceptLanguage,
+    Header::AcceptRanges,
+    Header::Age,
+    Header::Allow,
+    Header::Authorization,
+    Header::CacheControl,
+    Header::Connection,
+    Header::ContentDisposition,
+    Header::ContentEncoding,
+    Header::ContentLanguage,
+    Header::ContentLength,
+    Header::ContentLocation,
+    Header::ContentRange,
+    Header::ContentType,
+    Header::Cookie,
+    Header::Date,
+    Header::ETag,
+    Header::Expect,
+    Header::Expires,
+    Header::Forwarded,
+    Header::From,
+    Header::Host,
+    Header::IfMatch,
+    Header::IfModifiedSince,
+    Header::IfNoneMatch,
+    Header::IfRange,
+    Header::IfUnmodifiedSince,
+    Header::KeepAlive,
+    Header::LastModified,
+    Header::Link,
+    Header::Location,
+    Header::MaxForwards,
+    Header::Origin,
+    Header::Pragma,
+    Header::ProxyAuthenticate,
+    Header::ProxyAuthorization,
+    Header::Range,
+    Header::Referer,
+    Header::RetryAfter,
+    Header::SecWebSocketAccept,
+    Header::SecWebSocketKey,
+    Header::SecWebSocketProtocol,
+    Header::SecWebSocketVersion,
+    Header::Server,
+    Header::SetCookie,
+    Header::StrictTransportSecurity,
+    Header::TE,
+    Header::Trailer,
+    Header::TransferEncoding,
+    Header::Upgrade,
+    Header::UserAgent,
+    Header::Vary,
+    Header::Via,
+    Header::Warning,
+    Header::WWWAuthenticate,
+    Header::XContentTypeOptions,
+];
+
+impl Header {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Header::Accept => "Accept",
+            Header::AcceptCharset => "Accept-Charset",
+            Header::AcceptEncoding => "Accept-Encoding",
+            Header::AcceptLanguage => "Accept-Language",
+            Header::AcceptRanges => "Accept-Ranges",
+            Header::Age => "Age",
+            Header::Allow => "Allow",
+            Header::Authorization => "Authorization",
+            Header::CacheControl => "Cache-Control",
+            Header::Connection => "Connection",
+            Header::ContentDisposition => "Content-Disposition",
+            Header::ContentEncoding => "Content-Encoding",
+            Header::ContentLanguage => "Content-Language",
+            Header::ContentLength => "Content-Length",
+            Header::ContentLocation => "Content-Location",
+            Header::ContentRange => "Content-Range",
+            Header::ContentType => "Content-Type",
+            Header::Cookie => "Cookie",
+            Header::Date => "Date",
+            Header::ETag => "ETag",
+            Header::Expect => "Expect",
+            Header::Expires => "Expires",
+            Header::Forwarded => "Forwarded",
+            Header::From => "From",
+            Header::Host => "Host",
+            Header::IfMatch => "If-Match",
+            Header::IfModifiedSince => "If-Modified-Since",
+            Header::IfNoneMatch => "If-None-Match",
+            Header::IfRange => "If-Range",
+            Header::IfUnmodifiedSince => "If-Unmodified-Since",
+            Header::KeepAlive => "Keep-Alive",
+            Header::LastModified => "Last-Modified",
+            Header::Link => "Link",
+            Header::Location => "Location",
+            Header::MaxForwards => "Max-Forwards",
+            Header::Origin => "Origin",
+            Header::Pragma => "Pragma",
+            Header::ProxyAuthenticate => "Proxy-Authenticate",
+            Header::ProxyAuthorization => "Proxy-Authorization",
+            Header::Range => "Range",
+            Header::Referer => "Referer",
+            Header::RetryAfter => "Retry-After",
+            Header::SecWebSocketAccept => "Sec-WebSocket-Accept",
+            Header::SecWebSocketKey => "Sec-WebSocket-Key",
+            Header::SecWebSocketProtocol => "Sec-WebSocket-Protocol",
+            Header::SecWebSocketVersion => "Sec-WebSocket-Version",
+            Header::Server => "Server",
+            Header::SetCookie => "Set-Cookie",
+            Header::StrictTransportSecurity => "Strict-Transport-Security",
+            Header::TE => "TE",
+            Header::Trailer => "Trailer",
+            Header::TransferEncoding => "Transfer-Encoding",
+            Header::Upgrade => "Upgrade",
+            Header::UserAgent => "User-Agent",
+            Header::Vary => "Vary",
+            Header::Via => "Via",
+            Header::Warning => "Warning",
+            Header::WWWAuthenticate => "WWW-Authenticate",
+            Header::XContentTypeOptions => "X-Content-Type-Options",
+        }
+    }
+}
+
+impl FromStr for Header {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Header, Self::Err> {
+        ALL_HEADERS
+            .into_iter()
+            .find(|header| header.as_str().eq_ignore_ascii_case(value))
+            .ok_or("Unknown header.")
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum HeaderError {
+    InvalidName(String),
+    InvalidValue(String),
+    NothingToFold,
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeaderError::InvalidName(name) => write!(f, "Invalid header name {name:?}"),
+            HeaderError::InvalidValue(name) => write!(f, "Invalid value for header {name}"),
+            HeaderError::NothingToFold => write!(f, "Folded header line without a header"),
+        }
+    }
+}
+
+impl Error for HeaderError {}
+
+impl From<HeaderError> for io::Error {
+    fn from(error: HeaderError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
+// tchar from RFC 9110 section 5.6.2
+fn is_token_char(character: u8) -> bool {
+    character.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&character)
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(is_token_char)
+}
+
+pub fn is_valid_value(value: &str) -> bool {
+    // Field values may not carry control characters other than horizontal tabs.
+    value
+        .bytes()
+        .all(|character| character == b'\t' || !character.is_ascii_control())
+}
+
+/// Header fields in the order they were received, with case-insensitive lookup.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeaderMap {
+    entries: Vec<(String, String)>,
+}
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        Self { entries: vec![] }
+    }
+
+    fn validate(name: &str, value: &str) -> Result<(), HeaderError> {
+        if !is_valid_name(name) {
+            return Err(HeaderError::InvalidName(String::from(name)));
+        }
+
+        if !is_valid_value(value) {
+            return Err(HeaderError::InvalidValue(String::from(name)));
+        }
+
+        Ok(())
+    }
+
+    /// First value stored for `name`.
+    pub fn get(&self, name: &str) -> Option<&String> {
+        self.entries
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+
+    /// Every value stored for `name`, in order.
+    pub fn get_all(&self, name: &str) -> Vec<&String> {
+        self.entries
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+            .collect()
+    }
+
+    /// All values for `name` joined as a single list, as allowed for list-based fields.
+    /// Not meaningful for Set-Cookie, use `get_all` for it.
+    pub fn get_combined(&self, name: &str) -> Option<String> {
+        let values = self.get_all(name);
+
+        if values.is_empty() {
+            return None;
+        }
+
+        Some(
+            values
+                .iter()
+                .map(|value| value.as_str())
+                .collect::<Vec<&str>>()
+                .join(", "),
+        )
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Sets `name` to `value`, replacing any previous values.
+    pub fn insert(&mut self, name: &str, value: &str) -> Result<(), HeaderError> {
+        Self::validate(name, value.trim())?;
+
+        match self
+            .entries
+            .iter()
+            .position(|(key, _)| key.eq_ignore_ascii_case(name))
+        {
+            Some(index) => {
+                self.entries[index].1 = String::from(value.trim());
+
+                // Drop any later duplicates so only the replaced value remains.
+                let mut position = 0;
+                self.entries.retain(|(key, _)| {
+                    let keep = position <= index || !key.eq_ignore_ascii_case(name);
+                    position += 1;
+                    keep
+                });
+            }
+            None => self
+                .entries
+                .push((String::from(name), String::from(value.trim()))),
+        }
+
+        Ok(())
+    }
+
+    /// Adds another value for `name`, keeping the existing ones.
+    pub fn append(&mut self, name: &str, value: &str) -> Result<(), HeaderError> {
+        Self::validate(name, value.trim())?;
+
+        self.entries
+            .push((String::from(name), String::from(value.trim())));
+
+        Ok(())
+    }
+
+    /// Handles an obs-fold continuation line by joining it onto the last value with a space.
+    pub fn fold_into_last(&mut self, continuation: &str) -> Result<(), HeaderError> {
+        let continuation = continuation.trim();
+
+        let (name, value) = self.entries.last_mut().ok_or(HeaderError::NothingToFold)?;
+
+        if !is_valid_value(continuation) {
+            return Err(HeaderError::InvalidValue(name.clone()));
+        }
+
+        if !continuation.is_empty() {
+            if !value.is_empty() {
+                value.push(' ');
+            }
+            value.push_str(continuation);
+        }
+
+        Ok(())
+    }
+
+    /// Removes every value for `name`, returning them.
+    pub fn remove(&mut self, name: &str) -> Vec<String> {
+        let mut removed = vec![];
+
+        self.entries.retain(|(key, value)| {
+            if key.eq_ignore_ascii_case(name) {
+                removed.push(value.clone());
+                false
+            } else {
+                true
+            }
+        });
+
+        removed
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for &'a HeaderMap {
+    type Item = (&'a String, &'a String);
+    type IntoIter = Box<dyn Iterator<Item = (&'a String, &'a String)> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+#[cfg(test)]
+mod header_map_tests {
+    use super::{Header, HeaderError, HeaderMap};
+    use std::str::FromStr;
+
+    #[test]
+    fn lookup_is_case_insensitive() {
+        let mut headers = HeaderMap::new();
+        headers.append("content-encoding", "gzip").unwrap();
+
+        assert!(headers.contains_key(Header::ContentEncoding.as_str()));
+        assert_eq!(headers.get("CONTENT-ENCODING"), Some(&String::from("gzip")));
+    }
+
+    #[test]
+    fn keeps_repeated_headers_in_order() {
+        let mut headers = HeaderMap::new();
+        headers.append("Set-Cookie", "a=1").unwrap();
+        headers.append("Server", "test").unwrap();
+        headers.append("set-cookie", "b=2").unwrap();
+
+        assert_eq!(
+            headers.get_all(Header::SetCookie.as_str()),
+            vec!["a=1", "b=2"]
+        );
+        assert_eq!(headers.len(), 3);
+    }
+
+    #[test]
+    fn insert_replaces_every_previous_value() {
+        let mut headers = HeaderMap::new();
+        headers.append("Accept", "text/html").unwrap();
+        headers.append("Host", "example.org").unwrap();
+        headers.append("accept", "text/plain").unwrap();
+
+        headers.insert("ACCEPT", "*/*").unwrap();
+
+        assert_eq!(headers.get_all("Accept"), vec!["*/*"]);
+        assert_eq!(
+            headers.iter().next(),
+            Some((&String::from("Accept"), &String::from("*/*")))
+        );
+    }
+
+    #[test]
+    fn combines_list_values() {
+        let mut headers = HeaderMap::new();
+        headers.append("Content-Encoding", "gzip").unwrap();
+        headers.append("Content-Encoding", "br").unwrap();
+
+        assert_eq!(
+            headers.get_combined(Header::ContentEncoding.as_str()),
+            Some(String::from("gzip, br"))
+        );
+        assert_eq!(headers.get_combined("Vary"), None);
+    }
+
+    #[test]
+    fn folds_continuation_lines() {
+        let mut headers = HeaderMap::new();
+        headers.append("X-Folded", "first").unwrap();
+        headers.fold_into_last("   second").unwrap();
+
+        assert_eq!(headers.get("x-folded"), Some(&String::from("first second")));
+        assert_eq!(
+            HeaderMap::new().fold_into_last(" orphan"),
+            Err(HeaderError::NothingToFold)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_names_and_values() {
+        let mut headers = HeaderMap::new();
+
+        assert!(headers.append("Bad Name", "value").is_err());
+        assert!(headers.append("", "value").is_err());
+        assert!(headers.append("X-Injected", "a\r\nHost: evil").is_err());
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn removes_all_values() {
+        let mut headers = HeaderMap::new();
+        headers.append("Cookie", "a=1").unwrap();
+        headers.append("cookie", "b=2").unwrap();
+
+        assert_eq!(headers.remove("COOKIE"), vec!["a=1", "b=2"]);
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn parses_known_header_names() {
+        assert_eq!(
+            Header::from_str("www-authenticate"),
+            Ok(Header::WWWAuthenticate)
+        );
+        assert!(Header::from_str("X-Custom").is_err());
+    }
+}
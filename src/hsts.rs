@@ -0,0 +1,332 @@
+use crate::uri::{Scheme, URI};
+
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const INCLUDE_SUBDOMAINS: &str = "includeSubDomains";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HstsPolicy {
+    pub host: String,
+    // Unix time in seconds, None for preloaded entries which never expire.
+    pub expires: Option<u64>,
+    pub include_subdomains: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HstsStore {
+    policies: Vec<HstsPolicy>,
+    preloaded: Vec<HstsPolicy>,
+    // Where learned policies are persisted, if anywhere.
+    path: Option<PathBuf>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+fn normalize_host(host: &str) -> String {
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+fn is_ip_literal(host: &str) -> bool {
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+        .is_ok()
+}
+
+/// Parses a Strict-Transport-Security value (RFC 6797 section 6.1) into
+/// `(max_age, include_subdomains)`. Returns None for invalid headers, which must be ignored.
+pub fn parse_header(value: &str) -> Option<(u64, bool)> {
+    let mut max_age = None;
+    let mut include_subdomains = false;
+
+    for directive in value.split(';') {
+        let directive = directive.trim();
+
+        if directive.is_empty() {
+            continue;
+        }
+
+        let (name, directive_value) = match directive.split_once('=') {
+            Some((name, directive_value)) => (name.trim(), Some(directive_value.trim())),
+            None => (directive, None),
+        };
+
+        if name.eq_ignore_ascii_case("max-age") {
+            // Directives may appear only once.
+            if max_age.is_some() {
+                return None;
+            }
+
+            let seconds = directive_value?.trim_matches('"');
+            max_age = Some(seconds.parse().ok()?);
+        } else if name.eq_ignore_ascii_case(INCLUDE_SUBDOMAINS) {
+            if include_subdomains || directive_value.is_some() {
+                return None;
+            }
+
+            include_subdomains = true;
+        }
+        // Unknown directives are ignored.
+    }
+
+    Some((max_age?, include_subdomains))
+}
+
+fn parse_policy_line(line: &str) -> Option<HstsPolicy> {
+    let mut fields = line.split(';');
+
+    let host = fields.next()?;
+    let expires = fields.next()?.parse().ok()?;
+    let include_subdomains = fields.next()? == "1";
+
+    Some(HstsPolicy {
+        host: String::from(host),
+        expires: Some(expires),
+        include_subdomains,
+    })
+}
+
+impl HstsStore {
+    /// Loads persisted policies from `path`, which is created on the first update.
+    pub fn load(path: &Path) -> io::Result<HstsStore> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+
+        let current_time = now();
+
+        let policies = contents
+            .lines()
+            .filter_map(parse_policy_line)
+            .filter(|policy| policy.expires.is_some_and(|expires| expires > current_time))
+            .collect();
+
+        Ok(HstsStore {
+            policies,
+            preloaded: Vec::new(),
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    /// Adds a preload list: one host per line, optionally followed by `includeSubDomains`.
+    /// Blank lines and `#` comments are ignored.
+    pub fn load_preload_list(&mut self, path: &Path) -> io::Result<()> {
+        let contents = fs::read_to_string(path)?;
+
+        for line in contents.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let mut fields = line.split_whitespace();
+
+            let host = match fields.next() {
+                Some(host) => host,
+                None => continue,
+            };
+
+            self.preloaded.push(HstsPolicy {
+                host: normalize_host(host),
+                expires: None,
+                include_subdomains: fields
+                    .any(|field| field.eq_ignore_ascii_case(INCLUDE_SUBDOMAINS)),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let lines: Vec<String> = self
+            .policies
+            .iter()
+            .map(|policy| {
+                format!(
+                    "{};{};{}",
+                    policy.host,
+                    policy.expires.unwrap_or(0),
+                    if policy.include_subdomains { 1 } else { 0 }
+                )
+            })
+            .collect();
+
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        fs::write(path, lines.join("\n"))
+    }
+
+    /// Records the Strict-Transport-Security header of a response received over a secure,
+    /// verified connection to `host`.
+    pub fn observe(&mut self, host: &str, header: &str) -> io::Result<()> {
+        let host = normalize_host(host);
+
+        // Only the first header counts, and IP addresses never get a policy (section 8.1).
+        let (max_age, include_subdomains) = match parse_header(header) {
+            Some(directives) if !is_ip_literal(&host) => directives,
+            _ => return Ok(()),
+        };
+
+        self.policies.retain(|policy| policy.host != host);
+
+        // max-age=0 tells us to forget the host.
+        if max_age > 0 {
+            log::debug!("HSTS policy for {host}: max-age={max_age}");
+
+            self.policies.push(HstsPolicy {
+                host,
+                expires: Some(now().saturating_add(max_age)),
+                include_subdomains,
+            });
+        }
+
+        self.save()
+    }
+
+    pub fn is_known_host(&self, host: &str) -> bool {
+        let host = normalize_host(host);
+        let current_time = now();
+
+        self.policies
+            .iter()
+            .chain(&self.preloaded)
+            .filter(|policy| policy.expires.is_none_or(|expires| expires > current_time))
+            .any(|policy| {
+                policy.host == host
+                    || (policy.include_subdomains
+                        && host
+                            .strip_suffix(&policy.host)
+                            .is_some_and(|prefix| prefix.ends_with('.')))
+            })
+    }
+
+    /// Rewrites `http://` URIs of known HSTS hosts to `https://`, returning whether it did.
+    pub fn upgrade(&self, url: &mut URI) -> bool {
+        let authority = match (&url.scheme, &url.authority) {
+            (Scheme::HTTP, Some(authority)) if self.is_known_host(&authority.host) => authority,
+            _ => return false,
+        };
+
+        log::debug!("HSTS upgrade for {}", authority.host);
+        upgrade_to_https(url);
+
+        true
+    }
+}
+
+/// Switches an `http://` URI to `https://`, moving the default port 80 to 443.
+pub fn upgrade_to_https(url: &mut URI) {
+    url.scheme = Scheme::HTTPS;
+
+    if let Some(authority) = url.authority.as_mut() {
+        if authority.port == 80 {
+            authority.port = 443;
+        }
+    }
+}
+
+#[cfg(test)]
+mod hsts_tests {
+    use super::{parse_header, HstsStore};
+    use crate::testing::temporary_directory;
+    use crate::uri::{Scheme, URI};
+    use std::fs;
+
+    #[test]
+    fn parses_header_directives() {
+        assert_eq!(parse_header("max-age=31536000"), Some((31536000, false)));
+        assert_eq!(
+            parse_header("Max-Age=\"600\"; includeSubDomains; preload"),
+            Some((600, true))
+        );
+        assert_eq!(parse_header("includeSubDomains"), None);
+        assert_eq!(parse_header("max-age=1; max-age=2"), None);
+        assert_eq!(parse_header("max-age=soon"), None);
+    }
+
+    #[test]
+    fn matches_hosts_and_subdomains() {
+        let mut store = HstsStore::default();
+        store.observe("Example.com.", "max-age=600").unwrap();
+        store
+            .observe("secure.test", "max-age=600; includeSubDomains")
+            .unwrap();
+        store.observe("127.0.0.1", "max-age=600").unwrap();
+
+        assert!(store.is_known_host("example.com"));
+        assert!(!store.is_known_host("www.example.com"));
+        assert!(store.is_known_host("a.b.secure.test"));
+        assert!(!store.is_known_host("insecure.test"));
+        assert!(!store.is_known_host("127.0.0.1"));
+
+        store.observe("example.com", "max-age=0").unwrap();
+        assert!(!store.is_known_host("example.com"));
+    }
+
+    #[test]
+    fn upgrades_known_hosts() {
+        let mut store = HstsStore::default();
+        store.observe("example.com", "max-age=600").unwrap();
+
+        let mut url = URI::parse("http://example.com/index.html");
+        assert!(store.upgrade(&mut url));
+        assert_eq!(url.scheme, Scheme::HTTPS);
+        assert_eq!(url.as_str(), "https://example.com/index.html");
+
+        let mut url = URI::parse("http://example.com:8080/");
+        assert!(store.upgrade(&mut url));
+        assert_eq!(url.as_str(), "https://example.com:8080/");
+
+        let mut url = URI::parse("http://example.org/");
+        assert!(!store.upgrade(&mut url));
+        assert_eq!(url.scheme, Scheme::HTTP);
+    }
+
+    #[test]
+    fn persists_learned_policies() {
+        let path = temporary_directory("hsts-policies").join("hsts");
+
+        let mut store = HstsStore::load(&path).unwrap();
+        store
+            .observe("example.com", "max-age=600; includeSubDomains")
+            .unwrap();
+
+        let reloaded = HstsStore::load(&path).unwrap();
+        assert!(reloaded.is_known_host("www.example.com"));
+
+        // Expired entries are dropped on load.
+        fs::write(&path, "old.example;1;0").unwrap();
+        assert!(!HstsStore::load(&path).unwrap().is_known_host("old.example"));
+    }
+
+    #[test]
+    fn reads_preload_lists() {
+        let path = temporary_directory("hsts-preload").join("preload");
+        fs::write(
+            &path,
+            "# preloaded hosts\nbank.example includeSubDomains\n\nintranet.example\n",
+        )
+        .unwrap();
+
+        let mut store = HstsStore::default();
+        store.load_preload_list(&path).unwrap();
+
+        assert!(store.is_known_host("login.bank.example"));
+        assert!(store.is_known_host("intranet.example"));
+        assert!(!store.is_known_host("wiki.intranet.example"));
+    }
+}
@@ -0,0 +1,226 @@
+//! The document tree built from a page's markup.
+//!
+//! Nodes live in one vector owned by the `Document` and refer to each other by index, which
+//! lets the tree builder move them around while it repairs misnested markup.
+
+use super::tokenizer::{Attribute, Doctype};
+
+use std::fmt;
+
+pub type NodeId = usize;
+
+/// The document node, which every tree starts from.
+pub const ROOT: NodeId = 0;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<Attribute>,
+}
+
+impl Element {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.name == name)
+            .map(|attribute| attribute.value.as_str())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Text {
+    pub data: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Comment {
+    pub data: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NodeData {
+    Document,
+    Doctype(Doctype),
+    Element(Element),
+    Text(Text),
+    Comment(Comment),
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub parent: Option<NodeId>,
+    pub children: Vec<NodeId>,
+    pub data: NodeData,
+}
+
+#[derive(Clone, Debug)]
+pub struct Document {
+    nodes: Vec<Node>,
+    // Set for pages without a standards mode doctype, which get laid out the old way.
+    pub quirks_mode: bool,
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Self {
+            nodes: vec![Node {
+                parent: None,
+                children: Vec::new(),
+                data: NodeData::Document,
+            }],
+            quirks_mode: false,
+        }
+    }
+}
+
+impl Document {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id]
+    }
+
+    pub fn element(&self, id: NodeId) -> Option<&Element> {
+        match &self.nodes[id].data {
+            NodeData::Element(element) => Some(element),
+            _ => None,
+        }
+    }
+
+    pub fn element_mut(&mut self, id: NodeId) -> Option<&mut Element> {
+        match &mut self.nodes[id].data {
+            NodeData::Element(element) => Some(element),
+            _ => None,
+        }
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id].parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.nodes[id].children
+    }
+
+    /// The nodes under `id` in document order, not including `id` itself.
+    pub fn descendants(&self, id: NodeId) -> Vec<NodeId> {
+        let mut descendants = Vec::new();
+        let mut pending: Vec<NodeId> = self.children(id).iter().rev().copied().collect();
+
+        while let Some(node) = pending.pop() {
+            descendants.push(node);
+            pending.extend(self.children(node).iter().rev());
+        }
+
+        descendants
+    }
+
+    /// Adds a node that isn't in the tree yet.
+    pub fn create(&mut self, data: NodeData) -> NodeId {
+        self.nodes.push(Node {
+            parent: None,
+            children: Vec::new(),
+            data,
+        });
+
+        self.nodes.len() - 1
+    }
+
+    /// Takes a node out of the tree, along with everything under it.
+    pub fn detach(&mut self, id: NodeId) {
+        if let Some(parent) = self.nodes[id].parent.take() {
+            self.nodes[parent].children.retain(|&child| child != id);
+        }
+    }
+
+    /// Moves `child` under `parent`, before `before` or after the last child.
+    pub fn insert(&mut self, parent: NodeId, child: NodeId, before: Option<NodeId>) {
+        self.detach(child);
+        self.nodes[child].parent = Some(parent);
+
+        let children = &mut self.nodes[parent].children;
+        let index = before
+            .and_then(|before| children.iter().position(|&sibling| sibling == before))
+            .unwrap_or(children.len());
+
+        children.insert(index, child);
+    }
+
+    pub fn append(&mut self, parent: NodeId, child: NodeId) {
+        self.insert(parent, child, None);
+    }
+
+    /// Inserts text where `insert` would put a node, joining it to a text node right
+    /// before it.
+    pub fn insert_text(&mut self, parent: NodeId, before: Option<NodeId>, text: &str) {
+        let children = &self.nodes[parent].children;
+        let previous = match before {
+            Some(before) => children
+                .iter()
+                .position(|&sibling| sibling == before)
+                .and_then(|index| index.checked_sub(1))
+                .map(|index| children[index]),
+            None => children.last().copied(),
+        };
+
+        if let Some(previous) = previous {
+            if let NodeData::Text(existing) = &mut self.nodes[previous].data {
+                existing.data.push_str(text);
+                return;
+            }
+        }
+
+        let node = self.create(NodeData::Text(Text {
+            data: String::from(text),
+        }));
+        self.insert(parent, node, before);
+    }
+
+    fn fmt_node(&self, f: &mut fmt::Formatter<'_>, id: NodeId, depth: usize) -> fmt::Result {
+        write!(f, "{:indent$}", "", indent = depth * 2)?;
+
+        match &self.nodes[id].data {
+            NodeData::Document => (),
+            NodeData::Doctype(doctype) => {
+                write!(f, "<!DOCTYPE {}", doctype.name.as_deref().unwrap_or(""))?;
+                if doctype.public_id.is_some() || doctype.system_id.is_some() {
+                    write!(
+                        f,
+                        " \"{}\" \"{}\"",
+                        doctype.public_id.as_deref().unwrap_or(""),
+                        doctype.system_id.as_deref().unwrap_or("")
+                    )?;
+                }
+                writeln!(f, ">")?;
+            }
+            NodeData::Element(element) => {
+                write!(f, "<{}", element.name)?;
+                for attribute in &element.attributes {
+                    write!(f, " {}=\"{}\"", attribute.name, attribute.value)?;
+                }
+                writeln!(f, ">")?;
+            }
+            NodeData::Text(text) => writeln!(f, "\"{}\"", text.data)?,
+            NodeData::Comment(comment) => writeln!(f, "<!-- {} -->", comment.data)?,
+        }
+
+        for &child in self.children(id) {
+            self.fmt_node(f, child, depth + 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Document {
+    /// One node per line, indented under its parent, for inspecting how a page was parsed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for &child in self.children(ROOT) {
+            self.fmt_node(f, child, 0)?;
+        }
+
+        Ok(())
+    }
+}
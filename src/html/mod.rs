@@ -0,0 +1,11 @@
+//! HTML parsing, following the HTML Living Standard.
+
+pub mod dom;
+pub mod entities;
+pub mod tokenizer;
+pub mod tree_builder;
+pub mod view_source;
+
+pub use dom::{Document, Element, Node, NodeData, NodeId};
+pub use tokenizer::{Attribute, Doctype, Tag, Token, Tokenizer};
+pub use tree_builder::parse;
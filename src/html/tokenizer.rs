@@ -0,0 +1,1115 @@
+//! The HTML tokenizer (HTML Living Standard section 13.2.5): turns markup into doctype,
+//! tag, comment and character tokens.
+//!
+//! The tokenizer switches itself to the RCDATA, RAWTEXT, script data and PLAINTEXT states
+//! after the start tags that call for them, which the standard leaves to the tree builder.
+//! The script data escape states (`<!--` inside scripts) aren't implemented: script data
+//! simply ends at `</script`.
+
+use super::entities;
+
+use std::collections::VecDeque;
+use std::ops::Range;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Doctype {
+    pub name: Option<String>,
+    pub public_id: Option<String>,
+    pub system_id: Option<String>,
+    pub force_quirks: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attribute {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Tag {
+    // Lowercase for HTML elements.
+    pub name: String,
+    pub attributes: Vec<Attribute>,
+    pub self_closing: bool,
+}
+
+impl Tag {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.name == name)
+            .map(|attribute| attribute.value.as_str())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Token {
+    Doctype(Doctype),
+    StartTag(Tag),
+    EndTag(Tag),
+    Comment(String),
+    // A run of text; adjacent characters are emitted together.
+    Character(String),
+    EndOfFile,
+}
+
+/// The kinds of text that only end at a matching end tag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextKind {
+    // Character references are decoded, e.g. in `<title>` and `<textarea>`.
+    Rcdata,
+    Rawtext,
+    ScriptData,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Identifier {
+    Public,
+    System,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    Data,
+    Text(TextKind),
+    Plaintext,
+    TagOpen,
+    EndTagOpen,
+    TagName,
+    TextLessThanSign(TextKind),
+    TextEndTagOpen(TextKind),
+    TextEndTagName(TextKind),
+    BeforeAttributeName,
+    AttributeName,
+    AfterAttributeName,
+    BeforeAttributeValue,
+    AttributeValueQuoted(char),
+    AttributeValueUnquoted,
+    AfterAttributeValueQuoted,
+    SelfClosingStartTag,
+    BogusComment,
+    MarkupDeclarationOpen,
+    CommentStart,
+    CommentStartDash,
+    Comment,
+    CommentEndDash,
+    CommentEnd,
+    CommentEndBang,
+    Doctype,
+    BeforeDoctypeName,
+    DoctypeName,
+    AfterDoctypeName,
+    AfterDoctypeKeyword(Identifier),
+    BeforeDoctypeIdentifier(Identifier),
+    DoctypeIdentifierQuoted(Identifier, char),
+    AfterDoctypeIdentifier(Identifier),
+    BetweenDoctypeIdentifiers,
+    BogusDoctype,
+    CdataSection,
+}
+
+// Tab, line feed, form feed and space; carriage returns are gone after preprocessing.
+pub(super) fn is_whitespace(character: char) -> bool {
+    matches!(character, '\t' | '\n' | '\x0C' | ' ')
+}
+
+// The state a start tag leaves the tokenizer in, for the elements whose contents aren't
+// markup.
+fn state_after(tag_name: &str) -> Option<State> {
+    match tag_name {
+        "title" | "textarea" => Some(State::Text(TextKind::Rcdata)),
+        "style" | "xmp" | "iframe" | "noembed" | "noframes" => Some(State::Text(TextKind::Rawtext)),
+        "script" => Some(State::Text(TextKind::ScriptData)),
+        "plaintext" => Some(State::Plaintext),
+        _ => None,
+    }
+}
+
+/// Tokenizes a whole document, yielding tokens up to and including `Token::EndOfFile`.
+pub struct Tokenizer {
+    input: Vec<char>,
+    position: usize,
+    state: State,
+    // Tokens ready to be returned, with where they are in the input.
+    tokens: VecDeque<(Token, Range<usize>)>,
+    // Characters not yet emitted, so runs come out as one token.
+    text: String,
+    tag: Tag,
+    is_end_tag: bool,
+    attribute: Option<Attribute>,
+    comment: String,
+    doctype: Doctype,
+    // What was read of a possible end tag in RCDATA, RAWTEXT or script data.
+    buffer: String,
+    last_start_tag: String,
+    cdata_allowed: bool,
+    // Where the markup being read started, and where the last token emitted ended.
+    markup_start: usize,
+    last_end: usize,
+    finished: bool,
+}
+
+impl Tokenizer {
+    pub fn new(input: &str) -> Self {
+        Self {
+            input: input.chars().collect(),
+            position: 0,
+            state: State::Data,
+            tokens: VecDeque::new(),
+            text: String::new(),
+            tag: Tag::default(),
+            is_end_tag: false,
+            attribute: None,
+            comment: String::new(),
+            doctype: Doctype::default(),
+            buffer: String::new(),
+            last_start_tag: String::new(),
+            cdata_allowed: false,
+            markup_start: 0,
+            last_end: 0,
+            finished: false,
+        }
+    }
+
+    /// Whether `<![CDATA[` starts a CDATA section rather than a bogus comment, which is
+    /// only the case in foreign content such as SVG. The tree builder keeps this up to date.
+    pub fn set_cdata_allowed(&mut self, allowed: bool) {
+        self.cdata_allowed = allowed;
+    }
+
+    // Newlines are normalized as they're read (section 13.2.3.5) rather than beforehand, so
+    // token spans point into the input as it was given.
+    fn consume(&mut self) -> Option<char> {
+        let character = self.input.get(self.position).copied();
+        self.position += 1;
+
+        if character == Some('\r') {
+            if self.input.get(self.position) == Some(&'\n') {
+                self.position += 1;
+            }
+            return Some('\n');
+        }
+
+        character
+    }
+
+    fn reconsume(&mut self, state: State) {
+        self.position -= 1;
+        self.state = state;
+    }
+
+    // Consumes `expected` if the input continues with it, ignoring ASCII case.
+    fn consume_if(&mut self, expected: &str) -> bool {
+        let length = expected.chars().count();
+
+        let matches = self
+            .input
+            .get(self.position..self.position + length)
+            .is_some_and(|next| {
+                next.iter()
+                    .zip(expected.chars())
+                    .all(|(a, b)| a.eq_ignore_ascii_case(&b))
+            });
+
+        if matches {
+            self.position += length;
+        }
+
+        matches
+    }
+
+    fn emit(&mut self, token: Token) {
+        let end = self.position.min(self.input.len());
+
+        // Text covers everything since the last token, up to the markup being emitted.
+        if !self.text.is_empty() {
+            let text = std::mem::take(&mut self.text);
+            self.tokens
+                .push_back((Token::Character(text), self.last_end..self.markup_start));
+        }
+
+        self.tokens.push_back((token, self.markup_start..end));
+        self.last_end = end;
+    }
+
+    fn emit_eof(&mut self) {
+        self.markup_start = self.input.len();
+        self.position = self.input.len();
+        self.emit(Token::EndOfFile);
+        self.finished = true;
+    }
+
+    fn new_tag(&mut self, is_end_tag: bool) {
+        self.tag = Tag::default();
+        self.is_end_tag = is_end_tag;
+        self.attribute = None;
+    }
+
+    fn start_attribute(&mut self, name: &str) {
+        self.finish_attribute();
+        self.attribute = Some(Attribute {
+            name: String::from(name),
+            value: String::new(),
+        });
+    }
+
+    // Adds the attribute being read to the tag, unless the tag already has one by that name.
+    fn finish_attribute(&mut self) {
+        if let Some(attribute) = self.attribute.take() {
+            if self.tag.attribute(&attribute.name).is_none() {
+                self.tag.attributes.push(attribute);
+            }
+        }
+    }
+
+    fn attribute_name(&mut self) -> &mut String {
+        &mut self
+            .attribute
+            .get_or_insert_with(|| Attribute {
+                name: String::new(),
+                value: String::new(),
+            })
+            .name
+    }
+
+    fn attribute_value(&mut self) -> &mut String {
+        &mut self
+            .attribute
+            .get_or_insert_with(|| Attribute {
+                name: String::new(),
+                value: String::new(),
+            })
+            .value
+    }
+
+    fn emit_tag(&mut self) {
+        self.finish_attribute();
+        let tag = std::mem::take(&mut self.tag);
+        self.state = State::Data;
+
+        if self.is_end_tag {
+            self.emit(Token::EndTag(tag));
+        } else {
+            if let Some(state) = state_after(&tag.name) {
+                self.state = state;
+            }
+            self.last_start_tag = tag.name.clone();
+            self.emit(Token::StartTag(tag));
+        }
+    }
+
+    fn emit_comment(&mut self) {
+        let comment = std::mem::take(&mut self.comment);
+        self.emit(Token::Comment(comment));
+    }
+
+    fn emit_doctype(&mut self, force_quirks: bool) {
+        let mut doctype = std::mem::take(&mut self.doctype);
+        doctype.force_quirks |= force_quirks;
+        self.emit(Token::Doctype(doctype));
+    }
+
+    // Only the end tag of the last start tag ends RCDATA, RAWTEXT and script data.
+    fn is_appropriate_end_tag(&self) -> bool {
+        !self.last_start_tag.is_empty() && self.tag.name == self.last_start_tag
+    }
+
+    fn doctype_identifier(&mut self, identifier: Identifier) -> &mut Option<String> {
+        match identifier {
+            Identifier::Public => &mut self.doctype.public_id,
+            Identifier::System => &mut self.doctype.system_id,
+        }
+    }
+
+    // Reads a character reference after its `&` (section 13.2.5.72), adding what it stands
+    // for to the attribute value or text.
+    fn character_reference(&mut self, in_attribute: bool) {
+        let decoded = match self.input.get(self.position) {
+            Some(character) if character.is_ascii_alphanumeric() => {
+                self.named_reference(in_attribute)
+            }
+            Some('#') => {
+                self.position += 1;
+                self.numeric_reference()
+            }
+            _ => None,
+        };
+
+        let decoded = decoded.unwrap_or_else(|| String::from("&"));
+
+        if in_attribute {
+            self.attribute_value().push_str(&decoded);
+        } else {
+            self.text.push_str(&decoded);
+        }
+    }
+
+    fn named_reference(&mut self, in_attribute: bool) -> Option<String> {
+        let (length, name, value) = entities::longest_match(&self.input[self.position..])?;
+
+        // In attributes, `&copy=` and `&copyright` are left alone for compatibility.
+        let next = self.input.get(self.position + length);
+        let historical = in_attribute
+            && !name.ends_with(';')
+            && next.is_some_and(|next| *next == '=' || next.is_ascii_alphanumeric());
+
+        if historical {
+            return None;
+        }
+
+        self.position += length;
+        Some(String::from(value))
+    }
+
+    fn numeric_reference(&mut self) -> Option<String> {
+        let hexadecimal = matches!(self.input.get(self.position), Some('x' | 'X'));
+        let start = self.position;
+        if hexadecimal {
+            self.position += 1;
+        }
+
+        let radix = if hexadecimal { 16 } else { 10 };
+        let mut value: u32 = 0;
+        let mut digits = 0;
+
+        while let Some(digit) = self
+            .input
+            .get(self.position)
+            .and_then(|character| character.to_digit(radix))
+        {
+            // Anything past the largest code point is replaced anyway.
+            value = value
+                .saturating_mul(radix)
+                .saturating_add(digit)
+                .min(0x110000);
+            digits += 1;
+            self.position += 1;
+        }
+
+        if digits == 0 {
+            // Not a reference after all: `&#` and any `x` stay as text.
+            let prefix: String = self.input[start - 1..self.position].iter().collect();
+            return Some(format!("&{prefix}"));
+        }
+
+        if self.input.get(self.position) == Some(&';') {
+            self.position += 1;
+        }
+
+        Some(String::from(entities::numeric(value)))
+    }
+
+    fn step(&mut self) {
+        let character = self.consume();
+
+        match self.state {
+            State::Data => match character {
+                Some('&') => self.character_reference(false),
+                Some('<') => {
+                    self.markup_start = self.position - 1;
+                    self.state = State::TagOpen;
+                }
+                Some(character) => self.text.push(character),
+                None => self.emit_eof(),
+            },
+            State::Text(kind) => match character {
+                Some('&') if kind == TextKind::Rcdata => self.character_reference(false),
+                Some('<') => {
+                    self.markup_start = self.position - 1;
+                    self.state = State::TextLessThanSign(kind);
+                }
+                Some('\0') => self.text.push(char::REPLACEMENT_CHARACTER),
+                Some(character) => self.text.push(character),
+                None => self.emit_eof(),
+            },
+            State::Plaintext => match character {
+                Some('\0') => self.text.push(char::REPLACEMENT_CHARACTER),
+                Some(character) => self.text.push(character),
+                None => self.emit_eof(),
+            },
+            State::TagOpen => match character {
+                Some('!') => self.state = State::MarkupDeclarationOpen,
+                Some('/') => self.state = State::EndTagOpen,
+                Some(character) if character.is_ascii_alphabetic() => {
+                    self.new_tag(false);
+                    self.reconsume(State::TagName);
+                }
+                Some('?') => {
+                    self.comment.clear();
+                    self.reconsume(State::BogusComment);
+                }
+                Some(_) => {
+                    self.text.push('<');
+                    self.reconsume(State::Data);
+                }
+                None => {
+                    self.text.push('<');
+                    self.emit_eof();
+                }
+            },
+            State::EndTagOpen => match character {
+                Some(character) if character.is_ascii_alphabetic() => {
+                    self.new_tag(true);
+                    self.reconsume(State::TagName);
+                }
+                Some('>') => self.state = State::Data,
+                Some(_) => {
+                    self.comment.clear();
+                    self.reconsume(State::BogusComment);
+                }
+                None => {
+                    self.text.push_str("</");
+                    self.emit_eof();
+                }
+            },
+            State::TagName => match character {
+                Some(character) if is_whitespace(character) => {
+                    self.state = State::BeforeAttributeName
+                }
+                Some('/') => self.state = State::SelfClosingStartTag,
+                Some('>') => self.emit_tag(),
+                Some('\0') => self.tag.name.push(char::REPLACEMENT_CHARACTER),
+                Some(character) => self.tag.name.push(character.to_ascii_lowercase()),
+                None => self.emit_eof(),
+            },
+            State::TextLessThanSign(kind) => match character {
+                Some('/') => {
+                    self.buffer.clear();
+                    self.state = State::TextEndTagOpen(kind);
+                }
+                _ => {
+                    self.text.push('<');
+                    self.reconsume(State::Text(kind));
+                }
+            },
+            State::TextEndTagOpen(kind) => match character {
+                Some(character) if character.is_ascii_alphabetic() => {
+                    self.new_tag(true);
+                    self.reconsume(State::TextEndTagName(kind));
+                }
+                _ => {
+                    self.text.push_str("</");
+                    self.reconsume(State::Text(kind));
+                }
+            },
+            State::TextEndTagName(kind) => match character {
+                Some(character) if is_whitespace(character) && self.is_appropriate_end_tag() => {
+                    self.state = State::BeforeAttributeName
+                }
+                Some('/') if self.is_appropriate_end_tag() => {
+                    self.state = State::SelfClosingStartTag
+                }
+                Some('>') if self.is_appropriate_end_tag() => self.emit_tag(),
+                Some(character) if character.is_ascii_alphabetic() => {
+                    self.tag.name.push(character.to_ascii_lowercase());
+                    self.buffer.push(character);
+                }
+                _ => {
+                    // Not the end tag after all, just text.
+                    self.text.push_str("</");
+                    let buffer = std::mem::take(&mut self.buffer);
+                    self.text.push_str(&buffer);
+                    self.reconsume(State::Text(kind));
+                }
+            },
+            State::BeforeAttributeName => match character {
+                Some(character) if is_whitespace(character) => (),
+                Some('/' | '>') | None => self.reconsume(State::AfterAttributeName),
+                Some('=') => {
+                    self.start_attribute("=");
+                    self.state = State::AttributeName;
+                }
+                Some(_) => {
+                    self.start_attribute("");
+                    self.reconsume(State::AttributeName);
+                }
+            },
+            State::AttributeName => match character {
+                Some(character) if is_whitespace(character) => {
+                    self.reconsume(State::AfterAttributeName)
+                }
+                Some('/' | '>') | None => self.reconsume(State::AfterAttributeName),
+                Some('=') => self.state = State::BeforeAttributeValue,
+                Some('\0') => self.attribute_name().push(char::REPLACEMENT_CHARACTER),
+                Some(character) => self.attribute_name().push(character.to_ascii_lowercase()),
+            },
+            State::AfterAttributeName => match character {
+                Some(character) if is_whitespace(character) => (),
+                Some('/') => self.state = State::SelfClosingStartTag,
+                Some('=') => self.state = State::BeforeAttributeValue,
+                Some('>') => self.emit_tag(),
+                Some(_) => {
+                    self.start_attribute("");
+                    self.reconsume(State::AttributeName);
+                }
+                None => self.emit_eof(),
+            },
+            State::BeforeAttributeValue => match character {
+                Some(character) if is_whitespace(character) => (),
+                Some(quote @ ('"' | '\'')) => self.state = State::AttributeValueQuoted(quote),
+                Some('>') => self.emit_tag(),
+                _ => self.reconsume(State::AttributeValueUnquoted),
+            },
+            State::AttributeValueQuoted(quote) => match character {
+                Some(character) if character == quote => {
+                    self.state = State::AfterAttributeValueQuoted
+                }
+                Some('&') => self.character_reference(true),
+                Some('\0') => self.attribute_value().push(char::REPLACEMENT_CHARACTER),
+                Some(character) => self.attribute_value().push(character),
+                None => self.emit_eof(),
+            },
+            State::AttributeValueUnquoted => match character {
+                Some(character) if is_whitespace(character) => {
+                    self.state = State::BeforeAttributeName
+                }
+                Some('&') => self.character_reference(true),
+                Some('>') => self.emit_tag(),
+                Some('\0') => self.attribute_value().push(char::REPLACEMENT_CHARACTER),
+                Some(character) => self.attribute_value().push(character),
+                None => self.emit_eof(),
+            },
+            State::AfterAttributeValueQuoted => match character {
+                Some(character) if is_whitespace(character) => {
+                    self.state = State::BeforeAttributeName
+                }
+                Some('/') => self.state = State::SelfClosingStartTag,
+                Some('>') => self.emit_tag(),
+                Some(_) => self.reconsume(State::BeforeAttributeName),
+                None => self.emit_eof(),
+            },
+            State::SelfClosingStartTag => match character {
+                Some('>') => {
+                    self.tag.self_closing = true;
+                    self.emit_tag();
+                }
+                Some(_) => self.reconsume(State::BeforeAttributeName),
+                None => self.emit_eof(),
+            },
+            State::BogusComment => match character {
+                Some('>') => {
+                    self.emit_comment();
+                    self.state = State::Data;
+                }
+                Some('\0') => self.comment.push(char::REPLACEMENT_CHARACTER),
+                Some(character) => self.comment.push(character),
+                None => {
+                    self.emit_comment();
+                    self.emit_eof();
+                }
+            },
+            State::MarkupDeclarationOpen => {
+                // Looks ahead rather than consuming one character.
+                self.position -= 1;
+                self.comment.clear();
+
+                if self.consume_if("--") {
+                    self.state = State::CommentStart;
+                } else if self.consume_if("doctype") {
+                    self.state = State::Doctype;
+                } else if self.input[self.position..]
+                    .starts_with(&['[', 'C', 'D', 'A', 'T', 'A', '['])
+                {
+                    self.position += 7;
+                    if self.cdata_allowed {
+                        self.state = State::CdataSection;
+                    } else {
+                        self.comment.push_str("[CDATA[");
+                        self.state = State::BogusComment;
+                    }
+                } else {
+                    self.state = State::BogusComment;
+                }
+            }
+            State::CommentStart => match character {
+                Some('-') => self.state = State::CommentStartDash,
+                Some('>') => {
+                    self.emit_comment();
+                    self.state = State::Data;
+                }
+                _ => self.reconsume(State::Comment),
+            },
+            State::CommentStartDash => match character {
+                Some('-') => self.state = State::CommentEnd,
+                Some('>') => {
+                    self.emit_comment();
+                    self.state = State::Data;
+                }
+                Some(_) => {
+                    self.comment.push('-');
+                    self.reconsume(State::Comment);
+                }
+                None => {
+                    self.emit_comment();
+                    self.emit_eof();
+                }
+            },
+            State::Comment => match character {
+                Some('-') => self.state = State::CommentEndDash,
+                Some('\0') => self.comment.push(char::REPLACEMENT_CHARACTER),
+                Some(character) => self.comment.push(character),
+                None => {
+                    self.emit_comment();
+                    self.emit_eof();
+                }
+            },
+            State::CommentEndDash => match character {
+                Some('-') => self.state = State::CommentEnd,
+                Some(_) => {
+                    self.comment.push('-');
+                    self.reconsume(State::Comment);
+                }
+                None => {
+                    self.emit_comment();
+                    self.emit_eof();
+                }
+            },
+            State::CommentEnd => match character {
+                Some('>') => {
+                    self.emit_comment();
+                    self.state = State::Data;
+                }
+                Some('!') => self.state = State::CommentEndBang,
+                Some('-') => self.comment.push('-'),
+                Some(_) => {
+                    self.comment.push_str("--");
+                    self.reconsume(State::Comment);
+                }
+                None => {
+                    self.emit_comment();
+                    self.emit_eof();
+                }
+            },
+            State::CommentEndBang => match character {
+                Some('-') => {
+                    self.comment.push_str("--!");
+                    self.state = State::CommentEndDash;
+                }
+                Some('>') => {
+                    self.emit_comment();
+                    self.state = State::Data;
+                }
+                Some(_) => {
+                    self.comment.push_str("--!");
+                    self.reconsume(State::Comment);
+                }
+                None => {
+                    self.emit_comment();
+                    self.emit_eof();
+                }
+            },
+            State::Doctype => {
+                self.doctype = Doctype::default();
+                match character {
+                    Some(character) if is_whitespace(character) => {
+                        self.state = State::BeforeDoctypeName
+                    }
+                    Some(_) => self.reconsume(State::BeforeDoctypeName),
+                    None => {
+                        self.emit_doctype(true);
+                        self.emit_eof();
+                    }
+                }
+            }
+            State::BeforeDoctypeName => match character {
+                Some(character) if is_whitespace(character) => (),
+                Some('>') => {
+                    self.emit_doctype(true);
+                    self.state = State::Data;
+                }
+                Some(character) => {
+                    let character = match character {
+                        '\0' => char::REPLACEMENT_CHARACTER,
+                        character => character.to_ascii_lowercase(),
+                    };
+                    self.doctype.name = Some(String::from(character));
+                    self.state = State::DoctypeName;
+                }
+                None => {
+                    self.emit_doctype(true);
+                    self.emit_eof();
+                }
+            },
+            State::DoctypeName => match character {
+                Some(character) if is_whitespace(character) => self.state = State::AfterDoctypeName,
+                Some('>') => {
+                    self.emit_doctype(false);
+                    self.state = State::Data;
+                }
+                Some(character) => {
+                    let character = match character {
+                        '\0' => char::REPLACEMENT_CHARACTER,
+                        character => character.to_ascii_lowercase(),
+                    };
+                    self.doctype
+                        .name
+                        .get_or_insert_with(String::new)
+                        .push(character);
+                }
+                None => {
+                    self.emit_doctype(true);
+                    self.emit_eof();
+                }
+            },
+            State::AfterDoctypeName => match character {
+                Some(character) if is_whitespace(character) => (),
+                Some('>') => {
+                    self.emit_doctype(false);
+                    self.state = State::Data;
+                }
+                Some(_) => {
+                    self.position -= 1;
+
+                    if self.consume_if("public") {
+                        self.state = State::AfterDoctypeKeyword(Identifier::Public);
+                    } else if self.consume_if("system") {
+                        self.state = State::AfterDoctypeKeyword(Identifier::System);
+                    } else {
+                        self.doctype.force_quirks = true;
+                        self.state = State::BogusDoctype;
+                    }
+                }
+                None => {
+                    self.emit_doctype(true);
+                    self.emit_eof();
+                }
+            },
+            State::AfterDoctypeKeyword(identifier) => match character {
+                Some(character) if is_whitespace(character) => {
+                    self.state = State::BeforeDoctypeIdentifier(identifier)
+                }
+                _ => self.reconsume(State::BeforeDoctypeIdentifier(identifier)),
+            },
+            State::BeforeDoctypeIdentifier(identifier) => match character {
+                Some(character) if is_whitespace(character) => (),
+                Some(quote @ ('"' | '\'')) => {
+                    *self.doctype_identifier(identifier) = Some(String::new());
+                    self.state = State::DoctypeIdentifierQuoted(identifier, quote);
+                }
+                Some('>') => {
+                    self.emit_doctype(true);
+                    self.state = State::Data;
+                }
+                Some(_) => {
+                    self.doctype.force_quirks = true;
+                    self.reconsume(State::BogusDoctype);
+                }
+                None => {
+                    self.emit_doctype(true);
+                    self.emit_eof();
+                }
+            },
+            State::DoctypeIdentifierQuoted(identifier, quote) => match character {
+                Some(character) if character == quote => {
+                    self.state = State::AfterDoctypeIdentifier(identifier)
+                }
+                Some('>') => {
+                    self.emit_doctype(true);
+                    self.state = State::Data;
+                }
+                Some(character) => {
+                    let character = match character {
+                        '\0' => char::REPLACEMENT_CHARACTER,
+                        character => character,
+                    };
+                    self.doctype_identifier(identifier)
+                        .get_or_insert_with(String::new)
+                        .push(character);
+                }
+                None => {
+                    self.emit_doctype(true);
+                    self.emit_eof();
+                }
+            },
+            State::AfterDoctypeIdentifier(Identifier::Public) => match character {
+                Some(character) if is_whitespace(character) => {
+                    self.state = State::BetweenDoctypeIdentifiers
+                }
+                _ => self.reconsume(State::BetweenDoctypeIdentifiers),
+            },
+            State::BetweenDoctypeIdentifiers => match character {
+                Some(character) if is_whitespace(character) => (),
+                Some('>') => {
+                    self.emit_doctype(false);
+                    self.state = State::Data;
+                }
+                Some(quote @ ('"' | '\'')) => {
+                    self.doctype.system_id = Some(String::new());
+                    self.state = State::DoctypeIdentifierQuoted(Identifier::System, quote);
+                }
+                Some(_) => {
+                    self.doctype.force_quirks = true;
+                    self.reconsume(State::BogusDoctype);
+                }
+                None => {
+                    self.emit_doctype(true);
+                    self.emit_eof();
+                }
+            },
+            State::AfterDoctypeIdentifier(Identifier::System) => match character {
+                Some(character) if is_whitespace(character) => (),
+                Some('>') => {
+                    self.emit_doctype(false);
+                    self.state = State::Data;
+                }
+                Some(_) => self.reconsume(State::BogusDoctype),
+                None => {
+                    self.emit_doctype(true);
+                    self.emit_eof();
+                }
+            },
+            State::BogusDoctype => match character {
+                Some('>') => {
+                    self.emit_doctype(false);
+                    self.state = State::Data;
+                }
+                Some(_) => (),
+                None => {
+                    self.emit_doctype(false);
+                    self.emit_eof();
+                }
+            },
+            State::CdataSection => match character {
+                Some(']') if self.input[self.position..].starts_with(&[']', '>']) => {
+                    self.position += 2;
+                    self.state = State::Data;
+                }
+                Some(character) => self.text.push(character),
+                None => self.emit_eof(),
+            },
+        }
+    }
+}
+
+impl Tokenizer {
+    /// The next token along with where it is in the input, counted in characters. Character
+    /// tokens span everything between the tokens around them, including any markup that was
+    /// dropped, like `</>`.
+    pub fn next_with_span(&mut self) -> Option<(Token, Range<usize>)> {
+        loop {
+            if let Some(token) = self.tokens.pop_front() {
+                return Some(token);
+            }
+
+            if self.finished {
+                return None;
+            }
+
+            self.step();
+        }
+    }
+}
+
+impl Iterator for Tokenizer {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.next_with_span().map(|(token, _)| token)
+    }
+}
+
+#[cfg(test)]
+mod tokenizer_tests {
+    use super::{Attribute, Doctype, Tag, Token, Tokenizer};
+
+    fn tokens(input: &str) -> Vec<Token> {
+        Tokenizer::new(input).collect()
+    }
+
+    fn start(name: &str, attributes: &[(&str, &str)]) -> Token {
+        Token::StartTag(Tag {
+            name: String::from(name),
+            attributes: attributes
+                .iter()
+                .map(|(name, value)| Attribute {
+                    name: String::from(*name),
+                    value: String::from(*value),
+                })
+                .collect(),
+            self_closing: false,
+        })
+    }
+
+    fn end(name: &str) -> Token {
+        Token::EndTag(Tag {
+            name: String::from(name),
+            ..Tag::default()
+        })
+    }
+
+    fn text(value: &str) -> Token {
+        Token::Character(String::from(value))
+    }
+
+    #[test]
+    fn reads_tags_with_attributes() {
+        assert_eq!(
+            tokens("<A HREF=\"/x?a=1&amp;b=2\" title='a > b' data-x=y checked class=one class=two>link</a>"),
+            vec![
+                start(
+                    "a",
+                    &[
+                        ("href", "/x?a=1&b=2"),
+                        ("title", "a > b"),
+                        ("data-x", "y"),
+                        ("checked", ""),
+                        ("class", "one"),
+                    ]
+                ),
+                text("link"),
+                end("a"),
+                Token::EndOfFile,
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_self_closing_tags() {
+        let mut expected = Tag {
+            name: String::from("br"),
+            ..Tag::default()
+        };
+        expected.self_closing = true;
+
+        assert_eq!(
+            tokens("a<br/>b"),
+            vec![
+                text("a"),
+                Token::StartTag(expected),
+                text("b"),
+                Token::EndOfFile
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_comments_and_bogus_comments() {
+        assert_eq!(
+            tokens("<!-- a > b -- c --><!---><?php x ?><![CDATA[d]]></ 1>"),
+            vec![
+                Token::Comment(String::from(" a > b -- c ")),
+                Token::Comment(String::from("")),
+                Token::Comment(String::from("?php x ?")),
+                Token::Comment(String::from("[CDATA[d]]")),
+                Token::Comment(String::from(" 1")),
+                Token::EndOfFile,
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_cdata_sections_in_foreign_content() {
+        let mut tokenizer = Tokenizer::new("<![CDATA[a<b]]>c");
+        tokenizer.set_cdata_allowed(true);
+
+        assert_eq!(
+            tokenizer.collect::<Vec<Token>>(),
+            vec![text("a<bc"), Token::EndOfFile]
+        );
+    }
+
+    #[test]
+    fn reads_doctypes() {
+        assert_eq!(
+            tokens("<!DOCTYPE html><!doctype HTML PUBLIC \"-//W3C//DTD HTML 4.01//EN\" 'http://www.w3.org/TR/html4/strict.dtd'><!DOCTYPE>"),
+            vec![
+                Token::Doctype(Doctype {
+                    name: Some(String::from("html")),
+                    ..Doctype::default()
+                }),
+                Token::Doctype(Doctype {
+                    name: Some(String::from("html")),
+                    public_id: Some(String::from("-//W3C//DTD HTML 4.01//EN")),
+                    system_id: Some(String::from("http://www.w3.org/TR/html4/strict.dtd")),
+                    force_quirks: false,
+                }),
+                Token::Doctype(Doctype {
+                    force_quirks: true,
+                    ..Doctype::default()
+                }),
+                Token::EndOfFile,
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_script_and_style_contents_as_text() {
+        assert_eq!(
+            tokens("<script>if (a < b && c</b) {}</script><style>p > a {}</STYLE >x"),
+            vec![
+                start("script", &[]),
+                text("if (a < b && c</b) {}"),
+                end("script"),
+                start("style", &[]),
+                text("p > a {}"),
+                end("style"),
+                text("x"),
+                Token::EndOfFile,
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_references_in_rcdata_only() {
+        assert_eq!(
+            tokens("<title>a &amp; <b></title><textarea>&lt;/textarea></textarea>"),
+            vec![
+                start("title", &[]),
+                text("a & <b>"),
+                end("title"),
+                start("textarea", &[]),
+                text("</textarea>"),
+                end("textarea"),
+                Token::EndOfFile,
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_character_references() {
+        assert_eq!(
+            tokens("&lt;&gt &#65;&#x42;&#X43 &#; &#0; &#x110000; &bogus; &amp"),
+            vec![
+                text("<> ABC &#; \u{FFFD} \u{FFFD} &bogus; &"),
+                Token::EndOfFile,
+            ]
+        );
+        assert_eq!(
+            tokens("&notit; &notin; &#150;&#x80; &hellip; &Hat;"),
+            vec![
+                text("\u{AC}it; \u{2209} \u{2013}\u{20AC} \u{2026} ^"),
+                Token::EndOfFile,
+            ]
+        );
+        // Legacy references without a semicolon stay as written in attribute values.
+        assert_eq!(
+            tokens("<a href=\"?a=1&copy=2&copy;&lt\">"),
+            vec![
+                start("a", &[("href", "?a=1&copy=2\u{A9}<")]),
+                Token::EndOfFile
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_where_tokens_are() {
+        let mut tokenizer = Tokenizer::new("a<b x=1>c&amp;</b>\r\nd<!-- e -->");
+        let spans: Vec<_> = std::iter::from_fn(|| tokenizer.next_with_span())
+            .map(|(_, span)| span)
+            .collect();
+
+        assert_eq!(
+            spans,
+            vec![0..1, 1..8, 8..14, 14..18, 18..21, 21..31, 31..31]
+        );
+    }
+
+    #[test]
+    fn handles_stray_markup_characters() {
+        assert_eq!(
+            tokens("a < b <3 </> <\r\n"),
+            vec![text("a < b <3  <\n"), Token::EndOfFile]
+        );
+        assert_eq!(tokens("<div"), vec![Token::EndOfFile]);
+    }
+}
@@ -0,0 +1,1859 @@
+//! Tree construction (HTML Living Standard section 13.2.6): builds a `Document` from tokens,
+//! adding the elements markup leaves implied and repairing misnested tags the way browsers
+//! do.
+//!
+//! Templates, framesets and foreign content aren't handled: `<template>`, `<frameset>`,
+//! `<svg>` and `<math>` are parsed like any other element, except that CDATA sections are
+//! read inside the latter two. Scripting is taken to be off, as no scripts are run.
+
+use super::dom::{Comment, Document, Element, NodeData, NodeId, ROOT};
+use super::tokenizer::{is_whitespace, Attribute, Doctype, Tag, Token, Tokenizer};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Initial,
+    BeforeHtml,
+    BeforeHead,
+    InHead,
+    InHeadNoscript,
+    AfterHead,
+    InBody,
+    Text,
+    InTable,
+    InCaption,
+    InColumnGroup,
+    InTableBody,
+    InRow,
+    InCell,
+    InSelect,
+    InSelectInTable,
+    AfterBody,
+    AfterAfterBody,
+}
+
+impl Mode {
+    // The modes that treat leading whitespace differently from the text after it.
+    fn splits_whitespace(self) -> bool {
+        matches!(
+            self,
+            Mode::Initial
+                | Mode::BeforeHtml
+                | Mode::BeforeHead
+                | Mode::InHead
+                | Mode::InHeadNoscript
+                | Mode::AfterHead
+                | Mode::InColumnGroup
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Scope {
+    Default,
+    ListItem,
+    Button,
+    Table,
+    Select,
+}
+
+// An entry in the list of active formatting elements, with the tag it was made from so it
+// can be made again.
+enum Formatting {
+    Marker,
+    Element(NodeId, Tag),
+}
+
+const SPECIAL: &[&str] = &[
+    "address",
+    "applet",
+    "area",
+    "article",
+    "aside",
+    "base",
+    "basefont",
+    "bgsound",
+    "blockquote",
+    "body",
+    "br",
+    "button",
+    "caption",
+    "center",
+    "col",
+    "colgroup",
+    "dd",
+    "details",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "embed",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "frame",
+    "frameset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hgroup",
+    "hr",
+    "html",
+    "iframe",
+    "img",
+    "input",
+    "keygen",
+    "li",
+    "link",
+    "listing",
+    "main",
+    "marquee",
+    "menu",
+    "meta",
+    "nav",
+    "noembed",
+    "noframes",
+    "noscript",
+    "object",
+    "ol",
+    "p",
+    "param",
+    "plaintext",
+    "pre",
+    "script",
+    "search",
+    "section",
+    "select",
+    "source",
+    "style",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "template",
+    "textarea",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "track",
+    "ul",
+    "wbr",
+    "xmp",
+];
+
+const FORMATTING: &[&str] = &[
+    "a", "b", "big", "code", "em", "font", "i", "nobr", "s", "small", "strike", "strong", "tt", "u",
+];
+
+// Elements whose start tag closes an open `<p>`.
+const BLOCKS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "center",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "header",
+    "hgroup",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "search",
+    "section",
+    "summary",
+    "ul",
+];
+
+const HEADINGS: &[&str] = &["h1", "h2", "h3", "h4", "h5", "h6"];
+
+const IMPLIED_END_TAGS: &[&str] = &[
+    "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc",
+];
+
+const DEFAULT_SCOPE: &[&str] = &[
+    "applet", "caption", "html", "table", "td", "th", "marquee", "object", "template",
+];
+
+// Where the stack of open elements is cleared back to before inserting table parts.
+const TABLE_CONTEXT: &[&str] = &["table", "template", "html"];
+const TABLE_BODY_CONTEXT: &[&str] = &["tbody", "tfoot", "thead", "template", "html"];
+const ROW_CONTEXT: &[&str] = &["tr", "template", "html"];
+
+// The most common of the public identifiers that put a page in quirks mode (section
+// 13.2.6.4.1), lowercase.
+const QUIRKY_PUBLIC_IDS: &[&str] = &[
+    "-//ietf//dtd html",
+    "-//netscape comm. corp.//dtd",
+    "-//w3c//dtd html 3",
+    "-//w3c//dtd html 4.0 frameset//",
+    "-//w3c//dtd html 4.0 transitional//",
+    "-//w3c//dtd w3 html",
+    "-//w3o//dtd w3 html",
+    "-//webtechs//dtd mozilla html",
+];
+
+fn is_whitespace_text(text: &str) -> bool {
+    text.chars().all(is_whitespace)
+}
+
+fn is_quirky(doctype: &Doctype) -> bool {
+    let public_id = doctype
+        .public_id
+        .as_deref()
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let old_transitional = doctype.system_id.is_none()
+        && (public_id.starts_with("-//w3c//dtd html 4.01 frameset//")
+            || public_id.starts_with("-//w3c//dtd html 4.01 transitional//"));
+
+    doctype.force_quirks
+        || doctype.name.as_deref() != Some("html")
+        || old_transitional
+        || QUIRKY_PUBLIC_IDS
+            .iter()
+            .any(|prefix| public_id.starts_with(prefix))
+}
+
+// A start tag for an element the markup left out.
+fn implied(name: &str) -> Tag {
+    Tag {
+        name: String::from(name),
+        ..Tag::default()
+    }
+}
+
+fn is_start_tag(token: &Token, names: &[&str]) -> bool {
+    matches!(token, Token::StartTag(tag) if names.contains(&tag.name.as_str()))
+}
+
+fn is_end_tag(token: &Token, names: &[&str]) -> bool {
+    matches!(token, Token::EndTag(tag) if names.contains(&tag.name.as_str()))
+}
+
+/// Parses a whole page into a document tree.
+pub fn parse(input: &str) -> Document {
+    let mut builder = TreeBuilder::new();
+    let mut tokenizer = Tokenizer::new(input);
+
+    while let Some(token) = tokenizer.next() {
+        builder.process(token);
+        tokenizer.set_cdata_allowed(builder.in_foreign_content());
+    }
+
+    builder.document
+}
+
+struct TreeBuilder {
+    document: Document,
+    mode: Mode,
+    // The mode to go back to after the text of e.g. a `<script>`.
+    original_mode: Mode,
+    open: Vec<NodeId>,
+    formatting: Vec<Formatting>,
+    head: Option<NodeId>,
+    form: Option<NodeId>,
+    // Set while misplaced content in tables gets moved out in front of them.
+    foster_parenting: bool,
+    // A newline right after `<pre>` or `<textarea>` isn't part of their text.
+    ignore_linefeed: bool,
+}
+
+impl TreeBuilder {
+    fn new() -> Self {
+        Self {
+            document: Document::new(),
+            mode: Mode::Initial,
+            original_mode: Mode::Initial,
+            open: Vec::new(),
+            formatting: Vec::new(),
+            head: None,
+            form: None,
+            foster_parenting: false,
+            ignore_linefeed: false,
+        }
+    }
+
+    fn process(&mut self, mut token: Token) {
+        if std::mem::take(&mut self.ignore_linefeed) {
+            if let Token::Character(text) = &mut token {
+                if text.starts_with('\n') {
+                    text.remove(0);
+                }
+                if text.is_empty() {
+                    return;
+                }
+            }
+        }
+
+        if let Token::Character(text) = &token {
+            let rest = text.trim_start_matches(is_whitespace);
+
+            if self.mode.splits_whitespace() && !rest.is_empty() && rest.len() < text.len() {
+                let (whitespace, rest) = text.split_at(text.len() - rest.len());
+                let (whitespace, rest) = (String::from(whitespace), String::from(rest));
+
+                self.process(Token::Character(whitespace));
+                self.process(Token::Character(rest));
+                return;
+            }
+        }
+
+        match self.mode {
+            Mode::Initial => self.initial(token),
+            Mode::BeforeHtml => self.before_html(token),
+            Mode::BeforeHead => self.before_head(token),
+            Mode::InHead => self.in_head(token),
+            Mode::InHeadNoscript => self.in_head_noscript(token),
+            Mode::AfterHead => self.after_head(token),
+            Mode::InBody => self.in_body(token),
+            Mode::Text => self.text(token),
+            Mode::InTable => self.in_table(token),
+            Mode::InCaption => self.in_caption(token),
+            Mode::InColumnGroup => self.in_column_group(token),
+            Mode::InTableBody => self.in_table_body(token),
+            Mode::InRow => self.in_row(token),
+            Mode::InCell => self.in_cell(token),
+            Mode::InSelect => self.in_select(token),
+            Mode::InSelectInTable => self.in_select_in_table(token),
+            Mode::AfterBody => self.after_body(token),
+            Mode::AfterAfterBody => self.after_after_body(token),
+        }
+    }
+
+    fn reprocess(&mut self, mode: Mode, token: Token) {
+        self.mode = mode;
+        self.process(token);
+    }
+
+    fn name(&self, node: NodeId) -> &str {
+        self.document
+            .element(node)
+            .map(|element| element.name.as_str())
+            .unwrap_or("")
+    }
+
+    fn current(&self) -> NodeId {
+        self.open.last().copied().unwrap_or(ROOT)
+    }
+
+    // Whether the current node is in `<svg>` or `<math>`, and not in one of their elements
+    // that hold HTML again.
+    fn in_foreign_content(&self) -> bool {
+        for &node in self.open.iter().rev() {
+            match self.name(node) {
+                "svg" | "math" => return true,
+                "foreignobject" | "desc" | "title" | "mi" | "mo" | "mn" | "ms" | "mtext" => {
+                    return false
+                }
+                _ => (),
+            }
+        }
+
+        false
+    }
+
+    fn current_is(&self, names: &[&str]) -> bool {
+        names.contains(&self.name(self.current()))
+    }
+
+    fn in_scope(&self, names: &[&str], scope: Scope) -> bool {
+        for &node in self.open.iter().rev() {
+            let name = self.name(node);
+
+            if names.contains(&name) {
+                return true;
+            }
+
+            let boundary = match scope {
+                Scope::Table => TABLE_CONTEXT.contains(&name),
+                Scope::Select => !matches!(name, "optgroup" | "option"),
+                Scope::ListItem => DEFAULT_SCOPE.contains(&name) || matches!(name, "ol" | "ul"),
+                Scope::Button => DEFAULT_SCOPE.contains(&name) || name == "button",
+                Scope::Default => DEFAULT_SCOPE.contains(&name),
+            };
+
+            if boundary {
+                return false;
+            }
+        }
+
+        false
+    }
+
+    fn node_in_scope(&self, target: NodeId) -> bool {
+        for &node in self.open.iter().rev() {
+            if node == target {
+                return true;
+            }
+            if DEFAULT_SCOPE.contains(&self.name(node)) {
+                return false;
+            }
+        }
+
+        false
+    }
+
+    // Where a new node goes: the current node, unless it has to be foster parented out of a
+    // table (section 13.2.6.1).
+    fn insertion_location(&self, target: Option<NodeId>) -> (NodeId, Option<NodeId>) {
+        let target = target.unwrap_or_else(|| self.current());
+
+        if !self.foster_parenting
+            || !matches!(
+                self.name(target),
+                "table" | "tbody" | "tfoot" | "thead" | "tr"
+            )
+        {
+            return (target, None);
+        }
+
+        match self
+            .open
+            .iter()
+            .rposition(|&node| self.name(node) == "table")
+        {
+            Some(index) => {
+                let table = self.open[index];
+                match self.document.parent(table) {
+                    Some(parent) => (parent, Some(table)),
+                    None => (self.open[index - 1], None),
+                }
+            }
+            None => (self.open[0], None),
+        }
+    }
+
+    fn create_element(&mut self, tag: &Tag) -> NodeId {
+        self.document.create(NodeData::Element(Element {
+            name: tag.name.clone(),
+            attributes: tag.attributes.clone(),
+        }))
+    }
+
+    fn insert_element(&mut self, tag: &Tag) -> NodeId {
+        let (parent, before) = self.insertion_location(None);
+        let node = self.create_element(tag);
+
+        self.document.insert(parent, node, before);
+        self.open.push(node);
+
+        node
+    }
+
+    // Inserts an element that can't have children.
+    fn insert_void_element(&mut self, tag: &Tag) {
+        self.insert_element(tag);
+        self.open.pop();
+    }
+
+    // Inserts an element whose contents the tokenizer reads as text, like `<title>`.
+    fn insert_text_element(&mut self, tag: &Tag) {
+        self.insert_element(tag);
+        self.original_mode = self.mode;
+        self.mode = Mode::Text;
+    }
+
+    fn insert_character(&mut self, text: &str) {
+        let (parent, before) = self.insertion_location(None);
+
+        // Text can't go directly in the document.
+        if parent != ROOT {
+            self.document.insert_text(parent, before, text);
+        }
+    }
+
+    fn insert_comment(&mut self, data: String) {
+        let (parent, before) = self.insertion_location(None);
+        let node = self.document.create(NodeData::Comment(Comment { data }));
+
+        self.document.insert(parent, node, before);
+    }
+
+    fn append_comment(&mut self, parent: NodeId, data: String) {
+        let node = self.document.create(NodeData::Comment(Comment { data }));
+
+        self.document.append(parent, node);
+    }
+
+    fn add_missing_attributes(&mut self, node: NodeId, attributes: Vec<Attribute>) {
+        if let Some(element) = self.document.element_mut(node) {
+            for attribute in attributes {
+                if element.attribute(&attribute.name).is_none() {
+                    element.attributes.push(attribute);
+                }
+            }
+        }
+    }
+
+    fn pop_until(&mut self, names: &[&str]) {
+        while let Some(node) = self.open.pop() {
+            if names.contains(&self.name(node)) {
+                break;
+            }
+        }
+    }
+
+    fn clear_back_to(&mut self, context: &[&str]) {
+        while !self.open.is_empty() && !self.current_is(context) {
+            self.open.pop();
+        }
+    }
+
+    fn generate_implied_end_tags_except(&mut self, except: &str) {
+        while self.current_is(IMPLIED_END_TAGS) && self.name(self.current()) != except {
+            self.open.pop();
+        }
+    }
+
+    fn generate_implied_end_tags(&mut self) {
+        self.generate_implied_end_tags_except("");
+    }
+
+    fn close_p(&mut self) {
+        if self.in_scope(&["p"], Scope::Button) {
+            self.generate_implied_end_tags_except("p");
+            self.pop_until(&["p"]);
+        }
+    }
+
+    fn formatting_position(&self, node: NodeId) -> Option<usize> {
+        self.formatting
+            .iter()
+            .position(|entry| matches!(entry, Formatting::Element(element, _) if *element == node))
+    }
+
+    // The last formatting element named `name` since the last marker, with its position in
+    // the list.
+    fn formatting_element(&self, name: &str) -> Option<(usize, NodeId, Tag)> {
+        self.formatting
+            .iter()
+            .enumerate()
+            .rev()
+            .take_while(|(_, entry)| !matches!(entry, Formatting::Marker))
+            .find_map(|(index, entry)| match entry {
+                Formatting::Element(node, tag) if tag.name == name => {
+                    Some((index, *node, tag.clone()))
+                }
+                _ => None,
+            })
+    }
+
+    fn push_formatting(&mut self, node: NodeId, tag: Tag) {
+        let same_attributes = |other: &Tag| {
+            other.attributes.len() == tag.attributes.len()
+                && other
+                    .attributes
+                    .iter()
+                    .all(|attribute| tag.attributes.contains(attribute))
+        };
+
+        // Only the last three identical elements are kept (the Noah's Ark clause).
+        let identical: Vec<usize> = self
+            .formatting
+            .iter()
+            .enumerate()
+            .rev()
+            .take_while(|(_, entry)| !matches!(entry, Formatting::Marker))
+            .filter(|(_, entry)| {
+                matches!(entry, Formatting::Element(_, other) if other.name == tag.name && same_attributes(other))
+            })
+            .map(|(index, _)| index)
+            .collect();
+
+        if let [.., _, _, earliest] = identical[..] {
+            self.formatting.remove(earliest);
+        }
+
+        self.formatting.push(Formatting::Element(node, tag));
+    }
+
+    fn clear_formatting_to_marker(&mut self) {
+        while let Some(entry) = self.formatting.pop() {
+            if let Formatting::Marker = entry {
+                break;
+            }
+        }
+    }
+
+    // Reopens formatting elements closed by a block, so `<b>a<p>b` makes both bold.
+    fn reconstruct_formatting(&mut self) {
+        let closed = |entry: &Formatting| match entry {
+            Formatting::Marker => false,
+            Formatting::Element(node, _) => !self.open.contains(node),
+        };
+
+        if !self.formatting.last().is_some_and(closed) {
+            return;
+        }
+
+        let mut start = self.formatting.len() - 1;
+        while start > 0 && closed(&self.formatting[start - 1]) {
+            start -= 1;
+        }
+
+        for index in start..self.formatting.len() {
+            if let Formatting::Element(_, tag) = &self.formatting[index] {
+                let tag = tag.clone();
+                let node = self.insert_element(&tag);
+                self.formatting[index] = Formatting::Element(node, tag);
+            }
+        }
+    }
+
+    // The adoption agency algorithm (section 13.2.6.4.7), which closes a formatting element
+    // and repairs the tree when it was misnested, as in `<b>1<p>2</b>3</p>`. Returns false if
+    // the end tag should be treated like any other.
+    fn adoption_agency(&mut self, subject: &str) -> bool {
+        let current = self.current();
+        if self.name(current) == subject && self.formatting_position(current).is_none() {
+            self.open.pop();
+            return true;
+        }
+
+        for _ in 0..8 {
+            let Some((mut bookmark, formatting_element, tag)) = self.formatting_element(subject)
+            else {
+                return false;
+            };
+
+            let Some(stack_index) = self
+                .open
+                .iter()
+                .position(|&node| node == formatting_element)
+            else {
+                self.formatting.remove(bookmark);
+                return true;
+            };
+
+            if !self.node_in_scope(formatting_element) {
+                return true;
+            }
+
+            let furthest_block = self.open[stack_index + 1..]
+                .iter()
+                .position(|&node| SPECIAL.contains(&self.name(node)))
+                .map(|offset| stack_index + 1 + offset);
+
+            let Some(furthest_block_index) = furthest_block else {
+                self.open.truncate(stack_index);
+                self.formatting.remove(bookmark);
+                return true;
+            };
+
+            let furthest_block = self.open[furthest_block_index];
+            let common_ancestor = self.open[stack_index - 1];
+            let mut node_index = furthest_block_index;
+            let mut last_node = furthest_block;
+
+            for inner in 1.. {
+                node_index -= 1;
+                let node = self.open[node_index];
+
+                if node == formatting_element {
+                    break;
+                }
+
+                let mut position = self.formatting_position(node);
+
+                if inner > 3 {
+                    if let Some(index) = position.take() {
+                        self.formatting.remove(index);
+                        if index < bookmark {
+                            bookmark -= 1;
+                        }
+                    }
+                }
+
+                let Some(position) = position else {
+                    self.open.remove(node_index);
+                    continue;
+                };
+
+                let node_tag = match &self.formatting[position] {
+                    Formatting::Element(_, node_tag) => node_tag.clone(),
+                    Formatting::Marker => unreachable!("Markers aren't open elements"),
+                };
+                let replacement = self.create_element(&node_tag);
+                self.formatting[position] = Formatting::Element(replacement, node_tag);
+                self.open[node_index] = replacement;
+
+                if last_node == furthest_block {
+                    bookmark = position + 1;
+                }
+
+                self.document.append(replacement, last_node);
+                last_node = replacement;
+            }
+
+            let (parent, before) = self.insertion_location(Some(common_ancestor));
+            self.document.insert(parent, last_node, before);
+
+            let replacement = self.create_element(&tag);
+            for child in self.document.children(furthest_block).to_vec() {
+                self.document.append(replacement, child);
+            }
+            self.document.append(furthest_block, replacement);
+
+            if let Some(index) = self.formatting_position(formatting_element) {
+                self.formatting.remove(index);
+                if index < bookmark {
+                    bookmark -= 1;
+                }
+            }
+            self.formatting
+                .insert(bookmark, Formatting::Element(replacement, tag));
+
+            self.open.retain(|&node| node != formatting_element);
+            let furthest_block_index = self
+                .open
+                .iter()
+                .position(|&node| node == furthest_block)
+                .expect("Furthest block left the stack");
+            self.open.insert(furthest_block_index + 1, replacement);
+        }
+
+        true
+    }
+
+    fn reset_mode(&mut self) {
+        for index in (0..self.open.len()).rev() {
+            let last = index == 0;
+
+            self.mode = match self.name(self.open[index]) {
+                "select"
+                    if self.open[..index]
+                        .iter()
+                        .any(|&node| self.name(node) == "table") =>
+                {
+                    Mode::InSelectInTable
+                }
+                "select" => Mode::InSelect,
+                "td" | "th" if !last => Mode::InCell,
+                "tr" => Mode::InRow,
+                "tbody" | "thead" | "tfoot" => Mode::InTableBody,
+                "caption" => Mode::InCaption,
+                "colgroup" => Mode::InColumnGroup,
+                "table" => Mode::InTable,
+                "head" if !last => Mode::InHead,
+                "body" => Mode::InBody,
+                "html" if self.head.is_none() => Mode::BeforeHead,
+                "html" => Mode::AfterHead,
+                _ if last => Mode::InBody,
+                _ => continue,
+            };
+
+            return;
+        }
+    }
+
+    fn initial(&mut self, token: Token) {
+        match token {
+            Token::Character(text) if is_whitespace_text(&text) => (),
+            Token::Comment(data) => self.append_comment(ROOT, data),
+            Token::Doctype(doctype) => {
+                self.document.quirks_mode = is_quirky(&doctype);
+                let node = self.document.create(NodeData::Doctype(doctype));
+                self.document.append(ROOT, node);
+                self.mode = Mode::BeforeHtml;
+            }
+            token => {
+                self.document.quirks_mode = true;
+                self.reprocess(Mode::BeforeHtml, token);
+            }
+        }
+    }
+
+    fn before_html(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => (),
+            Token::Character(text) if is_whitespace_text(&text) => (),
+            Token::Comment(data) => self.append_comment(ROOT, data),
+            Token::StartTag(tag) if tag.name == "html" => {
+                self.insert_element(&tag);
+                self.mode = Mode::BeforeHead;
+            }
+            ref token
+                if matches!(token, Token::EndTag(_))
+                    && !is_end_tag(token, &["head", "body", "html", "br"]) => {}
+            token => {
+                self.insert_element(&implied("html"));
+                self.reprocess(Mode::BeforeHead, token);
+            }
+        }
+    }
+
+    fn before_head(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => (),
+            Token::Character(text) if is_whitespace_text(&text) => (),
+            Token::Comment(data) => self.insert_comment(data),
+            Token::StartTag(tag) if tag.name == "html" => self.in_body(Token::StartTag(tag)),
+            Token::StartTag(tag) if tag.name == "head" => {
+                self.head = Some(self.insert_element(&tag));
+                self.mode = Mode::InHead;
+            }
+            ref token
+                if matches!(token, Token::EndTag(_))
+                    && !is_end_tag(token, &["head", "body", "html", "br"]) => {}
+            token => {
+                self.head = Some(self.insert_element(&implied("head")));
+                self.reprocess(Mode::InHead, token);
+            }
+        }
+    }
+
+    fn in_head(&mut self, token: Token) {
+        match token {
+            Token::Character(text) if is_whitespace_text(&text) => self.insert_character(&text),
+            Token::Comment(data) => self.insert_comment(data),
+            Token::Doctype(_) => (),
+            Token::StartTag(tag) => match tag.name.as_str() {
+                "html" => self.in_body(Token::StartTag(tag)),
+                "base" | "basefont" | "bgsound" | "link" | "meta" => self.insert_void_element(&tag),
+                "title" | "noframes" | "style" | "script" => self.insert_text_element(&tag),
+                "noscript" => {
+                    self.insert_element(&tag);
+                    self.mode = Mode::InHeadNoscript;
+                }
+                "head" => (),
+                _ => {
+                    self.open.pop();
+                    self.reprocess(Mode::AfterHead, Token::StartTag(tag));
+                }
+            },
+            Token::EndTag(tag) if tag.name == "head" => {
+                self.open.pop();
+                self.mode = Mode::AfterHead;
+            }
+            ref token
+                if matches!(token, Token::EndTag(_))
+                    && !is_end_tag(token, &["body", "html", "br"]) => {}
+            token => {
+                self.open.pop();
+                self.reprocess(Mode::AfterHead, token);
+            }
+        }
+    }
+
+    fn in_head_noscript(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => (),
+            Token::StartTag(tag) if tag.name == "html" => self.in_body(Token::StartTag(tag)),
+            Token::EndTag(tag) if tag.name == "noscript" => {
+                self.open.pop();
+                self.mode = Mode::InHead;
+            }
+            Token::Character(text) if is_whitespace_text(&text) => {
+                self.in_head(Token::Character(text))
+            }
+            Token::Comment(data) => self.in_head(Token::Comment(data)),
+            ref token
+                if is_start_tag(
+                    token,
+                    &["basefont", "bgsound", "link", "meta", "noframes", "style"],
+                ) =>
+            {
+                self.in_head(token.clone())
+            }
+            ref token if is_start_tag(token, &["head", "noscript"]) => (),
+            ref token if matches!(token, Token::EndTag(_)) && !is_end_tag(token, &["br"]) => {}
+            token => {
+                self.open.pop();
+                self.reprocess(Mode::InHead, token);
+            }
+        }
+    }
+
+    fn after_head(&mut self, token: Token) {
+        match token {
+            Token::Character(text) if is_whitespace_text(&text) => self.insert_character(&text),
+            Token::Comment(data) => self.insert_comment(data),
+            Token::Doctype(_) => (),
+            Token::StartTag(tag) if tag.name == "html" => self.in_body(Token::StartTag(tag)),
+            Token::StartTag(tag) if tag.name == "body" => {
+                self.insert_element(&tag);
+                self.mode = Mode::InBody;
+            }
+            ref token
+                if is_start_tag(
+                    token,
+                    &[
+                        "base", "basefont", "bgsound", "link", "meta", "noframes", "script",
+                        "style", "title",
+                    ],
+                ) =>
+            {
+                // Late head elements still go in the head.
+                let head = self.head.expect("Head element missing after head");
+                self.open.push(head);
+                self.in_head(token.clone());
+                self.open.retain(|&node| node != head);
+            }
+            ref token if is_start_tag(token, &["head"]) => (),
+            ref token
+                if matches!(token, Token::EndTag(_))
+                    && !is_end_tag(token, &["body", "html", "br"]) => {}
+            token => {
+                self.insert_element(&implied("body"));
+                self.reprocess(Mode::InBody, token);
+            }
+        }
+    }
+
+    fn in_body(&mut self, token: Token) {
+        match token {
+            Token::Character(text) => {
+                let text = text.replace('\0', "");
+
+                if !text.is_empty() {
+                    self.reconstruct_formatting();
+                    self.insert_character(&text);
+                }
+            }
+            Token::Comment(data) => self.insert_comment(data),
+            Token::Doctype(_) => (),
+            Token::StartTag(tag) => self.start_tag_in_body(tag),
+            Token::EndTag(tag) => self.end_tag_in_body(tag),
+            Token::EndOfFile => (),
+        }
+    }
+
+    // Closes an open `<li>`, `<dd>` or `<dt>` before another one starts.
+    fn close_list_item(&mut self, names: &[&str]) {
+        for index in (0..self.open.len()).rev() {
+            let name = self.name(self.open[index]);
+
+            if names.contains(&name) {
+                let name = String::from(name);
+                self.generate_implied_end_tags_except(&name);
+                self.pop_until(&[&name]);
+                return;
+            }
+
+            if SPECIAL.contains(&name) && !matches!(name, "address" | "div" | "p") {
+                return;
+            }
+        }
+    }
+
+    fn start_tag_in_body(&mut self, tag: Tag) {
+        let name = tag.name.as_str();
+
+        match name {
+            "html" => {
+                let html = self.open[0];
+                self.add_missing_attributes(html, tag.attributes);
+            }
+            "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script" | "style"
+            | "title" => self.in_head(Token::StartTag(tag)),
+            "body" => {
+                if let Some(&body) = self.open.get(1) {
+                    if self.name(body) == "body" {
+                        self.add_missing_attributes(body, tag.attributes);
+                    }
+                }
+            }
+            _ if BLOCKS.contains(&name) => {
+                self.close_p();
+                self.insert_element(&tag);
+            }
+            _ if HEADINGS.contains(&name) => {
+                self.close_p();
+                if self.current_is(HEADINGS) {
+                    self.open.pop();
+                }
+                self.insert_element(&tag);
+            }
+            "pre" | "listing" => {
+                self.close_p();
+                self.insert_element(&tag);
+                self.ignore_linefeed = true;
+            }
+            "form" => {
+                if self.form.is_none() {
+                    self.close_p();
+                    self.form = Some(self.insert_element(&tag));
+                }
+            }
+            "li" => {
+                self.close_list_item(&["li"]);
+                self.close_p();
+                self.insert_element(&tag);
+            }
+            "dd" | "dt" => {
+                self.close_list_item(&["dd", "dt"]);
+                self.close_p();
+                self.insert_element(&tag);
+            }
+            "plaintext" => {
+                self.close_p();
+                self.insert_element(&tag);
+            }
+            "button" => {
+                if self.in_scope(&["button"], Scope::Default) {
+                    self.generate_implied_end_tags();
+                    self.pop_until(&["button"]);
+                }
+                self.reconstruct_formatting();
+                self.insert_element(&tag);
+            }
+            "a" => {
+                // A link can't contain another one.
+                if let Some((_, link, _)) = self.formatting_element("a") {
+                    self.adoption_agency("a");
+                    self.formatting.retain(
+                        |entry| !matches!(entry, Formatting::Element(node, _) if *node == link),
+                    );
+                    self.open.retain(|&node| node != link);
+                }
+                self.reconstruct_formatting();
+                let node = self.insert_element(&tag);
+                self.push_formatting(node, tag);
+            }
+            "nobr" => {
+                self.reconstruct_formatting();
+                if self.in_scope(&["nobr"], Scope::Default) {
+                    self.adoption_agency("nobr");
+                    self.reconstruct_formatting();
+                }
+                let node = self.insert_element(&tag);
+                self.push_formatting(node, tag);
+            }
+            _ if FORMATTING.contains(&name) => {
+                self.reconstruct_formatting();
+                let node = self.insert_element(&tag);
+                self.push_formatting(node, tag);
+            }
+            "applet" | "marquee" | "object" => {
+                self.reconstruct_formatting();
+                self.insert_element(&tag);
+                self.formatting.push(Formatting::Marker);
+            }
+            "table" => {
+                if !self.document.quirks_mode {
+                    self.close_p();
+                }
+                self.insert_element(&tag);
+                self.mode = Mode::InTable;
+            }
+            "area" | "br" | "embed" | "img" | "keygen" | "wbr" | "input" => {
+                self.reconstruct_formatting();
+                self.insert_void_element(&tag);
+            }
+            "param" | "source" | "track" => self.insert_void_element(&tag),
+            "hr" => {
+                self.close_p();
+                self.insert_void_element(&tag);
+            }
+            "image" => self.start_tag_in_body(Tag {
+                name: String::from("img"),
+                ..tag
+            }),
+            "textarea" => {
+                self.insert_text_element(&tag);
+                self.ignore_linefeed = true;
+            }
+            "xmp" => {
+                self.close_p();
+                self.reconstruct_formatting();
+                self.insert_text_element(&tag);
+            }
+            "iframe" | "noembed" => self.insert_text_element(&tag),
+            "select" => {
+                self.reconstruct_formatting();
+                self.insert_element(&tag);
+                self.mode = match self.mode {
+                    Mode::InTable
+                    | Mode::InCaption
+                    | Mode::InTableBody
+                    | Mode::InRow
+                    | Mode::InCell => Mode::InSelectInTable,
+                    _ => Mode::InSelect,
+                };
+            }
+            "optgroup" | "option" => {
+                if self.current_is(&["option"]) {
+                    self.open.pop();
+                }
+                self.reconstruct_formatting();
+                self.insert_element(&tag);
+            }
+            "rb" | "rtc" => {
+                if self.in_scope(&["ruby"], Scope::Default) {
+                    self.generate_implied_end_tags();
+                }
+                self.insert_element(&tag);
+            }
+            "rp" | "rt" => {
+                if self.in_scope(&["ruby"], Scope::Default) {
+                    self.generate_implied_end_tags_except("rtc");
+                }
+                self.insert_element(&tag);
+            }
+            "caption" | "col" | "colgroup" | "frame" | "head" | "tbody" | "td" | "tfoot" | "th"
+            | "thead" | "tr" => (),
+            _ => {
+                self.reconstruct_formatting();
+                self.insert_element(&tag);
+            }
+        }
+    }
+
+    fn end_tag_in_body(&mut self, tag: Tag) {
+        let name = tag.name.as_str();
+
+        match name {
+            "body" => {
+                if self.in_scope(&["body"], Scope::Default) {
+                    self.mode = Mode::AfterBody;
+                }
+            }
+            "html" => {
+                if self.in_scope(&["body"], Scope::Default) {
+                    self.reprocess(Mode::AfterBody, Token::EndTag(tag));
+                }
+            }
+            "form" => {
+                if let Some(form) = self.form.take() {
+                    if self.node_in_scope(form) {
+                        self.generate_implied_end_tags();
+                        self.open.retain(|&node| node != form);
+                    }
+                }
+            }
+            "p" => {
+                if !self.in_scope(&["p"], Scope::Button) {
+                    self.insert_element(&implied("p"));
+                }
+                self.close_p();
+            }
+            "li" => {
+                if self.in_scope(&["li"], Scope::ListItem) {
+                    self.generate_implied_end_tags_except("li");
+                    self.pop_until(&["li"]);
+                }
+            }
+            "dd" | "dt" => {
+                if self.in_scope(&[name], Scope::Default) {
+                    self.generate_implied_end_tags_except(name);
+                    self.pop_until(&[name]);
+                }
+            }
+            _ if HEADINGS.contains(&name) => {
+                if self.in_scope(HEADINGS, Scope::Default) {
+                    self.generate_implied_end_tags();
+                    self.pop_until(HEADINGS);
+                }
+            }
+            _ if BLOCKS.contains(&name) || matches!(name, "button" | "listing" | "pre") => {
+                if self.in_scope(&[name], Scope::Default) {
+                    self.generate_implied_end_tags();
+                    self.pop_until(&[name]);
+                }
+            }
+            _ if FORMATTING.contains(&name) => {
+                if !self.adoption_agency(name) {
+                    self.any_other_end_tag(name);
+                }
+            }
+            "applet" | "marquee" | "object" => {
+                if self.in_scope(&[name], Scope::Default) {
+                    self.generate_implied_end_tags();
+                    self.pop_until(&[name]);
+                    self.clear_formatting_to_marker();
+                }
+            }
+            "br" => self.start_tag_in_body(implied("br")),
+            _ => self.any_other_end_tag(name),
+        }
+    }
+
+    fn any_other_end_tag(&mut self, name: &str) {
+        for index in (0..self.open.len()).rev() {
+            let node_name = self.name(self.open[index]);
+
+            if node_name == name {
+                self.generate_implied_end_tags_except(name);
+                self.open.truncate(index);
+                return;
+            }
+
+            if SPECIAL.contains(&node_name) {
+                return;
+            }
+        }
+    }
+
+    fn text(&mut self, token: Token) {
+        match token {
+            Token::Character(text) => self.insert_character(&text),
+            Token::EndOfFile => {
+                self.open.pop();
+                self.reprocess(self.original_mode, Token::EndOfFile);
+            }
+            _ => {
+                self.open.pop();
+                self.mode = self.original_mode;
+            }
+        }
+    }
+
+    // Handles content that doesn't belong in a table by moving it in front of the table.
+    fn foster(&mut self, token: Token) {
+        self.foster_parenting = true;
+        self.in_body(token);
+        self.foster_parenting = false;
+    }
+
+    fn in_table(&mut self, token: Token) {
+        match token {
+            Token::Character(text)
+                if is_whitespace_text(&text)
+                    && self.current_is(&["table", "tbody", "template", "tfoot", "thead", "tr"]) =>
+            {
+                self.insert_character(&text)
+            }
+            Token::Comment(data) => self.insert_comment(data),
+            Token::Doctype(_) => (),
+            Token::StartTag(tag) => match tag.name.as_str() {
+                "caption" => {
+                    self.clear_back_to(TABLE_CONTEXT);
+                    self.formatting.push(Formatting::Marker);
+                    self.insert_element(&tag);
+                    self.mode = Mode::InCaption;
+                }
+                "colgroup" => {
+                    self.clear_back_to(TABLE_CONTEXT);
+                    self.insert_element(&tag);
+                    self.mode = Mode::InColumnGroup;
+                }
+                "col" => {
+                    self.clear_back_to(TABLE_CONTEXT);
+                    self.insert_element(&implied("colgroup"));
+                    self.reprocess(Mode::InColumnGroup, Token::StartTag(tag));
+                }
+                "tbody" | "tfoot" | "thead" => {
+                    self.clear_back_to(TABLE_CONTEXT);
+                    self.insert_element(&tag);
+                    self.mode = Mode::InTableBody;
+                }
+                "td" | "th" | "tr" => {
+                    self.clear_back_to(TABLE_CONTEXT);
+                    self.insert_element(&implied("tbody"));
+                    self.reprocess(Mode::InTableBody, Token::StartTag(tag));
+                }
+                "table" => {
+                    if self.in_scope(&["table"], Scope::Table) {
+                        self.pop_until(&["table"]);
+                        self.reset_mode();
+                        self.process(Token::StartTag(tag));
+                    }
+                }
+                "style" | "script" => self.in_head(Token::StartTag(tag)),
+                "input"
+                    if tag
+                        .attribute("type")
+                        .is_some_and(|kind| kind.eq_ignore_ascii_case("hidden")) =>
+                {
+                    self.insert_void_element(&tag)
+                }
+                "form" => {
+                    if self.form.is_none() {
+                        self.form = Some(self.insert_element(&tag));
+                        self.open.pop();
+                    }
+                }
+                _ => self.foster(Token::StartTag(tag)),
+            },
+            Token::EndTag(tag) => match tag.name.as_str() {
+                "table" => {
+                    if self.in_scope(&["table"], Scope::Table) {
+                        self.pop_until(&["table"]);
+                        self.reset_mode();
+                    }
+                }
+                "body" | "caption" | "col" | "colgroup" | "html" | "tbody" | "td" | "tfoot"
+                | "th" | "thead" | "tr" => (),
+                _ => self.foster(Token::EndTag(tag)),
+            },
+            Token::EndOfFile => self.in_body(token),
+            token => self.foster(token),
+        }
+    }
+
+    fn close_caption(&mut self) -> bool {
+        if !self.in_scope(&["caption"], Scope::Table) {
+            return false;
+        }
+
+        self.generate_implied_end_tags();
+        self.pop_until(&["caption"]);
+        self.clear_formatting_to_marker();
+        self.mode = Mode::InTable;
+
+        true
+    }
+
+    fn in_caption(&mut self, token: Token) {
+        const TABLE_PARTS: &[&str] = &[
+            "caption", "col", "colgroup", "tbody", "td", "tfoot", "th", "thead", "tr",
+        ];
+
+        if is_end_tag(&token, &["caption"]) {
+            self.close_caption();
+        } else if is_start_tag(&token, TABLE_PARTS) || is_end_tag(&token, &["table"]) {
+            if self.close_caption() {
+                self.process(token);
+            }
+        } else if !is_end_tag(
+            &token,
+            &[
+                "body", "col", "colgroup", "html", "tbody", "td", "tfoot", "th", "thead", "tr",
+            ],
+        ) {
+            self.in_body(token);
+        }
+    }
+
+    fn in_column_group(&mut self, token: Token) {
+        match token {
+            Token::Character(text) if is_whitespace_text(&text) => self.insert_character(&text),
+            Token::Comment(data) => self.insert_comment(data),
+            Token::Doctype(_) => (),
+            Token::StartTag(tag) if tag.name == "html" => self.in_body(Token::StartTag(tag)),
+            Token::StartTag(tag) if tag.name == "col" => self.insert_void_element(&tag),
+            Token::EndTag(tag) if tag.name == "colgroup" => {
+                if self.current_is(&["colgroup"]) {
+                    self.open.pop();
+                    self.mode = Mode::InTable;
+                }
+            }
+            Token::EndTag(tag) if tag.name == "col" => (),
+            Token::EndOfFile => self.in_body(token),
+            token => {
+                if self.current_is(&["colgroup"]) {
+                    self.open.pop();
+                    self.reprocess(Mode::InTable, token);
+                }
+            }
+        }
+    }
+
+    fn in_table_body(&mut self, token: Token) {
+        match token {
+            Token::StartTag(tag) if tag.name == "tr" => {
+                self.clear_back_to(TABLE_BODY_CONTEXT);
+                self.insert_element(&tag);
+                self.mode = Mode::InRow;
+            }
+            Token::StartTag(tag) if matches!(tag.name.as_str(), "td" | "th") => {
+                self.clear_back_to(TABLE_BODY_CONTEXT);
+                self.insert_element(&implied("tr"));
+                self.reprocess(Mode::InRow, Token::StartTag(tag));
+            }
+            Token::EndTag(tag) if matches!(tag.name.as_str(), "tbody" | "tfoot" | "thead") => {
+                if self.in_scope(&[&tag.name], Scope::Table) {
+                    self.clear_back_to(TABLE_BODY_CONTEXT);
+                    self.open.pop();
+                    self.mode = Mode::InTable;
+                }
+            }
+            ref token
+                if is_start_tag(
+                    token,
+                    &["caption", "col", "colgroup", "tbody", "tfoot", "thead"],
+                ) || is_end_tag(token, &["table"]) =>
+            {
+                if self.in_scope(&["tbody", "thead", "tfoot"], Scope::Table) {
+                    self.clear_back_to(TABLE_BODY_CONTEXT);
+                    self.open.pop();
+                    self.reprocess(Mode::InTable, token.clone());
+                }
+            }
+            ref token
+                if is_end_tag(
+                    token,
+                    &[
+                        "body", "caption", "col", "colgroup", "html", "td", "th", "tr",
+                    ],
+                ) => {}
+            token => self.in_table(token),
+        }
+    }
+
+    fn close_row(&mut self) -> bool {
+        if !self.in_scope(&["tr"], Scope::Table) {
+            return false;
+        }
+
+        self.clear_back_to(ROW_CONTEXT);
+        self.open.pop();
+        self.mode = Mode::InTableBody;
+
+        true
+    }
+
+    fn in_row(&mut self, token: Token) {
+        match token {
+            Token::StartTag(tag) if matches!(tag.name.as_str(), "td" | "th") => {
+                self.clear_back_to(ROW_CONTEXT);
+                self.insert_element(&tag);
+                self.mode = Mode::InCell;
+                self.formatting.push(Formatting::Marker);
+            }
+            Token::EndTag(tag) if tag.name == "tr" => {
+                self.close_row();
+            }
+            ref token
+                if is_start_tag(
+                    token,
+                    &[
+                        "caption", "col", "colgroup", "tbody", "tfoot", "thead", "tr",
+                    ],
+                ) || is_end_tag(token, &["table"]) =>
+            {
+                if self.close_row() {
+                    self.process(token.clone());
+                }
+            }
+            Token::EndTag(tag) if matches!(tag.name.as_str(), "tbody" | "tfoot" | "thead") => {
+                if self.in_scope(&[&tag.name], Scope::Table) && self.close_row() {
+                    self.process(Token::EndTag(tag));
+                }
+            }
+            ref token
+                if is_end_tag(
+                    token,
+                    &["body", "caption", "col", "colgroup", "html", "td", "th"],
+                ) => {}
+            token => self.in_table(token),
+        }
+    }
+
+    fn close_cell(&mut self) {
+        self.generate_implied_end_tags();
+        self.pop_until(&["td", "th"]);
+        self.clear_formatting_to_marker();
+        self.mode = Mode::InRow;
+    }
+
+    fn in_cell(&mut self, token: Token) {
+        match token {
+            Token::EndTag(tag) if matches!(tag.name.as_str(), "td" | "th") => {
+                if self.in_scope(&[&tag.name], Scope::Table) {
+                    self.generate_implied_end_tags();
+                    self.pop_until(&[&tag.name]);
+                    self.clear_formatting_to_marker();
+                    self.mode = Mode::InRow;
+                }
+            }
+            ref token
+                if is_start_tag(
+                    token,
+                    &[
+                        "caption", "col", "colgroup", "tbody", "td", "tfoot", "th", "thead", "tr",
+                    ],
+                ) =>
+            {
+                if self.in_scope(&["td", "th"], Scope::Table) {
+                    self.close_cell();
+                    self.process(token.clone());
+                }
+            }
+            Token::EndTag(tag)
+                if matches!(
+                    tag.name.as_str(),
+                    "table" | "tbody" | "tfoot" | "thead" | "tr"
+                ) =>
+            {
+                if self.in_scope(&[&tag.name], Scope::Table) {
+                    self.close_cell();
+                    self.process(Token::EndTag(tag));
+                }
+            }
+            ref token if is_end_tag(token, &["body", "caption", "col", "colgroup", "html"]) => {}
+            token => self.in_body(token),
+        }
+    }
+
+    fn close_select(&mut self) -> bool {
+        if !self.in_scope(&["select"], Scope::Select) {
+            return false;
+        }
+
+        self.pop_until(&["select"]);
+        self.reset_mode();
+
+        true
+    }
+
+    fn in_select(&mut self, token: Token) {
+        match token {
+            Token::Character(text) => {
+                let text = text.replace('\0', "");
+                if !text.is_empty() {
+                    self.insert_character(&text);
+                }
+            }
+            Token::Comment(data) => self.insert_comment(data),
+            Token::Doctype(_) => (),
+            Token::StartTag(tag) => match tag.name.as_str() {
+                "html" => self.in_body(Token::StartTag(tag)),
+                "option" => {
+                    if self.current_is(&["option"]) {
+                        self.open.pop();
+                    }
+                    self.insert_element(&tag);
+                }
+                "optgroup" => {
+                    if self.current_is(&["option"]) {
+                        self.open.pop();
+                    }
+                    if self.current_is(&["optgroup"]) {
+                        self.open.pop();
+                    }
+                    self.insert_element(&tag);
+                }
+                "select" => {
+                    self.close_select();
+                }
+                "input" | "keygen" | "textarea" if self.close_select() => {
+                    self.process(Token::StartTag(tag))
+                }
+                "script" => self.in_head(Token::StartTag(tag)),
+                _ => (),
+            },
+            Token::EndTag(tag) => match tag.name.as_str() {
+                "optgroup" => {
+                    let parent_is_optgroup = self
+                        .open
+                        .len()
+                        .checked_sub(2)
+                        .is_some_and(|index| self.name(self.open[index]) == "optgroup");
+
+                    if self.current_is(&["option"]) && parent_is_optgroup {
+                        self.open.pop();
+                    }
+                    if self.current_is(&["optgroup"]) {
+                        self.open.pop();
+                    }
+                }
+                "option" if self.current_is(&["option"]) => {
+                    self.open.pop();
+                }
+                "select" => {
+                    self.close_select();
+                }
+                _ => (),
+            },
+            Token::EndOfFile => self.in_body(token),
+        }
+    }
+
+    fn in_select_in_table(&mut self, token: Token) {
+        const TABLE_PARTS: &[&str] = &[
+            "caption", "table", "tbody", "tfoot", "thead", "tr", "td", "th",
+        ];
+
+        match token {
+            ref token if is_start_tag(token, TABLE_PARTS) => {
+                self.pop_until(&["select"]);
+                self.reset_mode();
+                self.process(token.clone());
+            }
+            Token::EndTag(tag) if TABLE_PARTS.contains(&tag.name.as_str()) => {
+                if self.in_scope(&[&tag.name], Scope::Table) {
+                    self.pop_until(&["select"]);
+                    self.reset_mode();
+                    self.process(Token::EndTag(tag));
+                }
+            }
+            token => self.in_select(token),
+        }
+    }
+
+    fn after_body(&mut self, token: Token) {
+        match token {
+            Token::Character(text) if is_whitespace_text(&text) => {
+                self.in_body(Token::Character(text))
+            }
+            Token::Comment(data) => {
+                let html = self.open[0];
+                self.append_comment(html, data);
+            }
+            Token::Doctype(_) => (),
+            Token::StartTag(tag) if tag.name == "html" => self.in_body(Token::StartTag(tag)),
+            Token::EndTag(tag) if tag.name == "html" => self.mode = Mode::AfterAfterBody,
+            Token::EndOfFile => (),
+            token => self.reprocess(Mode::InBody, token),
+        }
+    }
+
+    fn after_after_body(&mut self, token: Token) {
+        match token {
+            Token::Comment(data) => self.append_comment(ROOT, data),
+            Token::Doctype(_) => (),
+            Token::Character(text) if is_whitespace_text(&text) => {
+                self.in_body(Token::Character(text))
+            }
+            Token::StartTag(tag) if tag.name == "html" => self.in_body(Token::StartTag(tag)),
+            Token::EndOfFile => (),
+            token => self.reprocess(Mode::InBody, token),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tree_builder_tests {
+    use super::parse;
+
+    // Dumps the tree of `input`, checking it against `expected` with its common indentation
+    // removed.
+    fn assert_tree(input: &str, expected: &str) {
+        let lines: Vec<&str> = expected
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect();
+        let indent = lines
+            .iter()
+            .map(|line| line.len() - line.trim_start().len())
+            .min()
+            .unwrap_or(0);
+        let expected: String = lines
+            .iter()
+            .map(|line| format!("{}\n", &line[indent..]))
+            .collect();
+
+        assert_eq!(parse(input).to_string(), expected);
+    }
+
+    #[test]
+    fn adds_implied_html_head_and_body() {
+        assert_tree(
+            "Hello",
+            r#"
+            <html>
+              <head>
+              <body>
+                "Hello"
+            "#,
+        );
+    }
+
+    #[test]
+    fn reads_cdata_sections_only_in_svg_and_math() {
+        assert_tree(
+            "<svg><![CDATA[x<y]]><foreignObject><![CDATA[z]]></foreignObject></svg><![CDATA[w]]>",
+            r#"
+            <html>
+              <head>
+              <body>
+                <svg>
+                  "x<y"
+                  <foreignobject>
+                    <!-- [CDATA[z]] -->
+                <!-- [CDATA[w]] -->
+            "#,
+        );
+    }
+
+    #[test]
+    fn puts_head_elements_in_the_head() {
+        assert_tree(
+            "<!DOCTYPE html>\n<title>A &amp; B</title><script>if (a<b) {}</script>\n<p>Text",
+            r#"
+            <!DOCTYPE html>
+            <html>
+              <head>
+                <title>
+                  "A & B"
+                <script>
+                  "if (a<b) {}"
+                "
+            "
+              <body>
+                <p>
+                  "Text"
+            "#,
+        );
+    }
+
+    #[test]
+    fn closes_paragraphs_and_list_items() {
+        assert_tree(
+            "<p>One<p>Two<div>Three</div><ul><li>A<li>B</ul><dl><dt>C<dd>D</dl>",
+            r#"
+            <html>
+              <head>
+              <body>
+                <p>
+                  "One"
+                <p>
+                  "Two"
+                <div>
+                  "Three"
+                <ul>
+                  <li>
+                    "A"
+                  <li>
+                    "B"
+                <dl>
+                  <dt>
+                    "C"
+                  <dd>
+                    "D"
+            "#,
+        );
+    }
+
+    #[test]
+    fn closes_options_and_table_cells() {
+        assert_tree(
+            "<select><option>A<option selected>B</select><table><tr><td>1<td>2<tr><th>3</table>",
+            r#"
+            <html>
+              <head>
+              <body>
+                <select>
+                  <option>
+                    "A"
+                  <option selected="">
+                    "B"
+                <table>
+                  <tbody>
+                    <tr>
+                      <td>
+                        "1"
+                      <td>
+                        "2"
+                    <tr>
+                      <th>
+                        "3"
+            "#,
+        );
+    }
+
+    #[test]
+    fn never_opens_void_elements() {
+        assert_tree(
+            "<p>a<br>b<img src=x.png>c<input></input>d</br>e",
+            r#"
+            <html>
+              <head>
+              <body>
+                <p>
+                  "a"
+                  <br>
+                  "b"
+                  <img src="x.png">
+                  "c"
+                  <input>
+                  "d"
+                  <br>
+                  "e"
+            "#,
+        );
+    }
+
+    #[test]
+    fn repairs_misnested_formatting() {
+        assert_tree(
+            "<b>1<p>2</b>3</p><i>4<u>5</i>6</u>",
+            r#"
+            <html>
+              <head>
+              <body>
+                <b>
+                  "1"
+                <p>
+                  <b>
+                    "2"
+                  "3"
+                <i>
+                  "4"
+                  <u>
+                    "5"
+                <u>
+                  "6"
+            "#,
+        );
+    }
+
+    #[test]
+    fn reopens_formatting_after_blocks() {
+        assert_tree(
+            "<p><em>a<p>b</em>c<a href=1>d<a href=2>e",
+            r#"
+            <html>
+              <head>
+              <body>
+                <p>
+                  <em>
+                    "a"
+                <p>
+                  <em>
+                    "b"
+                  "c"
+                  <a href="1">
+                    "d"
+                  <a href="2">
+                    "e"
+            "#,
+        );
+    }
+
+    #[test]
+    fn moves_misplaced_content_out_of_tables() {
+        assert_tree(
+            "<table>a<tr><td>b</td></tr><p>c</table>",
+            r#"
+            <html>
+              <head>
+              <body>
+                "a"
+                <p>
+                  "c"
+                <table>
+                  <tbody>
+                    <tr>
+                      <td>
+                        "b"
+            "#,
+        );
+    }
+
+    #[test]
+    fn keeps_comments_and_text_around_the_body() {
+        assert_tree(
+            "<!--first--><html><body>x</body><!--after body--></html>\n<!--last-->",
+            r#"
+            <!-- first -->
+            <html>
+              <head>
+              <body>
+                "x
+            "
+              <!-- after body -->
+            <!-- last -->
+            "#,
+        );
+    }
+}
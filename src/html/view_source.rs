@@ -0,0 +1,429 @@
+//! Shows the source of a page exactly as it was received, with line numbers and its markup
+//! highlighted from the tokens the tokenizer finds.
+
+use super::entities;
+use super::tokenizer::{Token, Tokenizer};
+
+use std::io::{self, Write};
+use std::ops::Range;
+
+/// How source is written out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Plain,
+    // Colored with ANSI escape sequences, for terminals.
+    Ansi,
+    // A standalone HTML document.
+    Html,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Class {
+    Tag,
+    AttributeName,
+    AttributeValue,
+    Comment,
+    Doctype,
+    Entity,
+}
+
+impl Class {
+    fn ansi(self) -> &'static str {
+        match self {
+            Class::Tag => "\x1b[35m",
+            Class::AttributeName => "\x1b[36m",
+            Class::AttributeValue => "\x1b[34m",
+            Class::Comment => "\x1b[32m",
+            Class::Doctype => "\x1b[90m",
+            Class::Entity => "\x1b[33m",
+        }
+    }
+
+    fn css(self) -> &'static str {
+        match self {
+            Class::Tag => "tag",
+            Class::AttributeName => "attribute-name",
+            Class::AttributeValue => "attribute-value",
+            Class::Comment => "comment",
+            Class::Doctype => "doctype",
+            Class::Entity => "entity",
+        }
+    }
+}
+
+const ANSI_RESET: &str = "\x1b[0m";
+const ANSI_DIM: &str = "\x1b[2m";
+
+const HTML_HEADER: &str = "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>Source</title>
+<style>
+.line-number { color: #999; user-select: none; }
+.tag { color: #881280; }
+.attribute-name { color: #994500; }
+.attribute-value { color: #1a1aa6; }
+.comment { color: #236e25; }
+.doctype { color: #999; }
+.entity { color: #c4550c; }
+</style>
+</head>
+<body>
+<pre>
+";
+
+const HTML_FOOTER: &str = "</pre>
+</body>
+</html>
+";
+
+// Classes for the parts of a start or end tag, from `<` to `>`.
+fn tag_segments(
+    source: &[char],
+    span: Range<usize>,
+    segments: &mut Vec<(Option<Class>, Range<usize>)>,
+) {
+    let end = span.end;
+    let mut position = span.start;
+
+    // `<` or `</` and the name.
+    let name_end = (position + 1..end)
+        .find(|&index| {
+            index > position + 1
+                && matches!(source[index], '\t' | '\n' | '\r' | '\x0C' | ' ' | '/' | '>')
+        })
+        .unwrap_or(end);
+    segments.push((Some(Class::Tag), position..name_end));
+    position = name_end;
+
+    while position < end {
+        let character = source[position];
+
+        if character.is_whitespace() {
+            let next = (position..end)
+                .find(|&index| !source[index].is_whitespace())
+                .unwrap_or(end);
+            segments.push((None, position..next));
+            position = next;
+        } else if character == '/' || character == '>' {
+            segments.push((Some(Class::Tag), position..position + 1));
+            position += 1;
+        } else if character == '=' {
+            segments.push((None, position..position + 1));
+            position += 1;
+
+            let value_start = (position..end)
+                .find(|&index| !source[index].is_whitespace())
+                .unwrap_or(end);
+            segments.push((None, position..value_start));
+
+            let value_end = match source.get(value_start) {
+                Some(&quote @ ('"' | '\'')) => (value_start + 1..end)
+                    .find(|&index| source[index] == quote)
+                    .map_or(end, |index| index + 1),
+                _ => (value_start..end)
+                    .find(|&index| source[index].is_whitespace() || source[index] == '>')
+                    .unwrap_or(end),
+            };
+            text_segments(
+                source,
+                value_start..value_end,
+                Some(Class::AttributeValue),
+                segments,
+            );
+            position = value_end;
+        } else {
+            // An attribute name, which may start with `=` or contain quotes.
+            let name_end = (position + 1..end)
+                .find(|&index| {
+                    source[index].is_whitespace() || matches!(source[index], '/' | '>' | '=')
+                })
+                .unwrap_or(end);
+            segments.push((Some(Class::AttributeName), position..name_end));
+            position = name_end;
+        }
+    }
+}
+
+// The length of the character reference at the start of `input`, after its `&`.
+fn reference_length(input: &[char]) -> Option<usize> {
+    match input.first() {
+        Some('#') => {
+            let hexadecimal = matches!(input.get(1), Some('x' | 'X'));
+            let digits_start = if hexadecimal { 2 } else { 1 };
+            let radix = if hexadecimal { 16 } else { 10 };
+
+            let digits = input[digits_start.min(input.len())..]
+                .iter()
+                .take_while(|character| character.is_digit(radix))
+                .count();
+
+            if digits == 0 {
+                return None;
+            }
+
+            let length = digits_start + digits;
+            Some(length + usize::from(input.get(length) == Some(&';')))
+        }
+        _ => entities::longest_match(input).map(|(length, _, _)| length),
+    }
+}
+
+// Splits text into runs of `class` and the character references in it.
+fn text_segments(
+    source: &[char],
+    span: Range<usize>,
+    class: Option<Class>,
+    segments: &mut Vec<(Option<Class>, Range<usize>)>,
+) {
+    let mut start = span.start;
+    let mut position = span.start;
+
+    while position < span.end {
+        if source[position] != '&' {
+            position += 1;
+            continue;
+        }
+
+        let rest = &source[position + 1..span.end];
+        match reference_length(rest) {
+            Some(length) => {
+                segments.push((class, start..position));
+                segments.push((Some(Class::Entity), position..position + 1 + length));
+                position += 1 + length;
+                start = position;
+            }
+            None => position += 1,
+        }
+    }
+
+    segments.push((class, start..span.end));
+}
+
+// The source split into runs, each with how it should be highlighted.
+fn segments(source: &[char], input: &str) -> Vec<(Option<Class>, Range<usize>)> {
+    let mut segments = Vec::new();
+    let mut tokenizer = Tokenizer::new(input);
+    let mut covered = 0;
+    // Text in e.g. `<script>` has no character references.
+    let mut in_raw_text = false;
+
+    while let Some((token, span)) = tokenizer.next_with_span() {
+        // Anything the tokenizer dropped shows up as it is.
+        if span.start > covered {
+            segments.push((None, covered..span.start));
+        }
+
+        match token {
+            Token::StartTag(tag) => {
+                in_raw_text = matches!(
+                    tag.name.as_str(),
+                    "script" | "style" | "xmp" | "iframe" | "noembed" | "noframes" | "plaintext"
+                );
+                tag_segments(source, span.clone(), &mut segments);
+            }
+            Token::EndTag(_) => {
+                in_raw_text = false;
+                tag_segments(source, span.clone(), &mut segments);
+            }
+            Token::Character(_) if in_raw_text => segments.push((None, span.clone())),
+            Token::Character(_) => text_segments(source, span.clone(), None, &mut segments),
+            Token::Comment(_) => segments.push((Some(Class::Comment), span.clone())),
+            Token::Doctype(_) => segments.push((Some(Class::Doctype), span.clone())),
+            Token::EndOfFile => (),
+        }
+
+        covered = covered.max(span.end);
+    }
+
+    if covered < source.len() {
+        segments.push((None, covered..source.len()));
+    }
+
+    segments
+}
+
+// Writes lines with their numbers, numbering each line only once something is on it so a
+// final newline doesn't make an empty last line.
+struct Painter<'a, W: Write> {
+    output: &'a mut W,
+    format: Format,
+    width: usize,
+    line: usize,
+    at_line_start: bool,
+}
+
+impl<W: Write> Painter<'_, W> {
+    fn start_line(&mut self) -> io::Result<()> {
+        if !self.at_line_start {
+            return Ok(());
+        }
+
+        self.at_line_start = false;
+        self.line += 1;
+        let (line, width) = (self.line, self.width);
+
+        match self.format {
+            Format::Plain => write!(self.output, "{line:>width$}  "),
+            Format::Ansi => write!(self.output, "{ANSI_DIM}{line:>width$}{ANSI_RESET}  "),
+            Format::Html => write!(
+                self.output,
+                "<span class=\"line-number\">{line:>width$}</span>  "
+            ),
+        }
+    }
+
+    // Writes part of a line.
+    fn write_run(&mut self, class: Option<Class>, text: &str) -> io::Result<()> {
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        self.start_line()?;
+
+        match (self.format, class) {
+            (Format::Ansi, Some(class)) => {
+                write!(self.output, "{}{text}{ANSI_RESET}", class.ansi())
+            }
+            (Format::Html, Some(class)) => write!(
+                self.output,
+                "<span class=\"{}\">{}</span>",
+                class.css(),
+                escape(text)
+            ),
+            (Format::Html, None) => self.output.write_all(escape(text).as_bytes()),
+            _ => self.output.write_all(text.as_bytes()),
+        }
+    }
+
+    fn write(&mut self, class: Option<Class>, text: &str) -> io::Result<()> {
+        let mut lines = text.split('\n');
+
+        if let Some(first) = lines.next() {
+            self.write_run(class, first)?;
+        }
+
+        // Colors are closed at the end of each line and reopened after the next number.
+        for line in lines {
+            self.start_line()?;
+            self.output.write_all(b"\n")?;
+            self.at_line_start = true;
+            self.write_run(class, line)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Writes `source` line by line with line numbers, highlighting its tags, attributes,
+/// comments, doctype and character references unless `format` is `Format::Plain`.
+pub fn render(output: &mut impl Write, source: &str, format: Format) -> io::Result<()> {
+    let characters: Vec<char> = source.chars().collect();
+    let lines = source.split_terminator('\n').count();
+
+    if format == Format::Html {
+        output.write_all(HTML_HEADER.as_bytes())?;
+    }
+
+    let mut painter = Painter {
+        output,
+        format,
+        width: lines.max(1).to_string().len(),
+        line: 0,
+        at_line_start: true,
+    };
+
+    // Neighbouring runs of the same class are written as one.
+    let mut run: (Option<Class>, String) = (None, String::new());
+
+    for (class, span) in segments(&characters, source) {
+        if class != run.0 {
+            painter.write(run.0, &run.1)?;
+            run = (class, String::new());
+        }
+        run.1.extend(&characters[span]);
+    }
+    painter.write(run.0, &run.1)?;
+
+    // The last line gets a newline even if the source didn't end with one.
+    if !painter.at_line_start {
+        painter.output.write_all(b"\n")?;
+    }
+
+    if format == Format::Html {
+        painter.output.write_all(HTML_FOOTER.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod view_source_tests {
+    use super::{render, Format};
+
+    fn view(source: &str, format: Format) -> String {
+        let mut output = Vec::new();
+        render(&mut output, source, format).unwrap();
+
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn shows_source_exactly_with_line_numbers() {
+        let source = "<p title=\"&amp;lt;\">&amp;lt; &bogus; </>\r\n<!-- x -->\n<div";
+
+        assert_eq!(
+            view(source, Format::Plain),
+            "1  <p title=\"&amp;lt;\">&amp;lt; &bogus; </>\r\n2  <!-- x -->\n3  <div\n"
+        );
+        assert_eq!(view("", Format::Plain), "");
+
+        let lines: String = (1..=10).map(|line| format!("{line}\n")).collect();
+        assert!(view(&lines, Format::Plain).starts_with(" 1  1\n 2  2\n"));
+    }
+
+    #[test]
+    fn highlights_markup() {
+        assert_eq!(
+            view(
+                "<!DOCTYPE html>\n<a href='/?a&amp;b' hidden>x &lt; y</a><!-- a\nb -->",
+                Format::Ansi
+            ),
+            concat!(
+                "\x1b[2m1\x1b[0m  \x1b[90m<!DOCTYPE html>\x1b[0m\n",
+                "\x1b[2m2\x1b[0m  \x1b[35m<a\x1b[0m \x1b[36mhref\x1b[0m=",
+                "\x1b[34m'/?a\x1b[0m\x1b[33m&amp;\x1b[0m\x1b[34mb'\x1b[0m ",
+                "\x1b[36mhidden\x1b[0m\x1b[35m>\x1b[0mx \x1b[33m&lt;\x1b[0m y",
+                "\x1b[35m</a>\x1b[0m\x1b[32m<!-- a\x1b[0m\n",
+                "\x1b[2m3\x1b[0m  \x1b[32mb -->\x1b[0m\n",
+            )
+        );
+    }
+
+    #[test]
+    fn leaves_script_text_alone() {
+        assert_eq!(
+            view("<script>a &amp;& b</script>", Format::Ansi),
+            "\x1b[2m1\x1b[0m  \x1b[35m<script>\x1b[0ma &amp;& b\x1b[35m</script>\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn writes_html_documents() {
+        let html = view("<b>&amp;</b>", Format::Html);
+
+        assert!(html.starts_with("<!DOCTYPE html>\n"));
+        assert!(html.contains(
+            "<span class=\"line-number\">1</span>  <span class=\"tag\">&lt;b&gt;</span><span class=\"entity\">&amp;amp;</span><span class=\"tag\">&lt;/b&gt;</span>\n"
+        ));
+        assert!(html.ends_with("</pre>\n</body>\n</html>\n"));
+    }
+}
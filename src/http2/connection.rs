@@ -0,0 +1,906 @@
+use crate::header::HeaderMap;
+use crate::http2::frame::{self, protocol_error, Frame, FrameType};
+use crate::http2::hpack::{Decoder, Encoder};
+use crate::http2::{HTTP2Request, HTTP2Response, PREFACE};
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+// Receive window we advertise, for both the connection and each stream.
+const LOCAL_WINDOW_SIZE: u32 = 1 << 20;
+const DEFAULT_WINDOW_SIZE: u32 = 65_535;
+// Largest header block or decoded header list we accept, so a server can't exhaust memory
+// with an endless run of CONTINUATION frames.
+const MAX_HEADER_LIST_SIZE: u32 = 64 * 1024;
+
+struct StreamState {
+    request_index: usize,
+    status_code: Option<u16>,
+    headers: HeaderMap,
+    body: Vec<u8>,
+    // Request body not sent yet, waiting on flow control.
+    pending_body: Vec<u8>,
+    send_window: i64,
+    receive_window: i64,
+}
+
+/// One HTTP/2 connection. Requests given to `send_all` are multiplexed as concurrent streams.
+pub struct Connection<S: Read + Write> {
+    stream: S,
+    encoder: Encoder,
+    decoder: Decoder,
+    next_stream_id: u32,
+    // Peer settings, governing what we send.
+    max_frame_size: u32,
+    max_concurrent_streams: u32,
+    initial_window_size: i64,
+    send_window: i64,
+    receive_window: i64,
+    // Last stream the peer will process, once it sent GOAWAY.
+    go_away: Option<u32>,
+}
+
+fn copy_error(error: &io::Error) -> io::Error {
+    io::Error::new(error.kind(), error.to_string())
+}
+
+impl<S: Read + Write> Connection<S> {
+    /// Sends the connection preface and our SETTINGS, then waits for the server's SETTINGS.
+    pub fn handshake(stream: S) -> io::Result<Connection<S>> {
+        let mut connection = Connection {
+            stream,
+            encoder: Encoder::new(),
+            decoder: Decoder::default(),
+            next_stream_id: 1,
+            max_frame_size: frame::DEFAULT_MAX_FRAME_SIZE,
+            max_concurrent_streams: u32::MAX,
+            initial_window_size: DEFAULT_WINDOW_SIZE as i64,
+            send_window: DEFAULT_WINDOW_SIZE as i64,
+            receive_window: LOCAL_WINDOW_SIZE as i64,
+            go_away: None,
+        };
+
+        connection.stream.write_all(PREFACE)?;
+        connection.write_frame(Frame::settings(&[
+            (frame::SETTINGS_ENABLE_PUSH, 0),
+            (frame::SETTINGS_INITIAL_WINDOW_SIZE, LOCAL_WINDOW_SIZE),
+            (frame::SETTINGS_MAX_HEADER_LIST_SIZE, MAX_HEADER_LIST_SIZE),
+        ]))?;
+        // The connection window can only be raised with WINDOW_UPDATE.
+        connection.write_frame(Frame::window_update(
+            0,
+            LOCAL_WINDOW_SIZE - DEFAULT_WINDOW_SIZE,
+        ))?;
+
+        // The server preface must be a SETTINGS frame (section 3.4).
+        let settings = connection.read_frame()?;
+        if settings.kind != FrameType::Settings || settings.has_flag(frame::ACK) {
+            return Err(protocol_error("server preface isn't SETTINGS"));
+        }
+        connection.apply_settings(&settings, &mut HashMap::new())?;
+
+        Ok(connection)
+    }
+
+    pub fn max_concurrent_streams(&self) -> u32 {
+        self.max_concurrent_streams
+    }
+
+    fn write_frame(&mut self, frame: Frame) -> io::Result<()> {
+        frame.write_to(&mut self.stream)?;
+        self.stream.flush()
+    }
+
+    fn read_frame(&mut self) -> io::Result<Frame> {
+        Frame::read_from(&mut self.stream, frame::DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    fn apply_settings(
+        &mut self,
+        settings: &Frame,
+        streams: &mut HashMap<u32, StreamState>,
+    ) -> io::Result<()> {
+        if settings.stream_id != 0 {
+            return Err(protocol_error("SETTINGS on a stream"));
+        }
+
+        if settings.has_flag(frame::ACK) {
+            return Ok(());
+        }
+
+        for (identifier, value) in settings.settings_parameters()? {
+            match identifier {
+                frame::SETTINGS_MAX_CONCURRENT_STREAMS => self.max_concurrent_streams = value,
+                frame::SETTINGS_INITIAL_WINDOW_SIZE => {
+                    if value > frame::MAX_WINDOW_SIZE {
+                        return Err(protocol_error("initial window size too large"));
+                    }
+
+                    // Applies retroactively to every open stream (section 6.9.2).
+                    let delta = value as i64 - self.initial_window_size;
+                    for state in streams.values_mut() {
+                        state.send_window += delta;
+                    }
+                    self.initial_window_size = value as i64;
+                }
+                frame::SETTINGS_MAX_FRAME_SIZE => {
+                    if !(frame::DEFAULT_MAX_FRAME_SIZE..=frame::MAX_MAX_FRAME_SIZE).contains(&value)
+                    {
+                        return Err(protocol_error("invalid max frame size"));
+                    }
+                    self.max_frame_size = value;
+                }
+                // We never index into the peer's table, and push is off on our side.
+                _ => (),
+            }
+        }
+
+        self.write_frame(Frame::new(FrameType::Settings, frame::ACK, 0, Vec::new()))
+    }
+
+    fn open_stream(&mut self, request: &HTTP2Request) -> io::Result<u32> {
+        let stream_id = self.next_stream_id;
+        self.next_stream_id += 2;
+
+        let headers = request.header_list();
+        let block = self.encoder.encode(
+            headers
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str())),
+        );
+
+        let mut chunks = block.chunks(self.max_frame_size as usize);
+        let first = chunks.next().unwrap_or_default();
+        let rest: Vec<&[u8]> = chunks.collect();
+
+        let mut flags = 0;
+        if request.body.is_empty() {
+            flags |= frame::END_STREAM;
+        }
+        if rest.is_empty() {
+            flags |= frame::END_HEADERS;
+        }
+
+        self.write_frame(Frame::new(
+            FrameType::Headers,
+            flags,
+            stream_id,
+            first.to_vec(),
+        ))?;
+
+        for (index, chunk) in rest.iter().enumerate() {
+            let flags = if index == rest.len() - 1 {
+                frame::END_HEADERS
+            } else {
+                0
+            };
+
+            self.write_frame(Frame::new(
+                FrameType::Continuation,
+                flags,
+                stream_id,
+                chunk.to_vec(),
+            ))?;
+        }
+
+        log::debug!(
+            "HTTP/2 stream {stream_id}: {} {}",
+            request.method,
+            request.path
+        );
+
+        Ok(stream_id)
+    }
+
+    // Sends as much pending request body as the flow-control windows allow.
+    fn send_pending_data(&mut self, streams: &mut HashMap<u32, StreamState>) -> io::Result<()> {
+        let mut stream_ids: Vec<u32> = streams.keys().copied().collect();
+        stream_ids.sort();
+
+        for stream_id in stream_ids {
+            let state = streams.get_mut(&stream_id).unwrap();
+
+            while !state.pending_body.is_empty() {
+                let allowed = state
+                    .send_window
+                    .min(self.send_window)
+                    .min(self.max_frame_size as i64);
+
+                if allowed <= 0 {
+                    break;
+                }
+
+                let length = state.pending_body.len().min(allowed as usize);
+                let chunk: Vec<u8> = state.pending_body.drain(..length).collect();
+                let flags = if state.pending_body.is_empty() {
+                    frame::END_STREAM
+                } else {
+                    0
+                };
+
+                state.send_window -= length as i64;
+                self.send_window -= length as i64;
+                Frame::new(FrameType::Data, flags, stream_id, chunk).write_to(&mut self.stream)?;
+            }
+        }
+
+        self.stream.flush()
+    }
+
+    // Reads CONTINUATION frames until the header block is complete.
+    fn read_header_block(&mut self, headers: Frame) -> io::Result<Vec<u8>> {
+        let mut block = headers.content()?.to_vec();
+        let mut end_headers = headers.has_flag(frame::END_HEADERS);
+
+        loop {
+            if block.len() > MAX_HEADER_LIST_SIZE as usize {
+                self.write_frame(Frame::go_away(0, frame::PROTOCOL_ERROR))?;
+                return Err(protocol_error("header block too large"));
+            }
+            if end_headers {
+                return Ok(block);
+            }
+
+            let continuation = self.read_frame()?;
+
+            if continuation.kind != FrameType::Continuation
+                || continuation.stream_id != headers.stream_id
+            {
+                return Err(protocol_error("expected CONTINUATION"));
+            }
+
+            block.extend_from_slice(&continuation.payload);
+            end_headers = continuation.has_flag(frame::END_HEADERS);
+        }
+    }
+
+    fn receive_headers(state: &mut StreamState, fields: Vec<(String, String)>) -> io::Result<()> {
+        // A block after the final response headers carries trailers.
+        let is_trailer = state.status_code.is_some_and(|status| status >= 200);
+
+        for (name, value) in fields {
+            if name == ":status" {
+                if is_trailer {
+                    return Err(protocol_error(":status in trailers"));
+                }
+
+                let status_code = value
+                    .parse()
+                    .map_err(|_| protocol_error("invalid :status"))?;
+                state.status_code = Some(status_code);
+            } else if name.starts_with(':') {
+                return Err(protocol_error("unexpected pseudo-header"));
+            } else {
+                state.headers.append(&name, &value)?;
+            }
+        }
+
+        match state.status_code {
+            None => Err(protocol_error("response without :status")),
+            // Informational responses are dropped, the final one follows.
+            Some(status) if status < 200 => {
+                state.status_code = None;
+                state.headers = HeaderMap::new();
+                Ok(())
+            }
+            Some(_) => Ok(()),
+        }
+    }
+
+    // Handles one frame, returning the streams it finished along with their outcome.
+    fn handle_frame(
+        &mut self,
+        frame: Frame,
+        streams: &mut HashMap<u32, StreamState>,
+    ) -> io::Result<Vec<(u32, io::Result<()>)>> {
+        let mut finished = Vec::new();
+
+        match frame.kind {
+            FrameType::Data => {
+                let length = frame.payload.len() as i64;
+
+                self.receive_window -= length;
+                if self.receive_window < 0 {
+                    return Err(protocol_error("peer overran the connection window"));
+                }
+
+                // Give the window straight back as the body is buffered.
+                if length > 0 {
+                    self.write_frame(Frame::window_update(0, length as u32))?;
+                    self.receive_window += length;
+                }
+
+                if let Some(state) = streams.get_mut(&frame.stream_id) {
+                    state.receive_window -= length;
+                    if state.receive_window < 0 {
+                        return Err(protocol_error("peer overran a stream window"));
+                    }
+
+                    if state.status_code.is_none() {
+                        return Err(protocol_error("DATA before HEADERS"));
+                    }
+
+                    state.body.extend_from_slice(frame.content()?);
+
+                    if frame.has_flag(frame::END_STREAM) {
+                        finished.push((frame.stream_id, Ok(())));
+                    } else if length > 0 {
+                        self.write_frame(Frame::window_update(frame.stream_id, length as u32))?;
+                        state.receive_window += length;
+                    }
+                }
+            }
+            FrameType::Headers => {
+                let stream_id = frame.stream_id;
+                let end_stream = frame.has_flag(frame::END_STREAM);
+
+                let block = self.read_header_block(frame)?;
+                // Decode even for unknown streams to keep the HPACK table in sync.
+                let fields = match self
+                    .decoder
+                    .decode_limited(&block, MAX_HEADER_LIST_SIZE as usize)
+                {
+                    Ok(Some(fields)) => fields,
+                    Ok(None) => {
+                        self.write_frame(Frame::go_away(0, frame::PROTOCOL_ERROR))?;
+                        return Err(protocol_error("header list too large"));
+                    }
+                    Err(e) => {
+                        self.write_frame(Frame::go_away(0, frame::COMPRESSION_ERROR))?;
+                        return Err(e);
+                    }
+                };
+
+                if let Some(state) = streams.get_mut(&stream_id) {
+                    match Self::receive_headers(state, fields) {
+                        Err(e) => {
+                            self.write_frame(Frame::rst_stream(stream_id, frame::PROTOCOL_ERROR))?;
+                            finished.push((stream_id, Err(e)));
+                        }
+                        Ok(()) if end_stream => finished.push((stream_id, Ok(()))),
+                        Ok(()) => (),
+                    }
+                }
+            }
+            FrameType::Settings => self.apply_settings(&frame, streams)?,
+            FrameType::Ping => {
+                if frame.payload.len() != 8 {
+                    return Err(protocol_error("PING must be 8 octets"));
+                }
+
+                if !frame.has_flag(frame::ACK) {
+                    self.write_frame(Frame::new(FrameType::Ping, frame::ACK, 0, frame.payload))?;
+                }
+            }
+            FrameType::WindowUpdate => {
+                let increment = frame.window_increment()? as i64;
+
+                if frame.stream_id == 0 {
+                    if increment == 0 {
+                        return Err(protocol_error("zero WINDOW_UPDATE"));
+                    }
+
+                    self.send_window += increment;
+                    if self.send_window > frame::MAX_WINDOW_SIZE as i64 {
+                        return Err(protocol_error("connection window overflow"));
+                    }
+                } else if let Some(state) = streams.get_mut(&frame.stream_id) {
+                    state.send_window += increment;
+
+                    if increment == 0 || state.send_window > frame::MAX_WINDOW_SIZE as i64 {
+                        self.write_frame(Frame::rst_stream(
+                            frame.stream_id,
+                            frame::FLOW_CONTROL_ERROR,
+                        ))?;
+                        finished.push((
+                            frame.stream_id,
+                            Err(protocol_error("invalid stream WINDOW_UPDATE")),
+                        ));
+                    }
+                }
+            }
+            FrameType::RstStream => {
+                let error_code = frame.error_code()?;
+
+                // REFUSED_STREAM guarantees nothing was processed, so it's safe to retry.
+                let kind = if error_code == frame::REFUSED_STREAM {
+                    io::ErrorKind::ConnectionRefused
+                } else {
+                    io::ErrorKind::ConnectionReset
+                };
+
+                finished.push((
+                    frame.stream_id,
+                    Err(io::Error::new(
+                        kind,
+                        format!(
+                            "HTTP/2 stream {} reset with error code {error_code:#x}",
+                            frame.stream_id
+                        ),
+                    )),
+                ));
+            }
+            FrameType::GoAway => {
+                let last_stream_id = frame.last_stream_id()?;
+                let error_code = frame.error_code()?;
+
+                log::debug!("HTTP/2 GOAWAY: last stream {last_stream_id}, error {error_code:#x}");
+                self.go_away = Some(last_stream_id);
+
+                // Streams above the last one were never processed and may be retried.
+                for &stream_id in streams.keys() {
+                    if stream_id > last_stream_id {
+                        finished.push((
+                            stream_id,
+                            Err(io::Error::new(
+                                io::ErrorKind::ConnectionAborted,
+                                "HTTP/2 stream refused by GOAWAY",
+                            )),
+                        ));
+                    }
+                }
+            }
+            FrameType::PushPromise => {
+                self.write_frame(Frame::go_away(0, frame::PROTOCOL_ERROR))?;
+                return Err(protocol_error("PUSH_PROMISE although push is disabled"));
+            }
+            FrameType::Continuation => {
+                return Err(protocol_error("unexpected CONTINUATION"));
+            }
+            FrameType::Priority | FrameType::Unknown(_) => (),
+        }
+
+        Ok(finished)
+    }
+
+    /// Sends every request, keeping up to SETTINGS_MAX_CONCURRENT_STREAMS of them in flight,
+    /// and returns the responses in request order.
+    pub fn send_all(&mut self, requests: &[HTTP2Request]) -> Vec<io::Result<HTTP2Response>> {
+        let mut results: Vec<Option<io::Result<HTTP2Response>>> = std::iter::repeat_with(|| None)
+            .take(requests.len())
+            .collect();
+        let mut streams: HashMap<u32, StreamState> = HashMap::new();
+        let mut next_request = 0;
+
+        if let Err(e) = self.run(requests, &mut results, &mut streams, &mut next_request) {
+            for state in streams.values() {
+                results[state.request_index] = Some(Err(copy_error(&e)));
+            }
+            for result in results.iter_mut().skip(next_request) {
+                *result = Some(Err(copy_error(&e)));
+            }
+        }
+
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| Err(protocol_error("stream never completed"))))
+            .collect()
+    }
+
+    fn run(
+        &mut self,
+        requests: &[HTTP2Request],
+        results: &mut [Option<io::Result<HTTP2Response>>],
+        streams: &mut HashMap<u32, StreamState>,
+        next_request: &mut usize,
+    ) -> io::Result<()> {
+        loop {
+            if self.go_away.is_none() {
+                while *next_request < requests.len()
+                    && (streams.len() as u32) < self.max_concurrent_streams
+                {
+                    let request = &requests[*next_request];
+                    let stream_id = self.open_stream(request)?;
+
+                    streams.insert(
+                        stream_id,
+                        StreamState {
+                            request_index: *next_request,
+                            status_code: None,
+                            headers: HeaderMap::new(),
+                            body: Vec::new(),
+                            pending_body: request.body.clone(),
+                            send_window: self.initial_window_size,
+                            receive_window: LOCAL_WINDOW_SIZE as i64,
+                        },
+                    );
+                    *next_request += 1;
+                }
+            }
+
+            self.send_pending_data(streams)?;
+
+            if streams.is_empty() {
+                if *next_request < requests.len() {
+                    // The server went away before we could open the remaining streams.
+                    for result in results.iter_mut().skip(*next_request) {
+                        *result = Some(Err(io::Error::new(
+                            io::ErrorKind::ConnectionAborted,
+                            "HTTP/2 connection closed by GOAWAY",
+                        )));
+                    }
+                    *next_request = requests.len();
+                }
+
+                return Ok(());
+            }
+
+            let frame = self.read_frame()?;
+
+            for (stream_id, outcome) in self.handle_frame(frame, streams)? {
+                let state = match streams.remove(&stream_id) {
+                    Some(state) => state,
+                    None => continue,
+                };
+
+                results[state.request_index] = Some(outcome.map(|()| HTTP2Response {
+                    status_code: state.status_code.unwrap_or_default(),
+                    headers: state.headers,
+                    body: state.body,
+                }));
+            }
+        }
+    }
+
+    /// Politely closes the connection.
+    pub fn close(mut self) -> io::Result<S> {
+        // We never accept server-initiated streams, so the last one processed is 0.
+        self.write_frame(Frame::go_away(0, frame::NO_ERROR))?;
+
+        Ok(self.stream)
+    }
+}
+
+#[cfg(test)]
+mod connection_tests {
+    use super::Connection;
+    use crate::header::HeaderMap;
+    use crate::http2::frame::{self, Frame, FrameType};
+    use crate::http2::hpack::{Decoder, Encoder};
+    use crate::http2::{HTTP2Request, PREFACE};
+    use std::collections::HashMap;
+    use std::io::{self, Read};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+
+    // Just enough of an HTTP/2 server to drive the client: it honours the client's flow
+    // control windows and answers ACKs itself.
+    struct StandIn {
+        stream: TcpStream,
+        decoder: Decoder,
+        encoder: Encoder,
+        send_window: i64,
+        initial_window_size: i64,
+        stream_windows: HashMap<u32, i64>,
+        pings_acknowledged: u32,
+    }
+
+    impl StandIn {
+        fn accept(listener: TcpListener, settings: &[(u16, u32)]) -> StandIn {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            let mut preface = [0u8; 24];
+            stream.read_exact(&mut preface).unwrap();
+            assert_eq!(preface, PREFACE);
+
+            Frame::settings(settings).write_to(&mut stream).unwrap();
+
+            StandIn {
+                stream,
+                decoder: Decoder::default(),
+                encoder: Encoder::new(),
+                send_window: 65_535,
+                initial_window_size: 65_535,
+                stream_windows: HashMap::new(),
+                pings_acknowledged: 0,
+            }
+        }
+
+        // Next frame that isn't SETTINGS, WINDOW_UPDATE or a PING ACK.
+        fn next_frame(&mut self) -> Frame {
+            loop {
+                if let Some(frame) = self.next_frame_or_control() {
+                    return frame;
+                }
+            }
+        }
+
+        // Handles one control frame, or returns any other frame.
+        fn next_frame_or_control(&mut self) -> Option<Frame> {
+            let frame = Frame::read_from(&mut self.stream, 1 << 20).unwrap();
+
+            match frame.kind {
+                FrameType::Settings if !frame.has_flag(frame::ACK) => {
+                    for (identifier, value) in frame.settings_parameters().unwrap() {
+                        if identifier == frame::SETTINGS_INITIAL_WINDOW_SIZE {
+                            self.initial_window_size = value as i64;
+                        }
+                    }
+                    Frame::new(FrameType::Settings, frame::ACK, 0, Vec::new())
+                        .write_to(&mut self.stream)
+                        .unwrap();
+                }
+                FrameType::Settings => (),
+                FrameType::WindowUpdate => {
+                    let increment = frame.window_increment().unwrap() as i64;
+                    match frame.stream_id {
+                        0 => self.send_window += increment,
+                        id => *self.stream_windows.entry(id).or_insert(0) += increment,
+                    }
+                }
+                FrameType::Ping if frame.has_flag(frame::ACK) => self.pings_acknowledged += 1,
+                _ => return Some(frame),
+            }
+
+            None
+        }
+
+        fn next_request(&mut self) -> (u32, Vec<(String, String)>) {
+            let frame = self.next_frame();
+            assert_eq!(frame.kind, FrameType::Headers);
+            assert!(frame.has_flag(frame::END_HEADERS));
+
+            let headers = self.decoder.decode(frame.content().unwrap()).unwrap();
+            self.stream_windows
+                .entry(frame.stream_id)
+                .or_insert(self.initial_window_size);
+
+            (frame.stream_id, headers)
+        }
+
+        fn respond_headers(&mut self, stream_id: u32, status: &str, end_stream: bool) {
+            let block = self
+                .encoder
+                .encode([(":status", status), ("content-type", "text/plain")]);
+            let flags = frame::END_HEADERS | if end_stream { frame::END_STREAM } else { 0 };
+
+            Frame::new(FrameType::Headers, flags, stream_id, block)
+                .write_to(&mut self.stream)
+                .unwrap();
+        }
+
+        fn respond_data(&mut self, stream_id: u32, mut body: &[u8]) {
+            loop {
+                let window = self.send_window.min(self.stream_windows[&stream_id]);
+
+                if window <= 0 && !body.is_empty() {
+                    // Blocked until the client opens its windows again.
+                    if let Some(frame) = self.next_frame_or_control() {
+                        panic!("Unexpected frame while blocked: {frame:?}");
+                    }
+                    continue;
+                }
+
+                let length = body.len().min(window.max(0) as usize).min(16_384);
+                let (chunk, rest) = body.split_at(length);
+                let flags = if rest.is_empty() {
+                    frame::END_STREAM
+                } else {
+                    0
+                };
+
+                Frame::new(FrameType::Data, flags, stream_id, chunk.to_vec())
+                    .write_to(&mut self.stream)
+                    .unwrap();
+                self.send_window -= length as i64;
+                *self.stream_windows.get_mut(&stream_id).unwrap() -= length as i64;
+
+                body = rest;
+                if body.is_empty() {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn get(path: &str) -> HTTP2Request {
+        let mut headers = HeaderMap::new();
+        headers.insert("Host", "localhost").unwrap();
+        headers.insert("Connection", "close").unwrap();
+        headers.insert("User-Agent", "Bored Browser").unwrap();
+
+        HTTP2Request {
+            method: String::from("GET"),
+            scheme: String::from("https"),
+            authority: String::from("localhost"),
+            path: String::from(path),
+            headers,
+            body: Vec::new(),
+        }
+    }
+
+    fn start(settings: &'static [(u16, u32)], serve: fn(StandIn)) -> (TcpStream, JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || serve(StandIn::accept(listener, settings)));
+
+        let client = TcpStream::connect(address).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+
+        (client, server)
+    }
+
+    #[test]
+    fn multiplexes_requests_over_one_connection() {
+        let (client, server) = start(&[], |mut server| {
+            // All three requests arrive before any response is sent.
+            let requests: Vec<(u32, Vec<(String, String)>)> =
+                (0..3).map(|_| server.next_request()).collect();
+
+            for (_, headers) in &requests {
+                assert!(headers.contains(&(String::from(":authority"), String::from("localhost"))));
+                assert!(!headers
+                    .iter()
+                    .any(|(name, _)| name == "connection" || name == "host"));
+                assert!(
+                    headers.contains(&(String::from("user-agent"), String::from("Bored Browser")))
+                );
+            }
+
+            Frame::new(FrameType::Ping, 0, 0, vec![7; 8])
+                .write_to(&mut server.stream)
+                .unwrap();
+
+            // Answer in reverse order, interleaving the bodies.
+            for (stream_id, _) in requests.iter().rev() {
+                server.respond_headers(*stream_id, "200", false);
+            }
+            for (stream_id, headers) in requests.iter().rev() {
+                let path = &headers.iter().find(|(name, _)| name == ":path").unwrap().1;
+                server.respond_data(*stream_id, path.as_bytes());
+            }
+
+            let frame = server.next_frame();
+            assert_eq!(frame.kind, FrameType::GoAway, "{frame:?}");
+            assert_eq!(server.pings_acknowledged, 1);
+        });
+
+        let mut connection = Connection::handshake(client).unwrap();
+        let responses = connection.send_all(&[get("/"), get("/style.css"), get("/script.js")]);
+
+        let bodies: Vec<Vec<u8>> = responses
+            .into_iter()
+            .map(|response| {
+                let response = response.unwrap();
+                assert_eq!(response.status_code, 200);
+                assert_eq!(response.headers.get("Content-Type").unwrap(), "text/plain");
+                response.body
+            })
+            .collect();
+        assert_eq!(
+            bodies,
+            [
+                b"/".to_vec(),
+                b"/style.css".to_vec(),
+                b"/script.js".to_vec()
+            ]
+        );
+
+        connection.close().unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn honours_flow_control_and_concurrency_limits() {
+        const SETTINGS: &[(u16, u32)] = &[
+            (frame::SETTINGS_MAX_CONCURRENT_STREAMS, 1),
+            (frame::SETTINGS_INITIAL_WINDOW_SIZE, 10),
+        ];
+
+        let (client, server) = start(SETTINGS, |mut server| {
+            // The upload trickles in 10 octets at a time as we open the stream window.
+            let (stream_id, headers) = server.next_request();
+            assert!(headers.contains(&(String::from(":method"), String::from("POST"))));
+
+            let mut upload = Vec::new();
+            loop {
+                let frame = server.next_frame();
+                assert_eq!(frame.kind, FrameType::Data);
+                assert!(frame.payload.len() <= 10);
+                upload.extend_from_slice(&frame.payload);
+
+                if frame.has_flag(frame::END_STREAM) {
+                    break;
+                }
+                Frame::window_update(stream_id, 10)
+                    .write_to(&mut server.stream)
+                    .unwrap();
+            }
+            assert_eq!(upload, vec![b'x'; 35]);
+
+            // 2.5 MiB only fits through the client's 1 MiB windows if it keeps reopening them.
+            server.respond_headers(stream_id, "200", false);
+            server.respond_data(stream_id, &vec![b'y'; 5 << 19]);
+
+            // Only now, with the first stream closed, may the second one open.
+            let (second_stream_id, _) = server.next_request();
+            assert_eq!(second_stream_id, stream_id + 2);
+            server.respond_headers(second_stream_id, "204", true);
+        });
+
+        let mut upload = get("/upload");
+        upload.method = String::from("POST");
+        upload.body = vec![b'x'; 35];
+
+        let mut connection = Connection::handshake(client).unwrap();
+        assert_eq!(connection.max_concurrent_streams(), 1);
+
+        let responses = connection.send_all(&[upload, get("/next")]);
+
+        server.join().unwrap();
+        let mut responses = responses.into_iter();
+        assert_eq!(responses.next().unwrap().unwrap().body.len(), 5 << 19);
+        assert_eq!(responses.next().unwrap().unwrap().status_code, 204);
+    }
+
+    #[test]
+    fn go_away_fails_unprocessed_streams_as_retryable() {
+        let (client, server) = start(&[], |mut server| {
+            let (first, _) = server.next_request();
+            let (_second, _) = server.next_request();
+
+            Frame::go_away(first, frame::NO_ERROR)
+                .write_to(&mut server.stream)
+                .unwrap();
+            server.respond_headers(first, "200", false);
+            server.respond_data(first, b"done");
+        });
+
+        let mut connection = Connection::handshake(client).unwrap();
+        let mut responses = connection.send_all(&[get("/"), get("/late")]).into_iter();
+
+        server.join().unwrap();
+        assert_eq!(responses.next().unwrap().unwrap().body, b"done");
+        assert_eq!(
+            responses.next().unwrap().unwrap_err().kind(),
+            io::ErrorKind::ConnectionAborted
+        );
+    }
+
+    #[test]
+    fn rejects_endless_continuation_frames() {
+        let (client, server) = start(&[], |mut server| {
+            let (stream_id, _) = server.next_request();
+
+            Frame::new(FrameType::Headers, 0, stream_id, vec![0; 16_384])
+                .write_to(&mut server.stream)
+                .unwrap();
+            for _ in 0..4 {
+                Frame::new(FrameType::Continuation, 0, stream_id, vec![0; 16_384])
+                    .write_to(&mut server.stream)
+                    .unwrap();
+            }
+
+            let frame = server.next_frame();
+            assert_eq!(frame.kind, FrameType::GoAway, "{frame:?}");
+            assert_eq!(frame.error_code().unwrap(), frame::PROTOCOL_ERROR);
+        });
+
+        let mut connection = Connection::handshake(client).unwrap();
+        let error = connection.send_all(&[get("/")]).remove(0).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn rejects_servers_that_do_not_speak_http2() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut preface = [0u8; 24];
+            stream.read_exact(&mut preface).unwrap();
+            io::Write::write_all(&mut stream, b"HTTP/1.1 400 Bad Request\r\n\r\n").unwrap();
+        });
+
+        let client = TcpStream::connect(address).unwrap();
+        assert!(Connection::handshake(client).is_err());
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,315 @@
+use std::io::{self, Read, Write};
+
+pub const FRAME_HEADER_LENGTH: usize = 9;
+pub const DEFAULT_MAX_FRAME_SIZE: u32 = 16_384;
+pub const MAX_MAX_FRAME_SIZE: u32 = 16_777_215;
+pub const MAX_WINDOW_SIZE: u32 = 0x7fff_ffff;
+
+// Flags (RFC 9113 section 6).
+pub const END_STREAM: u8 = 0x1;
+pub const ACK: u8 = 0x1;
+pub const END_HEADERS: u8 = 0x4;
+pub const PADDED: u8 = 0x8;
+pub const PRIORITY: u8 = 0x20;
+
+// SETTINGS parameters (section 6.5.2).
+pub const SETTINGS_HEADER_TABLE_SIZE: u16 = 0x1;
+pub const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+pub const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+pub const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+pub const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+pub const SETTINGS_MAX_HEADER_LIST_SIZE: u16 = 0x6;
+
+// Error codes (section 7).
+pub const NO_ERROR: u32 = 0x0;
+pub const PROTOCOL_ERROR: u32 = 0x1;
+pub const FLOW_CONTROL_ERROR: u32 = 0x3;
+pub const FRAME_SIZE_ERROR: u32 = 0x6;
+pub const REFUSED_STREAM: u32 = 0x7;
+pub const COMPRESSION_ERROR: u32 = 0x9;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameType {
+    Data,
+    Headers,
+    Priority,
+    RstStream,
+    Settings,
+    PushPromise,
+    Ping,
+    GoAway,
+    WindowUpdate,
+    Continuation,
+    // Unknown frame types must be ignored (section 4.1).
+    Unknown(u8),
+}
+
+impl FrameType {
+    fn from_u8(value: u8) -> FrameType {
+        match value {
+            0x0 => FrameType::Data,
+            0x1 => FrameType::Headers,
+            0x2 => FrameType::Priority,
+            0x3 => FrameType::RstStream,
+            0x4 => FrameType::Settings,
+            0x5 => FrameType::PushPromise,
+            0x6 => FrameType::Ping,
+            0x7 => FrameType::GoAway,
+            0x8 => FrameType::WindowUpdate,
+            0x9 => FrameType::Continuation,
+            other => FrameType::Unknown(other),
+        }
+    }
+
+    fn as_u8(&self) -> u8 {
+        match self {
+            FrameType::Data => 0x0,
+            FrameType::Headers => 0x1,
+            FrameType::Priority => 0x2,
+            FrameType::RstStream => 0x3,
+            FrameType::Settings => 0x4,
+            FrameType::PushPromise => 0x5,
+            FrameType::Ping => 0x6,
+            FrameType::GoAway => 0x7,
+            FrameType::WindowUpdate => 0x8,
+            FrameType::Continuation => 0x9,
+            FrameType::Unknown(value) => *value,
+        }
+    }
+}
+
+pub fn protocol_error(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("HTTP/2 protocol error: {message}"),
+    )
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub kind: FrameType,
+    pub flags: u8,
+    pub stream_id: u32,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(kind: FrameType, flags: u8, stream_id: u32, payload: Vec<u8>) -> Frame {
+        Frame {
+            kind,
+            flags,
+            stream_id,
+            payload,
+        }
+    }
+
+    pub fn has_flag(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    pub fn settings(parameters: &[(u16, u32)]) -> Frame {
+        let payload = parameters
+            .iter()
+            .flat_map(|(identifier, value)| {
+                let mut entry = identifier.to_be_bytes().to_vec();
+                entry.extend_from_slice(&value.to_be_bytes());
+                entry
+            })
+            .collect();
+
+        Frame::new(FrameType::Settings, 0, 0, payload)
+    }
+
+    pub fn window_update(stream_id: u32, increment: u32) -> Frame {
+        Frame::new(
+            FrameType::WindowUpdate,
+            0,
+            stream_id,
+            increment.to_be_bytes().to_vec(),
+        )
+    }
+
+    pub fn rst_stream(stream_id: u32, error_code: u32) -> Frame {
+        Frame::new(
+            FrameType::RstStream,
+            0,
+            stream_id,
+            error_code.to_be_bytes().to_vec(),
+        )
+    }
+
+    pub fn go_away(last_stream_id: u32, error_code: u32) -> Frame {
+        let mut payload = last_stream_id.to_be_bytes().to_vec();
+        payload.extend_from_slice(&error_code.to_be_bytes());
+
+        Frame::new(FrameType::GoAway, 0, 0, payload)
+    }
+
+    /// Reads one frame, rejecting payloads above our advertised SETTINGS_MAX_FRAME_SIZE.
+    pub fn read_from(reader: &mut impl Read, max_frame_size: u32) -> io::Result<Frame> {
+        let mut header = [0u8; FRAME_HEADER_LENGTH];
+        reader.read_exact(&mut header)?;
+
+        let length = u32::from_be_bytes([0, header[0], header[1], header[2]]);
+        if length > max_frame_size {
+            return Err(protocol_error("frame larger than SETTINGS_MAX_FRAME_SIZE"));
+        }
+
+        let mut payload = vec![0u8; length as usize];
+        reader.read_exact(&mut payload)?;
+
+        Ok(Frame {
+            kind: FrameType::from_u8(header[3]),
+            flags: header[4],
+            // The reserved high bit is ignored on receipt.
+            stream_id: read_u32(&header[5..9]) & MAX_WINDOW_SIZE,
+            payload,
+        })
+    }
+
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        let length = (self.payload.len() as u32).to_be_bytes();
+
+        let mut output = Vec::with_capacity(FRAME_HEADER_LENGTH + self.payload.len());
+        output.extend_from_slice(&length[1..]);
+        output.push(self.kind.as_u8());
+        output.push(self.flags);
+        output.extend_from_slice(&(self.stream_id & MAX_WINDOW_SIZE).to_be_bytes());
+        output.extend_from_slice(&self.payload);
+
+        writer.write_all(&output)
+    }
+
+    // Strips the padding (and priority fields) from DATA and HEADERS payloads.
+    pub fn content(&self) -> io::Result<&[u8]> {
+        let mut payload = self.payload.as_slice();
+        let mut padding = 0;
+
+        if self.has_flag(PADDED) && matches!(self.kind, FrameType::Data | FrameType::Headers) {
+            let (&length, rest) = payload
+                .split_first()
+                .ok_or_else(|| protocol_error("missing pad length"))?;
+            padding = length as usize;
+            payload = rest;
+        }
+
+        if self.has_flag(PRIORITY) && self.kind == FrameType::Headers {
+            payload = payload
+                .get(5..)
+                .ok_or_else(|| protocol_error("truncated priority fields"))?;
+        }
+
+        if padding > payload.len() {
+            return Err(protocol_error("padding exceeds the payload"));
+        }
+
+        Ok(&payload[..payload.len() - padding])
+    }
+
+    pub fn settings_parameters(&self) -> io::Result<Vec<(u16, u32)>> {
+        if !self.payload.len().is_multiple_of(6) {
+            return Err(protocol_error("SETTINGS length isn't a multiple of 6"));
+        }
+
+        Ok(self
+            .payload
+            .chunks(6)
+            .map(|entry| {
+                (
+                    u16::from_be_bytes([entry[0], entry[1]]),
+                    read_u32(&entry[2..]),
+                )
+            })
+            .collect())
+    }
+
+    pub fn window_increment(&self) -> io::Result<u32> {
+        if self.payload.len() != 4 {
+            return Err(protocol_error("WINDOW_UPDATE must be 4 octets"));
+        }
+
+        Ok(read_u32(&self.payload) & MAX_WINDOW_SIZE)
+    }
+
+    pub fn error_code(&self) -> io::Result<u32> {
+        match self.kind {
+            FrameType::RstStream if self.payload.len() == 4 => Ok(read_u32(&self.payload)),
+            FrameType::GoAway if self.payload.len() >= 8 => Ok(read_u32(&self.payload[4..])),
+            _ => Err(protocol_error("truncated error code")),
+        }
+    }
+
+    pub fn last_stream_id(&self) -> io::Result<u32> {
+        if self.kind != FrameType::GoAway || self.payload.len() < 8 {
+            return Err(protocol_error("truncated GOAWAY"));
+        }
+
+        Ok(read_u32(&self.payload) & MAX_WINDOW_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod frame_tests {
+    use super::{Frame, FrameType, END_HEADERS, PADDED, PRIORITY};
+    use std::io::Cursor;
+
+    #[test]
+    fn round_trips_frames() {
+        let frame = Frame::new(FrameType::Headers, END_HEADERS, 3, vec![1, 2, 3]);
+
+        let mut bytes = Vec::new();
+        frame.write_to(&mut bytes).unwrap();
+
+        assert_eq!(bytes, [0, 0, 3, 1, 4, 0, 0, 0, 3, 1, 2, 3]);
+        assert_eq!(
+            Frame::read_from(&mut Cursor::new(bytes), 16_384).unwrap(),
+            frame
+        );
+    }
+
+    #[test]
+    fn rejects_oversized_frames() {
+        let frame = Frame::new(FrameType::Data, 0, 1, vec![0; 20]);
+
+        let mut bytes = Vec::new();
+        frame.write_to(&mut bytes).unwrap();
+
+        assert!(Frame::read_from(&mut Cursor::new(bytes), 10).is_err());
+    }
+
+    #[test]
+    fn strips_padding_and_priority() {
+        let frame = Frame::new(
+            FrameType::Headers,
+            PADDED | PRIORITY,
+            1,
+            vec![2, 0, 0, 0, 0, 16, 0xaa, 0xbb, 0, 0],
+        );
+        assert_eq!(frame.content().unwrap(), [0xaa, 0xbb]);
+
+        let frame = Frame::new(FrameType::Data, PADDED, 1, vec![5, 1]);
+        assert!(frame.content().is_err());
+    }
+
+    #[test]
+    fn parses_settings_and_control_frames() {
+        let settings = Frame::settings(&[(0x4, 65_535), (0x5, 16_384)]);
+        assert_eq!(
+            settings.settings_parameters().unwrap(),
+            [(0x4, 65_535), (0x5, 16_384)]
+        );
+
+        assert_eq!(
+            Frame::window_update(1, 1024).window_increment().unwrap(),
+            1024
+        );
+
+        let go_away = Frame::go_away(7, 2);
+        assert_eq!(go_away.last_stream_id().unwrap(), 7);
+        assert_eq!(go_away.error_code().unwrap(), 2);
+    }
+}
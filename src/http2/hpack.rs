@@ -0,0 +1,494 @@
+use crate::http2::huffman;
+
+use std::collections::VecDeque;
+use std::io;
+
+// RFC 7541 Appendix A.
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+// Every dynamic table entry costs 32 octets on top of its name and value (section 4.1).
+const ENTRY_OVERHEAD: usize = 32;
+
+pub const DEFAULT_TABLE_SIZE: usize = 4096;
+
+// Headers whose values shouldn't end up in any compression table (section 7.1.3).
+const SENSITIVE_HEADERS: [&str; 3] = ["authorization", "cookie", "proxy-authorization"];
+
+fn compression_error(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("HPACK compression error: {message}"),
+    )
+}
+
+fn encode_integer(output: &mut Vec<u8>, first_byte: u8, prefix_bits: u8, value: usize) {
+    let max_prefix = (1usize << prefix_bits) - 1;
+
+    if value < max_prefix {
+        output.push(first_byte | value as u8);
+        return;
+    }
+
+    output.push(first_byte | max_prefix as u8);
+
+    let mut value = value - max_prefix;
+    while value >= 128 {
+        output.push((value % 128) as u8 | 0x80);
+        value /= 128;
+    }
+    output.push(value as u8);
+}
+
+fn encode_string(output: &mut Vec<u8>, value: &str) {
+    let bytes = value.as_bytes();
+
+    if huffman::encoded_len(bytes) < bytes.len() {
+        let encoded = huffman::encode(bytes);
+        encode_integer(output, 0x80, 7, encoded.len());
+        output.extend_from_slice(&encoded);
+    } else {
+        encode_integer(output, 0x00, 7, bytes.len());
+        output.extend_from_slice(bytes);
+    }
+}
+
+/// Encodes header blocks using only the static table, so there's no encoder state to
+/// keep in sync with the peer.
+#[derive(Debug, Default)]
+pub struct Encoder {}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn encode<'a>(&self, headers: impl IntoIterator<Item = (&'a str, &'a str)>) -> Vec<u8> {
+        let mut output = Vec::new();
+
+        for (name, value) in headers {
+            let exact = STATIC_TABLE
+                .iter()
+                .position(|&entry| entry == (name, value));
+
+            if let Some(index) = exact {
+                // Indexed header field (section 6.1).
+                encode_integer(&mut output, 0x80, 7, index + 1);
+                continue;
+            }
+
+            // Literal without indexing (section 6.2.2), or never indexed for secrets (6.2.3).
+            let first_byte = if SENSITIVE_HEADERS.contains(&name) {
+                0x10
+            } else {
+                0x00
+            };
+
+            match STATIC_TABLE.iter().position(|&(entry, _)| entry == name) {
+                Some(index) => encode_integer(&mut output, first_byte, 4, index + 1),
+                None => {
+                    output.push(first_byte);
+                    encode_string(&mut output, name);
+                }
+            }
+            encode_string(&mut output, value);
+        }
+
+        output
+    }
+}
+
+#[derive(Debug)]
+pub struct Decoder {
+    // Newest entry first, matching the index order of section 2.3.3.
+    dynamic_table: VecDeque<(String, String)>,
+    size: usize,
+    max_size: usize,
+    // Upper bound for max_size, as advertised in our SETTINGS_HEADER_TABLE_SIZE.
+    allowed_max_size: usize,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new(DEFAULT_TABLE_SIZE)
+    }
+}
+
+struct Input<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Input<'_> {
+    fn next_byte(&mut self) -> io::Result<u8> {
+        let byte = *self
+            .data
+            .get(self.position)
+            .ok_or_else(|| compression_error("truncated header block"))?;
+        self.position += 1;
+
+        Ok(byte)
+    }
+
+    fn integer(&mut self, first_byte: u8, prefix_bits: u8) -> io::Result<usize> {
+        let max_prefix = (1usize << prefix_bits) - 1;
+        let mut value = first_byte as usize & max_prefix;
+
+        if value < max_prefix {
+            return Ok(value);
+        }
+
+        let mut shift = 0;
+        loop {
+            let byte = self.next_byte()?;
+
+            if shift > 28 {
+                return Err(compression_error("integer overflow"));
+            }
+
+            value += ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let first_byte = self.next_byte()?;
+        let length = self.integer(first_byte, 7)?;
+
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| compression_error("truncated string literal"))?;
+        let raw = &self.data[self.position..end];
+        self.position = end;
+
+        let bytes = if first_byte & 0x80 != 0 {
+            huffman::decode(raw)?
+        } else {
+            raw.to_vec()
+        };
+
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+impl Decoder {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            dynamic_table: VecDeque::new(),
+            size: 0,
+            max_size,
+            allowed_max_size: max_size,
+        }
+    }
+
+    pub fn table_size(&self) -> usize {
+        self.size
+    }
+
+    fn evict(&mut self) {
+        while self.size > self.max_size {
+            match self.dynamic_table.pop_back() {
+                Some((name, value)) => self.size -= name.len() + value.len() + ENTRY_OVERHEAD,
+                None => break,
+            }
+        }
+    }
+
+    fn insert(&mut self, name: String, value: String) {
+        let entry_size = name.len() + value.len() + ENTRY_OVERHEAD;
+
+        // An entry larger than the whole table just empties it (section 4.4).
+        self.size += entry_size;
+        self.dynamic_table.push_front((name, value));
+        self.evict();
+    }
+
+    fn get(&self, index: usize) -> io::Result<(String, String)> {
+        match index {
+            0 => Err(compression_error("index 0")),
+            1..=61 => {
+                let (name, value) = STATIC_TABLE[index - 1];
+                Ok((String::from(name), String::from(value)))
+            }
+            _ => self
+                .dynamic_table
+                .get(index - 62)
+                .cloned()
+                .ok_or_else(|| compression_error("index out of range")),
+        }
+    }
+
+    pub fn decode(&mut self, block: &[u8]) -> io::Result<Vec<(String, String)>> {
+        self.decode_limited(block, usize::MAX)
+            .map(Option::unwrap_or_default)
+    }
+
+    /// Decodes a header block, giving up with `None` once the fields add up to more than
+    /// `max_list_size`, counted as SETTINGS_MAX_HEADER_LIST_SIZE counts them. The dynamic
+    /// table is then out of step with the encoder's, so the connection can't go on.
+    pub fn decode_limited(
+        &mut self,
+        block: &[u8],
+        max_list_size: usize,
+    ) -> io::Result<Option<Vec<(String, String)>>> {
+        let mut input = Input {
+            data: block,
+            position: 0,
+        };
+        let mut headers = Vec::new();
+        let mut list_size: usize = 0;
+
+        while input.position < block.len() {
+            let first_byte = input.next_byte()?;
+
+            let field = if first_byte & 0x80 != 0 {
+                // Indexed header field.
+                let index = input.integer(first_byte, 7)?;
+                self.get(index)?
+            } else if first_byte & 0x40 != 0 {
+                // Literal with incremental indexing.
+                let (name, value) = self.literal(&mut input, first_byte, 6)?;
+                self.insert(name.clone(), value.clone());
+                (name, value)
+            } else if first_byte & 0x20 != 0 {
+                // Dynamic table size update, only allowed before any field.
+                if !headers.is_empty() {
+                    return Err(compression_error("table size update after a header"));
+                }
+
+                let max_size = input.integer(first_byte, 5)?;
+                if max_size > self.allowed_max_size {
+                    return Err(compression_error("table size update above the limit"));
+                }
+
+                self.max_size = max_size;
+                self.evict();
+                continue;
+            } else {
+                // Literal without indexing or never indexed.
+                self.literal(&mut input, first_byte, 4)?
+            };
+
+            list_size = list_size.saturating_add(field.0.len() + field.1.len() + ENTRY_OVERHEAD);
+            if list_size > max_list_size {
+                return Ok(None);
+            }
+            headers.push(field);
+        }
+
+        Ok(Some(headers))
+    }
+
+    fn literal(
+        &self,
+        input: &mut Input,
+        first_byte: u8,
+        prefix_bits: u8,
+    ) -> io::Result<(String, String)> {
+        let name = match input.integer(first_byte, prefix_bits)? {
+            0 => input.string()?,
+            index => self.get(index)?.0,
+        };
+        let value = input.string()?;
+
+        Ok((name, value))
+    }
+}
+
+#[cfg(test)]
+mod hpack_tests {
+    use super::{Decoder, Encoder};
+
+    fn unhex(value: &str) -> Vec<u8> {
+        let value: String = value.split_whitespace().collect();
+
+        (0..value.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&value[index..index + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn pairs(headers: &[(&str, &str)]) -> Vec<(String, String)> {
+        headers
+            .iter()
+            .map(|(name, value)| (String::from(*name), String::from(*value)))
+            .collect()
+    }
+
+    #[test]
+    fn decodes_rfc_request_examples_with_huffman() {
+        // RFC 7541 Appendix C.4.
+        let mut decoder = Decoder::default();
+
+        assert_eq!(
+            decoder
+                .decode(&unhex("8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff"))
+                .unwrap(),
+            pairs(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+            ])
+        );
+        assert_eq!(decoder.table_size(), 57);
+
+        assert_eq!(
+            decoder
+                .decode(&unhex("8286 84be 5886 a8eb 1064 9cbf"))
+                .unwrap(),
+            pairs(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+                ("cache-control", "no-cache"),
+            ])
+        );
+        assert_eq!(decoder.table_size(), 110);
+
+        assert_eq!(
+            decoder
+                .decode(&unhex(
+                    "8287 85bf 4088 25a8 49e9 5ba9 7d7f 8925 a849 e95b b8e8 b4bf"
+                ))
+                .unwrap(),
+            pairs(&[
+                (":method", "GET"),
+                (":scheme", "https"),
+                (":path", "/index.html"),
+                (":authority", "www.example.com"),
+                ("custom-key", "custom-value"),
+            ])
+        );
+        assert_eq!(decoder.table_size(), 164);
+    }
+
+    #[test]
+    fn evicts_entries_beyond_the_table_size() {
+        let mut decoder = Decoder::new(60);
+
+        decoder
+            .decode(&unhex("4088 25a8 49e9 5ba9 7d7f 8925 a849 e95b b8e8 b4bf"))
+            .unwrap();
+        assert_eq!(decoder.table_size(), 54);
+
+        // A second entry pushes the first one out.
+        decoder
+            .decode(&unhex("4088 25a8 49e9 5ba9 7d7f 8925 a849 e95b b8e8 b4bf"))
+            .unwrap();
+        assert_eq!(decoder.table_size(), 54);
+
+        // Size update to zero clears the table, and index 62 no longer exists.
+        assert!(decoder.decode(&unhex("20 be")).is_err());
+        assert_eq!(decoder.table_size(), 0);
+    }
+
+    #[test]
+    fn stops_once_the_header_list_is_too_large() {
+        // One 54-byte entry, then two more references to it from the dynamic table.
+        let block = unhex("4088 25a8 49e9 5ba9 7d7f 8925 a849 e95b b8e8 b4bf be be");
+
+        assert_eq!(
+            Decoder::default().decode_limited(&block, 162).unwrap(),
+            Some(pairs(&[("custom-key", "custom-value"); 3]))
+        );
+        assert_eq!(
+            Decoder::default().decode_limited(&block, 161).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_blocks() {
+        let mut decoder = Decoder::default();
+
+        assert!(decoder.decode(&unhex("80")).is_err());
+        assert!(decoder.decode(&unhex("ff")).is_err());
+        assert!(decoder.decode(&unhex("0085 f2b2")).is_err());
+        assert!(decoder.decode(&unhex("82 20")).is_err());
+    }
+
+    #[test]
+    fn encoder_output_round_trips() {
+        let headers = [
+            (":method", "GET"),
+            (":path", "/style.css"),
+            (":authority", "internal.example"),
+            ("cookie", "session=secret"),
+            ("x-custom", "value"),
+        ];
+
+        let block = Encoder::new().encode(headers);
+
+        assert_eq!(block[0], 0x82);
+        assert_eq!(Decoder::default().decode(&block).unwrap(), pairs(&headers));
+    }
+}
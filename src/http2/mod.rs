@@ -0,0 +1,90 @@
+//! A small HTTP/2 client (RFC 9113): framing, HPACK, flow control and stream
+//! multiplexing over any established (usually TLS) stream.
+
+pub mod connection;
+pub mod frame;
+pub mod hpack;
+mod huffman;
+
+use crate::header::HeaderMap;
+
+pub use connection::Connection;
+
+/// Connection preface every client sends first (section 3.4).
+pub const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// ALPN protocol identifier for HTTP/2 over TLS.
+pub const ALPN_PROTOCOL: &str = "h2";
+
+// Connection-specific headers that are forbidden in HTTP/2 (section 8.2.2).
+const CONNECTION_HEADERS: [&str; 7] = [
+    "connection",
+    "host",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "transfer-encoding",
+    "upgrade",
+];
+
+#[derive(Clone, Debug)]
+pub struct HTTP2Request {
+    pub method: String,
+    pub scheme: String,
+    pub authority: String,
+    pub path: String,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl HTTP2Request {
+    // Pseudo-headers first, then the regular headers with lowercase names.
+    fn header_list(&self) -> Vec<(String, String)> {
+        let path = if self.path.is_empty() {
+            "/"
+        } else {
+            &self.path
+        };
+
+        let mut headers = vec![
+            (String::from(":method"), self.method.clone()),
+            (String::from(":scheme"), self.scheme.clone()),
+            (String::from(":authority"), self.authority.clone()),
+            (String::from(":path"), String::from(path)),
+        ];
+
+        for (name, value) in &self.headers {
+            let name = name.to_ascii_lowercase();
+
+            if !CONNECTION_HEADERS.contains(&name.as_str()) {
+                headers.push((name, value.clone()));
+            }
+        }
+
+        headers
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct HTTP2Response {
+    pub status_code: u16,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl HTTP2Response {
+    /// Serializes the response as an HTTP/1.1-style message with an `HTTP/2` status line, so
+    /// it can go through the same parser and cache as HTTP/1.1 responses.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = format!("HTTP/2 {}\r\n", self.status_code).into_bytes();
+
+        for (name, value) in &self.headers {
+            output.extend_from_slice(format!("{name}: {value}\r\n").as_bytes());
+        }
+
+        output.extend_from_slice(b"\r\n");
+        output.extend_from_slice(&self.body);
+
+        output
+    }
+}
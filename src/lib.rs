@@ -0,0 +1,30 @@
+pub mod auth;
+pub mod browser;
+pub mod cache;
+pub mod css;
+pub mod date;
+pub mod download;
+pub mod encoding;
+pub mod har;
+pub mod header;
+pub mod hsts;
+pub mod html;
+pub mod http2;
+pub mod logger;
+pub mod mime;
+pub mod parallel;
+pub mod parser;
+pub mod proxy;
+pub mod range;
+pub mod redirect;
+pub mod request;
+pub mod resolver;
+pub mod retry;
+pub mod sse;
+pub mod tls;
+pub mod transport;
+pub mod uri;
+pub mod websocket;
+
+#[cfg(test)]
+mod testing;
@@ -1,152 +1,45 @@
-use crate::logger::CONSOLE_LOGGER;
-use crate::request::{Request, RequestOptions};
-use crate::uri::Scheme;
-use crate::uri::URI;
+use bored_browser::auth::Netrc;
+use bored_browser::browser::{Browser, Options};
+use bored_browser::html::view_source::Format;
+use bored_browser::logger::CONSOLE_LOGGER;
+use bored_browser::proxy::{Proxy, ProxyConfig};
+use bored_browser::tls::TlsVersion;
+use bored_browser::transport::TransportMode;
 
 use core::panic;
 use log::LevelFilter;
-use regex::Regex;
-use std::collections::HashMap;
 use std::env;
 use std::fs;
-
-pub mod cache;
-pub mod logger;
-pub mod request;
-pub mod uri;
-
-struct Options {
-    debug: bool,
-    url: String,
-    clear_cache: bool,
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+fn next_value<'a>(argument: &str, arguments: &mut impl Iterator<Item = &'a String>) -> &'a String {
+    arguments
+        .next()
+        .unwrap_or_else(|| panic!("Missing value for {argument}"))
 }
 
-struct Browser {
-    options: Options,
-    request: Request,
-}
-
-impl Browser {
-    pub fn new(options: Options) -> Self {
-        let cache = cache::Cache::initialize(options.clear_cache);
-        let requester = Request::init(RequestOptions { cache });
-        Self {
-            options,
-            request: requester,
-        }
-    }
-
-    fn transform(&mut self, data: &str) -> String {
-        let lt_re = Regex::new(r"<").unwrap();
-        let gt_re = Regex::new(r">").unwrap();
-
-        let no_lt = String::from(lt_re.replace_all(data, "&lt;"));
-
-        String::from(gt_re.replace_all(&no_lt.as_str(), "&gt;"))
-    }
-
-    fn show(&mut self, source: &str, only_body: bool) {
-        let mut in_angle = false;
-        let mut in_body = false;
-
-        let html_entities = HashMap::from([("&lt;", "<"), ("&gt;", ">")]);
-
-        let mut current_tag = String::new();
-        let mut possible_entity = String::new();
-
-        for character in source.chars() {
-            if character == '<' {
-                in_angle = true
-            } else if character == '>' {
-                if current_tag == "body" {
-                    in_body = true
-                } else if current_tag == "/body" {
-                    in_body = false
-                }
-                current_tag = String::new();
-                in_angle = false
-            } else if !in_angle {
-                if only_body && !in_body {
-                    // way to show only inside the body element
-                    continue;
-                }
-
-                if character == '&' || possible_entity.len() > 0 {
-                    // HTML entity interpretation
-                    if character == '&' && possible_entity.len() == 0 {
-                        possible_entity += &character.to_string();
-                    } else if possible_entity.len() > 0 {
-                        if possible_entity.len() > 25 {
-                            // No entity has an allowable name space large than 23 + 2, dump current buffer.
-                            print!("{possible_entity}");
-                            possible_entity = String::new();
-                            continue;
-                        }
+fn parse_seconds(argument: &str, value: &str) -> Duration {
+    let seconds: f64 = value
+        .parse()
+        .unwrap_or_else(|_| panic!("Invalid number of seconds for {argument}: {value}"));
 
-                        possible_entity += &character.to_string();
-
-                        if character == ';' {
-                            if html_entities.contains_key(&possible_entity.as_str()) {
-                                let string_value =
-                                    html_entities.get(&possible_entity.as_str()).unwrap_or(&"");
-                                print!("{}", string_value)
-                            } else {
-                                print!("{possible_entity}")
-                            }
-
-                            possible_entity = String::new();
-                        }
-                    }
-
-                    continue;
-                }
-
-                print!("{character}")
-            } else if in_angle {
-                current_tag += &character.to_string();
-            }
-        }
-
-        if possible_entity.len() > 0 {
-            // If buffer still full, dump its content
-            print!("{possible_entity}");
-            possible_entity = String::new();
-        }
-    }
-
-    fn load(&mut self) {
-        let uri = URI::parse(&self.options.url);
-
-        match uri.scheme {
-            Scheme::HTTPS | Scheme::HTTP => {
-                let response = self.request.send(&uri).expect("Couldn't parse response...");
-
-                if uri.flags.contains_key(&String::from("view-source")) {
-                    let transformed_response = self.transform(&response.data.as_str());
-                    self.show(&transformed_response, false)
-                } else {
-                    self.show(&response.data, true)
-                }
-            }
-            Scheme::Data => {
-                // _ is the content_type
-                let (_, path_data) = uri.path.split_once(',').unwrap_or((&uri.path, ""));
-
-                // Writing end-of-file.
-                let data = String::new() + path_data + "\r\n";
-                self.show(&data, false)
-            }
-            Scheme::File => {
-                let data = fs::read_to_string(&uri.path).expect("File not found...");
-                self.show(&data, false)
-            }
-            Scheme::VIEWSOURCE => panic!("Unexpected view-source scheme provided to browser."),
-        }
-    }
+    Duration::from_secs_f64(seconds)
+}
 
-    pub fn run(&mut self) {
-        self.load()
-    }
+// URLs listed one per line, skipping blank lines and `#` comments.
+fn read_url_file(path: &str) -> Vec<String> {
+    let contents =
+        fs::read_to_string(path).unwrap_or_else(|e| panic!("Couldn't read URL file {path}: {e}"));
+
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
 }
 
 fn main() {
@@ -155,23 +48,158 @@ fn main() {
     let args: Vec<String> = env::args().collect();
 
     let mut options = Options {
-        debug: false,
-        url: String::new(),
-        clear_cache: false,
+        proxies: ProxyConfig::from_env(),
+        ..Options::default()
     };
 
-    for argument in &args[1..] {
+    // Source is only colored when written to a terminal.
+    if io::stdout().is_terminal() {
+        options.source_format = Format::Ansi;
+    }
+
+    let mut arguments = args[1..].iter();
+    let mut urls: Vec<String> = Vec::new();
+
+    while let Some(argument) = arguments.next() {
         if argument == "--debug" {
             options.debug = true;
         } else if argument == "--clearCache" {
             options.clear_cache = true;
-        } else if options.url.len() == 0 && !argument.starts_with('-') {
-            options.url = String::from(argument);
+        } else if argument == "--connectTimeout" {
+            let value = next_value(argument, &mut arguments);
+            options.timeouts.connect = Some(parse_seconds(argument, value));
+        } else if argument == "--readTimeout" {
+            let value = next_value(argument, &mut arguments);
+            options.timeouts.read = Some(parse_seconds(argument, value));
+        } else if argument == "--timeout" {
+            let value = next_value(argument, &mut arguments);
+            options.timeouts.total = Some(parse_seconds(argument, value));
+        } else if argument == "--retries" {
+            let value = next_value(argument, &mut arguments);
+            options.retry.max_retries = value
+                .parse()
+                .unwrap_or_else(|_| panic!("Invalid retry count {value}"));
+        } else if argument == "--maxRedirects" {
+            let value = next_value(argument, &mut arguments);
+            options.redirect.max_redirects = value
+                .parse()
+                .unwrap_or_else(|_| panic!("Invalid redirect count {value}"));
+        } else if argument == "--sameOriginRedirects" {
+            options.redirect.same_origin_only = true;
+        } else if argument == "--allowDowngrade" {
+            options.redirect.allow_downgrade = true;
+        } else if argument == "--proxy" {
+            let value = next_value(argument, &mut arguments);
+            let proxy = Proxy::parse(value).unwrap_or_else(|e| panic!("{e}"));
+            options.proxies = ProxyConfig {
+                no_proxy: options.proxies.no_proxy.clone(),
+                ..ProxyConfig::with_proxy(proxy)
+            };
+        } else if argument == "--noProxy" {
+            let value = next_value(argument, &mut arguments);
+            options.proxies.no_proxy = ProxyConfig::parse_no_proxy(value);
+        } else if argument == "--caCert" {
+            let value = next_value(argument, &mut arguments);
+            options.tls.ca_files.push(PathBuf::from(value));
+        } else if argument == "--caPath" {
+            let value = next_value(argument, &mut arguments);
+            options.tls.ca_dirs.push(PathBuf::from(value));
+        } else if argument == "--cert" {
+            let value = next_value(argument, &mut arguments);
+            options.tls.client_certificate = Some(PathBuf::from(value));
+        } else if argument == "--key" {
+            let value = next_value(argument, &mut arguments);
+            options.tls.client_key = Some(PathBuf::from(value));
+        } else if argument == "--tlsMin" {
+            let value = next_value(argument, &mut arguments);
+            options.tls.min_version =
+                Some(TlsVersion::from_str(value).unwrap_or_else(|e| panic!("{e} ({value})")));
+        } else if argument == "--pin" {
+            let value = next_value(argument, &mut arguments);
+            options.tls.add_pin(value).unwrap_or_else(|e| panic!("{e}"));
+        } else if argument == "--hstsFile" || argument == "--hsts-file" {
+            let value = next_value(argument, &mut arguments);
+            options.hsts_file = PathBuf::from(value);
+        } else if argument == "--hstsPreload" {
+            let value = next_value(argument, &mut arguments);
+            options.hsts_preload = Some(PathBuf::from(value));
+        } else if argument == "--httpsFirst" {
+            options.https_first = true;
+        } else if argument == "--netrc" {
+            let value = next_value(argument, &mut arguments);
+            options.netrc = Some(PathBuf::from(value));
+        } else if argument == "--cacheDir" {
+            let value = next_value(argument, &mut arguments);
+            options.cache_directory = PathBuf::from(value);
+        } else if argument == "--record" {
+            let value = next_value(argument, &mut arguments);
+            options.transport = TransportMode::Record(PathBuf::from(value));
+        } else if argument == "--replay" {
+            let value = next_value(argument, &mut arguments);
+            options.transport = TransportMode::Replay(PathBuf::from(value));
+        } else if argument == "--har" {
+            let value = next_value(argument, &mut arguments);
+            options.har = Some(PathBuf::from(value));
+        } else if argument == "--save" {
+            let value = next_value(argument, &mut arguments);
+            options.save = Some(PathBuf::from(value));
+        } else if argument == "--follow" {
+            options.follow = true;
+        } else if argument == "--urlFile" {
+            let value = next_value(argument, &mut arguments);
+            urls.extend(read_url_file(value));
+        } else if argument == "--parallel" {
+            let value = next_value(argument, &mut arguments);
+            options.parallel.workers = value
+                .parse()
+                .unwrap_or_else(|_| panic!("Invalid number of workers {value}"));
+        } else if argument == "--perHost" {
+            let value = next_value(argument, &mut arguments);
+            options.parallel.per_host = value
+                .parse()
+                .unwrap_or_else(|_| panic!("Invalid number of connections per host {value}"));
+        } else if argument == "--resolve" {
+            let value = next_value(argument, &mut arguments);
+            options
+                .resolver
+                .add_override(value)
+                .unwrap_or_else(|e| panic!("{e}"));
+        } else if argument == "--hosts" {
+            let value = next_value(argument, &mut arguments);
+            options
+                .resolver
+                .load_hosts_file(Path::new(value))
+                .unwrap_or_else(|e| panic!("Couldn't read hosts file {value}: {e}"));
+        } else if argument == "--http1" {
+            options.http2 = false;
+        } else if argument == "--showTls" || argument == "--show-tls" {
+            options.show_tls = true;
+        } else if argument == "--dumpDom" || argument == "--dump-dom" {
+            options.dump_dom = true;
+        } else if argument == "--dumpCss" || argument == "--dump-css" {
+            options.dump_css = true;
+        } else if argument == "--noColor" || argument == "--no-color" {
+            options.source_format = Format::Plain;
+        } else if argument == "--viewSourceHtml" || argument == "--view-source-html" {
+            options.source_format = Format::Html;
+        } else if argument == "--insecure" {
+            log::warn!("TLS certificate verification is disabled");
+            options.tls.insecure = true;
+        } else if !argument.starts_with('-') {
+            urls.push(String::from(argument));
         } else {
             panic!("Unknown argument {argument}")
         }
     }
 
+    if urls.len() == 1 {
+        options.url = urls.remove(0);
+    } else if options.save.is_some() || options.follow {
+        panic!("--save and --follow take a single URL");
+    } else {
+        options.urls = urls;
+    }
+
     if options.debug {
         log::set_max_level(LevelFilter::Debug);
         log::debug!("Debug Mode enabled");
@@ -179,5 +207,10 @@ fn main() {
         log::set_max_level(LevelFilter::Info);
     }
 
-    Browser::new(options).run();
+    // An explicit --netrc must exist; the default one is optional.
+    if options.netrc.is_none() {
+        options.netrc = Netrc::default_path().filter(|path| path.is_file());
+    }
+
+    Browser::new(options, io::stdout()).run();
 }
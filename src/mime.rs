@@ -0,0 +1,628 @@
+//! MIME types and the WHATWG MIME Sniffing Standard: working out what a response really is
+//! from its Content-Type and the first bytes of its body.
+
+use crate::header::{Header, HeaderMap};
+
+use std::fmt;
+
+// Bytes of the body the sniffing rules look at (section 5.2).
+const RESOURCE_HEADER_SIZE: usize = 1445;
+
+/// A parsed MIME type, with the type, subtype and parameter names in lowercase.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MimeType {
+    pub top_level: String,
+    pub subtype: String,
+    pub parameters: Vec<(String, String)>,
+}
+
+/// How the browser presents a response of a given MIME type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Category {
+    Html,
+    PlainText,
+    Json,
+    Xml,
+    Image,
+    // Anything that can't be shown as text, left for --save.
+    Download,
+}
+
+fn is_http_whitespace(character: char) -> bool {
+    matches!(character, ' ' | '\t' | '\r' | '\n')
+}
+
+fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte))
+}
+
+// Reads a quoted-string parameter value after its opening quote, returning it unescaped
+// along with what follows the closing quote.
+fn quoted_string(value: &str) -> (String, &str) {
+    let mut output = String::new();
+    let mut characters = value.char_indices();
+
+    while let Some((index, character)) = characters.next() {
+        match character {
+            '"' => return (output, &value[index + 1..]),
+            '\\' => match characters.next() {
+                Some((_, escaped)) => output.push(escaped),
+                None => output.push('\\'),
+            },
+            character => output.push(character),
+        }
+    }
+
+    (output, "")
+}
+
+impl MimeType {
+    pub fn new(top_level: &str, subtype: &str) -> Self {
+        Self {
+            top_level: String::from(top_level),
+            subtype: String::from(subtype),
+            parameters: Vec::new(),
+        }
+    }
+
+    /// Parses a MIME type (MIME Sniffing Standard section 4.4), None when it's invalid.
+    pub fn parse(value: &str) -> Option<MimeType> {
+        let value = value.trim_matches(is_http_whitespace);
+
+        let (top_level, rest) = value.split_once('/')?;
+        let (subtype, mut rest) = rest.split_once(';').unwrap_or((rest, ""));
+        let subtype = subtype.trim_end_matches(is_http_whitespace);
+
+        if !is_token(top_level) || !is_token(subtype) {
+            return None;
+        }
+
+        let mut mime_type = MimeType::new(
+            &top_level.to_ascii_lowercase(),
+            &subtype.to_ascii_lowercase(),
+        );
+
+        while !rest.is_empty() {
+            let parameter = rest.trim_start_matches(is_http_whitespace);
+            let name_end = parameter.find([';', '=']).unwrap_or(parameter.len());
+            let name = parameter[..name_end].to_ascii_lowercase();
+            rest = &parameter[name_end..];
+
+            let value = match rest.strip_prefix('=') {
+                Some(after) if after.starts_with('"') => {
+                    let (value, after) = quoted_string(&after[1..]);
+                    rest = after.split_once(';').map_or("", |(_, rest)| rest);
+                    value
+                }
+                Some(after) => {
+                    let (value, after) = after.split_once(';').unwrap_or((after, ""));
+                    rest = after;
+                    String::from(value.trim_end_matches(is_http_whitespace))
+                }
+                None => {
+                    rest = rest.strip_prefix(';').unwrap_or(rest);
+                    continue;
+                }
+            };
+
+            let known = mime_type
+                .parameters
+                .iter()
+                .any(|(existing, _)| *existing == name);
+
+            if is_token(&name) && !value.is_empty() && !known {
+                mime_type.parameters.push((name, value));
+            }
+        }
+
+        Some(mime_type)
+    }
+
+    /// The type without parameters, e.g. `text/html`.
+    pub fn essence(&self) -> String {
+        format!("{}/{}", self.top_level, self.subtype)
+    }
+
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn is_html(&self) -> bool {
+        self.essence() == "text/html"
+    }
+
+    pub fn is_xml(&self) -> bool {
+        self.subtype.ends_with("+xml")
+            || matches!(self.essence().as_str(), "text/xml" | "application/xml")
+    }
+
+    pub fn is_json(&self) -> bool {
+        self.subtype.ends_with("+json")
+            || matches!(self.essence().as_str(), "application/json" | "text/json")
+    }
+
+    pub fn is_image(&self) -> bool {
+        self.top_level == "image"
+    }
+
+    pub fn is_audio_or_video(&self) -> bool {
+        matches!(self.top_level.as_str(), "audio" | "video") || self.essence() == "application/ogg"
+    }
+
+    pub fn is_javascript(&self) -> bool {
+        matches!(
+            self.essence().as_str(),
+            "application/javascript" | "application/ecmascript" | "text/javascript"
+        )
+    }
+
+    // A type we don't know anything from (section 7.1).
+    fn is_unknown(&self) -> bool {
+        matches!(
+            self.essence().as_str(),
+            "unknown/unknown" | "application/unknown" | "*/*"
+        )
+    }
+
+    pub fn category(&self) -> Category {
+        if self.is_html() {
+            Category::Html
+        } else if self.is_json() {
+            Category::Json
+        } else if self.is_xml() {
+            Category::Xml
+        } else if self.is_image() {
+            Category::Image
+        } else if self.top_level == "text" || self.is_javascript() {
+            Category::PlainText
+        } else {
+            Category::Download
+        }
+    }
+}
+
+impl fmt::Display for MimeType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.top_level, self.subtype)?;
+
+        for (name, value) in &self.parameters {
+            if is_token(value) {
+                write!(f, ";{name}={value}")?;
+            } else {
+                let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+                write!(f, ";{name}=\"{escaped}\"")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// A byte pattern with its mask; `skip_whitespace` ignores leading whitespace bytes first.
+struct Pattern {
+    bytes: &'static [u8],
+    mask: &'static [u8],
+    skip_whitespace: bool,
+    mime_type: &'static str,
+}
+
+const fn pattern(bytes: &'static [u8], mask: &'static [u8], mime_type: &'static str) -> Pattern {
+    Pattern {
+        bytes,
+        mask,
+        skip_whitespace: false,
+        mime_type,
+    }
+}
+
+// An HTML pattern: case-insensitive, after any whitespace, and followed by a space or `>`.
+const fn tag(bytes: &'static [u8], mask: &'static [u8]) -> Pattern {
+    Pattern {
+        skip_whitespace: true,
+        ..pattern(bytes, mask, "text/html")
+    }
+}
+
+// Ignores the case of the letters after `<`, for tags up to `<SCRIPT`.
+const UPPERCASE: &[u8] = &[0xFF, 0xDF, 0xDF, 0xDF, 0xDF, 0xDF, 0xDF];
+
+// Section 7.1, rules for identifying an unknown MIME type: the scriptable patterns.
+const SCRIPTABLE_PATTERNS: &[Pattern] = &[
+    tag(
+        b"<!DOCTYPE HTML",
+        &[
+            0xFF, 0xFF, 0xDF, 0xDF, 0xDF, 0xDF, 0xDF, 0xDF, 0xDF, 0xFF, 0xDF, 0xDF, 0xDF, 0xDF,
+        ],
+    ),
+    tag(b"<HTML", UPPERCASE),
+    tag(b"<HEAD", UPPERCASE),
+    tag(b"<SCRIPT", UPPERCASE),
+    tag(b"<IFRAME", UPPERCASE),
+    tag(b"<H1", &[0xFF, 0xDF, 0xFF]),
+    tag(b"<DIV", UPPERCASE),
+    tag(b"<FONT", UPPERCASE),
+    tag(b"<TABLE", UPPERCASE),
+    tag(b"<A", UPPERCASE),
+    tag(b"<STYLE", UPPERCASE),
+    tag(b"<TITLE", UPPERCASE),
+    tag(b"<B", UPPERCASE),
+    tag(b"<BODY", UPPERCASE),
+    tag(b"<BR", UPPERCASE),
+    tag(b"<P", UPPERCASE),
+    tag(b"<!--", &[0xFF, 0xFF, 0xFF, 0xFF]),
+    Pattern {
+        skip_whitespace: true,
+        ..pattern(b"<?xml", &[0xFF; 5], "text/xml")
+    },
+    pattern(b"%PDF-", &[0xFF; 5], "application/pdf"),
+];
+
+// The rest of section 7.1: patterns that are never scriptable.
+const NON_SCRIPTABLE_PATTERNS: &[Pattern] = &[
+    pattern(b"%!PS-Adobe-", &[0xFF; 11], "application/postscript"),
+    pattern(b"\xFE\xFF", &[0xFF; 2], "text/plain"),
+    pattern(b"\xFF\xFE", &[0xFF; 2], "text/plain"),
+    pattern(b"\xEF\xBB\xBF", &[0xFF; 3], "text/plain"),
+];
+
+// Section 6.1.
+const IMAGE_PATTERNS: &[Pattern] = &[
+    pattern(b"\x00\x00\x01\x00", &[0xFF; 4], "image/x-icon"),
+    pattern(b"\x00\x00\x02\x00", &[0xFF; 4], "image/x-icon"),
+    pattern(b"BM", &[0xFF; 2], "image/bmp"),
+    pattern(b"GIF87a", &[0xFF; 6], "image/gif"),
+    pattern(b"GIF89a", &[0xFF; 6], "image/gif"),
+    pattern(
+        b"RIFF\x00\x00\x00\x00WEBPVP",
+        &[
+            0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+        ],
+        "image/webp",
+    ),
+    pattern(b"\x89PNG\r\n\x1A\n", &[0xFF; 8], "image/png"),
+    pattern(b"\xFF\xD8\xFF", &[0xFF; 3], "image/jpeg"),
+];
+
+// Section 6.2, without the MP4, WebM and MP3 algorithms.
+const AUDIO_VIDEO_PATTERNS: &[Pattern] = &[
+    pattern(
+        b"FORM\x00\x00\x00\x00AIFF",
+        &[0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF],
+        "audio/aiff",
+    ),
+    pattern(b"ID3", &[0xFF; 3], "audio/mpeg"),
+    pattern(b"OggS\x00", &[0xFF; 5], "application/ogg"),
+    pattern(b"MThd\x00\x00\x00\x06", &[0xFF; 8], "audio/midi"),
+    pattern(
+        b"RIFF\x00\x00\x00\x00AVI ",
+        &[0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF],
+        "video/avi",
+    ),
+    pattern(
+        b"RIFF\x00\x00\x00\x00WAVE",
+        &[0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF],
+        "audio/wave",
+    ),
+];
+
+// Section 6.3.
+const ARCHIVE_PATTERNS: &[Pattern] = &[
+    pattern(b"\x1F\x8B\x08", &[0xFF; 3], "application/x-gzip"),
+    pattern(b"PK\x03\x04", &[0xFF; 4], "application/zip"),
+    pattern(
+        b"Rar \x1A\x07\x00",
+        &[0xFF; 7],
+        "application/x-rar-compressed",
+    ),
+];
+
+// Pattern matching algorithm (section 4.6).
+fn matches(pattern: &Pattern, input: &[u8]) -> bool {
+    let mut start = 0;
+
+    if pattern.skip_whitespace {
+        while input
+            .get(start)
+            .is_some_and(|byte| matches!(byte, b'\t' | b'\n' | 0x0C | b'\r' | b' '))
+        {
+            start += 1;
+        }
+    }
+
+    let input = &input[start..];
+
+    if input.len() < pattern.bytes.len() {
+        return false;
+    }
+
+    let prefix_matches = pattern
+        .bytes
+        .iter()
+        .zip(pattern.mask)
+        .zip(input)
+        .all(|((byte, mask), input)| input & mask == *byte);
+
+    // HTML patterns must end the tag name.
+    let terminated = pattern.mime_type != "text/html"
+        || matches!(input.get(pattern.bytes.len()), Some(b' ' | b'>'));
+
+    prefix_matches && terminated
+}
+
+fn match_patterns(patterns: &[Pattern], input: &[u8]) -> Option<MimeType> {
+    patterns
+        .iter()
+        .find(|pattern| matches(pattern, input))
+        .and_then(|pattern| MimeType::parse(pattern.mime_type))
+}
+
+// The ISO base media file format's `ftyp` box with an "mp4" brand (section 6.2.1).
+fn is_mp4(input: &[u8]) -> bool {
+    if input.len() < 12 || &input[4..8] != b"ftyp" {
+        return false;
+    }
+
+    let box_size = u32::from_be_bytes([input[0], input[1], input[2], input[3]]) as usize;
+
+    if input.len() < box_size || !box_size.is_multiple_of(4) {
+        return false;
+    }
+
+    if &input[8..11] == b"mp4" {
+        return true;
+    }
+
+    // Compatible brands follow the major brand and minor version.
+    (16..box_size)
+        .step_by(4)
+        .any(|offset| input.get(offset..offset + 3) == Some(b"mp4"))
+}
+
+fn is_binary_data_byte(byte: u8) -> bool {
+    matches!(byte, 0x00..=0x08 | 0x0B | 0x0E..=0x1A | 0x1C..=0x1F)
+}
+
+fn sniff_image(input: &[u8]) -> Option<MimeType> {
+    match_patterns(IMAGE_PATTERNS, input)
+}
+
+fn sniff_audio_or_video(input: &[u8]) -> Option<MimeType> {
+    match_patterns(AUDIO_VIDEO_PATTERNS, input)
+        .or_else(|| is_mp4(input).then(|| MimeType::new("video", "mp4")))
+}
+
+// Rules for identifying an unknown MIME type (section 7.1).
+fn sniff_unknown(input: &[u8], sniff_scriptable: bool) -> MimeType {
+    let scriptable = if sniff_scriptable {
+        match_patterns(SCRIPTABLE_PATTERNS, input)
+    } else {
+        None
+    };
+
+    scriptable
+        .or_else(|| match_patterns(NON_SCRIPTABLE_PATTERNS, input))
+        .or_else(|| sniff_image(input))
+        .or_else(|| sniff_audio_or_video(input))
+        .or_else(|| match_patterns(ARCHIVE_PATTERNS, input))
+        .unwrap_or_else(|| {
+            if input.iter().any(|&byte| is_binary_data_byte(byte)) {
+                MimeType::new("application", "octet-stream")
+            } else {
+                MimeType::new("text", "plain")
+            }
+        })
+}
+
+// Rules for distinguishing if a resource is text or binary (section 7.2).
+fn sniff_text_or_binary(input: &[u8]) -> MimeType {
+    let has_bom = input.starts_with(b"\xFE\xFF")
+        || input.starts_with(b"\xFF\xFE")
+        || input.starts_with(b"\xEF\xBB\xBF");
+
+    if has_bom || !input.iter().any(|&byte| is_binary_data_byte(byte)) {
+        MimeType::new("text", "plain")
+    } else {
+        MimeType::new("application", "octet-stream")
+    }
+}
+
+/// Whether the response opted out of sniffing with `X-Content-Type-Options: nosniff`.
+pub fn is_no_sniff(headers: &HeaderMap) -> bool {
+    headers
+        .get(Header::XContentTypeOptions.as_str())
+        .and_then(|value| value.split(',').next())
+        .is_some_and(|value| value.trim().eq_ignore_ascii_case("nosniff"))
+}
+
+/// The MIME type to treat a response as (section 7, determining the computed MIME type),
+/// given its Content-Type header, whether sniffing is off, and its body.
+pub fn sniff(content_type: Option<&str>, no_sniff: bool, body: &[u8]) -> MimeType {
+    let input = &body[..body.len().min(RESOURCE_HEADER_SIZE)];
+    let supplied = content_type.and_then(MimeType::parse);
+
+    let supplied = match supplied {
+        Some(supplied) if !supplied.is_unknown() => supplied,
+        _ => return sniff_unknown(input, !no_sniff),
+    };
+
+    if no_sniff {
+        return supplied;
+    }
+
+    // Old Apache servers labelled everything with one of these (section 5.1).
+    let apache_bug = matches!(
+        content_type,
+        Some(
+            "text/plain"
+                | "text/plain; charset=ISO-8859-1"
+                | "text/plain; charset=iso-8859-1"
+                | "text/plain; charset=UTF-8"
+        )
+    );
+
+    if apache_bug {
+        return sniff_text_or_binary(input);
+    }
+
+    if supplied.is_xml() || supplied.is_html() {
+        return supplied;
+    }
+
+    let sniffed = if supplied.is_image() {
+        sniff_image(input)
+    } else if supplied.is_audio_or_video() {
+        sniff_audio_or_video(input)
+    } else {
+        None
+    };
+
+    sniffed.unwrap_or(supplied)
+}
+
+#[cfg(test)]
+mod mime_type_tests {
+    use super::{Category, MimeType};
+
+    #[test]
+    fn parses_types_and_parameters() {
+        let mime_type = MimeType::parse(
+            " Text/HTML ; Charset=\"utf-8\" ;foo=\"a \\\"b\\\"\"; charset=latin1;bare",
+        )
+        .unwrap();
+
+        assert_eq!(mime_type.essence(), "text/html");
+        assert_eq!(mime_type.parameter("charset"), Some("utf-8"));
+        assert_eq!(mime_type.parameter("foo"), Some("a \"b\""));
+        assert_eq!(mime_type.parameters.len(), 2);
+        assert_eq!(
+            mime_type.to_string(),
+            "text/html;charset=utf-8;foo=\"a \\\"b\\\"\""
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_types() {
+        for value in ["", "text", "text/", "/html", "te xt/html", "text/ht(ml"] {
+            assert_eq!(MimeType::parse(value), None, "{value}");
+        }
+    }
+
+    #[test]
+    fn categorizes_types() {
+        for (value, category) in [
+            ("text/html", Category::Html),
+            ("text/plain", Category::PlainText),
+            ("text/css", Category::PlainText),
+            ("application/javascript", Category::PlainText),
+            ("application/json", Category::Json),
+            ("application/ld+json", Category::Json),
+            ("application/xml", Category::Xml),
+            ("image/svg+xml", Category::Xml),
+            ("image/png", Category::Image),
+            ("application/pdf", Category::Download),
+        ] {
+            assert_eq!(
+                MimeType::parse(value).unwrap().category(),
+                category,
+                "{value}"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod sniff_tests {
+    use super::sniff;
+    use crate::header::HeaderMap;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1A\n\x00\x00\x00\x0DIHDR";
+
+    fn essence(content_type: Option<&str>, no_sniff: bool, body: &[u8]) -> String {
+        sniff(content_type, no_sniff, body).essence()
+    }
+
+    #[test]
+    fn sniffs_responses_without_a_type() {
+        assert_eq!(essence(None, false, b"  \n<!doctype html>"), "text/html");
+        assert_eq!(essence(None, false, b"<p>hi</p>"), "text/html");
+        assert_eq!(essence(None, false, b"<pre>x</pre>"), "text/plain");
+        assert_eq!(essence(Some("*/*"), false, b"<?xml version"), "text/xml");
+        assert_eq!(essence(None, false, b"%PDF-1.7"), "application/pdf");
+        assert_eq!(essence(None, false, PNG), "image/png");
+        assert_eq!(
+            essence(None, false, b"\x1F\x8B\x08\x00"),
+            "application/x-gzip"
+        );
+        assert_eq!(essence(None, false, b"plain words"), "text/plain");
+        assert_eq!(
+            essence(None, false, b"\x00\x01\x02"),
+            "application/octet-stream"
+        );
+        assert_eq!(
+            essence(
+                None,
+                false,
+                b"\x00\x00\x00\x18ftypmp42\x00\x00\x00\x00mp41isom"
+            ),
+            "video/mp4"
+        );
+    }
+
+    #[test]
+    fn never_sniffs_html_when_told_not_to() {
+        assert_eq!(essence(None, true, b"<html>"), "text/plain");
+        assert_eq!(
+            essence(Some("text/plain;charset=utf-8"), true, PNG),
+            "text/plain"
+        );
+    }
+
+    #[test]
+    fn distinguishes_text_from_binary_for_apache_defaults() {
+        assert_eq!(
+            essence(Some("text/plain"), false, PNG),
+            "application/octet-stream"
+        );
+        assert_eq!(essence(Some("text/plain"), false, b"a < b"), "text/plain");
+        // Only the exact default values are suspicious.
+        assert_eq!(
+            essence(Some("text/plain; charset=utf-8"), false, PNG),
+            "text/plain"
+        );
+    }
+
+    #[test]
+    fn trusts_supplied_types_except_for_mislabelled_media() {
+        assert_eq!(essence(Some("text/html"), false, PNG), "text/html");
+        assert_eq!(
+            essence(Some("application/json"), false, b"<html>"),
+            "application/json"
+        );
+        assert_eq!(essence(Some("image/gif"), false, PNG), "image/png");
+        assert_eq!(
+            essence(Some("image/gif"), false, b"not an image"),
+            "image/gif"
+        );
+        assert_eq!(
+            sniff(Some("text/html; charset=utf-8"), false, b"").parameter("charset"),
+            Some("utf-8")
+        );
+    }
+
+    #[test]
+    fn reads_the_nosniff_header() {
+        let mut headers = HeaderMap::new();
+        assert!(!super::is_no_sniff(&headers));
+
+        headers
+            .insert("X-Content-Type-Options", "NoSniff, other")
+            .unwrap();
+        assert!(super::is_no_sniff(&headers));
+    }
+}
@@ -0,0 +1,261 @@
+//! Runs jobs on a bounded pool of threads, never more than a few at once against one host,
+//! handing results back in the order the jobs were given.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Condvar, Mutex};
+use std::thread;
+
+/// How many jobs run at once, in total and per host.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Limits {
+    pub workers: usize,
+    pub per_host: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            workers: 8,
+            per_host: 2,
+        }
+    }
+}
+
+struct Queue {
+    // Jobs not yet started, in input order.
+    pending: Vec<usize>,
+    // Jobs running against each host.
+    active: HashMap<String, usize>,
+}
+
+// A running job's place in its host's limit, given back when the job ends, even by panicking.
+struct Slot<'a> {
+    queue: &'a Mutex<Queue>,
+    ready: &'a Condvar,
+    host: Option<&'a str>,
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        if let Some(host) = self.host {
+            let mut queue = self.queue.lock().expect("Queue lock poisoned");
+
+            if let Some(active) = queue.active.get_mut(host) {
+                *active -= 1;
+            }
+        }
+
+        self.ready.notify_all();
+    }
+}
+
+// Waits for the first pending job whose host has room, None once nothing is left to start.
+fn next_job<'a>(
+    queue: &'a Mutex<Queue>,
+    ready: &'a Condvar,
+    hosts: &'a [Option<String>],
+    per_host: usize,
+) -> Option<(usize, Slot<'a>)> {
+    let mut guard = queue.lock().expect("Queue lock poisoned");
+
+    loop {
+        if guard.pending.is_empty() {
+            return None;
+        }
+
+        let position = guard.pending.iter().position(|&index| {
+            hosts[index]
+                .as_ref()
+                .is_none_or(|host| guard.active.get(host).copied().unwrap_or(0) < per_host)
+        });
+
+        if let Some(position) = position {
+            let index = guard.pending.remove(position);
+            let host = hosts[index].as_deref();
+
+            if let Some(host) = host {
+                *guard.active.entry(String::from(host)).or_insert(0) += 1;
+            }
+
+            return Some((index, Slot { queue, ready, host }));
+        }
+
+        guard = ready.wait(guard).expect("Queue lock poisoned");
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause")
+}
+
+/// Runs `work` on every job, at most `limits.workers` at once and `limits.per_host` at once
+/// for jobs with the same `host` (None for jobs without one, which are only bound by the
+/// worker count). `emit` is called on this thread with each job's index and result as soon
+/// as every job before it has been emitted. A job whose `work` panics ends with an error.
+pub fn run_ordered<T, R>(
+    jobs: &[T],
+    limits: &Limits,
+    host: impl Fn(&T) -> Option<String>,
+    work: impl Fn(&T) -> io::Result<R> + Sync,
+    mut emit: impl FnMut(usize, io::Result<R>),
+) where
+    T: Sync,
+    R: Send,
+{
+    let hosts: Vec<Option<String>> = jobs.iter().map(host).collect();
+    let per_host = limits.per_host.max(1);
+    let workers = limits.workers.clamp(1, jobs.len().max(1));
+
+    let queue = Mutex::new(Queue {
+        pending: (0..jobs.len()).collect(),
+        active: HashMap::new(),
+    });
+    let ready = Condvar::new();
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let sender = sender.clone();
+            let (queue, ready, hosts, work) = (&queue, &ready, &hosts, &work);
+
+            scope.spawn(move || {
+                while let Some((index, slot)) = next_job(queue, ready, hosts, per_host) {
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| work(&jobs[index])))
+                        .unwrap_or_else(|payload| {
+                            Err(io::Error::other(format!(
+                                "Job panicked: {}",
+                                panic_message(payload.as_ref())
+                            )))
+                        });
+                    drop(slot);
+
+                    if sender.send((index, result)).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        let mut finished: Vec<Option<io::Result<R>>> =
+            std::iter::repeat_with(|| None).take(jobs.len()).collect();
+        let mut next = 0;
+
+        for (index, result) in receiver {
+            finished[index] = Some(result);
+
+            while let Some(result) = finished.get_mut(next).and_then(Option::take) {
+                emit(next, result);
+                next += 1;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod run_ordered_tests {
+    use super::{run_ordered, Limits};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn emits_results_in_input_order() {
+        let jobs: Vec<u64> = (0..20).collect();
+        let mut emitted = Vec::new();
+
+        run_ordered(
+            &jobs,
+            &Limits::default(),
+            |_| None,
+            |&job| {
+                // Later jobs finish first.
+                thread::sleep(Duration::from_millis(20 - job));
+                Ok(job * 2)
+            },
+            |index, result| emitted.push((index, result.unwrap())),
+        );
+
+        let expected: Vec<(usize, u64)> = (0..20).map(|job| (job, job as u64 * 2)).collect();
+        assert_eq!(emitted, expected);
+    }
+
+    #[test]
+    fn respects_the_worker_and_per_host_limits() {
+        let jobs: Vec<(&str, usize)> = (0..12)
+            .map(|index| (if index % 3 == 0 { "a" } else { "b" }, index))
+            .collect();
+        let running = Mutex::new(Vec::<&str>::new());
+        let most_total = AtomicUsize::new(0);
+        let most_per_host = AtomicUsize::new(0);
+        let mut emitted = 0;
+
+        run_ordered(
+            &jobs,
+            &Limits {
+                workers: 3,
+                per_host: 2,
+            },
+            |(host, _)| Some(String::from(*host)),
+            |&(host, _)| {
+                {
+                    let mut running = running.lock().unwrap();
+                    running.push(host);
+                    let same_host = running.iter().filter(|&&other| other == host).count();
+                    most_total.fetch_max(running.len(), Ordering::SeqCst);
+                    most_per_host.fetch_max(same_host, Ordering::SeqCst);
+                }
+
+                thread::sleep(Duration::from_millis(10));
+
+                let mut running = running.lock().unwrap();
+                let position = running.iter().position(|&other| other == host).unwrap();
+                running.remove(position);
+                Ok(())
+            },
+            |_, result| {
+                result.unwrap();
+                emitted += 1;
+            },
+        );
+
+        assert_eq!(emitted, 12);
+        assert!(most_total.load(Ordering::SeqCst) <= 3);
+        assert_eq!(most_per_host.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn reports_panicking_jobs_as_errors() {
+        let jobs: Vec<u64> = (0..6).collect();
+        let mut emitted = Vec::new();
+
+        run_ordered(
+            &jobs,
+            &Limits::default(),
+            |_| None,
+            |&job| {
+                if job == 2 {
+                    panic!("job {job} failed");
+                }
+                Ok(job)
+            },
+            |index, result| emitted.push((index, result.map_err(|e| e.to_string()))),
+        );
+
+        let expected: Vec<(usize, Result<u64, String>)> = (0..6)
+            .map(|job| match job {
+                2 => (2, Err(String::from("Job panicked: job 2 failed"))),
+                job => (job as usize, Ok(job)),
+            })
+            .collect();
+        assert_eq!(emitted, expected);
+    }
+}
@@ -0,0 +1,714 @@
+use crate::header::{Header, HeaderError, HeaderMap};
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Read};
+
+pub const MAX_HEADER_COUNT: usize = 100;
+pub const MAX_LINE_LENGTH: usize = 8 * 1024;
+pub const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseError {
+    Incomplete,
+    IncompleteBody,
+    InvalidStatusLine(String),
+    InvalidVersion(String),
+    InvalidStatusCode(String),
+    InvalidHeader(String),
+    InvalidContentLength(String),
+    InvalidChunkSize(String),
+    TooManyHeaders,
+    LineTooLong,
+    HeadTooLarge,
+    Io(io::ErrorKind),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Incomplete => write!(f, "Connection closed before the end of the headers"),
+            ParseError::IncompleteBody => write!(f, "Connection closed before the end of the body"),
+            ParseError::InvalidStatusLine(line) => write!(f, "Invalid status line {line:?}"),
+            ParseError::InvalidVersion(version) => write!(f, "Invalid HTTP version {version:?}"),
+            ParseError::InvalidStatusCode(code) => write!(f, "Invalid status code {code:?}"),
+            ParseError::InvalidHeader(line) => write!(f, "Invalid header line {line:?}"),
+            ParseError::InvalidContentLength(value) => {
+                write!(f, "Invalid Content-Length {value:?}")
+            }
+            ParseError::InvalidChunkSize(line) => write!(f, "Invalid chunk size {line:?}"),
+            ParseError::TooManyHeaders => {
+                write!(f, "More than {MAX_HEADER_COUNT} headers in response")
+            }
+            ParseError::LineTooLong => write!(f, "Line longer than {MAX_LINE_LENGTH} bytes"),
+            ParseError::HeadTooLarge => write!(f, "Headers larger than {MAX_HEAD_SIZE} bytes"),
+            ParseError::Io(kind) => write!(f, "I/O error while parsing: {kind}"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+impl From<io::Error> for ParseError {
+    fn from(error: io::Error) -> Self {
+        ParseError::Io(error.kind())
+    }
+}
+
+impl From<HeaderError> for ParseError {
+    fn from(error: HeaderError) -> Self {
+        ParseError::InvalidHeader(error.to_string())
+    }
+}
+
+impl From<ParseError> for io::Error {
+    fn from(error: ParseError) -> Self {
+        let kind = match error {
+            ParseError::Incomplete | ParseError::IncompleteBody => io::ErrorKind::UnexpectedEof,
+            ParseError::Io(kind) => kind,
+            _ => io::ErrorKind::InvalidData,
+        };
+
+        io::Error::new(kind, error)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResponseHead {
+    pub http_version: String,
+    pub status_code: u16,
+    pub status_message: String,
+    pub headers: HeaderMap,
+}
+
+impl ResponseHead {
+    // Responses to which RFC 9112 section 6.3 forbids a body.
+    fn has_body(&self) -> bool {
+        !(100..200).contains(&self.status_code)
+            && self.status_code != 204
+            && self.status_code != 304
+    }
+}
+
+/// Reads one line terminated by LF, stripping the LF and an optional preceding CR.
+/// Returns None if the reader is already at end of input.
+fn read_line(reader: &mut impl BufRead) -> Result<Option<Vec<u8>>, ParseError> {
+    let mut line = vec![];
+
+    let bytes_read = reader
+        .by_ref()
+        .take(MAX_LINE_LENGTH as u64 + 2)
+        .read_until(b'\n', &mut line)?;
+
+    if bytes_read == 0 {
+        return Ok(None);
+    }
+
+    if line.last() != Some(&b'\n') {
+        if line.len() > MAX_LINE_LENGTH {
+            return Err(ParseError::LineTooLong);
+        }
+
+        // Input ended in the middle of a line.
+        return Err(ParseError::Incomplete);
+    }
+
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    if line.len() > MAX_LINE_LENGTH {
+        return Err(ParseError::LineTooLong);
+    }
+
+    Ok(Some(line))
+}
+
+fn parse_version(version: &str) -> Result<String, ParseError> {
+    let digits = version
+        .strip_prefix("HTTP/")
+        .ok_or_else(|| ParseError::InvalidVersion(String::from(version)))?;
+
+    let valid = match digits.as_bytes() {
+        [major, b'.', minor] => major.is_ascii_digit() && minor.is_ascii_digit(),
+        [major] => major.is_ascii_digit(),
+        _ => false,
+    };
+
+    if !valid {
+        return Err(ParseError::InvalidVersion(String::from(version)));
+    }
+
+    Ok(String::from(digits))
+}
+
+/// Parses `HTTP-version SP status-code [SP reason-phrase]`, keeping the full reason phrase.
+pub fn parse_status_line(line: &[u8]) -> Result<(String, u16, String), ParseError> {
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim_matches([' ', '\t']);
+
+    let (version, remainder) = line
+        .split_once(' ')
+        .ok_or_else(|| ParseError::InvalidStatusLine(String::from(line)))?;
+
+    let remainder = remainder.trim_start_matches(' ');
+    let (code, reason) = remainder.split_once(' ').unwrap_or((remainder, ""));
+
+    let http_version = parse_version(version)?;
+
+    if code.len() != 3 || !code.bytes().all(|c| c.is_ascii_digit()) {
+        return Err(ParseError::InvalidStatusCode(String::from(code)));
+    }
+
+    let status_code = code
+        .parse::<u16>()
+        .map_err(|_| ParseError::InvalidStatusCode(String::from(code)))?;
+
+    if !(100..=599).contains(&status_code) {
+        return Err(ParseError::InvalidStatusCode(String::from(code)));
+    }
+
+    Ok((http_version, status_code, String::from(reason.trim())))
+}
+
+/// Parses a status line and header block, stopping right after the empty line.
+pub fn parse_head(reader: &mut impl BufRead) -> Result<ResponseHead, ParseError> {
+    let mut head_size = 0;
+
+    // Tolerate stray empty lines before the status line (RFC 9112 section 2.2).
+    let status_line = loop {
+        let line = read_line(reader)?.ok_or(ParseError::Incomplete)?;
+        head_size += line.len() + 2;
+
+        if head_size > MAX_HEAD_SIZE {
+            return Err(ParseError::HeadTooLarge);
+        }
+
+        if !line.is_empty() {
+            break line;
+        }
+    };
+
+    let (http_version, status_code, status_message) = parse_status_line(&status_line)?;
+
+    let mut headers = HeaderMap::new();
+
+    loop {
+        let line = read_line(reader)?.ok_or(ParseError::Incomplete)?;
+        head_size += line.len() + 2;
+
+        if head_size > MAX_HEAD_SIZE {
+            return Err(ParseError::HeadTooLarge);
+        }
+
+        if line.is_empty() {
+            break;
+        }
+
+        let line = String::from_utf8_lossy(&line);
+
+        // obs-fold: a line starting with whitespace continues the previous header
+        if line.starts_with([' ', '\t']) {
+            headers.fold_into_last(&line)?;
+            continue;
+        }
+
+        if headers.len() >= MAX_HEADER_COUNT {
+            return Err(ParseError::TooManyHeaders);
+        }
+
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| ParseError::InvalidHeader(String::from(line.as_ref())))?;
+
+        // Whitespace before the colon is invalid, but is stripped rather than rejected.
+        headers.append(name.trim_end_matches([' ', '\t']), value)?;
+    }
+
+    Ok(ResponseHead {
+        http_version,
+        status_code,
+        status_message,
+        headers,
+    })
+}
+
+fn is_chunked(headers: &HeaderMap) -> bool {
+    headers
+        .get_combined(Header::TransferEncoding.as_str())
+        .is_some_and(|transfer_encoding| {
+            // chunked must be the final transfer coding when present
+            transfer_encoding
+                .rsplit(',')
+                .next()
+                .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+        })
+}
+
+fn content_length(headers: &HeaderMap) -> Result<Option<u64>, ParseError> {
+    let mut length = None;
+
+    // Repeated or comma-joined values are only acceptable when they all agree.
+    for value in headers.get_all(Header::ContentLength.as_str()) {
+        for part in value.split(',') {
+            let part = part.trim();
+            let parsed = part
+                .parse::<u64>()
+                .map_err(|_| ParseError::InvalidContentLength(String::from(part)))?;
+
+            if length.is_some_and(|length| length != parsed) {
+                return Err(ParseError::InvalidContentLength(value.clone()));
+            }
+
+            length = Some(parsed);
+        }
+    }
+
+    Ok(length)
+}
+
+// A connection closing inside a chunked body is an incomplete body, not incomplete headers.
+fn in_body(error: ParseError) -> ParseError {
+    match error {
+        ParseError::Incomplete => ParseError::IncompleteBody,
+        e => e,
+    }
+}
+
+fn read_chunk_size(reader: &mut impl BufRead) -> Result<u64, ParseError> {
+    let line = read_line(reader)
+        .map_err(in_body)?
+        .ok_or(ParseError::IncompleteBody)?;
+
+    let line = String::from_utf8_lossy(&line);
+
+    // Chunk extensions after ';' carry nothing we use.
+    let size = line.split(';').next().unwrap_or("").trim();
+
+    u64::from_str_radix(size, 16)
+        .map_err(|_| ParseError::InvalidChunkSize(String::from(line.as_ref())))
+}
+
+fn read_chunk_end(reader: &mut impl BufRead) -> Result<(), ParseError> {
+    match read_line(reader) {
+        Ok(Some(line)) if line.is_empty() => Ok(()),
+        Ok(Some(_)) => Err(ParseError::InvalidChunkSize(String::from(
+            "missing CRLF after chunk data",
+        ))),
+        Ok(None) | Err(ParseError::Incomplete) => Err(ParseError::IncompleteBody),
+        Err(e) => Err(e),
+    }
+}
+
+// Skips the trailer section; a missing final CRLF is tolerated.
+fn skip_trailers(reader: &mut impl BufRead) -> Result<(), ParseError> {
+    let mut trailer_size = 0;
+
+    while let Some(line) = read_line(reader).map_err(in_body)? {
+        if line.is_empty() {
+            break;
+        }
+
+        trailer_size += line.len();
+        if trailer_size > MAX_HEAD_SIZE {
+            return Err(ParseError::HeadTooLarge);
+        }
+    }
+
+    Ok(())
+}
+
+fn read_chunked_body(reader: &mut impl BufRead) -> Result<Vec<u8>, ParseError> {
+    let mut data = vec![];
+
+    loop {
+        let chunk_size = read_chunk_size(reader)?;
+
+        if chunk_size == 0 {
+            break;
+        }
+
+        let read = reader.by_ref().take(chunk_size).read_to_end(&mut data)?;
+
+        if (read as u64) < chunk_size {
+            return Err(ParseError::IncompleteBody);
+        }
+
+        read_chunk_end(reader)?;
+    }
+
+    skip_trailers(reader)?;
+
+    Ok(data)
+}
+
+/// Reads the message body framed according to the response head.
+pub fn read_body(reader: &mut impl BufRead, head: &ResponseHead) -> Result<Vec<u8>, ParseError> {
+    let mut data = vec![];
+
+    if !head.has_body() {
+        return Ok(data);
+    }
+
+    if is_chunked(&head.headers) {
+        return read_chunked_body(reader);
+    }
+
+    match content_length(&head.headers)? {
+        Some(length) => {
+            let read = reader.by_ref().take(length).read_to_end(&mut data)?;
+
+            if (read as u64) < length {
+                return Err(ParseError::IncompleteBody);
+            }
+        }
+        None => {
+            reader.read_to_end(&mut data)?;
+        }
+    }
+
+    Ok(data)
+}
+
+#[derive(Debug)]
+enum Framing {
+    // Bytes left in the body.
+    Length(u64),
+    // Bytes left in the current chunk, and whether a chunk has been read yet.
+    Chunked { left: u64, started: bool },
+    UntilClose,
+    Done,
+}
+
+/// Reads a message body as it arrives, undoing chunked framing, for responses too long-lived
+/// to read to the end first (e.g. event streams).
+pub struct BodyReader<R: BufRead> {
+    reader: R,
+    framing: Framing,
+}
+
+impl<R: BufRead> BodyReader<R> {
+    pub fn new(reader: R, head: &ResponseHead) -> Result<BodyReader<R>, ParseError> {
+        let framing = if !head.has_body() {
+            Framing::Done
+        } else if is_chunked(&head.headers) {
+            Framing::Chunked {
+                left: 0,
+                started: false,
+            }
+        } else {
+            match content_length(&head.headers)? {
+                Some(length) => Framing::Length(length),
+                None => Framing::UntilClose,
+            }
+        };
+
+        Ok(BodyReader { reader, framing })
+    }
+
+    // Moves on to the next chunk, returning false after the last one.
+    fn next_chunk(&mut self, started: bool) -> Result<bool, ParseError> {
+        if started {
+            read_chunk_end(&mut self.reader)?;
+        }
+
+        match read_chunk_size(&mut self.reader)? {
+            0 => {
+                skip_trailers(&mut self.reader)?;
+                self.framing = Framing::Done;
+                Ok(false)
+            }
+            left => {
+                self.framing = Framing::Chunked {
+                    left,
+                    started: true,
+                };
+                Ok(true)
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Read for BodyReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if let Framing::Chunked { left: 0, started } = self.framing {
+            if !self.next_chunk(started)? {
+                return Ok(0);
+            }
+        }
+
+        let left = match self.framing {
+            Framing::Done => return Ok(0),
+            Framing::UntilClose => return self.reader.read(buffer),
+            Framing::Length(left) | Framing::Chunked { left, .. } => left,
+        };
+
+        if left == 0 {
+            self.framing = Framing::Done;
+            return Ok(0);
+        }
+
+        let wanted = buffer.len().min(left as usize);
+        let read = self.reader.read(&mut buffer[..wanted])?;
+
+        if read == 0 {
+            return Err(ParseError::IncompleteBody.into());
+        }
+
+        match &mut self.framing {
+            Framing::Length(left) | Framing::Chunked { left, .. } => *left -= read as u64,
+            _ => (),
+        }
+
+        Ok(read)
+    }
+}
+
+/// Parses a complete response, skipping any interim 1xx responses before the final one.
+pub fn parse_response(data: &[u8]) -> Result<(ResponseHead, Vec<u8>), ParseError> {
+    let mut reader = io::BufReader::new(data);
+
+    loop {
+        let head = parse_head(&mut reader)?;
+
+        // 101 Switching Protocols is final: whatever follows is no longer HTTP/1.1.
+        if (100..200).contains(&head.status_code) && head.status_code != 101 {
+            continue;
+        }
+
+        let body = read_body(&mut reader, &head)?;
+
+        return Ok((head, body));
+    }
+}
+
+#[cfg(test)]
+mod status_line_tests {
+    use super::{parse_status_line, ParseError};
+
+    #[test]
+    fn keeps_the_whole_reason_phrase() {
+        let (version, code, reason) = parse_status_line(b"HTTP/1.1 404 Not Found").unwrap();
+
+        assert_eq!(version, "1.1");
+        assert_eq!(code, 404);
+        assert_eq!(reason, "Not Found");
+    }
+
+    #[test]
+    fn accepts_a_missing_reason_phrase() {
+        assert_eq!(
+            parse_status_line(b"HTTP/1.1 204").unwrap(),
+            (String::from("1.1"), 204, String::new())
+        );
+        assert_eq!(
+            parse_status_line(b"HTTP/1.0 200 ").unwrap(),
+            (String::from("1.0"), 200, String::new())
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_status_lines() {
+        assert!(matches!(
+            parse_status_line(b"garbage"),
+            Err(ParseError::InvalidStatusLine(_))
+        ));
+        assert!(matches!(
+            parse_status_line(b"HTTX/1.1 200 OK"),
+            Err(ParseError::InvalidVersion(_))
+        ));
+        assert!(matches!(
+            parse_status_line(b"HTTP/1.1 2000 OK"),
+            Err(ParseError::InvalidStatusCode(_))
+        ));
+        assert!(matches!(
+            parse_status_line(b"HTTP/1.1 abc OK"),
+            Err(ParseError::InvalidStatusCode(_))
+        ));
+    }
+}
+
+#[cfg(test)]
+mod response_parser_tests {
+    use super::{parse_response, ParseError, MAX_HEADER_COUNT, MAX_LINE_LENGTH};
+
+    #[test]
+    fn parses_headers_and_body() {
+        let (head, body) =
+            parse_response(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nServer: x\r\n\r\nhelloextra")
+                .unwrap();
+
+        assert_eq!(head.status_code, 200);
+        assert_eq!(head.headers.get("server"), Some(&String::from("x")));
+        assert_eq!(body, b"hello");
+    }
+
+    #[test]
+    fn accepts_bare_line_feeds_and_folded_headers() {
+        let (head, body) =
+            parse_response(b"HTTP/1.1 200 OK\nX-Long: first\n  second\n\nbody").unwrap();
+
+        assert_eq!(
+            head.headers.get("x-long"),
+            Some(&String::from("first second"))
+        );
+        assert_eq!(body, b"body");
+    }
+
+    #[test]
+    fn truncated_headers_are_an_error_not_a_hang() {
+        assert_eq!(
+            parse_response(b"HTTP/1.1 200 OK\r\nServer: x\r\n"),
+            Err(ParseError::Incomplete)
+        );
+        assert_eq!(
+            parse_response(b"HTTP/1.1 200 OK\r\nServ"),
+            Err(ParseError::Incomplete)
+        );
+        assert_eq!(parse_response(b""), Err(ParseError::Incomplete));
+    }
+
+    #[test]
+    fn limits_header_count_and_line_length() {
+        let mut many_headers = String::from("HTTP/1.1 200 OK\r\n");
+        for index in 0..=MAX_HEADER_COUNT {
+            many_headers.push_str(&format!("X-{index}: value\r\n"));
+        }
+        many_headers.push_str("\r\n");
+
+        assert_eq!(
+            parse_response(many_headers.as_bytes()),
+            Err(ParseError::TooManyHeaders)
+        );
+
+        let long_line = format!(
+            "HTTP/1.1 200 OK\r\nX-Long: {}\r\n\r\n",
+            "a".repeat(MAX_LINE_LENGTH)
+        );
+
+        assert_eq!(
+            parse_response(long_line.as_bytes()),
+            Err(ParseError::LineTooLong)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_header_lines() {
+        assert!(matches!(
+            parse_response(b"HTTP/1.1 200 OK\r\nno colon here\r\n\r\n"),
+            Err(ParseError::InvalidHeader(_))
+        ));
+        assert!(matches!(
+            parse_response(b"HTTP/1.1 200 OK\r\nBad Name: x\r\n\r\n"),
+            Err(ParseError::InvalidHeader(_))
+        ));
+    }
+
+    #[test]
+    fn decodes_chunked_bodies_with_extensions_and_trailers() {
+        let (_, body) = parse_response(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+              5;name=value\r\nhello\r\n6\r\n world\r\n0\r\nX-Trailer: yes\r\n\r\n",
+        )
+        .unwrap();
+
+        assert_eq!(body, b"hello world");
+    }
+
+    #[test]
+    fn truncated_bodies_are_reported() {
+        assert_eq!(
+            parse_response(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort"),
+            Err(ParseError::IncompleteBody)
+        );
+        assert_eq!(
+            parse_response(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\na\r\nshort"),
+            Err(ParseError::IncompleteBody)
+        );
+        assert!(matches!(
+            parse_response(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n"),
+            Err(ParseError::InvalidChunkSize(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_conflicting_content_lengths() {
+        assert!(matches!(
+            parse_response(b"HTTP/1.1 200 OK\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab"),
+            Err(ParseError::InvalidContentLength(_))
+        ));
+    }
+
+    #[test]
+    fn skips_interim_responses() {
+        let (head, body) =
+            parse_response(b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\n\r\ndone").unwrap();
+
+        assert_eq!(head.status_code, 200);
+        assert_eq!(body, b"done");
+    }
+}
+
+#[cfg(test)]
+mod body_reader_tests {
+    use super::{parse_head, BodyReader};
+    use std::io::{self, BufReader, Read};
+
+    // Reads the body of `response` a few bytes at a time, the way a slow stream arrives.
+    fn read_slowly(response: &[u8]) -> io::Result<Vec<u8>> {
+        let mut reader = BufReader::with_capacity(3, response);
+        let head = parse_head(&mut reader).unwrap();
+        let mut body = BodyReader::new(reader, &head).unwrap();
+
+        let mut output = Vec::new();
+        let mut buffer = [0; 2];
+        loop {
+            match body.read(&mut buffer)? {
+                0 => return Ok(output),
+                read => output.extend_from_slice(&buffer[..read]),
+            }
+        }
+    }
+
+    #[test]
+    fn reads_chunked_bodies_incrementally() {
+        assert_eq!(
+            read_slowly(
+                b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6;x=y\r\n world\r\n0\r\nTrailer: t\r\n\r\nnext"
+            )
+            .unwrap(),
+            b"hello world"
+        );
+    }
+
+    #[test]
+    fn stops_at_the_content_length_or_reads_until_close() {
+        assert_eq!(
+            read_slowly(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhelloextra").unwrap(),
+            b"hello"
+        );
+        assert_eq!(
+            read_slowly(b"HTTP/1.1 200 OK\r\n\r\nuntil the end").unwrap(),
+            b"until the end"
+        );
+        assert_eq!(
+            read_slowly(b"HTTP/1.1 204 No Content\r\n\r\nignored").unwrap(),
+            b""
+        );
+    }
+
+    #[test]
+    fn reports_bodies_cut_short() {
+        for response in [
+            &b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort"[..],
+            &b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\na\r\nshort"[..],
+            &b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n"[..],
+        ] {
+            assert_eq!(
+                read_slowly(response).unwrap_err().kind(),
+                io::ErrorKind::UnexpectedEof
+            );
+        }
+    }
+}
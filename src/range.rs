@@ -0,0 +1,178 @@
+use crate::header::{Header, HeaderMap};
+
+use std::fmt;
+use std::io;
+
+/// A byte range of a representation (RFC 9110 section 14.1.2), inclusive at both ends.
+/// `end` of None reads to the end.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: Option<u64>,
+}
+
+impl ByteRange {
+    pub fn new(start: u64, end: u64) -> Self {
+        Self {
+            start,
+            end: Some(end),
+        }
+    }
+
+    pub fn from(start: u64) -> Self {
+        Self { start, end: None }
+    }
+
+    /// The value of a Range header asking for this range.
+    pub fn header_value(&self) -> String {
+        match self.end {
+            Some(end) => format!("bytes={}-{end}", self.start),
+            None => format!("bytes={}-", self.start),
+        }
+    }
+}
+
+impl fmt::Display for ByteRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.header_value())
+    }
+}
+
+/// A parsed Content-Range header (RFC 9110 section 14.4).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContentRange {
+    // The bytes in this response, None for the "*" of a 416 response.
+    pub range: Option<(u64, u64)>,
+    // Length of the whole representation, None when the server doesn't know it.
+    pub complete_length: Option<u64>,
+}
+
+fn invalid(value: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid Content-Range: {value}"),
+    )
+}
+
+impl ContentRange {
+    pub fn parse(value: &str) -> io::Result<ContentRange> {
+        let (unit, rest) = value.trim().split_once(' ').ok_or_else(|| invalid(value))?;
+
+        if !unit.eq_ignore_ascii_case("bytes") {
+            return Err(invalid(value));
+        }
+
+        let (range, length) = rest.trim().split_once('/').ok_or_else(|| invalid(value))?;
+
+        let complete_length = match length {
+            "*" => None,
+            length => Some(length.parse().map_err(|_| invalid(value))?),
+        };
+
+        let range = match range {
+            "*" => None,
+            range => {
+                let (start, end) = range.split_once('-').ok_or_else(|| invalid(value))?;
+                let start: u64 = start.parse().map_err(|_| invalid(value))?;
+                let end: u64 = end.parse().map_err(|_| invalid(value))?;
+
+                if end < start || complete_length.is_some_and(|length| end >= length) {
+                    return Err(invalid(value));
+                }
+
+                Some((start, end))
+            }
+        };
+
+        // "*/*" says nothing at all.
+        if range.is_none() && complete_length.is_none() {
+            return Err(invalid(value));
+        }
+
+        Ok(ContentRange {
+            range,
+            complete_length,
+        })
+    }
+}
+
+/// The validator to send in If-Range for a response: a strong ETag, or else Last-Modified.
+/// Weak ETags can't be used for ranges (RFC 9110 section 13.1.5).
+pub fn validator(headers: &HeaderMap) -> Option<String> {
+    match headers.get(Header::ETag.as_str()) {
+        Some(etag) if !etag.starts_with("W/") => Some(etag.clone()),
+        _ => headers.get(Header::LastModified.as_str()).cloned(),
+    }
+}
+
+#[cfg(test)]
+mod range_tests {
+    use super::{validator, ByteRange, ContentRange};
+    use crate::header::HeaderMap;
+
+    #[test]
+    fn formats_range_headers() {
+        assert_eq!(ByteRange::new(0, 499).header_value(), "bytes=0-499");
+        assert_eq!(ByteRange::from(9500).header_value(), "bytes=9500-");
+    }
+
+    #[test]
+    fn parses_content_ranges() {
+        assert_eq!(
+            ContentRange::parse("bytes 42-1233/1234").unwrap(),
+            ContentRange {
+                range: Some((42, 1233)),
+                complete_length: Some(1234),
+            }
+        );
+        assert_eq!(
+            ContentRange::parse("bytes 42-1233/*").unwrap(),
+            ContentRange {
+                range: Some((42, 1233)),
+                complete_length: None,
+            }
+        );
+        assert_eq!(
+            ContentRange::parse("bytes */1234").unwrap(),
+            ContentRange {
+                range: None,
+                complete_length: Some(1234),
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_content_ranges() {
+        for value in [
+            "bytes */*",
+            "bytes 10-5/100",
+            "bytes 0-100/100",
+            "items 0-1/2",
+            "bytes 0-1",
+            "bytes a-b/c",
+        ] {
+            assert!(ContentRange::parse(value).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn prefers_strong_etags_as_validators() {
+        let mut headers = HeaderMap::new();
+        headers
+            .insert("Last-Modified", "Sun, 06 Nov 1994 08:49:37 GMT")
+            .unwrap();
+        assert_eq!(
+            validator(&headers).as_deref(),
+            Some("Sun, 06 Nov 1994 08:49:37 GMT")
+        );
+
+        headers.insert("ETag", "W/\"weak\"").unwrap();
+        assert_eq!(
+            validator(&headers).as_deref(),
+            Some("Sun, 06 Nov 1994 08:49:37 GMT")
+        );
+
+        headers.insert("ETag", "\"strong\"").unwrap();
+        assert_eq!(validator(&headers).as_deref(), Some("\"strong\""));
+    }
+}
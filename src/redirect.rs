@@ -0,0 +1,210 @@
+use crate::header::{Header, HeaderMap};
+use crate::uri::{Scheme, URI};
+
+use std::io;
+
+// Credentials meant for one origin, dropped when a redirect leaves it.
+const CROSS_ORIGIN_HEADERS: [Header; 2] = [Header::Authorization, Header::Cookie];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RedirectPolicy {
+    pub max_redirects: u32,
+    // Refuse redirects to any other scheme, host or port.
+    pub same_origin_only: bool,
+    // Follow https:// to http:// redirects, which are refused by default.
+    pub allow_downgrade: bool,
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> Self {
+        Self {
+            max_redirects: 5,
+            same_origin_only: false,
+            allow_downgrade: false,
+        }
+    }
+}
+
+fn redirect_error(message: String) -> io::Error {
+    io::Error::other(message)
+}
+
+impl RedirectPolicy {
+    pub fn never() -> Self {
+        Self {
+            max_redirects: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Checks whether the redirect from `from` to `to` may be followed, given the URLs
+    /// already visited on the way to `from` (including it).
+    pub fn check(&self, from: &URI, to: &URI, visited: &[String]) -> io::Result<()> {
+        let target = to.as_str();
+
+        if visited.len() > self.max_redirects as usize {
+            return Err(redirect_error(format!(
+                "Exceeded the maximum of {} redirects at {target}",
+                self.max_redirects
+            )));
+        }
+
+        if visited.contains(&target) {
+            return Err(redirect_error(format!("Redirect loop at {target}")));
+        }
+
+        if self.same_origin_only && !is_same_origin(from, to) {
+            return Err(redirect_error(format!(
+                "Refusing cross-origin redirect from {} to {target}",
+                from.as_str()
+            )));
+        }
+
+        if !self.allow_downgrade && from.scheme == Scheme::HTTPS && to.scheme == Scheme::HTTP {
+            return Err(redirect_error(format!(
+                "Refusing redirect from HTTPS to {target}"
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether a response status asks to be redirected via its Location header. Other 3xx
+/// responses, such as 304 Not Modified, are final.
+pub fn is_redirect(status_code: u16) -> bool {
+    matches!(status_code, 301 | 302 | 303 | 307 | 308)
+}
+
+pub fn is_same_origin(first: &URI, second: &URI) -> bool {
+    let origin = |url: &URI| {
+        url.authority
+            .as_ref()
+            .map(|authority| (authority.host.to_ascii_lowercase(), authority.port))
+    };
+
+    first.scheme == second.scheme && origin(first) == origin(second)
+}
+
+/// Prepares the headers of a request from `from` for its redirect to `to`: Host follows the
+/// new authority, and credentials are dropped when leaving the origin.
+pub fn rewrite_headers(headers: &mut HeaderMap, from: &URI, to: &URI) {
+    if let Some(host) = to.host_and_port() {
+        if headers.contains_key(Header::Host.as_str()) {
+            headers
+                .insert(Header::Host.as_str(), &host)
+                .expect("Invalid host");
+        }
+    }
+
+    if !is_same_origin(from, to) {
+        for header in CROSS_ORIGIN_HEADERS {
+            headers.remove(header.as_str());
+        }
+    }
+}
+
+#[cfg(test)]
+mod redirect_policy_tests {
+    use super::{is_redirect, rewrite_headers, RedirectPolicy};
+    use crate::header::{Header, HeaderMap};
+    use crate::uri::URI;
+
+    #[test]
+    fn follows_only_redirect_statuses() {
+        assert!(is_redirect(301));
+        assert!(is_redirect(308));
+        assert!(!is_redirect(300));
+        assert!(!is_redirect(304));
+    }
+
+    #[test]
+    fn limits_hops_and_detects_loops() {
+        let policy = RedirectPolicy {
+            max_redirects: 2,
+            ..RedirectPolicy::default()
+        };
+        let from = URI::parse("http://example.org/a");
+        let to = URI::parse("http://example.org/b");
+
+        let visited = vec![from.as_str()];
+        assert!(policy.check(&from, &to, &visited).is_ok());
+
+        let visited = vec![to.as_str(), from.as_str()];
+        let error = policy.check(&from, &to, &visited).unwrap_err();
+        assert!(error.to_string().contains("loop"));
+
+        let visited = vec![
+            String::from("http://example.org/1"),
+            String::from("http://example.org/2"),
+            from.as_str(),
+        ];
+        let error = policy.check(&from, &to, &visited).unwrap_err();
+        assert!(error.to_string().contains("maximum of 2"));
+    }
+
+    #[test]
+    fn refuses_downgrades_and_cross_origin_hops() {
+        let secure = URI::parse("https://example.org/");
+        let visited = vec![secure.as_str()];
+
+        let plain = URI::parse("http://example.org/");
+        assert!(RedirectPolicy::default()
+            .check(&secure, &plain, &visited)
+            .is_err());
+        let permissive = RedirectPolicy {
+            allow_downgrade: true,
+            ..RedirectPolicy::default()
+        };
+        assert!(permissive.check(&secure, &plain, &visited).is_ok());
+
+        let other = URI::parse("https://other.example/");
+        let same_origin = RedirectPolicy {
+            same_origin_only: true,
+            ..RedirectPolicy::default()
+        };
+        assert!(same_origin.check(&secure, &other, &visited).is_err());
+        assert!(same_origin
+            .check(&secure, &URI::parse("https://EXAMPLE.org/next"), &visited)
+            .is_ok());
+    }
+
+    #[test]
+    fn strips_credentials_across_origins() {
+        let mut headers = HeaderMap::new();
+        headers
+            .insert(Header::Host.as_str(), "example.org")
+            .unwrap();
+        headers
+            .insert(Header::Authorization.as_str(), "Basic eA==")
+            .unwrap();
+        headers.insert(Header::Cookie.as_str(), "id=1").unwrap();
+
+        let from = URI::parse("https://example.org/a");
+
+        let mut same = headers.clone();
+        rewrite_headers(&mut same, &from, &URI::parse("https://example.org/b"));
+        assert!(same.contains_key(Header::Authorization.as_str()));
+        assert!(same.contains_key(Header::Cookie.as_str()));
+
+        rewrite_headers(&mut headers, &from, &URI::parse("https://cdn.example/c"));
+        assert_eq!(headers.get(Header::Host.as_str()).unwrap(), "cdn.example");
+        assert!(!headers.contains_key(Header::Authorization.as_str()));
+        assert!(!headers.contains_key(Header::Cookie.as_str()));
+    }
+
+    #[test]
+    fn keeps_non_default_ports_in_host() {
+        let mut headers = HeaderMap::new();
+        headers
+            .insert(Header::Host.as_str(), "example.org")
+            .unwrap();
+        let from = URI::parse("http://example.org/");
+
+        rewrite_headers(&mut headers, &from, &URI::parse("http://h:8080/"));
+        assert_eq!(headers.get(Header::Host.as_str()).unwrap(), "h:8080");
+
+        rewrite_headers(&mut headers, &from, &URI::parse("https://[::1]:443/"));
+        assert_eq!(headers.get(Header::Host.as_str()).unwrap(), "[::1]");
+    }
+}
@@ -1,37 +1,29 @@
+use crate::auth::{self, CredentialStore, Credentials};
 use crate::cache::Cache;
+use crate::encoding;
+use crate::har::{HarEntry, HarLog, Timings};
+use crate::header::{Header, HeaderMap};
+use crate::hsts::{self, HstsStore};
+use crate::http2::{self, HTTP2Request};
+use crate::parser;
+use crate::proxy::{Proxy, ProxyConfig, ProxyKind};
+use crate::range::ByteRange;
+use crate::redirect::{self, RedirectPolicy};
+use crate::resolver::Resolver;
+use crate::retry::{self, RetryPolicy};
+use crate::tls::{TlsInfo, TlsOptions};
+use crate::transport::{
+    self, Network, RawResponse, Recorder, Replay, StreamingResponse, Transport, TransportMode,
+};
 use crate::uri::Scheme;
 use crate::uri::URI;
+use crate::websocket::{self, WebSocket};
 
-use flate2::read::GzDecoder;
-use openssl::ssl::{SslConnector, SslMethod};
-use std::collections::BTreeMap;
 use std::fmt;
-use std::io::{self, BufRead, Cursor, Read, Write};
-use std::net::TcpStream;
-
-pub enum Header {
-    AcceptEncoding,
-    Connection,
-    ContentEncoding,
-    Host,
-    Location,
-    TransferEncoding,
-    UserAgent,
-}
-
-impl Header {
-    fn as_str(&self) -> &'static str {
-        match self {
-            Header::AcceptEncoding => "Accept-Encoding",
-            Header::Connection => "Connection",
-            Header::ContentEncoding => "Content-Encoding",
-            Header::Host => "Host",
-            Header::Location => "Location",
-            Header::TransferEncoding => "Transfer-Encoding",
-            Header::UserAgent => "User-Agent",
-        }
-    }
-}
+use std::io;
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
 #[derive(Clone, Debug)]
 pub enum HTTPMethod {
@@ -46,7 +38,7 @@ pub enum HTTPMethod {
 }
 
 impl HTTPMethod {
-    fn as_str(&self) -> &'static str {
+    pub fn as_str(&self) -> &'static str {
         match self {
             HTTPMethod::CONNECT => "CONNECT",
             HTTPMethod::DELETE => "DELETE",
@@ -58,47 +50,118 @@ impl HTTPMethod {
             HTTPMethod::TRACE => "TRACE",
         }
     }
+
+    // Methods that can safely be retried (RFC 9110 section 9.2.2).
+    pub fn is_idempotent(&self) -> bool {
+        !matches!(self, HTTPMethod::CONNECT | HTTPMethod::POST)
+    }
 }
 #[derive(Clone, Debug)]
 pub struct HTTPRequest {
     pub url: URI,
     pub http_version: String,
     pub method: HTTPMethod,
-    pub headers: BTreeMap<String, String>,
+    pub headers: HeaderMap,
     pub data: String,
 }
 
 impl HTTPRequest {
-    fn build(&self) -> String {
+    fn get(url: &URI) -> HTTPRequest {
+        HTTPRequest {
+            url: url.clone(),
+            data: String::from(""),
+            http_version: String::from("1.1"),
+            headers: Request::build_default_headers(url),
+            method: HTTPMethod::GET,
+        }
+    }
+
+    pub(crate) fn to_http2(&self) -> HTTP2Request {
+        let authority = self.url.host_and_port().expect("No authority");
+
+        // HTTP/2 only goes straight to the origin server, never to an HTTP proxy.
+        let mut headers = self.headers.clone();
+        headers.remove(Header::ProxyAuthorization.as_str());
+
+        HTTP2Request {
+            method: String::from(self.method.as_str()),
+            scheme: String::from(self.url.scheme.as_str()),
+            authority,
+            path: self.url.path.clone(),
+            headers,
+            body: self.data.clone().into_bytes(),
+        }
+    }
+
+    pub(crate) fn build(&self) -> String {
+        self.build_with_target(&self.url.path, "", false)
+    }
+
+    // HTTP proxies expect the absolute URL as the request target (RFC 9112 section 3.2.2).
+    pub(crate) fn build_for_proxy(&self, proxy: &Proxy) -> String {
+        let extra_headers = match proxy.basic_authorization() {
+            Some(authorization)
+                if !self
+                    .headers
+                    .contains_key(Header::ProxyAuthorization.as_str()) =>
+            {
+                format!(
+                    "{}: {authorization}\r\n",
+                    Header::ProxyAuthorization.as_str()
+                )
+            }
+            _ => String::new(),
+        };
+
+        self.build_with_target(&self.url.as_str(), &extra_headers, true)
+    }
+
+    fn build_with_target(&self, target: &str, extra_headers: &str, to_proxy: bool) -> String {
         format!(
-            "{} {} HTTP/{}\r\n{}\r\n\r\n",
+            "{} {} HTTP/{}\r\n{}{}\r\n",
             self.method.as_str(),
-            self.url.path,
+            target,
             self.http_version,
-            self.build_headers()
+            self.build_headers(to_proxy),
+            extra_headers
         )
     }
 
-    fn build_headers(&self) -> String {
+    // Proxy credentials are left out unless the request goes to the proxy itself, so they
+    // never reach the origin server, directly or through a tunnel.
+    fn build_headers(&self, to_proxy: bool) -> String {
         let mut output = String::from("");
 
         for (key, value) in &self.headers {
-            output.push_str(&key);
+            if !to_proxy && key.eq_ignore_ascii_case(Header::ProxyAuthorization.as_str()) {
+                continue;
+            }
+
+            output.push_str(key);
             output.push_str(": ");
-            output.push_str(&value);
+            output.push_str(value);
             output.push_str("\r\n");
         }
 
-        String::from(output)
+        output
     }
 }
 
+#[derive(Clone, Debug)]
 pub struct HTTPResponse {
     pub http_version: String,
     pub status_code: u16,
     pub status_message: String,
-    pub headers: BTreeMap<String, String>,
+    pub headers: HeaderMap,
+    // The body as text, with any invalid UTF-8 replaced; `body` has the exact bytes.
     pub data: String,
+    pub body: Vec<u8>,
+    // Only set for responses fetched over TLS, not for cache hits.
+    pub tls: Option<TlsInfo>,
+    // URLs that redirected to this response, in the order they were requested.
+    pub redirect_chain: Vec<URI>,
+    // How long each phase took, for responses fetched over the network.
+    pub timings: Option<Timings>,
 }
 
 impl HTTPResponse {
@@ -106,13 +169,13 @@ impl HTTPResponse {
         let mut output = String::from("");
 
         for (key, value) in &self.headers {
-            output.push_str(&key);
+            output.push_str(key);
             output.push_str(": ");
-            output.push_str(&value);
+            output.push_str(value);
             output.push_str("\r\n");
         }
 
-        String::from(output)
+        output
     }
 }
 
@@ -130,312 +193,737 @@ impl fmt::Display for HTTPResponse {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Timeouts {
+    pub connect: Option<Duration>,
+    // Longest wait for any single read or write on the socket.
+    pub read: Option<Duration>,
+    // Budget for the whole request, including redirects and retries.
+    pub total: Option<Duration>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect: Some(Duration::from_secs(30)),
+            read: Some(Duration::from_secs(30)),
+            total: None,
+        }
+    }
+}
+
 pub struct RequestOptions {
     pub cache: Cache,
+    pub timeouts: Timeouts,
+    pub retry: RetryPolicy,
+    pub redirect: RedirectPolicy,
+    pub proxies: ProxyConfig,
+    pub tls: TlsOptions,
+    pub transport: TransportMode,
+    // Offer HTTP/2 over TLS, falling back to HTTP/1.1 when the server doesn't pick it.
+    pub http2: bool,
+    pub hsts: HstsStore,
+    // Try https:// first for every http:// URL, falling back when that fails.
+    pub https_first: bool,
+    // Where credentials for 401 and 407 challenges come from.
+    pub auth: CredentialStore,
+    // Keep a HAR log of every exchange, including cache hits.
+    pub har: bool,
+    // Where host names lead, before asking the system resolver.
+    pub resolver: Resolver,
 }
 
+impl RequestOptions {
+    pub fn new(cache: Cache) -> Self {
+        Self {
+            cache,
+            timeouts: Timeouts::default(),
+            retry: RetryPolicy::default(),
+            redirect: RedirectPolicy::default(),
+            proxies: ProxyConfig::from_env(),
+            tls: TlsOptions::default(),
+            transport: TransportMode::Network,
+            http2: true,
+            hsts: HstsStore::default(),
+            https_first: false,
+            auth: CredentialStore::default(),
+            har: false,
+            resolver: Resolver::new(),
+        }
+    }
+}
+
+/// Sends requests through the cache. Safe to share between threads: the cache, HSTS
+/// policies and HAR log are each behind a lock.
 pub struct Request {
-    cache: Cache,
+    cache: Mutex<Cache>,
+    timeouts: Timeouts,
+    retry: RetryPolicy,
+    redirect: RedirectPolicy,
+    proxies: ProxyConfig,
+    transport: Box<dyn Transport + Send + Sync>,
+    hsts: Mutex<HstsStore>,
+    https_first: bool,
+    auth: CredentialStore,
+    har: Option<Mutex<HarLog>>,
+}
+
+const HTTP1_ALPN_PROTOCOL: &str = "http/1.1";
+
+// Challenges answered per `send` before the last 401 or 407 is returned as is.
+const MAX_AUTH_ATTEMPTS: u32 = 3;
+
+// Raw response bytes, as cached, along with the parsed response.
+type Exchange = (Vec<u8>, HTTPResponse);
+
+// Responses `send` may answer with credentials, which are never cached.
+fn is_auth_challenge(status_code: u16) -> bool {
+    status_code == 401 || status_code == 407
+}
+
+// Cached entries never expire, so a failure stored once would be served from then on.
+fn is_cacheable_status(status_code: u16) -> bool {
+    status_code < 400 && !retry::is_retryable_status(status_code)
 }
 
 impl Request {
     pub fn init(options: RequestOptions) -> Request {
+        let mut tls = options.tls;
+
+        if options.http2 && tls.alpn_protocols.is_empty() {
+            tls.alpn_protocols = vec![
+                String::from(http2::ALPN_PROTOCOL),
+                String::from(HTTP1_ALPN_PROTOCOL),
+            ];
+        }
+
+        let network = Network::new(
+            options.timeouts.clone(),
+            options.proxies.clone(),
+            tls,
+            options.resolver,
+        );
+
+        let transport: Box<dyn Transport + Send + Sync> = match &options.transport {
+            TransportMode::Network => Box::new(network),
+            TransportMode::Record(directory) => Box::new(Recorder::new(network, directory)),
+            TransportMode::Replay(directory) => Box::new(Replay::new(directory)),
+        };
+
         Request {
-            cache: options.cache,
+            cache: Mutex::new(options.cache),
+            timeouts: options.timeouts,
+            retry: options.retry,
+            redirect: options.redirect,
+            proxies: options.proxies,
+            transport,
+            hsts: Mutex::new(options.hsts),
+            https_first: options.https_first,
+            auth: options.auth,
+            har: options.har.then(|| Mutex::new(HarLog::new())),
         }
     }
 
-    fn build_default_headers(url: &URI) -> BTreeMap<String, String> {
-        let output = BTreeMap::from([
-            (
-                String::from(Header::Host.as_str()),
-                String::from(&url.authority.as_ref().expect("No authority").host),
-            ),
-            (
-                // TODO: Implement keep-alive http://browser.engineering/http.html#exercises
-                String::from(Header::Connection.as_str()),
-                String::from("close"),
-            ),
-            (
-                String::from(Header::UserAgent.as_str()),
-                String::from("Bored Browser"),
-            ),
-            (
-                String::from(Header::AcceptEncoding.as_str()),
-                String::from("gzip"),
-            ),
-        ]);
+    fn cache(&self) -> MutexGuard<'_, Cache> {
+        self.cache.lock().expect("Cache lock poisoned")
+    }
 
-        output
+    fn hsts(&self) -> MutexGuard<'_, HstsStore> {
+        self.hsts.lock().expect("HSTS lock poisoned")
     }
 
-    fn make_request(request: &HTTPRequest) -> io::Result<Vec<u8>> {
-        let mut res = vec![];
+    fn build_default_headers(url: &URI) -> HeaderMap {
+        let mut output = HeaderMap::new();
+
+        let default_headers = [
+            (Header::Host, url.host_and_port().expect("No authority")),
+            // TODO: Implement keep-alive http://browser.engineering/http.html#exercises
+            (Header::Connection, String::from("close")),
+            (Header::UserAgent, String::from("Bored Browser")),
+            (Header::AcceptEncoding, encoding::accept_encoding()),
+        ];
+
+        for (header, value) in default_headers {
+            output
+                .insert(header.as_str(), &value)
+                .expect("Invalid default header");
+        }
 
-        let request_authority = request
-            .url
-            .authority
-            .as_ref()
-            .expect("No authority")
-            .clone();
+        output
+    }
 
-        let host = request_authority.host;
-        let port = request_authority.port.to_string();
+    // The HTTP proxy that plain HTTP requests to `url` are sent to directly, if any.
+    fn absolute_form_proxy(&self, url: &URI) -> Option<&Proxy> {
+        self.proxies
+            .proxy_for(url)
+            .filter(|proxy| proxy.kind == ProxyKind::Http && url.scheme == Scheme::HTTP)
+    }
 
-        // Make request
-        match request.url.scheme {
-            Scheme::HTTPS => {
-                let base_stream = TcpStream::connect(format!("{}:{}", host, port))
-                    .expect("Couldn't connect to the server...");
-                let connector = SslConnector::builder(SslMethod::tls()).unwrap().build();
+    // Credentials answering a proxy's 407 only carry over to a redirect that goes to the same
+    // proxy in absolute form.
+    fn drop_stale_proxy_authorization(&self, from: &URI, request: &mut HTTPRequest) {
+        let proxy = self.absolute_form_proxy(&request.url);
 
-                let mut stream = connector.connect(host.as_str(), base_stream).unwrap();
+        if proxy.is_none() || proxy != self.absolute_form_proxy(from) {
+            request.headers.remove(Header::ProxyAuthorization.as_str());
+        }
+    }
 
-                stream
-                    .write_all(request.build().as_bytes())
-                    .expect("Couldn't send data to server");
+    // Makes the request, retrying idempotent ones on connection failures and 5xx/429 responses.
+    fn fetch(&self, request: &HTTPRequest, deadline: Option<Instant>) -> io::Result<Exchange> {
+        let mut attempt = 0;
 
-                stream.flush()?;
+        loop {
+            let result = self
+                .transport
+                .send(request, deadline)
+                .and_then(Self::parse_raw_response);
 
-                stream.read_to_end(&mut res).unwrap();
-            }
-            Scheme::HTTP => {
-                let mut stream = TcpStream::connect(format!("{}:{}", host, port))
-                    .expect("Couldn't connect to the server...");
-                stream
-                    .write_all(request.build().as_bytes())
-                    .expect("Couldn't send data to server");
+            let can_retry = request.method.is_idempotent() && attempt < self.retry.max_retries;
 
-                stream.flush()?;
+            let delay = match &result {
+                Ok((_, response))
+                    if can_retry && retry::is_retryable_status(response.status_code) =>
+                {
+                    let retry_after = response
+                        .headers
+                        .get(Header::RetryAfter.as_str())
+                        .and_then(|value| retry::parse_retry_after(value, SystemTime::now()));
 
-                stream.read_to_end(&mut res).unwrap();
+                    self.retry.delay(attempt, retry_after)
+                }
+                Err(e) if can_retry && retry::is_retryable_error(e) => self.retry.backoff(attempt),
+                _ => return result,
+            };
+
+            // Give up rather than sleep past the total timeout.
+            match transport::remaining(deadline) {
+                Ok(Some(left)) if left <= delay => return result,
+                Err(_) => return result,
+                _ => (),
             }
-            _ => panic!("Unexpected scheme provided to Request"),
+
+            log::debug!(
+                "Retrying {} in {:?} (attempt {})",
+                request.url.as_str(),
+                delay,
+                attempt + 1
+            );
+            thread::sleep(delay);
+            attempt += 1;
         }
+    }
 
-        Ok(res)
+    // Sends requests sharing an origin concurrently when the transport supports it, e.g. as
+    // HTTP/2 streams. Fails as a whole otherwise.
+    fn fetch_multiplexed(
+        &self,
+        requests: &[HTTPRequest],
+        deadline: Option<Instant>,
+    ) -> io::Result<Vec<io::Result<Exchange>>> {
+        Ok(self
+            .transport
+            .send_multiplexed(requests, deadline)?
+            .into_iter()
+            .map(|result| result.and_then(Self::parse_raw_response))
+            .collect())
     }
 
-    fn parse_http_response(data_buffer: &Vec<u8>) -> io::Result<HTTPResponse> {
-        let mut res_stream = Cursor::new(data_buffer);
+    fn parse_raw_response(raw: RawResponse) -> io::Result<Exchange> {
+        let mut response = Self::parse_http_response(&raw.bytes)?;
+        response.tls = raw.tls;
+        response.timings = raw.timings;
 
-        let mut reader = io::BufReader::new(&mut res_stream);
+        Ok((raw.bytes, response))
+    }
 
-        // Extract HTTP information
-        let mut status_line: String = String::new();
-        reader.read_line(&mut status_line)?;
+    fn parse_http_response(data_buffer: &[u8]) -> io::Result<HTTPResponse> {
+        let (head, mut data) = parser::parse_response(data_buffer)?;
 
-        let status_parts: Vec<&str> = status_line.split(" ").collect();
+        // Undo any content codings (gzip, br, ...) applied by the server
+        if let Some(content_encoding) = head.headers.get_combined(Header::ContentEncoding.as_str())
+        {
+            data = encoding::decode(data, &content_encoding)?;
+        }
 
-        assert!(status_parts[0].contains("HTTP/"));
+        Ok(HTTPResponse {
+            http_version: head.http_version,
+            status_code: head.status_code,
+            status_message: head.status_message,
+            headers: head.headers,
+            data: String::from_utf8_lossy(&data).into_owned(),
+            body: data,
+            tls: None,
+            redirect_chain: Vec::new(),
+            timings: None,
+        })
+    }
 
-        let mut headers = BTreeMap::new();
+    fn build_request_from_redirect_response(
+        request: HTTPRequest,
+        response: &HTTPResponse,
+    ) -> io::Result<HTTPRequest> {
+        let location = response
+            .headers
+            .get(Header::Location.as_str())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Redirect response without Location header",
+                )
+            })?;
+
+        let url = request
+            .url
+            .resolve(location)
+            .filter(|url| matches!(url.scheme, Scheme::HTTP | Scheme::HTTPS))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid redirect location {location:?}"),
+                )
+            })?;
 
-        let http_version = String::from(status_parts[0].trim_start_matches("HTTP/"));
+        let mut new_request = request.clone();
+        new_request.url = url;
 
-        let status_code = status_parts[1].parse::<u16>().unwrap();
+        redirect::rewrite_headers(&mut new_request.headers, &request.url, &new_request.url);
 
-        let status_message = String::from(status_parts[2]);
+        // 303 always, and 301/302 for POST by convention, switch to a GET without a body.
+        let switches_to_get = match response.status_code {
+            303 => !matches!(request.method, HTTPMethod::GET | HTTPMethod::HEAD),
+            301 | 302 => matches!(request.method, HTTPMethod::POST),
+            _ => false,
+        };
 
-        loop {
-            let mut current_line: String = String::new();
-            reader.read_line(&mut current_line)?;
+        if switches_to_get {
+            new_request.method = HTTPMethod::GET;
+            new_request.data = String::new();
+        }
+
+        Ok(new_request)
+    }
 
-            if current_line == String::from("\r\n") {
-                break;
+    /// Fetches several URLs, e.g. a page and its subresources. Uncached HTTPS URLs sharing an
+    /// origin are multiplexed over one HTTP/2 connection when the server supports it; anything
+    /// else, including redirects, goes through `send` one at a time.
+    pub fn send_all(&self, urls: &[URI]) -> Vec<io::Result<HTTPResponse>> {
+        let deadline = self.timeouts.total.map(|total| Instant::now() + total);
+        let mut results: Vec<Option<io::Result<HTTPResponse>>> =
+            std::iter::repeat_with(|| None).take(urls.len()).collect();
+
+        let urls: Vec<URI> = urls
+            .iter()
+            .map(|url| {
+                let mut url = url.clone();
+                self.hsts().upgrade(&mut url);
+                url
+            })
+            .collect();
+
+        let mut origins: Vec<(String, Vec<usize>)> = Vec::new();
+
+        for (index, url) in urls.iter().enumerate() {
+            if url.scheme != Scheme::HTTPS || self.cache().extract(&HTTPRequest::get(url)).is_ok() {
+                continue;
             }
 
-            let (header, value) = current_line.split_once(":").unwrap_or((&current_line, ""));
+            let authority = url.authority.as_ref().expect("No authority");
+            let origin = format!("{}:{}", authority.host, authority.port);
 
-            headers.insert(String::from(header), String::from(value.trim()));
+            match origins.iter_mut().find(|(existing, _)| *existing == origin) {
+                Some((_, indices)) => indices.push(index),
+                None => origins.push((origin, vec![index])),
+            }
         }
 
-        let mut data = vec![];
-        let mut data_length = 0;
-        // Transfer encoding (chunked)
-        if headers.contains_key(Header::TransferEncoding.as_str()) {
-            assert!(
-                headers.get(Header::TransferEncoding.as_str()) == Some(&String::from("chunked"))
-            );
-
-            loop {
-                let mut length_buffer = String::new();
-                let bytes_read = reader
-                    .read_line(&mut length_buffer)
-                    .expect("reading line works");
+        for (origin, indices) in origins {
+            if indices.len() < 2 {
+                continue;
+            }
 
-                if bytes_read == 0 {
-                    break;
+            let requests: Vec<HTTPRequest> = indices
+                .iter()
+                .map(|&index| HTTPRequest::get(&urls[index]))
+                .collect();
+
+            let started = SystemTime::now();
+            let responses = match self.fetch_multiplexed(&requests, deadline) {
+                Ok(responses) => responses,
+                Err(e) => {
+                    log::debug!("Not multiplexing requests to {origin}: {e}");
+                    continue;
                 }
+            };
+
+            for ((index, request), result) in indices.into_iter().zip(&requests).zip(responses) {
+                match result {
+                    Ok((res, response))
+                        if !redirect::is_redirect(response.status_code)
+                            && !is_auth_challenge(response.status_code) =>
+                    {
+                        self.record_har(started, request, &res, &response, false);
+                        self.record_hsts(request, &response);
+                        if is_cacheable_status(response.status_code) {
+                            self.cache().insert(request, res, 0);
+                        }
+                        results[index] = Some(Ok(response));
+                    }
+                    Ok(_) => (),
+                    Err(e) => log::debug!("HTTP/2 request to {} failed: {e}", request.url.as_str()),
+                }
+            }
+        }
 
-                let bytes_to_read = u64::from_str_radix(length_buffer.trim_end(), 16).unwrap();
-
-                let mut data_buffer = vec![];
+        results
+            .into_iter()
+            .zip(&urls)
+            .map(|(result, url)| result.unwrap_or_else(|| self.send(url)))
+            .collect()
+    }
 
-                {
-                    let reader_reference = reader.by_ref();
+    /// The HAR log of every exchange so far, when enabled.
+    pub fn har(&self) -> Option<HarLog> {
+        self.har
+            .as_ref()
+            .map(|har| har.lock().expect("HAR lock poisoned").clone())
+    }
 
-                    // read at most specified number of bytes
-                    let data_bytes_read = reader_reference
-                        .take(bytes_to_read)
-                        .read_to_end(&mut data_buffer)?;
+    fn record_har(
+        &self,
+        started: SystemTime,
+        request: &HTTPRequest,
+        raw: &[u8],
+        response: &HTTPResponse,
+        from_cache: bool,
+    ) {
+        if let Some(har) = &self.har {
+            har.lock()
+                .expect("HAR lock poisoned")
+                .add(HarEntry::new(started, request, raw, response, from_cache));
+        }
+    }
 
-                    if bytes_read == 0 {
-                        break;
-                    }
+    // Remembers Strict-Transport-Security from responses over verified HTTPS connections.
+    fn record_hsts(&self, request: &HTTPRequest, response: &HTTPResponse) {
+        let verified = response.tls.as_ref().is_some_and(|info| info.verified);
 
-                    data_length += data_bytes_read;
-                } // drop our &mut reader_reference so we can use reader again
+        if request.url.scheme != Scheme::HTTPS || !verified {
+            return;
+        }
 
-                data.append(&mut data_buffer);
+        if let Some(header) = response
+            .headers
+            .get(Header::StrictTransportSecurity.as_str())
+        {
+            let host = &request.url.authority.as_ref().expect("No authority").host;
 
-                let mut spacing_buffer: [u8; 2] = [0; 2];
+            if let Err(e) = self.hsts().observe(host, header) {
+                log::warn!("Couldn't save HSTS policy for {host}: {e}");
+            }
+        }
+    }
 
-                reader.read(&mut spacing_buffer)?;
+    // In HTTPS-first mode, tries the https:// version of an http:// request before the
+    // original, switching the request over when it works.
+    fn fetch_https_first(
+        &self,
+        request: &mut HTTPRequest,
+        deadline: Option<Instant>,
+    ) -> io::Result<Exchange> {
+        if self.https_first && request.url.scheme == Scheme::HTTP {
+            let mut upgraded = request.clone();
+            hsts::upgrade_to_https(&mut upgraded.url);
+
+            match self.fetch(&upgraded, deadline) {
+                Ok(exchange) => {
+                    *request = upgraded;
+                    return Ok(exchange);
+                }
+                Err(e) => log::info!(
+                    "HTTPS-first: falling back to {} ({e})",
+                    request.url.as_str()
+                ),
             }
-        } else {
-            data_length = reader.read_to_end(&mut data)?;
         }
 
-        let mut data_string = String::new();
+        self.fetch(request, deadline)
+    }
 
-        // GZIP extraction if required
-        if headers.contains_key(Header::ContentEncoding.as_str()) {
-            assert!(headers.get(Header::ContentEncoding.as_str()) == Some(&String::from("gzip")));
-            let mut deflater = GzDecoder::new(data.as_slice());
-            deflater.read_to_string(&mut data_string)?;
-        } else {
-            data_string = String::from_utf8(data).expect("Could not parse data as utf8...");
-        }
+    // Answers a 401, or a 407 from an HTTP proxy we send absolute-form requests to, by adding
+    // credentials to the request. Returns whether the request is worth sending again.
+    fn authenticate(&self, request: &mut HTTPRequest, response: &HTTPResponse) -> io::Result<bool> {
+        let authority = request.url.authority.as_ref().expect("No authority");
+
+        let (challenge_header, authorization_header, host, known) = match response.status_code {
+            401 => (
+                Header::WWWAuthenticate,
+                Header::Authorization,
+                authority.host.clone(),
+                authority
+                    .userinfo
+                    .as_deref()
+                    .map(Credentials::from_userinfo),
+            ),
+            407 => match self.absolute_form_proxy(&request.url) {
+                Some(proxy) => (
+                    Header::ProxyAuthenticate,
+                    Header::ProxyAuthorization,
+                    proxy.host.clone(),
+                    proxy
+                        .credentials
+                        .as_ref()
+                        .map(|(user, password)| Credentials::new(user, password)),
+                ),
+                None => return Ok(false),
+            },
+            _ => return Ok(false),
+        };
 
-        reader.consume(data_length);
+        let challenges: Vec<auth::Challenge> = response
+            .headers
+            .get_all(challenge_header.as_str())
+            .into_iter()
+            .flat_map(|value| auth::parse_challenges(value))
+            .collect();
+
+        let challenge = match auth::select_challenge(&challenges) {
+            Some(challenge) => challenge,
+            None => {
+                log::debug!("No supported {} challenge", challenge_header.as_str());
+                return Ok(false);
+            }
+        };
 
-        Ok(HTTPResponse {
-            http_version,
-            status_code,
-            status_message,
-            headers,
-            data: data_string,
-        })
+        // A stale nonce means the credentials were fine, only the nonce expired.
+        let stale = challenge
+            .param("stale")
+            .is_some_and(|stale| stale.eq_ignore_ascii_case("true"));
+        let retry = request.headers.contains_key(authorization_header.as_str()) && !stale;
+
+        let credentials = match self.auth.lookup(known, &host, challenge.realm(), retry) {
+            Some(credentials) => credentials,
+            None => return Ok(false),
+        };
+
+        // Digest covers the request target, which is the absolute URL through a proxy.
+        let target = match self.absolute_form_proxy(&request.url) {
+            Some(_) => request.url.as_str(),
+            None => request.url.path.clone(),
+        };
+
+        let authorization =
+            auth::authorization(challenge, &credentials, request.method.as_str(), &target)?;
+
+        log::debug!(
+            "Answering {} challenge for {host} ({})",
+            challenge.scheme,
+            challenge.realm()
+        );
+        request
+            .headers
+            .insert(authorization_header.as_str(), &authorization)?;
+
+        Ok(true)
     }
 
-    fn build_request_from_redirect_response(
-        request: HTTPRequest,
-        response: &HTTPResponse,
-    ) -> HTTPRequest {
-        let mut new_request = request.clone();
+    /// Opens a WebSocket to a `ws://` or `wss://` URL.
+    pub fn websocket(&self, url: &URI) -> io::Result<WebSocket> {
+        let deadline = self.timeouts.total.map(|total| Instant::now() + total);
+        let key = websocket::generate_key()?;
+
+        let mut request = HTTPRequest::get(url);
+        request.headers.remove(Header::AcceptEncoding.as_str());
+        websocket::add_handshake_headers(&mut request.headers, &key)?;
+
+        let upgraded = self.transport.upgrade(&request, deadline)?;
 
-        let location_header = response
+        WebSocket::handshake(upgraded, &key)
+    }
+
+    /// Sends a GET with `headers` added, returning as soon as the response head arrives so
+    /// the body can be read as it streams in. Redirects are followed; nothing is cached.
+    pub fn send_streaming(&self, url: &URI, headers: &HeaderMap) -> io::Result<StreamingResponse> {
+        let mut request = HTTPRequest::get(url);
+        // Bodies are read as they arrive, so they can't be content-coded.
+        request
             .headers
-            .get(Header::Location.as_str())
-            .expect("Redirect response without Location header");
-
-        if location_header.starts_with(Scheme::HTTP.as_str()) {
-            // Absolute
-            println!("{location_header}");
-            let new_url = URI::parse(&location_header);
-            new_request.url = new_url;
-        } else {
-            // Relative path
-            if new_request.url.path.is_empty() {
-                let new_path = format!("{}", location_header);
-                new_request.url.path = new_path;
-            } else {
-                let last_slash_index = new_request.url.path.rfind('/').expect("No slash");
+            .insert(Header::AcceptEncoding.as_str(), "identity")?;
 
-                let path_without_last_relative_portion =
-                    new_request.url.path.split_at(last_slash_index).0;
+        for (name, value) in headers {
+            request.headers.insert(name, value)?;
+        }
+
+        let deadline = self.timeouts.total.map(|total| Instant::now() + total);
+        let mut visited: Vec<String> = Vec::new();
 
-                let new_path = format!("{path_without_last_relative_portion}{location_header}");
-                new_request.url.path = new_path;
+        loop {
+            self.hsts().upgrade(&mut request.url);
+
+            let response = self.transport.send_streaming(&request, deadline)?;
+
+            let is_redirect = redirect::is_redirect(response.head.status_code)
+                && response
+                    .head
+                    .headers
+                    .contains_key(Header::Location.as_str());
+
+            if !is_redirect {
+                return Ok(response);
             }
+
+            visited.push(request.url.as_str());
+
+            let head = HTTPResponse {
+                http_version: response.head.http_version,
+                status_code: response.head.status_code,
+                status_message: response.head.status_message,
+                headers: response.head.headers,
+                data: String::new(),
+                body: Vec::new(),
+                tls: None,
+                redirect_chain: Vec::new(),
+                timings: None,
+            };
+
+            let from = request.url.clone();
+            request = Self::build_request_from_redirect_response(request, &head)?;
+            self.hsts().upgrade(&mut request.url);
+            self.drop_stale_proxy_authorization(&from, &mut request);
+            self.redirect.check(&from, &request.url, &visited)?;
         }
+    }
 
-        new_request
+    pub fn send(&self, url: &URI) -> io::Result<HTTPResponse> {
+        self.send_request(HTTPRequest::get(url))
     }
 
-    pub fn send(&mut self, url: &URI) -> io::Result<HTTPResponse> {
-        let mut request = HTTPRequest {
-            url: url.clone(),
-            data: String::from(""),
-            http_version: String::from("1.1"),
-            headers: Self::build_default_headers(&url),
-            method: HTTPMethod::GET,
-        };
+    /// Fetches only `range` of `url`, expecting a 206 Partial Content response. With
+    /// `if_range`, a validator from an earlier response, the server sends the whole
+    /// representation instead (200) if it changed since.
+    pub fn send_range(
+        &self,
+        url: &URI,
+        range: &ByteRange,
+        if_range: Option<&str>,
+    ) -> io::Result<HTTPResponse> {
+        let mut request = HTTPRequest::get(url);
+        request
+            .headers
+            .insert(Header::Range.as_str(), &range.header_value())?;
+        // Ranges apply to the encoded bytes, so ask for them unencoded.
+        request
+            .headers
+            .insert(Header::AcceptEncoding.as_str(), "identity")?;
 
-        let mut redirect_count = 0;
-        let mut has_response = false;
-        let mut response: HTTPResponse = HTTPResponse {
-            http_version: String::from(""),
-            status_code: 404,
-            status_message: String::from(""),
-            headers: BTreeMap::new(),
-            data: String::from(""),
-        };
+        if let Some(validator) = if_range {
+            request
+                .headers
+                .insert(Header::IfRange.as_str(), validator)?;
+        }
 
-        while !has_response {
-            if redirect_count < 5 {
-                let cache_value = self.cache.extract(&request);
+        self.send_request(request)
+    }
 
-                match cache_value {
-                    Ok(value) => {
-                        log::debug!("Cache hit");
-                        has_response = true;
-                        response = Self::parse_http_response(&value).unwrap();
-                    }
-                    _ => {
-                        let res = Self::make_request(&request)?;
-
-                        let temp_response = Self::parse_http_response(&res);
-                        self.cache.insert(&request, res, 0);
-
-                        match temp_response {
-                            Ok(temp_response) => {
-                                if &temp_response.status_code < &300
-                                    || &temp_response.status_code > &399
-                                {
-                                    has_response = true;
-                                    response = temp_response;
-                                } else {
-                                    request = Self::build_request_from_redirect_response(
-                                        request,
-                                        &temp_response,
-                                    )
-                                }
-                            }
-                            Err(e) => (),
+    fn send_request(&self, mut request: HTTPRequest) -> io::Result<HTTPResponse> {
+        // Partial responses aren't cached, nor looked up in the cache.
+        let cacheable = !request.headers.contains_key(Header::Range.as_str());
+
+        let deadline = self.timeouts.total.map(|total| Instant::now() + total);
+
+        let mut auth_attempts = 0;
+        // Every URL requested so far, to detect redirect loops.
+        let mut visited: Vec<String> = Vec::new();
+        let mut redirect_chain = Vec::new();
+
+        loop {
+            // Applies to redirect targets as well as the initial URL.
+            self.hsts().upgrade(&mut request.url);
+
+            let started = SystemTime::now();
+
+            let cached = if cacheable {
+                self.cache().extract(&request)
+            } else {
+                Err(io::Error::from(io::ErrorKind::NotFound))
+            };
+
+            let response = match cached {
+                Ok(value) => {
+                    log::debug!("Cache hit");
+                    let response = Self::parse_http_response(&value)?;
+                    self.record_har(started, &request, &value, &response, true);
+
+                    response
+                }
+                Err(_) => {
+                    let (res, response) = self.fetch_https_first(&mut request, deadline)?;
+                    self.record_har(started, &request, &res, &response, false);
+                    self.record_hsts(&request, &response);
+
+                    if is_auth_challenge(response.status_code) {
+                        if auth_attempts < MAX_AUTH_ATTEMPTS
+                            && self.authenticate(&mut request, &response)?
+                        {
+                            // Retrying with credentials isn't a redirect.
+                            auth_attempts += 1;
+                            continue;
                         }
+                    } else if cacheable && is_cacheable_status(response.status_code) {
+                        self.cache().insert(&request, res, 0);
                     }
-                };
 
-                redirect_count += 1;
-            } else {
-                panic!("Exceeded maximum redirect count.");
+                    response
+                }
+            };
+
+            if !redirect::is_redirect(response.status_code)
+                || !response.headers.contains_key(Header::Location.as_str())
+            {
+                return Ok(HTTPResponse {
+                    redirect_chain,
+                    ..response
+                });
             }
-        }
 
-        if has_response {
-            return Ok(response);
-        }
+            visited.push(request.url.as_str());
+
+            let from = request.url.clone();
+            let mut next = Self::build_request_from_redirect_response(request, &response)?;
+            self.hsts().upgrade(&mut next.url);
+            self.drop_stale_proxy_authorization(&from, &mut next);
+            self.redirect.check(&from, &next.url, &visited)?;
 
-        return Err(io::Error::new(io::ErrorKind::Other, "No response"));
+            log::debug!(
+                "Following {} redirect to {}",
+                response.status_code,
+                next.url.as_str()
+            );
+            redirect_chain.push(from);
+            request = next;
+        }
     }
 }
 
 #[cfg(test)]
 mod redirect_response_to_request {
-    use std::collections::BTreeMap;
-
-    use crate::request::Header;
+    use crate::header::{Header, HeaderMap};
     use crate::uri::URI;
 
+    fn location_headers(location: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(Header::Location.as_str(), location).unwrap();
+
+        headers
+    }
+
     #[test]
     fn absolute_url_get_redirected_correctly() {
         let request = super::HTTPRequest {
             url: URI::parse(&String::from("http://www.example.org/this_is_a_redirect")),
             http_version: String::from("1.1"),
             method: super::HTTPMethod::GET,
-            headers: BTreeMap::new(),
+            headers: HeaderMap::new(),
             data: String::from(""),
         };
 
@@ -447,14 +935,16 @@ mod redirect_response_to_request {
             http_version: String::from("1.1"),
             status_code: 301,
             status_message: String::new(),
-            headers: BTreeMap::from([(
-                String::from(Header::Location.as_str()),
-                redirect_url_string.clone(),
-            )]),
+            headers: location_headers(&redirect_url_string),
             data: String::from(""),
+            body: Vec::new(),
+            tls: None,
+            redirect_chain: Vec::new(),
+            timings: None,
         };
 
-        let new_request = super::Request::build_request_from_redirect_response(request, &response);
+        let new_request =
+            super::Request::build_request_from_redirect_response(request, &response).unwrap();
 
         assert_eq!(
             redirect_url.authority.as_ref().expect("No authority").host,
@@ -484,7 +974,7 @@ mod redirect_response_to_request {
             url: URI::parse(&String::from("http://www.example.org/this_is_a_redirect")),
             http_version: String::from("1.1"),
             method: super::HTTPMethod::GET,
-            headers: BTreeMap::new(),
+            headers: HeaderMap::new(),
             data: String::from(""),
         };
 
@@ -496,14 +986,16 @@ mod redirect_response_to_request {
             http_version: String::from("1.1"),
             status_code: 301,
             status_message: String::new(),
-            headers: BTreeMap::from([(
-                String::from(Header::Location.as_str()),
-                String::from("/redirected"),
-            )]),
+            headers: location_headers("/redirected"),
             data: String::from(""),
+            body: Vec::new(),
+            tls: None,
+            redirect_chain: Vec::new(),
+            timings: None,
         };
 
-        let new_request = super::Request::build_request_from_redirect_response(request, &response);
+        let new_request =
+            super::Request::build_request_from_redirect_response(request, &response).unwrap();
 
         let authority = redirect_url.authority.unwrap();
         let new_request_authority = new_request.url.authority.unwrap();
@@ -515,18 +1007,18 @@ mod redirect_response_to_request {
     }
 
     #[test]
-    fn relative_url_get_redirected_correctly_with_deep_path() {
+    fn absolute_path_location_replaces_the_whole_path() {
         let request = super::HTTPRequest {
             url: URI::parse(&String::from(
                 "http://www.example.org/deep/path/this_is_a_redirect",
             )),
             http_version: String::from("1.1"),
             method: super::HTTPMethod::GET,
-            headers: BTreeMap::new(),
+            headers: HeaderMap::new(),
             data: String::from(""),
         };
 
-        let redirect_url_string = String::from("http://www.example.org/deep/path/redirected");
+        let redirect_url_string = String::from("http://www.example.org/redirected");
 
         let redirect_url = URI::parse(&redirect_url_string);
 
@@ -534,14 +1026,16 @@ mod redirect_response_to_request {
             http_version: String::from("1.1"),
             status_code: 301,
             status_message: String::new(),
-            headers: BTreeMap::from([(
-                String::from(Header::Location.as_str()),
-                String::from("/redirected"),
-            )]),
+            headers: location_headers("/redirected"),
             data: String::from(""),
+            body: Vec::new(),
+            tls: None,
+            redirect_chain: Vec::new(),
+            timings: None,
         };
 
-        let new_request = super::Request::build_request_from_redirect_response(request, &response);
+        let new_request =
+            super::Request::build_request_from_redirect_response(request, &response).unwrap();
 
         let authority = redirect_url.authority.unwrap();
         let new_request_authority = new_request.url.authority.unwrap();
@@ -551,4 +1045,120 @@ mod redirect_response_to_request {
         assert_eq!(authority.port, new_request_authority.port);
         assert_eq!(redirect_url.scheme, new_request.url.scheme);
     }
+
+    fn redirect(from: &str, location: &str) -> std::io::Result<String> {
+        let request = super::HTTPRequest::get(&URI::parse(from));
+        let response = super::HTTPResponse {
+            http_version: String::from("1.1"),
+            status_code: 302,
+            status_message: String::new(),
+            headers: location_headers(location),
+            data: String::from(""),
+            body: Vec::new(),
+            tls: None,
+            redirect_chain: Vec::new(),
+            timings: None,
+        };
+
+        super::Request::build_request_from_redirect_response(request, &response)
+            .map(|request| request.url.as_str())
+    }
+
+    #[test]
+    fn resolves_locations_like_links() {
+        let from = "http://www.example.org/a/b?x=1";
+
+        assert_eq!(
+            redirect(from, "/login").unwrap(),
+            "http://www.example.org/login"
+        );
+        assert_eq!(redirect(from, "c").unwrap(), "http://www.example.org/a/c");
+        assert_eq!(
+            redirect(from, "?q").unwrap(),
+            "http://www.example.org/a/b?q"
+        );
+        assert_eq!(redirect(from, "//h/p").unwrap(), "http://h/p");
+        assert_eq!(redirect(from, "HTTPS://h/").unwrap(), "https://h/");
+    }
+
+    #[test]
+    fn rejects_malformed_locations() {
+        let from = "http://www.example.org/";
+
+        for location in [
+            "http://x:abc/",
+            "http://a:99999/",
+            "https://[::1/",
+            "file:///etc/passwd",
+        ] {
+            let error = redirect(from, location).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData, "{location}");
+        }
+    }
+
+    #[test]
+    fn see_other_switches_to_get_and_strips_cross_origin_credentials() {
+        let mut headers = HeaderMap::new();
+        headers
+            .insert(Header::Host.as_str(), "www.example.org")
+            .unwrap();
+        headers
+            .insert(Header::Authorization.as_str(), "Basic eA==")
+            .unwrap();
+
+        let request = super::HTTPRequest {
+            url: URI::parse("http://www.example.org/form"),
+            http_version: String::from("1.1"),
+            method: super::HTTPMethod::POST,
+            headers,
+            data: String::from("name=value"),
+        };
+
+        let response = super::HTTPResponse {
+            http_version: String::from("1.1"),
+            status_code: 303,
+            status_message: String::new(),
+            headers: location_headers("http://other.example/done"),
+            data: String::from(""),
+            body: Vec::new(),
+            tls: None,
+            redirect_chain: Vec::new(),
+            timings: None,
+        };
+
+        let new_request =
+            super::Request::build_request_from_redirect_response(request, &response).unwrap();
+
+        assert!(matches!(new_request.method, super::HTTPMethod::GET));
+        assert!(new_request.data.is_empty());
+        assert_eq!(
+            new_request.headers.get(Header::Host.as_str()).unwrap(),
+            "other.example"
+        );
+        assert!(!new_request
+            .headers
+            .contains_key(Header::Authorization.as_str()));
+    }
+}
+
+#[cfg(test)]
+mod http2_request_tests {
+    use crate::uri::URI;
+
+    fn authority(url: &str) -> String {
+        super::HTTPRequest::get(&URI::parse(url))
+            .to_http2()
+            .authority
+    }
+
+    #[test]
+    fn brackets_ipv6_authorities() {
+        assert_eq!(authority("https://example.org/"), "example.org");
+        assert_eq!(authority("https://example.org:8443/"), "example.org:8443");
+        assert_eq!(authority("https://[::1]/"), "[::1]");
+        assert_eq!(
+            authority("https://[2001:db8::7]:8443/a"),
+            "[2001:db8::7]:8443"
+        );
+    }
 }
@@ -0,0 +1,372 @@
+//! Host name resolution with `--resolve` style overrides, a hosts file, a small cache of
+//! system lookups, and happy eyeballs (RFC 8305) connection racing.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::{mpsc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How long system lookups are reused; the system resolver doesn't tell us the real TTLs.
+const CACHE_TTL: Duration = Duration::from_secs(60);
+
+// Head start each connection attempt gets before the next address is tried (RFC 8305
+// section 5 recommends 250ms).
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+// Addresses from the system resolver and when they were looked up, by host and port.
+type Lookups = HashMap<(String, u16), (Instant, Vec<SocketAddr>)>;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+// An address as written in `--resolve` or a URL, with IPv6 ones possibly in brackets.
+fn parse_address(value: &str) -> Option<IpAddr> {
+    let value = value
+        .strip_prefix('[')
+        .and_then(|value| value.strip_suffix(']'))
+        .unwrap_or(value);
+
+    value.parse().ok()
+}
+
+/// Decides which addresses to connect to for a host.
+#[derive(Debug, Default)]
+pub struct Resolver {
+    // From `--resolve host:port:address`, checked first.
+    overrides: HashMap<(String, u16), Vec<IpAddr>>,
+    // From a hosts file, for any port.
+    hosts: HashMap<String, Vec<IpAddr>>,
+    cache: Mutex<Lookups>,
+}
+
+impl Clone for Resolver {
+    // Cached lookups aren't worth sharing.
+    fn clone(&self) -> Self {
+        Self {
+            overrides: self.overrides.clone(),
+            hosts: self.hosts.clone(),
+            cache: Mutex::default(),
+        }
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an override in curl's `--resolve` format, `host:port:address[,address...]`,
+    /// with IPv6 addresses in brackets.
+    pub fn add_override(&mut self, value: &str) -> io::Result<()> {
+        let error = || {
+            invalid(format!(
+                "Invalid --resolve entry {value}, expected host:port:address"
+            ))
+        };
+
+        let (host, rest) = value.split_once(':').ok_or_else(error)?;
+        let (port, addresses) = rest.split_once(':').ok_or_else(error)?;
+        let port: u16 = port.parse().map_err(|_| error())?;
+
+        let addresses = addresses
+            .split(',')
+            .map(|address| parse_address(address.trim()).ok_or_else(error))
+            .collect::<io::Result<Vec<IpAddr>>>()?;
+
+        if host.is_empty() {
+            return Err(error());
+        }
+
+        self.overrides
+            .insert((host.to_ascii_lowercase(), port), addresses);
+
+        Ok(())
+    }
+
+    /// Reads `address name [aliases...]` lines in the format of `/etc/hosts`.
+    pub fn load_hosts_file(&mut self, path: &Path) -> io::Result<()> {
+        let contents = fs::read_to_string(path)?;
+
+        for line in contents.lines() {
+            let line = line.split('#').next().unwrap_or("");
+            let mut fields = line.split_whitespace();
+
+            let address = match fields.next() {
+                Some(address) => parse_address(address)
+                    .ok_or_else(|| invalid(format!("Invalid address in hosts file: {address}")))?,
+                None => continue,
+            };
+
+            for name in fields {
+                self.hosts
+                    .entry(name.to_ascii_lowercase())
+                    .or_default()
+                    .push(address);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The addresses to try for `host:port`, in the order to try them.
+    pub fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        if let Some(address) = parse_address(host) {
+            return Ok(vec![SocketAddr::new(address, port)]);
+        }
+
+        let name = host.to_ascii_lowercase();
+
+        let configured = self
+            .overrides
+            .get(&(name.clone(), port))
+            .or_else(|| self.hosts.get(&name));
+
+        if let Some(addresses) = configured {
+            log::debug!("Resolved {host} from configuration");
+            return Ok(interleave(
+                addresses
+                    .iter()
+                    .map(|&address| SocketAddr::new(address, port))
+                    .collect(),
+            ));
+        }
+
+        let key = (name, port);
+
+        if let Some((resolved, addresses)) = self.cache().get(&key) {
+            if resolved.elapsed() < CACHE_TTL {
+                return Ok(addresses.clone());
+            }
+        }
+
+        let addresses = interleave((host, port).to_socket_addrs()?.collect());
+
+        if addresses.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No addresses found for {host}"),
+            ));
+        }
+
+        self.cache()
+            .insert(key, (Instant::now(), addresses.clone()));
+
+        Ok(addresses)
+    }
+
+    fn cache(&self) -> MutexGuard<'_, Lookups> {
+        self.cache.lock().expect("Resolver cache lock poisoned")
+    }
+}
+
+/// Orders addresses to alternate between IPv6 and IPv4, starting with the family of the
+/// first one (RFC 8305 section 4), so a broken family only costs one attempt delay.
+pub fn interleave(addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_is_ipv6 = match addresses.first() {
+        Some(address) => address.is_ipv6(),
+        None => return addresses,
+    };
+
+    let (mut preferred, mut other): (Vec<SocketAddr>, Vec<SocketAddr>) = addresses
+        .into_iter()
+        .partition(|address| address.is_ipv6() == first_is_ipv6);
+    let mut preferred = preferred.drain(..);
+    let mut other = other.drain(..);
+    let mut output = Vec::new();
+
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => return output,
+            (first, second) => output.extend(first.into_iter().chain(second)),
+        }
+    }
+}
+
+fn connect_one(address: SocketAddr, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    match timeout {
+        Some(timeout) => TcpStream::connect_timeout(&address, timeout),
+        None => TcpStream::connect(address),
+    }
+}
+
+/// Connects to the first of `addresses` to answer, starting another attempt every
+/// `attempt_delay` or as soon as one fails (RFC 8305 section 5). Each attempt gives up
+/// after `timeout`.
+pub fn connect(
+    addresses: &[SocketAddr],
+    attempt_delay: Duration,
+    timeout: Option<Duration>,
+) -> io::Result<TcpStream> {
+    match addresses {
+        [] => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "No addresses to connect to",
+            ))
+        }
+        [address] => return connect_one(*address, timeout),
+        _ => (),
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let mut started = 0;
+    let mut failed = 0;
+    let mut last_error = io::Error::other("No connection attempt finished");
+
+    let start_next = |started: &mut usize| {
+        let address = addresses[*started];
+        let sender = sender.clone();
+        *started += 1;
+
+        // Losing attempts finish in the background, dropping their connections.
+        thread::spawn(move || {
+            let _ = sender.send((address, connect_one(address, timeout)));
+        });
+    };
+
+    start_next(&mut started);
+
+    while failed < addresses.len() {
+        match receiver.recv_timeout(attempt_delay) {
+            Ok((address, Ok(stream))) => {
+                log::debug!("Connected to {address}");
+                return Ok(stream);
+            }
+            Ok((address, Err(e))) => {
+                log::debug!("Couldn't connect to {address}: {e}");
+                failed += 1;
+                last_error = e;
+
+                if started < addresses.len() {
+                    start_next(&mut started);
+                }
+            }
+            Err(_) if started < addresses.len() => start_next(&mut started),
+            Err(_) => (),
+        }
+    }
+
+    Err(last_error)
+}
+
+#[cfg(test)]
+mod resolver_tests {
+    use super::{connect, interleave, Resolver};
+    use crate::testing::temporary_directory;
+    use std::net::{SocketAddr, TcpListener};
+    use std::time::{Duration, Instant};
+
+    fn addresses(values: &[&str]) -> Vec<SocketAddr> {
+        values.iter().map(|value| value.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn overrides_take_precedence_for_their_port() {
+        let mut resolver = Resolver::new();
+        resolver
+            .add_override("Example.com:443:127.0.0.1,[::1]")
+            .unwrap();
+
+        assert_eq!(
+            resolver.resolve("example.com", 443).unwrap(),
+            addresses(&["127.0.0.1:443", "[::1]:443"])
+        );
+        assert!(resolver.add_override("example.com:443").is_err());
+        assert!(resolver
+            .add_override("example.com:https:127.0.0.1")
+            .is_err());
+        assert!(resolver.add_override("example.com:80:not-an-ip").is_err());
+    }
+
+    #[test]
+    fn reads_hosts_files() {
+        let path = temporary_directory("resolver-hosts").join("hosts");
+        std::fs::write(
+            &path,
+            "# comment\n10.0.0.1 intranet intranet.example # trailing\n\n::2 intranet\n",
+        )
+        .unwrap();
+
+        let mut resolver = Resolver::new();
+        resolver.load_hosts_file(&path).unwrap();
+
+        assert_eq!(
+            resolver.resolve("INTRANET", 8080).unwrap(),
+            addresses(&["10.0.0.1:8080", "[::2]:8080"])
+        );
+        assert_eq!(
+            resolver.resolve("intranet.example", 80).unwrap(),
+            addresses(&["10.0.0.1:80"])
+        );
+    }
+
+    #[test]
+    fn resolves_address_literals_directly() {
+        let resolver = Resolver::new();
+
+        assert_eq!(
+            resolver.resolve("::1", 80).unwrap(),
+            addresses(&["[::1]:80"])
+        );
+        assert_eq!(
+            resolver.resolve("[::1]", 80).unwrap(),
+            addresses(&["[::1]:80"])
+        );
+        assert_eq!(
+            resolver.resolve("127.0.0.1", 80).unwrap(),
+            addresses(&["127.0.0.1:80"])
+        );
+    }
+
+    #[test]
+    fn alternates_address_families() {
+        assert_eq!(
+            interleave(addresses(&[
+                "[::1]:80",
+                "[::2]:80",
+                "[::3]:80",
+                "10.0.0.1:80",
+                "10.0.0.2:80"
+            ])),
+            addresses(&[
+                "[::1]:80",
+                "10.0.0.1:80",
+                "[::2]:80",
+                "10.0.0.2:80",
+                "[::3]:80"
+            ])
+        );
+        assert_eq!(
+            interleave(addresses(&["10.0.0.1:80", "[::1]:80", "10.0.0.2:80"])),
+            addresses(&["10.0.0.1:80", "[::1]:80", "10.0.0.2:80"])
+        );
+    }
+
+    #[test]
+    fn connects_to_the_first_address_that_answers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        // Bound and dropped, so nothing listens there any more.
+        let refused = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let started = Instant::now();
+
+        let stream = connect(
+            &[refused, listener.local_addr().unwrap()],
+            Duration::from_secs(5),
+            Some(Duration::from_secs(5)),
+        )
+        .unwrap();
+
+        assert_eq!(stream.peer_addr().unwrap(), listener.local_addr().unwrap());
+        // A refused connection starts the next attempt without waiting out the delay.
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(connect(&[refused], Duration::from_secs(5), None).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+use crate::date;
+
+use std::io;
+use std::time::{Duration, SystemTime};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    // Upper bound for both the backoff and any Retry-After the server asks for.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn never() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Exponential backoff delay before retry number `attempt` (starting at 0).
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt);
+
+        self.base_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+
+    /// Delay before retrying, preferring the server's Retry-After when present.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        retry_after
+            .map(|delay| delay.min(self.max_delay))
+            .unwrap_or_else(|| self.backoff(attempt))
+    }
+}
+
+pub fn is_retryable_status(status_code: u16) -> bool {
+    status_code == 429 || (500..=599).contains(&status_code)
+}
+
+pub fn is_retryable_error(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::UnexpectedEof
+    )
+}
+
+/// Parses a Retry-After value, either delay-seconds or an HTTP-date relative to `now`.
+pub fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let retry_at = date::parse_http_date(value)?;
+
+    // A date in the past means "retry now".
+    Some(retry_at.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod retry_policy_tests {
+    use super::{is_retryable_error, is_retryable_status, parse_retry_after, RetryPolicy};
+    use std::io;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(500),
+        };
+
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(2), Duration::from_millis(400));
+        assert_eq!(policy.backoff(3), Duration::from_millis(500));
+        assert_eq!(policy.backoff(40), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn retry_after_wins_over_backoff_but_is_capped() {
+        let policy = RetryPolicy::default();
+
+        assert_eq!(
+            policy.delay(0, Some(Duration::from_secs(3))),
+            Duration::from_secs(3)
+        );
+        assert_eq!(
+            policy.delay(0, Some(Duration::from_secs(3600))),
+            policy.max_delay
+        );
+        assert_eq!(policy.delay(1, None), policy.backoff(1));
+    }
+
+    #[test]
+    fn parses_retry_after_seconds_and_dates() {
+        let now = UNIX_EPOCH + Duration::from_secs(784111777);
+
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Sun, 06 Nov 1994 08:50:07 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Sun, 06 Nov 1994 08:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn classifies_retryable_failures() {
+        assert!(is_retryable_status(503));
+        assert!(is_retryable_status(429));
+        assert!(!is_retryable_status(404));
+        assert!(is_retryable_error(&io::Error::from(
+            io::ErrorKind::ConnectionReset
+        )));
+        assert!(!is_retryable_error(&io::Error::from(
+            io::ErrorKind::InvalidData
+        )));
+    }
+}
@@ -0,0 +1,368 @@
+//! Server-Sent Events: parsing `text/event-stream` bodies as they arrive, and following a
+//! feed across reconnections.
+
+use crate::header::{Header, HeaderMap};
+use crate::request::Request;
+use crate::retry;
+use crate::transport::StreamingResponse;
+use crate::uri::URI;
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::Duration;
+
+pub const MIME_TYPE: &str = "text/event-stream";
+
+// Wait before reconnecting until the server sets its own with `retry:`.
+const DEFAULT_RETRY: Duration = Duration::from_secs(3);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    // "message" unless the server named it with `event:`.
+    pub event: String,
+    pub data: String,
+    // The last event ID when this event was dispatched, empty when there is none.
+    pub id: String,
+}
+
+/// Writes the event back in the event stream format, so output can be read like a feed.
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.id.is_empty() {
+            writeln!(f, "id: {}", self.id)?;
+        }
+
+        if self.event != "message" {
+            writeln!(f, "event: {}", self.event)?;
+        }
+
+        for line in self.data.split('\n') {
+            writeln!(f, "data: {line}")?;
+        }
+
+        writeln!(f)
+    }
+}
+
+/// Parses an event stream fed in pieces of any size (HTML Living Standard section 9.2.6).
+#[derive(Debug, Default)]
+pub struct EventParser {
+    // The incomplete line at the end of what was fed so far.
+    line: Vec<u8>,
+    // A CR ended the last line, so a LF right after it isn't another line break.
+    after_cr: bool,
+    started: bool,
+    data: String,
+    event: String,
+    // The ID of the event being received, only taken as the last event ID once it's complete.
+    id: String,
+    last_event_id: String,
+    retry: Option<Duration>,
+}
+
+impl EventParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses more of the stream, returning the events it completes.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Event> {
+        let mut events = Vec::new();
+
+        for &byte in bytes {
+            let after_cr = std::mem::replace(&mut self.after_cr, byte == b'\r');
+
+            match byte {
+                b'\n' if after_cr => (),
+                b'\r' | b'\n' => {
+                    let line = std::mem::take(&mut self.line);
+                    events.extend(self.process_line(&line));
+                }
+                byte => self.line.push(byte),
+            }
+        }
+
+        events
+    }
+
+    /// The ID to resume from with Last-Event-ID, empty when there is none.
+    pub fn last_event_id(&self) -> &str {
+        &self.last_event_id
+    }
+
+    /// How long the server asked clients to wait before reconnecting.
+    pub fn retry(&self) -> Option<Duration> {
+        self.retry
+    }
+
+    /// Forgets any partly received event, as after losing the connection.
+    pub fn reset(&mut self) {
+        self.line.clear();
+        self.after_cr = false;
+        self.started = false;
+        self.data.clear();
+        self.event.clear();
+        self.id.clone_from(&self.last_event_id);
+    }
+
+    fn process_line(&mut self, line: &[u8]) -> Option<Event> {
+        let mut line = String::from_utf8_lossy(line).into_owned();
+
+        // A byte order mark may start the stream.
+        if !self.started {
+            self.started = true;
+            if let Some(stripped) = line.strip_prefix('\u{FEFF}') {
+                line = String::from(stripped);
+            }
+        }
+
+        if line.is_empty() {
+            return self.dispatch();
+        }
+
+        if line.starts_with(':') {
+            // Comment, often sent to keep the connection alive.
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line.as_str(), ""),
+        };
+
+        match field {
+            "event" => self.event = String::from(value),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "id" if !value.contains('\0') => self.id = String::from(value),
+            "retry" if !value.is_empty() && value.bytes().all(|byte| byte.is_ascii_digit()) => {
+                self.retry = value.parse().ok().map(Duration::from_millis);
+            }
+            _ => (),
+        }
+
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<Event> {
+        self.last_event_id.clone_from(&self.id);
+        let event = std::mem::take(&mut self.event);
+        let mut data = std::mem::take(&mut self.data);
+
+        if data.is_empty() {
+            return None;
+        }
+        data.pop();
+
+        Some(Event {
+            event: if event.is_empty() {
+                String::from("message")
+            } else {
+                event
+            },
+            data,
+            id: self.last_event_id.clone(),
+        })
+    }
+}
+
+fn is_event_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get(Header::ContentType.as_str())
+        .and_then(|content_type| content_type.split(';').next())
+        .is_some_and(|mime_type| mime_type.trim().eq_ignore_ascii_case(MIME_TYPE))
+}
+
+// Prints the events of one connection to `output`, returning whether to reconnect.
+fn read_events(
+    mut response: StreamingResponse,
+    parser: &mut EventParser,
+    output: &mut impl Write,
+) -> io::Result<bool> {
+    match response.head.status_code {
+        204 => return Ok(false),
+        200 if is_event_stream(&response.head.headers) => (),
+        status => {
+            return Err(io::Error::other(format!(
+                "Not an event stream: {status} {}",
+                response.head.status_message
+            )))
+        }
+    }
+
+    // Events can be far apart, so only a closed connection ends the stream.
+    if let Some(socket) = &response.socket {
+        socket.set_read_timeout(None)?;
+    }
+
+    let mut buffer = [0; 8192];
+
+    loop {
+        let read = match response.body.read(&mut buffer) {
+            Ok(0) => return Ok(true),
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                log::debug!("Event stream interrupted: {e}");
+                return Ok(true);
+            }
+        };
+
+        for event in parser.feed(&buffer[..read]) {
+            write!(output, "{event}")?;
+        }
+        output.flush()?;
+    }
+}
+
+/// Prints events from the feed at `url` to `output` as they arrive. When the connection
+/// drops, reconnects after the server's retry delay, resuming with Last-Event-ID, up to
+/// `max_reconnects` times (forever for None). A response that isn't a 200 event stream
+/// ends it, as does a 204 which tells clients to stop.
+pub fn follow(
+    request: &Request,
+    url: &URI,
+    output: &mut impl Write,
+    max_reconnects: Option<u32>,
+) -> io::Result<()> {
+    let mut parser = EventParser::new();
+    let mut reconnects = 0;
+
+    loop {
+        let mut headers = HeaderMap::new();
+        headers.insert(Header::Accept.as_str(), MIME_TYPE)?;
+        headers.insert(Header::CacheControl.as_str(), "no-cache")?;
+        if !parser.last_event_id().is_empty() {
+            headers.insert("Last-Event-ID", parser.last_event_id())?;
+        }
+
+        let result = request
+            .send_streaming(url, &headers)
+            .and_then(|response| read_events(response, &mut parser, output));
+
+        match result {
+            Ok(false) => return Ok(()),
+            Ok(true) => (),
+            // Failing to connect at all is worth retrying, anything else isn't.
+            Err(e) if retry::is_retryable_error(&e) => {
+                log::debug!("Couldn't connect to the event stream: {e}");
+            }
+            Err(e) => return Err(e),
+        }
+
+        if max_reconnects.is_some_and(|max| reconnects >= max) {
+            return Ok(());
+        }
+        reconnects += 1;
+
+        let delay = parser.retry().unwrap_or(DEFAULT_RETRY);
+        log::info!("Reconnecting to {} in {delay:?}", url.as_str());
+        parser.reset();
+        thread::sleep(delay);
+    }
+}
+
+#[cfg(test)]
+mod event_parser_tests {
+    use super::{Event, EventParser};
+    use std::time::Duration;
+
+    fn event(event: &str, data: &str, id: &str) -> Event {
+        Event {
+            event: String::from(event),
+            data: String::from(data),
+            id: String::from(id),
+        }
+    }
+
+    #[test]
+    fn parses_fields_and_multiline_data() {
+        let mut parser = EventParser::new();
+
+        let events = parser.feed(
+            b": keep-alive\nevent: update\ndata: first\ndata:second\nid: 7\n\ndata: plain\n\n",
+        );
+
+        assert_eq!(
+            events,
+            vec![
+                event("update", "first\nsecond", "7"),
+                event("message", "plain", "7"),
+            ]
+        );
+        assert_eq!(parser.last_event_id(), "7");
+    }
+
+    #[test]
+    fn handles_input_split_anywhere() {
+        let stream = b"\xEF\xBB\xBFdata: caf\xC3\xA9\r\n\r\ndata\r\rdata: x\n\n";
+        let mut parser = EventParser::new();
+        let mut events = Vec::new();
+
+        for byte in stream {
+            events.extend(parser.feed(&[*byte]));
+        }
+
+        assert_eq!(
+            events,
+            vec![
+                event("message", "café", ""),
+                event("message", "", ""),
+                event("message", "x", ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_events_without_data_and_bad_fields() {
+        let mut parser = EventParser::new();
+
+        let events = parser.feed(b"event: lonely\n\nid: a\0b\nretry: soon\nunknown: x\n\n");
+
+        assert!(events.is_empty());
+        assert_eq!(parser.last_event_id(), "");
+        assert_eq!(parser.retry(), None);
+    }
+
+    #[test]
+    fn remembers_retry_but_not_the_id_of_lost_events_across_resets() {
+        let mut parser = EventParser::new();
+
+        parser.feed(b"retry: 1500\nid: 3\ndata: partial");
+        parser.reset();
+        let events = parser.feed(b"data: fresh\n\n");
+
+        assert_eq!(events, vec![event("message", "fresh", "")]);
+        assert_eq!(parser.last_event_id(), "");
+        assert_eq!(parser.retry(), Some(Duration::from_millis(1500)));
+    }
+
+    #[test]
+    fn keeps_the_id_of_the_last_complete_event() {
+        let mut parser = EventParser::new();
+
+        // Events without data still set the ID.
+        assert!(parser.feed(b"id: 7\n\n").is_empty());
+        assert_eq!(parser.last_event_id(), "7");
+
+        parser.feed(b"id: 8\ndata: partial");
+        parser.reset();
+        assert_eq!(parser.last_event_id(), "7");
+        assert_eq!(
+            parser.feed(b"data: fresh\n\n"),
+            vec![event("message", "fresh", "7")]
+        );
+    }
+
+    #[test]
+    fn prints_events_in_stream_format() {
+        assert_eq!(
+            event("update", "a\nb", "9").to_string(),
+            "id: 9\nevent: update\ndata: a\ndata: b\n\n"
+        );
+        assert_eq!(event("message", "x", "").to_string(), "data: x\n\n");
+    }
+}
@@ -0,0 +1,15 @@
+//! Helpers shared by the unit tests.
+
+use std::fs;
+use std::path::PathBuf;
+
+/// An empty directory for this test run. Every module's tests share the namespace, so names
+/// start with the module, like `transport-replay`.
+pub fn temporary_directory(name: &str) -> PathBuf {
+    let directory =
+        std::env::temp_dir().join(format!("bored-browser-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+
+    directory
+}
@@ -0,0 +1,727 @@
+use openssl::base64;
+use openssl::hash::MessageDigest;
+use openssl::ssl::{
+    HandshakeError, SslConnector, SslFiletype, SslMethod, SslRef, SslStream, SslVerifyMode,
+    SslVersion,
+};
+use openssl::x509::{X509NameRef, X509Ref, X509VerifyResult, X509};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+// Verification error codes from OpenSSL's x509_vfy.h
+const X509_V_ERR_UNABLE_TO_GET_ISSUER_CERT: i32 = 2;
+const X509_V_ERR_CERT_NOT_YET_VALID: i32 = 9;
+const X509_V_ERR_CERT_HAS_EXPIRED: i32 = 10;
+const X509_V_ERR_DEPTH_ZERO_SELF_SIGNED_CERT: i32 = 18;
+const X509_V_ERR_SELF_SIGNED_CERT_IN_CHAIN: i32 = 19;
+const X509_V_ERR_UNABLE_TO_GET_ISSUER_CERT_LOCALLY: i32 = 20;
+const X509_V_ERR_UNABLE_TO_VERIFY_LEAF_SIGNATURE: i32 = 21;
+const X509_V_ERR_CERT_REVOKED: i32 = 23;
+const X509_V_ERR_HOSTNAME_MISMATCH: i32 = 62;
+
+const PIN_PREFIX: &str = "sha256//";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TlsVersion {
+    Tls10,
+    Tls11,
+    Tls12,
+    Tls13,
+}
+
+impl FromStr for TlsVersion {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<TlsVersion, Self::Err> {
+        match value.trim_start_matches("TLSv").trim_start_matches("tls") {
+            "1" | "1.0" => Ok(TlsVersion::Tls10),
+            "1.1" => Ok(TlsVersion::Tls11),
+            "1.2" => Ok(TlsVersion::Tls12),
+            "1.3" => Ok(TlsVersion::Tls13),
+            _other => Err("Invalid TLS version."),
+        }
+    }
+}
+
+impl TlsVersion {
+    fn as_ssl_version(&self) -> SslVersion {
+        match self {
+            TlsVersion::Tls10 => SslVersion::TLS1,
+            TlsVersion::Tls11 => SslVersion::TLS1_1,
+            TlsVersion::Tls12 => SslVersion::TLS1_2,
+            TlsVersion::Tls13 => SslVersion::TLS1_3,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TlsOptions {
+    // Trusted in addition to the system roots.
+    pub ca_files: Vec<PathBuf>,
+    pub ca_dirs: Vec<PathBuf>,
+    pub client_certificate: Option<PathBuf>,
+    // Defaults to the certificate file, for combined PEM bundles.
+    pub client_key: Option<PathBuf>,
+    pub min_version: Option<TlsVersion>,
+    // Host to accepted base64 SHA-256 hashes of the server's SubjectPublicKeyInfo.
+    pub pins: HashMap<String, Vec<String>>,
+    // Skips certificate and hostname verification entirely.
+    pub insecure: bool,
+    // Offered during the handshake in order of preference, e.g. "h2" then "http/1.1".
+    pub alpn_protocols: Vec<String>,
+}
+
+fn tls_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn load_certificates(path: &PathBuf) -> io::Result<Vec<X509>> {
+    let pem = fs::read(path)?;
+
+    X509::stack_from_pem(&pem).map_err(|e| {
+        tls_error(format!(
+            "Couldn't read certificates from {}: {e}",
+            path.display()
+        ))
+    })
+}
+
+impl TlsOptions {
+    /// Adds a pin given as `host=sha256//<base64>`; several pins may be separated by `;`.
+    pub fn add_pin(&mut self, spec: &str) -> io::Result<()> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid pin {spec:?}, expected host=sha256//<base64>"),
+            )
+        };
+
+        let (host, hashes) = spec.split_once('=').ok_or_else(invalid)?;
+
+        for hash in hashes.split(';') {
+            let hash = hash.trim().strip_prefix(PIN_PREFIX).ok_or_else(invalid)?;
+
+            if base64::decode_block(hash).map_or(true, |digest| digest.len() != 32) {
+                return Err(invalid());
+            }
+
+            self.pins
+                .entry(host.to_ascii_lowercase())
+                .or_default()
+                .push(String::from(hash));
+        }
+
+        Ok(())
+    }
+
+    pub fn connector(&self) -> io::Result<SslConnector> {
+        let mut builder = SslConnector::builder(SslMethod::tls())?;
+
+        for ca_file in &self.ca_files {
+            for certificate in load_certificates(ca_file)? {
+                builder.cert_store_mut().add_cert(certificate)?;
+            }
+        }
+
+        // Unlike OpenSSL's CApath, the directory doesn't need c_rehash'd names.
+        for ca_dir in &self.ca_dirs {
+            for entry in fs::read_dir(ca_dir)? {
+                let path = entry?.path();
+
+                match load_certificates(&path) {
+                    Ok(certificates) => {
+                        for certificate in certificates {
+                            builder.cert_store_mut().add_cert(certificate)?;
+                        }
+                    }
+                    Err(e) => log::debug!("Skipping {}: {e}", path.display()),
+                }
+            }
+        }
+
+        if let Some(certificate) = &self.client_certificate {
+            let key = self.client_key.as_ref().unwrap_or(certificate);
+
+            builder
+                .set_certificate_chain_file(certificate)
+                .map_err(|e| {
+                    tls_error(format!(
+                        "Couldn't load client certificate {}: {e}",
+                        certificate.display()
+                    ))
+                })?;
+            builder
+                .set_private_key_file(key, SslFiletype::PEM)
+                .map_err(|e| {
+                    tls_error(format!("Couldn't load client key {}: {e}", key.display()))
+                })?;
+            builder
+                .check_private_key()
+                .map_err(|_| tls_error(String::from("Client key doesn't match the certificate")))?;
+        }
+
+        if let Some(min_version) = self.min_version {
+            builder.set_min_proto_version(Some(min_version.as_ssl_version()))?;
+        }
+
+        if !self.alpn_protocols.is_empty() {
+            // Wire format: each protocol name prefixed by its length.
+            let mut protocols = Vec::new();
+            for protocol in &self.alpn_protocols {
+                protocols.push(protocol.len() as u8);
+                protocols.extend_from_slice(protocol.as_bytes());
+            }
+
+            builder.set_alpn_protos(&protocols)?;
+        }
+
+        Ok(builder.build())
+    }
+
+    fn check_pins<S>(&self, host: &str, stream: &SslStream<S>) -> io::Result<()> {
+        let pins = match self.pins.get(&host.to_ascii_lowercase()) {
+            Some(pins) => pins,
+            None => return Ok(()),
+        };
+
+        let certificate = stream
+            .ssl()
+            .peer_certificate()
+            .ok_or_else(|| tls_error(format!("{host} sent no certificate")))?;
+
+        let hash = spki_sha256(&certificate)?;
+
+        if !pins.contains(&hash) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("Public key pin mismatch for {host}: server key is {PIN_PREFIX}{hash}"),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Base64 SHA-256 of the certificate's SubjectPublicKeyInfo, as used for pinning.
+pub fn spki_sha256(certificate: &X509Ref) -> io::Result<String> {
+    let public_key = certificate.public_key()?.public_key_to_der()?;
+    let digest = openssl::hash::hash(MessageDigest::sha256(), &public_key)?;
+
+    Ok(base64::encode_block(&digest))
+}
+
+fn fingerprint(certificate: &X509Ref, digest: MessageDigest) -> io::Result<String> {
+    let bytes = certificate.digest(digest)?;
+
+    Ok(bytes
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<String>>()
+        .join(":"))
+}
+
+fn format_name(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("?");
+            let value = entry
+                .data()
+                .as_utf8()
+                .map(|value| value.to_string())
+                .unwrap_or_default();
+
+            format!("{key}={value}")
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn format_ip_address(bytes: &[u8]) -> String {
+    match bytes.len() {
+        4 => IpAddr::from(<[u8; 4]>::try_from(bytes).unwrap()).to_string(),
+        16 => IpAddr::from(<[u8; 16]>::try_from(bytes).unwrap()).to_string(),
+        _ => format!("{bytes:?}"),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+    pub subject_alt_names: Vec<String>,
+    pub not_before: String,
+    pub not_after: String,
+    pub serial_number: String,
+    pub sha1_fingerprint: String,
+    pub sha256_fingerprint: String,
+    pub spki_sha256: String,
+}
+
+impl CertificateInfo {
+    pub fn from_certificate(certificate: &X509Ref) -> io::Result<CertificateInfo> {
+        let subject_alt_names = certificate
+            .subject_alt_names()
+            .map(|names| {
+                names
+                    .iter()
+                    .filter_map(|name| {
+                        if let Some(dns) = name.dnsname() {
+                            Some(format!("DNS:{dns}"))
+                        } else if let Some(ip) = name.ipaddress() {
+                            Some(format!("IP:{}", format_ip_address(ip)))
+                        } else if let Some(email) = name.email() {
+                            Some(format!("email:{email}"))
+                        } else {
+                            name.uri().map(|uri| format!("URI:{uri}"))
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(CertificateInfo {
+            subject: format_name(certificate.subject_name()),
+            issuer: format_name(certificate.issuer_name()),
+            subject_alt_names,
+            not_before: certificate.not_before().to_string(),
+            not_after: certificate.not_after().to_string(),
+            serial_number: certificate
+                .serial_number()
+                .to_bn()?
+                .to_hex_str()?
+                .to_string(),
+            sha1_fingerprint: fingerprint(certificate, MessageDigest::sha1())?,
+            sha256_fingerprint: fingerprint(certificate, MessageDigest::sha256())?,
+            spki_sha256: spki_sha256(certificate)?,
+        })
+    }
+}
+
+/// What was negotiated with the server, captured right after the handshake.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TlsInfo {
+    pub protocol: String,
+    pub cipher: Option<String>,
+    pub alpn: Option<String>,
+    // Leaf first, as sent by the server.
+    pub certificates: Vec<CertificateInfo>,
+    pub verified: bool,
+}
+
+impl TlsInfo {
+    pub fn from_ssl(ssl: &SslRef) -> io::Result<TlsInfo> {
+        let certificates = match ssl.peer_cert_chain() {
+            Some(chain) => chain
+                .iter()
+                .map(CertificateInfo::from_certificate)
+                .collect::<io::Result<Vec<CertificateInfo>>>()?,
+            None => match ssl.peer_certificate() {
+                Some(certificate) => vec![CertificateInfo::from_certificate(&certificate)?],
+                None => Vec::new(),
+            },
+        };
+
+        Ok(TlsInfo {
+            protocol: String::from(ssl.version_str()),
+            cipher: ssl
+                .current_cipher()
+                .map(|cipher| String::from(cipher.name())),
+            alpn: ssl
+                .selected_alpn_protocol()
+                .map(|protocol| String::from_utf8_lossy(protocol).into_owned()),
+            certificates,
+            verified: ssl.verify_result() == X509VerifyResult::OK,
+        })
+    }
+}
+
+impl fmt::Display for TlsInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Protocol: {}", self.protocol)?;
+        writeln!(f, "Cipher: {}", self.cipher.as_deref().unwrap_or("unknown"))?;
+        writeln!(f, "ALPN: {}", self.alpn.as_deref().unwrap_or("none"))?;
+        writeln!(f, "Verified: {}", if self.verified { "yes" } else { "no" })?;
+
+        for (depth, certificate) in self.certificates.iter().enumerate() {
+            writeln!(f, "Certificate {depth}:")?;
+            writeln!(f, "  Subject: {}", certificate.subject)?;
+            writeln!(f, "  Issuer: {}", certificate.issuer)?;
+            if !certificate.subject_alt_names.is_empty() {
+                writeln!(
+                    f,
+                    "  Alternative names: {}",
+                    certificate.subject_alt_names.join(", ")
+                )?;
+            }
+            writeln!(f, "  Valid from: {}", certificate.not_before)?;
+            writeln!(f, "  Valid until: {}", certificate.not_after)?;
+            writeln!(f, "  Serial number: {}", certificate.serial_number)?;
+            writeln!(f, "  SHA-1 fingerprint: {}", certificate.sha1_fingerprint)?;
+            writeln!(
+                f,
+                "  SHA-256 fingerprint: {}",
+                certificate.sha256_fingerprint
+            )?;
+            writeln!(
+                f,
+                "  Public key pin: {PIN_PREFIX}{}",
+                certificate.spki_sha256
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn describe_verify_result(result: X509VerifyResult, host: &str) -> String {
+    match result.as_raw() {
+        X509_V_ERR_CERT_HAS_EXPIRED => String::from("the certificate has expired"),
+        X509_V_ERR_CERT_NOT_YET_VALID => String::from("the certificate is not yet valid"),
+        X509_V_ERR_HOSTNAME_MISMATCH => format!("the certificate is not valid for {host}"),
+        X509_V_ERR_DEPTH_ZERO_SELF_SIGNED_CERT | X509_V_ERR_SELF_SIGNED_CERT_IN_CHAIN => {
+            String::from("the certificate chain ends in an untrusted self-signed root")
+        }
+        X509_V_ERR_UNABLE_TO_GET_ISSUER_CERT
+        | X509_V_ERR_UNABLE_TO_GET_ISSUER_CERT_LOCALLY
+        | X509_V_ERR_UNABLE_TO_VERIFY_LEAF_SIGNATURE => {
+            String::from("the certificate was issued by an untrusted root")
+        }
+        X509_V_ERR_CERT_REVOKED => String::from("the certificate has been revoked"),
+        _ => String::from(result.error_string()),
+    }
+}
+
+/// Performs the TLS handshake with `host`, reporting certificate problems in detail.
+pub fn connect<S: Read + Write>(
+    connector: &SslConnector,
+    options: &TlsOptions,
+    host: &str,
+    stream: S,
+) -> io::Result<SslStream<S>> {
+    let mut configuration = connector.configure()?;
+
+    if options.insecure {
+        configuration.set_verify_hostname(false);
+        configuration.set_verify(SslVerifyMode::NONE);
+    }
+
+    let stream = match configuration.connect(host, stream) {
+        Ok(stream) => stream,
+        Err(HandshakeError::Failure(handshake)) => {
+            let verify_result = handshake.ssl().verify_result();
+
+            let message = if verify_result != X509VerifyResult::OK {
+                format!(
+                    "Certificate verification failed for {host}: {}",
+                    describe_verify_result(verify_result, host)
+                )
+            } else {
+                format!("TLS handshake with {host} failed: {}", handshake.error())
+            };
+
+            return Err(tls_error(message));
+        }
+        Err(HandshakeError::WouldBlock(_)) => {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("Timed out during the TLS handshake with {host}"),
+            ))
+        }
+        Err(HandshakeError::SetupFailure(e)) => return Err(e.into()),
+    };
+
+    options.check_pins(host, &stream)?;
+
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tls_options_tests {
+    use super::{TlsOptions, TlsVersion};
+    use std::str::FromStr;
+
+    #[test]
+    fn parses_versions() {
+        assert_eq!(TlsVersion::from_str("1.2"), Ok(TlsVersion::Tls12));
+        assert_eq!(TlsVersion::from_str("TLSv1.3"), Ok(TlsVersion::Tls13));
+        assert!(TlsVersion::from_str("2.0").is_err());
+    }
+
+    #[test]
+    fn parses_pins() {
+        let mut options = TlsOptions::default();
+        let hash = "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=";
+
+        options
+            .add_pin(&format!("Internal.Example=sha256//{hash}"))
+            .unwrap();
+
+        assert_eq!(
+            options.pins.get("internal.example"),
+            Some(&vec![String::from(hash)])
+        );
+        assert!(options.add_pin("internal.example").is_err());
+        assert!(options.add_pin("internal.example=md5//abc").is_err());
+        assert!(options
+            .add_pin("internal.example=sha256//c2hvcnQ=")
+            .is_err());
+    }
+}
+
+#[cfg(test)]
+mod tls_handshake_tests {
+    use super::{connect, spki_sha256, TlsInfo, TlsOptions};
+    use crate::testing::temporary_directory;
+    use openssl::asn1::Asn1Time;
+    use openssl::bn::{BigNum, MsbOption};
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::{PKey, Private};
+    use openssl::rsa::Rsa;
+    use openssl::ssl::{select_next_proto, AlpnError, SslAcceptor, SslMethod};
+    use openssl::x509::extension::SubjectAlternativeName;
+    use openssl::x509::{X509NameBuilder, X509};
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::path::PathBuf;
+    use std::thread;
+
+    fn certificate(host: &str, not_before: Asn1Time, not_after: Asn1Time) -> (X509, PKey<Private>) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", host).unwrap();
+        let name = name.build();
+
+        let mut serial = BigNum::new().unwrap();
+        serial.rand(64, MsbOption::MAYBE_ZERO, false).unwrap();
+
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder
+            .set_serial_number(&serial.to_asn1_integer().unwrap())
+            .unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder.set_not_before(&not_before).unwrap();
+        builder.set_not_after(&not_after).unwrap();
+        let alternative_name = SubjectAlternativeName::new()
+            .dns(host)
+            .build(&builder.x509v3_context(None, None))
+            .unwrap();
+        builder.append_extension(alternative_name).unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+
+        (builder.build(), key)
+    }
+
+    fn valid_certificate(host: &str) -> (X509, PKey<Private>) {
+        certificate(
+            host,
+            Asn1Time::days_from_now(0).unwrap(),
+            Asn1Time::days_from_now(30).unwrap(),
+        )
+    }
+
+    // Serves one TLS connection with the given certificate and returns the client socket.
+    fn serve(certificate: X509, key: PKey<Private>) -> TcpStream {
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor.set_certificate(&certificate).unwrap();
+        acceptor.set_private_key(&key).unwrap();
+        acceptor.set_alpn_select_callback(|_, offered| {
+            select_next_proto(b"\x02h2", offered).ok_or(AlpnError::NOACK)
+        });
+        let acceptor = acceptor.build();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            if let Ok(mut stream) = acceptor.accept(stream) {
+                let _ = stream.write_all(b"hello");
+                let _ = stream.shutdown();
+            }
+        });
+
+        TcpStream::connect(address).unwrap()
+    }
+
+    fn write_ca_file(certificate: &X509, name: &str) -> PathBuf {
+        let path = temporary_directory(&format!("tls-{name}")).join("ca.pem");
+        fs::write(&path, certificate.to_pem().unwrap()).unwrap();
+
+        path
+    }
+
+    fn handshake(options: &TlsOptions, host: &str, stream: TcpStream) -> std::io::Result<String> {
+        let connector = options.connector()?;
+        let mut stream = connect(&connector, options, host, stream)?;
+
+        let mut greeting = String::new();
+        stream.read_to_string(&mut greeting)?;
+
+        Ok(greeting)
+    }
+
+    #[test]
+    fn reports_untrusted_roots() {
+        let (certificate, key) = valid_certificate("localhost");
+
+        let error =
+            handshake(&TlsOptions::default(), "localhost", serve(certificate, key)).unwrap_err();
+
+        assert!(error.to_string().contains("untrusted"), "{error}");
+    }
+
+    #[test]
+    fn trusts_extra_ca_files() {
+        let (certificate, key) = valid_certificate("localhost");
+        let options = TlsOptions {
+            ca_files: vec![write_ca_file(&certificate, "trusted")],
+            ..TlsOptions::default()
+        };
+
+        let greeting = handshake(&options, "localhost", serve(certificate, key)).unwrap();
+
+        assert_eq!(greeting, "hello");
+    }
+
+    #[test]
+    fn reports_hostname_mismatches() {
+        let (certificate, key) = valid_certificate("localhost");
+        let options = TlsOptions {
+            ca_files: vec![write_ca_file(&certificate, "mismatch")],
+            ..TlsOptions::default()
+        };
+
+        let error = handshake(&options, "internal.example", serve(certificate, key)).unwrap_err();
+
+        assert!(
+            error.to_string().contains("not valid for internal.example"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn reports_expired_certificates() {
+        let (certificate, key) = certificate(
+            "localhost",
+            Asn1Time::from_unix(1_000_000_000).unwrap(),
+            Asn1Time::from_unix(1_000_086_400).unwrap(),
+        );
+        let options = TlsOptions {
+            ca_files: vec![write_ca_file(&certificate, "expired")],
+            ..TlsOptions::default()
+        };
+
+        let error = handshake(&options, "localhost", serve(certificate, key)).unwrap_err();
+
+        assert!(error.to_string().contains("expired"), "{error}");
+    }
+
+    #[test]
+    fn insecure_skips_verification() {
+        let (certificate, key) = valid_certificate("localhost");
+        let options = TlsOptions {
+            insecure: true,
+            ..TlsOptions::default()
+        };
+
+        let greeting = handshake(&options, "elsewhere.example", serve(certificate, key)).unwrap();
+
+        assert_eq!(greeting, "hello");
+    }
+
+    #[test]
+    fn enforces_public_key_pins() {
+        let (certificate, key) = valid_certificate("localhost");
+        let (other_certificate, _) = valid_certificate("localhost");
+
+        let mut options = TlsOptions {
+            insecure: true,
+            ..TlsOptions::default()
+        };
+        options
+            .add_pin(&format!(
+                "localhost=sha256//{}",
+                spki_sha256(&certificate).unwrap()
+            ))
+            .unwrap();
+
+        let greeting = handshake(
+            &options,
+            "localhost",
+            serve(certificate.clone(), key.clone()),
+        )
+        .unwrap();
+        assert_eq!(greeting, "hello");
+
+        options.pins.clear();
+        options
+            .add_pin(&format!(
+                "localhost=sha256//{}",
+                spki_sha256(&other_certificate).unwrap()
+            ))
+            .unwrap();
+
+        let error = handshake(&options, "localhost", serve(certificate, key)).unwrap_err();
+        assert!(error.to_string().contains("pin mismatch"), "{error}");
+    }
+
+    #[test]
+    fn captures_connection_info() {
+        let (certificate, key) = valid_certificate("localhost");
+        let options = TlsOptions {
+            ca_files: vec![write_ca_file(&certificate, "info")],
+            ..TlsOptions::default()
+        };
+
+        let connector = options.connector().unwrap();
+        let stream = connect(
+            &connector,
+            &options,
+            "localhost",
+            serve(certificate.clone(), key),
+        )
+        .unwrap();
+        let info = TlsInfo::from_ssl(stream.ssl()).unwrap();
+
+        assert!(info.protocol.starts_with("TLSv1."), "{}", info.protocol);
+        assert!(info.cipher.is_some());
+        assert_eq!(info.alpn, None);
+        assert!(info.verified);
+
+        let leaf = &info.certificates[0];
+        assert_eq!(leaf.subject, "CN=localhost");
+        assert_eq!(leaf.issuer, "CN=localhost");
+        assert_eq!(leaf.subject_alt_names, vec![String::from("DNS:localhost")]);
+        assert_eq!(leaf.sha256_fingerprint.len(), 32 * 3 - 1);
+        assert_eq!(leaf.spki_sha256, spki_sha256(&certificate).unwrap());
+        assert!(info.to_string().contains("Subject: CN=localhost"));
+    }
+
+    #[test]
+    fn negotiates_alpn() {
+        let (certificate, key) = valid_certificate("localhost");
+        let options = TlsOptions {
+            insecure: true,
+            alpn_protocols: vec![String::from("h2"), String::from("http/1.1")],
+            ..TlsOptions::default()
+        };
+
+        let connector = options.connector().unwrap();
+        let stream = connect(&connector, &options, "localhost", serve(certificate, key)).unwrap();
+
+        assert_eq!(
+            TlsInfo::from_ssl(stream.ssl()).unwrap().alpn.as_deref(),
+            Some("h2")
+        );
+    }
+}
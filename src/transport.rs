@@ -0,0 +1,723 @@
+//! How requests reach servers: over the network, or recorded to and replayed from a
+//! fixture directory for deterministic tests.
+
+use crate::har::Timings;
+use crate::http2::{self, HTTP2Request};
+use crate::parser::{self, BodyReader, ResponseHead};
+use crate::proxy::{self, Proxy, ProxyConfig, ProxyKind};
+use crate::request::{HTTPRequest, Timeouts};
+use crate::resolver::{self, Resolver};
+use crate::tls::{self, TlsInfo, TlsOptions};
+use crate::uri::Scheme;
+
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const FIXTURE_EXTENSION: &str = "http";
+const FIXTURE_INDEX: &str = "index";
+
+/// A response exactly as received, with what we learned about the connection that carried it.
+#[derive(Clone, Debug, Default)]
+pub struct RawResponse {
+    pub bytes: Vec<u8>,
+    pub tls: Option<TlsInfo>,
+    pub timings: Option<Timings>,
+}
+
+/// A connection the server switched to another protocol with `101 Switching Protocols`.
+pub struct Upgraded {
+    pub head: ResponseHead,
+    pub stream: Box<dyn Stream>,
+    // The underlying socket, kept to adjust timeouts.
+    pub socket: TcpStream,
+    pub tls: Option<TlsInfo>,
+}
+
+/// A response whose body is read as it arrives rather than all at once.
+pub struct StreamingResponse {
+    pub head: ResponseHead,
+    pub body: Box<dyn Read>,
+    // The underlying socket, kept to adjust timeouts; None when not from the network.
+    pub socket: Option<TcpStream>,
+    pub tls: Option<TlsInfo>,
+}
+
+// Reads the final response head from `reader`, skipping interim 1xx responses, and frames
+// the body that follows.
+fn streaming_response(
+    mut reader: BufReader<Box<dyn Stream>>,
+    socket: Option<TcpStream>,
+    tls: Option<TlsInfo>,
+) -> io::Result<StreamingResponse> {
+    let head = loop {
+        let head = parser::parse_head(&mut reader).map_err(io::Error::from)?;
+
+        if !(100..200).contains(&head.status_code) {
+            break head;
+        }
+    };
+    let body = BodyReader::new(reader, &head)?;
+
+    Ok(StreamingResponse {
+        head,
+        body: Box::new(body),
+        socket,
+        tls,
+    })
+}
+
+pub trait Transport {
+    /// Sends one request and returns the response bytes.
+    fn send(&self, request: &HTTPRequest, deadline: Option<Instant>) -> io::Result<RawResponse>;
+
+    /// Sends requests to one origin concurrently, e.g. as HTTP/2 streams. Transports that
+    /// can't fail with Unsupported, and the requests are sent one at a time instead.
+    fn send_multiplexed(
+        &self,
+        _requests: &[HTTPRequest],
+        _deadline: Option<Instant>,
+    ) -> io::Result<Vec<io::Result<RawResponse>>> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Transport can't multiplex requests",
+        ))
+    }
+
+    /// Sends an HTTP/1.1 Upgrade request, e.g. a WebSocket handshake, and hands over the
+    /// connection along with the response head. Nothing past the head is read.
+    fn upgrade(&self, _request: &HTTPRequest, _deadline: Option<Instant>) -> io::Result<Upgraded> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Transport can't upgrade connections",
+        ))
+    }
+
+    /// Sends one request over HTTP/1.1 and returns once the response head has arrived,
+    /// leaving the body to be read as it streams in.
+    fn send_streaming(
+        &self,
+        _request: &HTTPRequest,
+        _deadline: Option<Instant>,
+    ) -> io::Result<StreamingResponse> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Transport can't stream responses",
+        ))
+    }
+}
+
+/// Which transport `Request` uses.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TransportMode {
+    #[default]
+    Network,
+    // Use the network, saving every response to the directory.
+    Record(PathBuf),
+    // Serve responses saved by Record, never touching the network.
+    Replay(PathBuf),
+}
+
+// Socket timeouts surface as WouldBlock on some platforms.
+fn read_error(e: io::Error) -> io::Error {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+            io::Error::new(io::ErrorKind::TimedOut, "Timed out reading the response")
+        }
+        _ => e,
+    }
+}
+
+fn shortest(first: Option<Duration>, second: Option<Duration>) -> Option<Duration> {
+    match (first, second) {
+        (Some(first), Some(second)) => Some(first.min(second)),
+        (first, None) => first,
+        (None, second) => second,
+    }
+}
+
+// Time left before the deadline, or a TimedOut error once it has passed.
+pub(crate) fn remaining(deadline: Option<Instant>) -> io::Result<Option<Duration>> {
+    match deadline {
+        None => Ok(None),
+        Some(deadline) => {
+            let now = Instant::now();
+
+            if now >= deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "Request exceeded its total timeout",
+                ));
+            }
+
+            Ok(Some(deadline - now))
+        }
+    }
+}
+
+pub trait Stream: Read + Write {}
+
+impl<T: Read + Write> Stream for T {}
+
+struct Connection<'a> {
+    stream: Box<dyn Stream>,
+    // The underlying socket, kept to adjust timeouts.
+    socket: TcpStream,
+    // HTTP proxy to address the request to in absolute-form, if any.
+    proxy: Option<&'a Proxy>,
+    // What the TLS handshake negotiated, for HTTPS.
+    tls: Option<TlsInfo>,
+    // DNS, connect and TLS handshake times so far.
+    timings: Timings,
+}
+
+impl Connection<'_> {
+    fn is_http2(&self) -> bool {
+        self.tls
+            .as_ref()
+            .and_then(|info| info.alpn.as_deref())
+            .is_some_and(|protocol| protocol == http2::ALPN_PROTOCOL)
+    }
+}
+
+/// Plain TCP, optionally through a proxy, with TLS for HTTPS.
+pub struct Network {
+    timeouts: Timeouts,
+    proxies: ProxyConfig,
+    tls: TlsOptions,
+    resolver: Resolver,
+}
+
+impl Network {
+    pub fn new(
+        timeouts: Timeouts,
+        proxies: ProxyConfig,
+        tls: TlsOptions,
+        resolver: Resolver,
+    ) -> Network {
+        Network {
+            timeouts,
+            proxies,
+            tls,
+            resolver,
+        }
+    }
+
+    fn connect(
+        &self,
+        host: &str,
+        port: u16,
+        deadline: Option<Instant>,
+        timings: &mut Timings,
+    ) -> io::Result<TcpStream> {
+        let resolving = Instant::now();
+        let addresses = self.resolver.resolve(host, port)?;
+        timings.dns = Some(resolving.elapsed());
+
+        let connecting = Instant::now();
+        let stream = resolver::connect(
+            &addresses,
+            resolver::CONNECTION_ATTEMPT_DELAY,
+            shortest(self.timeouts.connect, remaining(deadline)?),
+        )?;
+        stream.set_write_timeout(self.timeouts.read)?;
+        timings.connect = Some(connecting.elapsed());
+
+        Ok(stream)
+    }
+
+    fn read_response(
+        &self,
+        stream: &mut impl Read,
+        socket: &TcpStream,
+        deadline: Option<Instant>,
+        timings: &mut Timings,
+    ) -> io::Result<Vec<u8>> {
+        let mut res = vec![];
+        let mut buffer = [0; 8192];
+
+        let waiting = Instant::now();
+        let mut first_byte = None;
+
+        loop {
+            socket.set_read_timeout(shortest(self.timeouts.read, remaining(deadline)?))?;
+
+            match stream.read(&mut buffer) {
+                Ok(0) => {
+                    let first_byte = first_byte.unwrap_or_else(Instant::now);
+                    timings.wait = first_byte - waiting;
+                    timings.receive = first_byte.elapsed();
+
+                    return Ok(res);
+                }
+                Ok(bytes_read) => {
+                    first_byte.get_or_insert_with(Instant::now);
+                    res.extend_from_slice(&buffer[..bytes_read]);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(read_error(e)),
+            }
+        }
+    }
+
+    // Opens a connection to the request's origin, tunnelling through a proxy when configured.
+    // With `http1_only`, HTTP/2 isn't offered over TLS.
+    fn open_stream<'a>(
+        &'a self,
+        request: &HTTPRequest,
+        deadline: Option<Instant>,
+        http1_only: bool,
+    ) -> io::Result<Connection<'a>> {
+        let request_authority = request.url.authority.as_ref().expect("No authority");
+
+        let host = request_authority.host.as_str();
+        let port = request_authority.port;
+
+        let proxy = self.proxies.proxy_for(&request.url);
+        let mut timings = Timings::default();
+
+        let mut stream = match proxy {
+            Some(proxy) => {
+                log::debug!("Using proxy {}:{}", proxy.host, proxy.port);
+                self.connect(&proxy.host, proxy.port, deadline, &mut timings)?
+            }
+            None => self.connect(host, port, deadline, &mut timings)?,
+        };
+        let socket = stream.try_clone()?;
+        socket.set_read_timeout(shortest(self.timeouts.read, remaining(deadline)?))?;
+
+        let mut absolute_form_proxy = None;
+        // Tunnelling through the proxy counts as connecting.
+        let tunnelling = Instant::now();
+
+        match (proxy, &request.url.scheme) {
+            (None, _) => (),
+            (Some(proxy), Scheme::HTTP) if proxy.kind == ProxyKind::Http => {
+                absolute_form_proxy = Some(proxy);
+            }
+            (Some(proxy), _) if proxy.kind == ProxyKind::Http => {
+                proxy::http_connect(&mut stream, proxy, host, port)?;
+            }
+            (Some(proxy), _) => {
+                // socks5:// resolves names here, so our overrides apply; socks5h:// leaves
+                // that to the proxy.
+                let target = match proxy.kind {
+                    ProxyKind::Socks5 { remote_dns: false } => {
+                        self.resolver.resolve(host, port)?[0].ip().to_string()
+                    }
+                    _ => String::from(host),
+                };
+                proxy::socks5_connect(&mut stream, proxy, &target, port)?
+            }
+        }
+
+        timings.connect = timings
+            .connect
+            .map(|connect| connect + tunnelling.elapsed());
+
+        match request.url.scheme {
+            Scheme::HTTPS | Scheme::WSS => {
+                let handshaking = Instant::now();
+
+                let connector = if http1_only {
+                    TlsOptions {
+                        alpn_protocols: Vec::new(),
+                        ..self.tls.clone()
+                    }
+                    .connector()?
+                } else {
+                    self.tls.connector()?
+                };
+                let stream = tls::connect(&connector, &self.tls, host, stream)?;
+                let info = TlsInfo::from_ssl(stream.ssl())?;
+
+                let handshake = handshaking.elapsed();
+                timings.ssl = Some(handshake);
+                timings.connect = timings.connect.map(|connect| connect + handshake);
+
+                Ok(Connection {
+                    stream: Box::new(stream),
+                    socket,
+                    proxy: absolute_form_proxy,
+                    tls: Some(info),
+                    timings,
+                })
+            }
+            Scheme::HTTP | Scheme::WS => Ok(Connection {
+                stream: Box::new(stream),
+                socket,
+                proxy: absolute_form_proxy,
+                tls: None,
+                timings,
+            }),
+            _ => panic!("Unexpected scheme provided to Request"),
+        }
+    }
+}
+
+impl Transport for Network {
+    fn send(&self, request: &HTTPRequest, deadline: Option<Instant>) -> io::Result<RawResponse> {
+        let mut connection = self.open_stream(request, deadline, false)?;
+
+        if connection.is_http2() {
+            // Frames interleave, so the whole exchange counts as waiting.
+            let waiting = Instant::now();
+            let mut http2_connection =
+                http2::Connection::handshake(connection.stream).map_err(read_error)?;
+            let response = http2_connection
+                .send_all(std::slice::from_ref(&request.to_http2()))
+                .pop()
+                .expect("One response per request")
+                .map_err(read_error)?;
+            let _ = http2_connection.close();
+            connection.timings.wait = waiting.elapsed();
+
+            return Ok(RawResponse {
+                bytes: response.to_bytes(),
+                tls: connection.tls,
+                timings: Some(connection.timings),
+            });
+        }
+
+        let message = match connection.proxy {
+            Some(proxy) => request.build_for_proxy(proxy),
+            None => request.build(),
+        };
+
+        let sending = Instant::now();
+        connection.stream.write_all(message.as_bytes())?;
+        connection.stream.flush()?;
+        connection.timings.send = sending.elapsed();
+
+        let bytes = self.read_response(
+            &mut connection.stream,
+            &connection.socket,
+            deadline,
+            &mut connection.timings,
+        )?;
+
+        Ok(RawResponse {
+            bytes,
+            tls: connection.tls,
+            timings: Some(connection.timings),
+        })
+    }
+
+    // Upgrades only exist in HTTP/1.1.
+    fn upgrade(&self, request: &HTTPRequest, deadline: Option<Instant>) -> io::Result<Upgraded> {
+        let mut connection = self.open_stream(request, deadline, true)?;
+
+        connection.stream.write_all(request.build().as_bytes())?;
+        connection.stream.flush()?;
+
+        let head = proxy::read_head(&mut connection.stream).map_err(read_error)?;
+        let head = parser::parse_head(&mut head.as_slice())?;
+
+        Ok(Upgraded {
+            head,
+            stream: connection.stream,
+            socket: connection.socket,
+            tls: connection.tls,
+        })
+    }
+
+    fn send_streaming(
+        &self,
+        request: &HTTPRequest,
+        deadline: Option<Instant>,
+    ) -> io::Result<StreamingResponse> {
+        let mut connection = self.open_stream(request, deadline, true)?;
+
+        let message = match connection.proxy {
+            Some(proxy) => request.build_for_proxy(proxy),
+            None => request.build(),
+        };
+        connection.stream.write_all(message.as_bytes())?;
+        connection.stream.flush()?;
+
+        streaming_response(
+            BufReader::new(connection.stream),
+            Some(connection.socket),
+            connection.tls,
+        )
+        .map_err(read_error)
+    }
+
+    // Sends all the requests as concurrent streams on one HTTP/2 connection to their shared
+    // origin. Fails as a whole when the server doesn't speak HTTP/2.
+    fn send_multiplexed(
+        &self,
+        requests: &[HTTPRequest],
+        deadline: Option<Instant>,
+    ) -> io::Result<Vec<io::Result<RawResponse>>> {
+        let connection = self.open_stream(&requests[0], deadline, false)?;
+
+        if !connection.is_http2() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Server didn't negotiate HTTP/2",
+            ));
+        }
+
+        let waiting = Instant::now();
+        let mut http2_connection =
+            http2::Connection::handshake(connection.stream).map_err(read_error)?;
+        let http2_requests: Vec<HTTP2Request> =
+            requests.iter().map(HTTPRequest::to_http2).collect();
+
+        let results = http2_connection.send_all(&http2_requests);
+        let timings = Timings {
+            wait: waiting.elapsed(),
+            ..connection.timings
+        };
+        let _ = http2_connection.close();
+
+        Ok(results
+            .into_iter()
+            .map(|response| {
+                Ok(RawResponse {
+                    bytes: response.map_err(read_error)?.to_bytes(),
+                    tls: connection.tls.clone(),
+                    timings: Some(timings.clone()),
+                })
+            })
+            .collect())
+    }
+}
+
+/// A directory of recorded responses, one file per response to a method and URL. Repeated
+/// requests are numbered in the order they were made.
+#[derive(Clone, Debug)]
+pub struct Fixtures {
+    directory: PathBuf,
+}
+
+impl Fixtures {
+    pub fn new(directory: &Path) -> Fixtures {
+        Fixtures {
+            directory: directory.to_path_buf(),
+        }
+    }
+
+    fn name(method: &str, url: &str, index: usize) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(format!("{method} {url}"));
+
+        format!("{:x}-{index}.{FIXTURE_EXTENSION}", hasher.finalize())
+    }
+
+    /// Saves the `index`th response to `method url`, noting it in a readable index file.
+    pub fn save(&self, method: &str, url: &str, index: usize, bytes: &[u8]) -> io::Result<()> {
+        fs::create_dir_all(&self.directory)?;
+
+        let name = Self::name(method, url, index);
+        fs::write(self.directory.join(&name), bytes)?;
+
+        let mut index_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.directory.join(FIXTURE_INDEX))?;
+        writeln!(index_file, "{name} {method} {url}")
+    }
+
+    /// The `index`th response to `method url`, or the last one recorded before it.
+    pub fn load(&self, method: &str, url: &str, index: usize) -> io::Result<Vec<u8>> {
+        (0..=index)
+            .rev()
+            .find_map(|index| fs::read(self.directory.join(Self::name(method, url, index))).ok())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("No recorded response for {method} {url}"),
+                )
+            })
+    }
+}
+
+// How many times each method and URL was requested, to number repeated requests.
+#[derive(Debug, Default)]
+struct Counter(Mutex<HashMap<String, usize>>);
+
+impl Counter {
+    fn next(&self, request: &HTTPRequest) -> usize {
+        let key = format!("{} {}", request.method.as_str(), request.url.as_str());
+        let mut counts = self.0.lock().expect("Counter lock poisoned");
+        let count = counts.entry(key).or_insert(0);
+
+        *count += 1;
+        *count - 1
+    }
+}
+
+/// Passes requests on to another transport, saving every response it gets.
+pub struct Recorder<T: Transport> {
+    inner: T,
+    fixtures: Fixtures,
+    counter: Counter,
+}
+
+impl<T: Transport> Recorder<T> {
+    pub fn new(inner: T, directory: &Path) -> Recorder<T> {
+        Recorder {
+            inner,
+            fixtures: Fixtures::new(directory),
+            counter: Counter::default(),
+        }
+    }
+}
+
+impl<T: Transport> Transport for Recorder<T> {
+    fn send(&self, request: &HTTPRequest, deadline: Option<Instant>) -> io::Result<RawResponse> {
+        let response = self.inner.send(request, deadline)?;
+
+        self.fixtures.save(
+            request.method.as_str(),
+            &request.url.as_str(),
+            self.counter.next(request),
+            &response.bytes,
+        )?;
+
+        Ok(response)
+    }
+
+    // Upgraded connections carry another protocol, so they aren't recorded.
+    fn upgrade(&self, request: &HTTPRequest, deadline: Option<Instant>) -> io::Result<Upgraded> {
+        self.inner.upgrade(request, deadline)
+    }
+
+    // Streams may never end, so they aren't recorded either.
+    fn send_streaming(
+        &self,
+        request: &HTTPRequest,
+        deadline: Option<Instant>,
+    ) -> io::Result<StreamingResponse> {
+        self.inner.send_streaming(request, deadline)
+    }
+}
+
+/// Serves recorded responses without touching the network.
+pub struct Replay {
+    fixtures: Fixtures,
+    counter: Counter,
+}
+
+impl Replay {
+    pub fn new(directory: &Path) -> Replay {
+        Replay {
+            fixtures: Fixtures::new(directory),
+            counter: Counter::default(),
+        }
+    }
+}
+
+impl Transport for Replay {
+    fn send(&self, request: &HTTPRequest, _deadline: Option<Instant>) -> io::Result<RawResponse> {
+        let bytes = self.fixtures.load(
+            request.method.as_str(),
+            &request.url.as_str(),
+            self.counter.next(request),
+        )?;
+
+        Ok(RawResponse {
+            bytes,
+            ..RawResponse::default()
+        })
+    }
+
+    // Recorded responses are complete, so they stream in all at once.
+    fn send_streaming(
+        &self,
+        request: &HTTPRequest,
+        deadline: Option<Instant>,
+    ) -> io::Result<StreamingResponse> {
+        let bytes = self.send(request, deadline)?.bytes;
+        let stream: Box<dyn Stream> = Box::new(io::Cursor::new(bytes));
+
+        streaming_response(BufReader::new(stream), None, None)
+    }
+}
+
+#[cfg(test)]
+mod transport_tests {
+    use super::{Fixtures, Recorder, Replay, Transport};
+    use crate::header::HeaderMap;
+    use crate::request::{HTTPMethod, HTTPRequest};
+    use crate::testing::temporary_directory;
+    use crate::uri::URI;
+    use std::fs;
+
+    fn get(url: &str) -> HTTPRequest {
+        HTTPRequest {
+            url: URI::parse(url),
+            http_version: String::from("1.1"),
+            method: HTTPMethod::GET,
+            headers: HeaderMap::new(),
+            data: String::new(),
+        }
+    }
+
+    #[test]
+    fn replays_numbered_responses() {
+        let directory = temporary_directory("transport-replay");
+        let fixtures = Fixtures::new(&directory);
+        fixtures
+            .save(
+                "GET",
+                "http://example.org/",
+                0,
+                b"HTTP/1.1 503 Busy\r\n\r\n",
+            )
+            .unwrap();
+        fixtures
+            .save("GET", "http://example.org/", 1, b"HTTP/1.1 200 OK\r\n\r\n")
+            .unwrap();
+
+        let replay = Replay::new(&directory);
+        let request = get("http://example.org/");
+
+        let responses: Vec<Vec<u8>> = (0..3)
+            .map(|_| replay.send(&request, None).unwrap().bytes)
+            .collect();
+        assert_eq!(responses[0], b"HTTP/1.1 503 Busy\r\n\r\n");
+        // Once the recording runs out, the last response repeats.
+        assert_eq!(responses[1], b"HTTP/1.1 200 OK\r\n\r\n");
+        assert_eq!(responses[2], b"HTTP/1.1 200 OK\r\n\r\n");
+
+        let error = replay
+            .send(&get("http://example.org/other"), None)
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn records_what_it_passes_on() {
+        let source = temporary_directory("transport-source");
+        Fixtures::new(&source)
+            .save(
+                "GET",
+                "http://example.org/page",
+                0,
+                b"HTTP/1.1 200 OK\r\n\r\nhello",
+            )
+            .unwrap();
+
+        let destination = temporary_directory("transport-recorded");
+        let recorder = Recorder::new(Replay::new(&source), &destination);
+        recorder
+            .send(&get("http://example.org/page"), None)
+            .unwrap();
+
+        let replayed = Replay::new(&destination)
+            .send(&get("http://example.org/page"), None)
+            .unwrap();
+        assert_eq!(replayed.bytes, b"HTTP/1.1 200 OK\r\n\r\nhello");
+
+        let index = fs::read_to_string(destination.join("index")).unwrap();
+        assert!(index.ends_with("-0.http GET http://example.org/page\n"));
+    }
+}
@@ -0,0 +1,751 @@
+//! WebSocket client (RFC 6455) on top of connections upgraded by a `Transport`.
+
+use crate::header::{Header, HeaderMap};
+use crate::parser::ResponseHead;
+use crate::transport::{Stream, Upgraded};
+
+use openssl::base64;
+use openssl::rand::rand_bytes;
+use openssl::sha::sha1;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Appended to the client's key to derive Sec-WebSocket-Accept (RFC 6455 section 1.3).
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const VERSION: &str = "13";
+
+// Messages larger than this are refused rather than buffered.
+const MAX_MESSAGE_SIZE: usize = 16 << 20;
+
+// Outgoing messages are split into frames of at most this many bytes.
+const MAX_FRAME_SIZE: usize = 1 << 16;
+
+pub const NORMAL_CLOSURE: u16 = 1000;
+
+// How often `pipe` checks for input while waiting for messages.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// How long `pipe` waits for the server to answer our close frame.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn protocol_error(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("WebSocket protocol error: {message}"),
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Opcode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl Opcode {
+    fn from_u8(value: u8) -> io::Result<Opcode> {
+        match value {
+            0x0 => Ok(Opcode::Continuation),
+            0x1 => Ok(Opcode::Text),
+            0x2 => Ok(Opcode::Binary),
+            0x8 => Ok(Opcode::Close),
+            0x9 => Ok(Opcode::Ping),
+            0xA => Ok(Opcode::Pong),
+            other => Err(protocol_error(&format!("unknown opcode {other:#x}"))),
+        }
+    }
+
+    fn as_u8(&self) -> u8 {
+        match self {
+            Opcode::Continuation => 0x0,
+            Opcode::Text => 0x1,
+            Opcode::Binary => 0x2,
+            Opcode::Close => 0x8,
+            Opcode::Ping => 0x9,
+            Opcode::Pong => 0xA,
+        }
+    }
+
+    fn is_control(&self) -> bool {
+        matches!(self, Opcode::Close | Opcode::Ping | Opcode::Pong)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    // Whether this is the last frame of its message.
+    pub fin: bool,
+    pub opcode: Opcode,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(opcode: Opcode, payload: Vec<u8>) -> Frame {
+        Frame {
+            fin: true,
+            opcode,
+            payload,
+        }
+    }
+
+    /// The frame on the wire. Clients must mask every frame they send.
+    pub fn encode(&self, mask: Option<[u8; 4]>) -> Vec<u8> {
+        let mut output = vec![(self.fin as u8) << 7 | self.opcode.as_u8()];
+        let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+
+        match self.payload.len() {
+            length if length < 126 => output.push(mask_bit | length as u8),
+            length if length <= u16::MAX as usize => {
+                output.push(mask_bit | 126);
+                output.extend_from_slice(&(length as u16).to_be_bytes());
+            }
+            length => {
+                output.push(mask_bit | 127);
+                output.extend_from_slice(&(length as u64).to_be_bytes());
+            }
+        }
+
+        match mask {
+            Some(mask) => {
+                output.extend_from_slice(&mask);
+                output.extend(
+                    self.payload
+                        .iter()
+                        .enumerate()
+                        .map(|(index, byte)| byte ^ mask[index % 4]),
+                );
+            }
+            None => output.extend_from_slice(&self.payload),
+        }
+
+        output
+    }
+
+    /// Decodes the frame at the start of `bytes`, returning it with the number of bytes it
+    /// took, or None until enough bytes have arrived. Masked payloads are unmasked.
+    pub fn decode(bytes: &[u8]) -> io::Result<Option<(Frame, usize)>> {
+        if bytes.len() < 2 {
+            return Ok(None);
+        }
+
+        if bytes[0] & 0x70 != 0 {
+            return Err(protocol_error("reserved bits set without an extension"));
+        }
+
+        let fin = bytes[0] & 0x80 != 0;
+        let opcode = Opcode::from_u8(bytes[0] & 0x0F)?;
+        let masked = bytes[1] & 0x80 != 0;
+
+        let (length, mut offset) = match bytes[1] & 0x7F {
+            126 => match bytes.get(2..4) {
+                Some(length) => (u16::from_be_bytes([length[0], length[1]]) as u64, 4),
+                None => return Ok(None),
+            },
+            127 => match bytes.get(2..10) {
+                Some(length) => (u64::from_be_bytes(length.try_into().unwrap()), 10),
+                None => return Ok(None),
+            },
+            length => (length as u64, 2),
+        };
+
+        if opcode.is_control() && (!fin || length > 125) {
+            return Err(protocol_error("fragmented or oversized control frame"));
+        }
+
+        if length > MAX_MESSAGE_SIZE as u64 {
+            return Err(protocol_error(&format!("{length} byte frame is too large")));
+        }
+
+        let mask = if masked {
+            match bytes.get(offset..offset + 4) {
+                Some(mask) => {
+                    offset += 4;
+                    Some([mask[0], mask[1], mask[2], mask[3]])
+                }
+                None => return Ok(None),
+            }
+        } else {
+            None
+        };
+
+        let end = offset + length as usize;
+        let mut payload = match bytes.get(offset..end) {
+            Some(payload) => payload.to_vec(),
+            None => return Ok(None),
+        };
+
+        if let Some(mask) = mask {
+            payload
+                .iter_mut()
+                .enumerate()
+                .for_each(|(index, byte)| *byte ^= mask[index % 4]);
+        }
+
+        Ok(Some((
+            Frame {
+                fin,
+                opcode,
+                payload,
+            },
+            end,
+        )))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    // Status code and reason, when the peer gave one.
+    Close(Option<(u16, String)>),
+}
+
+/// The Sec-WebSocket-Accept value a server must answer `key` with.
+pub fn accept_key(key: &str) -> String {
+    base64::encode_block(&sha1(format!("{key}{ACCEPT_GUID}").as_bytes()))
+}
+
+/// A random Sec-WebSocket-Key.
+pub fn generate_key() -> io::Result<String> {
+    let mut nonce = [0; 16];
+    rand_bytes(&mut nonce)?;
+
+    Ok(base64::encode_block(&nonce))
+}
+
+/// Turns the headers of a GET request into an opening handshake offering `key`.
+pub fn add_handshake_headers(headers: &mut HeaderMap, key: &str) -> io::Result<()> {
+    headers.insert(Header::Upgrade.as_str(), "websocket")?;
+    headers.insert(Header::Connection.as_str(), "Upgrade")?;
+    headers.insert(Header::SecWebSocketKey.as_str(), key)?;
+    headers.insert(Header::SecWebSocketVersion.as_str(), VERSION)?;
+
+    Ok(())
+}
+
+// Checks the server agreed to the handshake (RFC 6455 section 4.1).
+fn check_handshake(head: &ResponseHead, key: &str) -> io::Result<()> {
+    if head.status_code != 101 {
+        return Err(io::Error::other(format!(
+            "WebSocket handshake refused: {} {}",
+            head.status_code, head.status_message
+        )));
+    }
+
+    let upgrade = head.headers.get(Header::Upgrade.as_str());
+    if !upgrade.is_some_and(|value| value.eq_ignore_ascii_case("websocket")) {
+        return Err(protocol_error(
+            "handshake response without Upgrade: websocket",
+        ));
+    }
+
+    let connection = head.headers.get_combined(Header::Connection.as_str());
+    let upgrades = connection.is_some_and(|value| {
+        value
+            .split(',')
+            .any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+    });
+    if !upgrades {
+        return Err(protocol_error(
+            "handshake response without Connection: Upgrade",
+        ));
+    }
+
+    match head.headers.get(Header::SecWebSocketAccept.as_str()) {
+        Some(accept) if *accept == accept_key(key) => Ok(()),
+        _ => Err(protocol_error("wrong Sec-WebSocket-Accept")),
+    }
+}
+
+pub struct WebSocket {
+    stream: Box<dyn Stream>,
+    // The underlying socket, kept to adjust timeouts.
+    socket: Option<TcpStream>,
+    // Received bytes not yet decoded into frames.
+    buffer: Vec<u8>,
+    // Opcode and payload so far of a fragmented message.
+    fragments: Option<(Opcode, Vec<u8>)>,
+    close_sent: bool,
+}
+
+impl WebSocket {
+    /// Completes the opening handshake for a request sent with `key`.
+    pub fn handshake(upgraded: Upgraded, key: &str) -> io::Result<WebSocket> {
+        check_handshake(&upgraded.head, key)?;
+
+        let socket = WebSocket::from_stream(upgraded.stream, Some(upgraded.socket));
+        // Messages can be far apart, so stop waiting only when asked to.
+        socket.set_read_timeout(None)?;
+
+        Ok(socket)
+    }
+
+    fn from_stream(stream: Box<dyn Stream>, socket: Option<TcpStream>) -> WebSocket {
+        WebSocket {
+            stream,
+            socket,
+            buffer: Vec::new(),
+            fragments: None,
+            close_sent: false,
+        }
+    }
+
+    /// How long `read` waits for data before returning None.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match &self.socket {
+            Some(socket) => socket.set_read_timeout(timeout),
+            None => Ok(()),
+        }
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        let mut mask = [0; 4];
+        rand_bytes(&mut mask)?;
+
+        self.stream.write_all(&frame.encode(Some(mask)))?;
+        self.stream.flush()
+    }
+
+    /// Sends a message, split into several frames when it's large.
+    pub fn send(&mut self, message: &Message) -> io::Result<()> {
+        if self.close_sent {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "WebSocket is closing",
+            ));
+        }
+
+        let (opcode, payload) = match message {
+            Message::Text(text) => (Opcode::Text, text.as_bytes().to_vec()),
+            Message::Binary(bytes) => (Opcode::Binary, bytes.clone()),
+            Message::Ping(bytes) => (Opcode::Ping, bytes.clone()),
+            Message::Pong(bytes) => (Opcode::Pong, bytes.clone()),
+            Message::Close(status) => {
+                let payload = match status {
+                    Some((code, reason)) => [&code.to_be_bytes(), reason.as_bytes()].concat(),
+                    None => Vec::new(),
+                };
+                self.close_sent = true;
+
+                return self.write_frame(&Frame::new(Opcode::Close, payload));
+            }
+        };
+
+        if opcode.is_control() || payload.len() <= MAX_FRAME_SIZE {
+            return self.write_frame(&Frame::new(opcode, payload));
+        }
+
+        let chunks: Vec<&[u8]> = payload.chunks(MAX_FRAME_SIZE).collect();
+        for (index, chunk) in chunks.iter().enumerate() {
+            self.write_frame(&Frame {
+                fin: index == chunks.len() - 1,
+                opcode: if index == 0 {
+                    opcode
+                } else {
+                    Opcode::Continuation
+                },
+                payload: chunk.to_vec(),
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Starts the closing handshake; `read` returns the server's Close once it answers.
+    pub fn close(&mut self, code: u16, reason: &str) -> io::Result<()> {
+        if self.close_sent {
+            return Ok(());
+        }
+
+        self.send(&Message::Close(Some((code, String::from(reason)))))
+    }
+
+    fn next_frame(&mut self) -> io::Result<Option<Frame>> {
+        if self.buffer.len() >= 2 && self.buffer[1] & 0x80 != 0 {
+            return Err(protocol_error("masked frame from the server"));
+        }
+
+        Ok(Frame::decode(&self.buffer)?.map(|(frame, length)| {
+            self.buffer.drain(..length);
+            frame
+        }))
+    }
+
+    // Turns a frame into a message, buffering fragments and answering control frames.
+    fn receive(&mut self, frame: Frame) -> io::Result<Option<Message>> {
+        let (opcode, payload) = match (frame.opcode, self.fragments.take()) {
+            (Opcode::Ping, fragments) => {
+                self.fragments = fragments;
+                if !self.close_sent {
+                    self.write_frame(&Frame::new(Opcode::Pong, frame.payload.clone()))?;
+                }
+                return Ok(Some(Message::Ping(frame.payload)));
+            }
+            (Opcode::Pong, fragments) => {
+                self.fragments = fragments;
+                return Ok(Some(Message::Pong(frame.payload)));
+            }
+            (Opcode::Close, _) => {
+                let status = match frame.payload.len() {
+                    0 => None,
+                    1 => return Err(protocol_error("close frame with a one byte payload")),
+                    _ => {
+                        let code = u16::from_be_bytes([frame.payload[0], frame.payload[1]]);
+                        let reason = String::from_utf8(frame.payload[2..].to_vec())
+                            .map_err(|_| protocol_error("close reason isn't UTF-8"))?;
+                        Some((code, reason))
+                    }
+                };
+
+                if !self.close_sent {
+                    let code = status.as_ref().map_or(NORMAL_CLOSURE, |(code, _)| *code);
+                    self.close(code, "")?;
+                }
+                return Ok(Some(Message::Close(status)));
+            }
+            (Opcode::Continuation, None) => {
+                return Err(protocol_error("continuation without a message to continue"))
+            }
+            (Opcode::Continuation, Some((opcode, mut payload))) => {
+                if payload.len() + frame.payload.len() > MAX_MESSAGE_SIZE {
+                    return Err(protocol_error("message is too large"));
+                }
+                payload.extend_from_slice(&frame.payload);
+                (opcode, payload)
+            }
+            (_, Some(_)) => {
+                return Err(protocol_error("new message before the last one ended"));
+            }
+            (opcode, None) => (opcode, frame.payload),
+        };
+
+        if !frame.fin {
+            self.fragments = Some((opcode, payload));
+            return Ok(None);
+        }
+
+        match opcode {
+            Opcode::Text => String::from_utf8(payload)
+                .map(|text| Some(Message::Text(text)))
+                .map_err(|_| protocol_error("text message isn't UTF-8")),
+            _ => Ok(Some(Message::Binary(payload))),
+        }
+    }
+
+    /// Reads the next message, answering pings and the server's close frame along the way.
+    /// Returns None when the read timeout passes first; call again to keep waiting.
+    pub fn read(&mut self) -> io::Result<Option<Message>> {
+        let mut buffer = [0; 8192];
+
+        loop {
+            while let Some(frame) = self.next_frame()? {
+                if let Some(message) = self.receive(frame)? {
+                    return Ok(Some(message));
+                }
+            }
+
+            match self.stream.read(&mut buffer) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "WebSocket closed without a close frame",
+                    ))
+                }
+                Ok(length) => self.buffer.extend_from_slice(&buffer[..length]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    return Ok(None)
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Sends each line of `input` as a text message and writes every message received to
+/// `output`, until the server closes the connection or, after `input` ends, answers our
+/// close frame.
+pub fn pipe(
+    socket: &mut WebSocket,
+    input: impl Read + Send + 'static,
+    output: &mut impl Write,
+) -> io::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        for line in BufReader::new(input).lines() {
+            let sent = line.map(|line| sender.send(line).is_ok());
+
+            if !matches!(sent, Ok(true)) {
+                break;
+            }
+        }
+    });
+
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+    let mut closing_since = None;
+
+    loop {
+        while closing_since.is_none() {
+            match receiver.try_recv() {
+                Ok(line) => socket.send(&Message::Text(line))?,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    socket.close(NORMAL_CLOSURE, "")?;
+                    closing_since = Some(Instant::now());
+                }
+            }
+        }
+
+        match socket.read()? {
+            Some(Message::Text(text)) => writeln!(output, "{text}")?,
+            Some(Message::Binary(bytes)) => output.write_all(&bytes)?,
+            Some(Message::Close(status)) => {
+                if let Some((code, reason)) = status {
+                    log::debug!("WebSocket closed: {code} {reason}");
+                }
+                return output.flush();
+            }
+            Some(Message::Ping(_)) | Some(Message::Pong(_)) | None => (),
+        }
+        output.flush()?;
+
+        if closing_since.is_some_and(|since: Instant| since.elapsed() > CLOSE_TIMEOUT) {
+            log::warn!("Server didn't answer the close frame");
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod websocket_tests {
+    use super::{accept_key, check_handshake, Frame, Message, Opcode, WebSocket};
+    use crate::parser;
+    use std::cell::RefCell;
+    use std::io::{self, Cursor, Read, Write};
+    use std::rc::Rc;
+
+    // Reads scripted server bytes and keeps what the client writes.
+    struct Duplex {
+        input: Cursor<Vec<u8>>,
+        output: Rc<RefCell<Vec<u8>>>,
+    }
+
+    impl Read for Duplex {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buffer)
+        }
+    }
+
+    impl Write for Duplex {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            self.output.borrow_mut().extend_from_slice(buffer);
+            Ok(buffer.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn websocket(server_frames: &[Frame]) -> (WebSocket, Rc<RefCell<Vec<u8>>>) {
+        let input: Vec<u8> = server_frames
+            .iter()
+            .flat_map(|frame| frame.encode(None))
+            .collect();
+        let output = Rc::new(RefCell::new(Vec::new()));
+
+        let duplex = Duplex {
+            input: Cursor::new(input),
+            output: Rc::clone(&output),
+        };
+
+        (WebSocket::from_stream(Box::new(duplex), None), output)
+    }
+
+    fn sent_frames(output: &[u8]) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        let mut offset = 0;
+
+        while let Some((frame, length)) = Frame::decode(&output[offset..]).unwrap() {
+            frames.push(frame);
+            offset += length;
+        }
+
+        frames
+    }
+
+    #[test]
+    fn derives_the_accept_key() {
+        // RFC 6455 section 1.3
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn validates_the_handshake_response() {
+        let head = |accept: &str| {
+            let response = format!(
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n\r\n"
+            );
+            parser::parse_head(&mut response.as_bytes()).unwrap()
+        };
+
+        let key = "dGhlIHNhbXBsZSBub25jZQ==";
+        assert!(check_handshake(&head("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="), key).is_ok());
+        assert!(check_handshake(&head("wrong"), key).is_err());
+
+        let refused = parser::parse_head(&mut &b"HTTP/1.1 200 OK\r\n\r\n"[..]).unwrap();
+        assert!(check_handshake(&refused, key).is_err());
+    }
+
+    #[test]
+    fn encodes_frames_as_in_the_rfc() {
+        // RFC 6455 section 5.7
+        let hello = Frame::new(Opcode::Text, b"Hello".to_vec());
+
+        assert_eq!(
+            hello.encode(None),
+            [0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f]
+        );
+        assert_eq!(
+            hello.encode(Some([0x37, 0xfa, 0x21, 0x3d])),
+            [0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58]
+        );
+
+        let long = Frame::new(Opcode::Binary, vec![0; 256]);
+        assert_eq!(long.encode(None)[..4], [0x82, 0x7E, 0x01, 0x00]);
+
+        let longer = Frame::new(Opcode::Binary, vec![0; 65536]);
+        assert_eq!(
+            longer.encode(None)[..10],
+            [0x82, 0x7F, 0, 0, 0, 0, 0, 1, 0, 0]
+        );
+    }
+
+    #[test]
+    fn decodes_masked_and_partial_frames() {
+        let masked = [
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+
+        assert_eq!(
+            Frame::decode(&masked).unwrap(),
+            Some((Frame::new(Opcode::Text, b"Hello".to_vec()), 11))
+        );
+        assert_eq!(Frame::decode(&masked[..6]).unwrap(), None);
+        assert_eq!(Frame::decode(&[0x82, 0x7E, 0x01]).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_invalid_frames() {
+        // Reserved bit, unknown opcode, fragmented ping, oversized close.
+        for bytes in [
+            &[0xC1, 0x00][..],
+            &[0x83, 0x00][..],
+            &[0x09, 0x00][..],
+            &[0x88, 0x7E, 0x00, 0x7E][..],
+        ] {
+            assert!(Frame::decode(bytes).is_err(), "{bytes:?}");
+        }
+    }
+
+    #[test]
+    fn reassembles_fragmented_messages_around_pings() {
+        let (mut socket, output) = websocket(&[
+            Frame {
+                fin: false,
+                opcode: Opcode::Text,
+                payload: b"Hel".to_vec(),
+            },
+            Frame::new(Opcode::Ping, b"are you there".to_vec()),
+            Frame::new(Opcode::Continuation, b"lo".to_vec()),
+        ]);
+
+        assert_eq!(
+            socket.read().unwrap(),
+            Some(Message::Ping(b"are you there".to_vec()))
+        );
+        assert_eq!(
+            socket.read().unwrap(),
+            Some(Message::Text(String::from("Hello")))
+        );
+
+        // Pings are answered with the same payload, masked.
+        let output = output.borrow();
+        assert_eq!(output[1] & 0x80, 0x80);
+        assert_eq!(
+            sent_frames(&output),
+            vec![Frame::new(Opcode::Pong, b"are you there".to_vec())]
+        );
+    }
+
+    #[test]
+    fn answers_the_closing_handshake() {
+        let mut payload = 1001u16.to_be_bytes().to_vec();
+        payload.extend_from_slice(b"going away");
+        let (mut socket, output) = websocket(&[Frame::new(Opcode::Close, payload)]);
+
+        assert_eq!(
+            socket.read().unwrap(),
+            Some(Message::Close(Some((1001, String::from("going away")))))
+        );
+        assert_eq!(
+            sent_frames(&output.borrow()),
+            vec![Frame::new(Opcode::Close, 1001u16.to_be_bytes().to_vec())]
+        );
+        assert!(socket.send(&Message::Text(String::from("late"))).is_err());
+    }
+
+    #[test]
+    fn fragments_large_messages() {
+        let (mut socket, output) = websocket(&[]);
+        let message = vec![7; super::MAX_FRAME_SIZE + 10];
+
+        socket.send(&Message::Binary(message.clone())).unwrap();
+
+        let frames = sent_frames(&output.borrow());
+        assert_eq!(frames.len(), 2);
+        assert_eq!((frames[0].fin, frames[0].opcode), (false, Opcode::Binary));
+        assert_eq!(
+            (frames[1].fin, frames[1].opcode),
+            (true, Opcode::Continuation)
+        );
+        assert_eq!(
+            [&frames[0].payload[..], &frames[1].payload[..]].concat(),
+            message
+        );
+    }
+
+    #[test]
+    fn rejects_masked_server_frames_and_bad_sequences() {
+        let (mut socket, _) = websocket(&[]);
+        socket.buffer = Frame::new(Opcode::Text, b"x".to_vec()).encode(Some([1, 2, 3, 4]));
+        assert!(socket.read().is_err());
+
+        let (mut socket, _) = websocket(&[Frame::new(Opcode::Continuation, b"x".to_vec())]);
+        assert!(socket.read().is_err());
+
+        let (mut socket, _) = websocket(&[]);
+        assert_eq!(
+            socket.read().unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+}
@@ -0,0 +1,166 @@
+mod support;
+
+use bored_browser::cache::Cache;
+use bored_browser::download::Download;
+use bored_browser::proxy::ProxyConfig;
+use bored_browser::request::{Request, RequestOptions};
+use bored_browser::retry::RetryPolicy;
+use bored_browser::uri::URI;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use support::{temporary_directory, Handler, TestServer};
+
+const BODY: &[u8] = b"0123456789abcdefghij\x00\xff binary tail";
+
+const ETAG: &str = "\"v1\"";
+
+fn request(directory: &Path) -> Request {
+    Request::init(RequestOptions {
+        proxies: ProxyConfig::default(),
+        retry: RetryPolicy::never(),
+        ..RequestOptions::new(Cache::initialize_at(&directory.join("cache"), false))
+    })
+}
+
+fn ranged_server() -> TestServer {
+    let server = TestServer::start();
+    server.route(
+        "/file",
+        Handler::Ranged {
+            body: BODY.to_vec(),
+            etag: String::from(ETAG),
+        },
+    );
+
+    server
+}
+
+// Leaves a `.part` file as an interrupted download of `bytes` with `validator` would.
+fn interrupted(destination: &Path, bytes: &[u8], validator: &str) -> PathBuf {
+    let part = Download::new(destination).part_path();
+    fs::write(&part, bytes).unwrap();
+    fs::write(format!("{}.validator", part.display()), validator).unwrap();
+
+    part
+}
+
+fn ranges_requested(server: &TestServer) -> Vec<String> {
+    server
+        .requests()
+        .iter()
+        .map(|request| String::from(request.header("Range").unwrap_or("")))
+        .collect()
+}
+
+#[test]
+fn downloads_in_ranged_chunks() {
+    let server = ranged_server();
+    let directory = temporary_directory("chunks");
+    let destination = directory.join("file.bin");
+
+    let download = Download {
+        chunk_size: 16,
+        ..Download::new(&destination)
+    };
+    let length = download
+        .run(&request(&directory), &URI::parse(&server.url("/file")))
+        .unwrap();
+
+    assert_eq!(length, BODY.len() as u64);
+    assert_eq!(fs::read(&destination).unwrap(), BODY);
+    assert!(!download.part_path().exists());
+    assert_eq!(
+        ranges_requested(&server),
+        vec!["bytes=0-15", "bytes=16-31", "bytes=32-47"]
+    );
+    assert_eq!(server.requests()[1].header("If-Range"), Some(ETAG));
+    assert_eq!(
+        server.requests()[0].header("Accept-Encoding"),
+        Some("identity")
+    );
+}
+
+#[test]
+fn resumes_from_the_part_file() {
+    let server = ranged_server();
+    let directory = temporary_directory("resume");
+    let destination = directory.join("file.bin");
+    interrupted(&destination, &BODY[..10], ETAG);
+
+    Download::new(&destination)
+        .run(&request(&directory), &URI::parse(&server.url("/file")))
+        .unwrap();
+
+    assert_eq!(fs::read(&destination).unwrap(), BODY);
+    assert_eq!(server.requests().len(), 1);
+    assert!(ranges_requested(&server)[0].starts_with("bytes=10-"));
+}
+
+#[test]
+fn starts_over_when_the_file_changed() {
+    let server = ranged_server();
+    let directory = temporary_directory("changed");
+    let destination = directory.join("file.bin");
+    interrupted(&destination, b"stale bytes", "\"v0\"");
+
+    Download::new(&destination)
+        .run(&request(&directory), &URI::parse(&server.url("/file")))
+        .unwrap();
+
+    assert_eq!(fs::read(&destination).unwrap(), BODY);
+    assert_eq!(server.requests()[0].header("If-Range"), Some("\"v0\""));
+}
+
+#[test]
+fn finishes_when_the_part_file_is_already_complete() {
+    let server = ranged_server();
+    let directory = temporary_directory("complete");
+    let destination = directory.join("file.bin");
+    interrupted(&destination, BODY, ETAG);
+
+    let length = Download::new(&destination)
+        .run(&request(&directory), &URI::parse(&server.url("/file")))
+        .unwrap();
+
+    assert_eq!(length, BODY.len() as u64);
+    assert_eq!(fs::read(&destination).unwrap(), BODY);
+}
+
+#[test]
+fn saves_whole_responses_from_servers_without_range_support() {
+    let server = TestServer::start();
+    server.route("/plain", Handler::ok("no ranges here"));
+    let directory = temporary_directory("no-ranges");
+    let destination = directory.join("plain.txt");
+    interrupted(&destination, b"no ", "\"unknown\"");
+
+    Download::new(&destination)
+        .run(&request(&directory), &URI::parse(&server.url("/plain")))
+        .unwrap();
+
+    assert_eq!(fs::read_to_string(&destination).unwrap(), "no ranges here");
+}
+
+#[test]
+fn fetches_only_the_requested_range() {
+    use bored_browser::range::ByteRange;
+
+    let server = ranged_server();
+    let directory = temporary_directory("range");
+    let url = URI::parse(&server.url("/file"));
+    let request = request(&directory);
+
+    let response = request
+        .send_range(&url, &ByteRange::new(20, 21), None)
+        .unwrap();
+    assert_eq!(response.status_code, 206);
+    assert_eq!(response.body, b"\x00\xff");
+
+    // Partial responses never end up in the cache.
+    request
+        .send_range(&url, &ByteRange::new(20, 21), None)
+        .unwrap();
+    assert_eq!(server.hits("/file"), 2);
+}
@@ -0,0 +1,131 @@
+mod support;
+
+use bored_browser::browser::{Browser, Options};
+use bored_browser::parallel::Limits;
+use bored_browser::retry::RetryPolicy;
+
+use std::path::Path;
+use std::time::Duration;
+
+use support::{temporary_directory, Handler, TestServer};
+
+fn load_all(urls: &[String], cache: &Path) -> String {
+    let options = Options {
+        urls: urls.to_vec(),
+        cache_directory: cache.to_path_buf(),
+        hsts_file: cache.with_file_name("hsts"),
+        retry: RetryPolicy::never(),
+        parallel: Limits {
+            workers: 4,
+            per_host: 2,
+        },
+        ..Options::default()
+    };
+
+    let mut output = Vec::new();
+    Browser::new(options, &mut output).load_all().unwrap();
+
+    String::from_utf8(output).unwrap()
+}
+
+fn page_server() -> TestServer {
+    let server = TestServer::start();
+    server.route(
+        "/slow",
+        Handler::SlowDrip {
+            response: b"HTTP/1.1 200 OK\r\nContent-Length: 17\r\n\r\n<body>Slow</body>".to_vec(),
+            bytes_per_write: 8,
+            pause: Duration::from_millis(30),
+        },
+    );
+    for page in ["one", "two", "three"] {
+        server.route(
+            &format!("/{page}"),
+            Handler::ok(&format!("<body>{page}</body>")),
+        );
+    }
+
+    server
+}
+
+#[test]
+fn shows_pages_in_the_order_given() {
+    let server = page_server();
+    let directory = temporary_directory("parallel-order");
+    let urls: Vec<String> = ["/slow", "/one", "/two", "/three"]
+        .iter()
+        .map(|path| server.url(path))
+        .collect();
+
+    let output = load_all(&urls, &directory.join("cache"));
+
+    assert_eq!(
+        output,
+        format!(
+            "==> {} <==\nSlow\n==> {} <==\none\n==> {} <==\ntwo\n==> {} <==\nthree\n",
+            urls[0], urls[1], urls[2], urls[3]
+        )
+    );
+}
+
+#[test]
+fn keeps_going_after_a_page_fails() {
+    let server = page_server();
+    let directory = temporary_directory("parallel-failure");
+    // Nothing listens on port 1.
+    let urls = vec![String::from("http://127.0.0.1:1/"), server.url("/one")];
+
+    let output = load_all(&urls, &directory.join("cache"));
+
+    assert!(
+        output.starts_with("==> http://127.0.0.1:1/ <==\nCouldn't load the page: "),
+        "{output}"
+    );
+    assert!(
+        output.ends_with(&format!("==> {} <==\none\n", urls[1])),
+        "{output}"
+    );
+}
+
+#[test]
+fn reports_malformed_urls_without_stopping() {
+    let server = page_server();
+    let directory = temporary_directory("parallel-malformed");
+    let urls = vec![
+        server.url("/one"),
+        String::from("http://x:abc/"),
+        String::from("not a url"),
+        server.url("/two"),
+    ];
+
+    let output = load_all(&urls, &directory.join("cache"));
+
+    assert_eq!(
+        output,
+        format!(
+            "==> {} <==\none\n\
+             ==> http://x:abc/ <==\nCouldn't load the page: Invalid URL\n\n\
+             ==> not a url <==\nCouldn't load the page: Invalid URL\n\n\
+             ==> {} <==\ntwo\n",
+            urls[0], urls[3]
+        )
+    );
+}
+
+#[test]
+fn shares_one_cache_between_workers() {
+    let server = page_server();
+    let directory = temporary_directory("parallel-cache");
+    let urls: Vec<String> = ["/one", "/two", "/three"]
+        .iter()
+        .map(|path| server.url(path))
+        .collect();
+
+    let first = load_all(&urls, &directory.join("cache"));
+    let second = load_all(&urls, &directory.join("cache"));
+
+    assert_eq!(first, second);
+    for path in ["/one", "/two", "/three"] {
+        assert_eq!(server.hits(path), 1, "{path}");
+    }
+}
@@ -0,0 +1,102 @@
+mod support;
+
+use bored_browser::cache::Cache;
+use bored_browser::proxy::ProxyConfig;
+use bored_browser::request::{Request, RequestOptions};
+use bored_browser::retry::RetryPolicy;
+use bored_browser::sse;
+use bored_browser::uri::URI;
+
+use std::path::Path;
+
+use support::{temporary_directory, Handler, TestServer};
+
+fn request(directory: &Path) -> Request {
+    Request::init(RequestOptions {
+        proxies: ProxyConfig::default(),
+        retry: RetryPolicy::never(),
+        ..RequestOptions::new(Cache::initialize_at(&directory.join("cache"), false))
+    })
+}
+
+// An event stream the server closes after sending `events`.
+fn stream(events: &str) -> Handler {
+    Handler::Raw(
+        format!("HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\r\n{events}").into_bytes(),
+    )
+}
+
+#[test]
+fn reconnects_with_the_last_event_id() {
+    let server = TestServer::start();
+    server.route(
+        "/events",
+        Handler::Sequence(vec![
+            stream("retry: 10\nid: 1\ndata: first\n\nevent: partial\ndata: lost"),
+            stream("id: 2\nevent: update\ndata: second\n\n"),
+        ]),
+    );
+    let directory = temporary_directory("sse-reconnect");
+    let mut output = Vec::new();
+
+    sse::follow(
+        &request(&directory),
+        &URI::parse(&server.url("/events")),
+        &mut output,
+        Some(1),
+    )
+    .unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "id: 1\ndata: first\n\nid: 2\nevent: update\ndata: second\n\n"
+    );
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].header("Accept"), Some("text/event-stream"));
+    assert_eq!(requests[0].header("Last-Event-ID"), None);
+    assert_eq!(requests[1].header("Last-Event-ID"), Some("1"));
+}
+
+#[test]
+fn stops_when_told_to() {
+    let server = TestServer::start();
+    server.route(
+        "/events",
+        Handler::Sequence(vec![
+            stream("retry: 10\ndata: only\n\n"),
+            Handler::Raw(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec()),
+        ]),
+    );
+    let directory = temporary_directory("sse-stop");
+    let mut output = Vec::new();
+
+    sse::follow(
+        &request(&directory),
+        &URI::parse(&server.url("/events")),
+        &mut output,
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(String::from_utf8(output).unwrap(), "data: only\n\n");
+    assert_eq!(server.hits("/events"), 2);
+}
+
+#[test]
+fn rejects_responses_that_are_not_event_streams() {
+    let server = TestServer::start();
+    server.route("/page", Handler::ok("<html></html>"));
+    let directory = temporary_directory("sse-not-a-stream");
+
+    let error = sse::follow(
+        &request(&directory),
+        &URI::parse(&server.url("/page")),
+        &mut Vec::new(),
+        None,
+    )
+    .unwrap_err();
+
+    assert!(error.to_string().contains("Not an event stream"), "{error}");
+}
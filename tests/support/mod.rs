@@ -0,0 +1,548 @@
+//! A scriptable HTTP/1.1 server on 127.0.0.1 for integration tests, optionally behind TLS
+//! with a freshly generated self-signed certificate for `localhost`.
+
+#![allow(dead_code)]
+
+use openssl::asn1::Asn1Time;
+use openssl::base64;
+use openssl::bn::{BigNum, MsbOption};
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::sha::sha1;
+use openssl::ssl::{SslAcceptor, SslMethod};
+use openssl::x509::extension::SubjectAlternativeName;
+use openssl::x509::{X509NameBuilder, X509};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// What the server does when a path is requested.
+#[derive(Clone, Debug)]
+pub enum Handler {
+    /// A response with a Content-Length body.
+    Respond {
+        status: u16,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    },
+    /// A chunked response, one chunk per entry.
+    Chunked(Vec<Vec<u8>>),
+    /// A gzip-compressed response.
+    #[cfg(feature = "gzip")]
+    Gzip(Vec<u8>),
+    /// A redirect to `location` with the given status.
+    Redirect(u16, String),
+    /// Writes the response a few bytes at a time, pausing between writes.
+    SlowDrip {
+        response: Vec<u8>,
+        bytes_per_write: usize,
+        pause: Duration,
+    },
+    /// Writes the start of the response and closes the connection.
+    AbruptClose(Vec<u8>),
+    /// Writes the bytes as they are, e.g. a malformed response.
+    Raw(Vec<u8>),
+    /// Serves `body` with a strong `etag`, honouring Range and If-Range.
+    Ranged { body: Vec<u8>, etag: String },
+    /// Accepts a WebSocket handshake, then echoes messages back until the client closes.
+    WebSocketEcho,
+    /// Uses each handler in turn for successive requests, repeating the last one.
+    Sequence(Vec<Handler>),
+}
+
+impl Handler {
+    pub fn ok(body: &str) -> Handler {
+        Handler::Respond {
+            status: 200,
+            headers: Vec::new(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    pub fn with_header(self, name: &str, value: &str) -> Handler {
+        match self {
+            Handler::Respond {
+                status,
+                mut headers,
+                body,
+            } => {
+                headers.push((String::from(name), String::from(value)));
+                Handler::Respond {
+                    status,
+                    headers,
+                    body,
+                }
+            }
+            other => other,
+        }
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        404 => "Not Found",
+        416 => "Range Not Satisfiable",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+fn response(status: u16, headers: &[(String, String)], body: &[u8]) -> Vec<u8> {
+    let mut head = format!("HTTP/1.1 {status} {}\r\n", reason(status));
+
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    ));
+
+    let mut output = head.into_bytes();
+    output.extend_from_slice(body);
+
+    output
+}
+
+#[cfg(feature = "gzip")]
+fn gzip(body: &[u8]) -> Vec<u8> {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(body).unwrap();
+
+    encoder.finish().unwrap()
+}
+
+/// A request as the server received it.
+#[derive(Clone, Debug)]
+pub struct ReceivedRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl ReceivedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn read_request(stream: &mut impl Read) -> Option<ReceivedRequest> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+
+    let mut parts = line.split_whitespace();
+    let method = String::from(parts.next()?);
+    let path = String::from(parts.next()?);
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 {
+            break;
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((String::from(name.trim()), String::from(value.trim())));
+        }
+    }
+
+    Some(ReceivedRequest {
+        method,
+        path,
+        headers,
+    })
+}
+
+type Routes = Arc<Mutex<HashMap<String, (Handler, usize)>>>;
+
+// The 200, 206 or 416 response to a request for a ranged resource.
+fn ranged(request: &ReceivedRequest, body: &[u8], etag: &str) -> Vec<u8> {
+    let etag_header = (String::from("ETag"), String::from(etag));
+    let length = body.len();
+
+    let range = request
+        .header("Range")
+        .filter(|_| request.header("If-Range").is_none_or(|value| value == etag))
+        .and_then(|value| value.strip_prefix("bytes="))
+        .and_then(|value| value.split_once('-'));
+
+    let (start, end) = match range {
+        Some((start, end)) => (
+            start.parse::<usize>().unwrap(),
+            end.parse::<usize>()
+                .map_or(length - 1, |end| end.min(length - 1)),
+        ),
+        None => return response(200, &[etag_header], body),
+    };
+
+    if start >= length {
+        let content_range = (String::from("Content-Range"), format!("bytes */{length}"));
+        return response(416, &[content_range], b"");
+    }
+
+    let content_range = (
+        String::from("Content-Range"),
+        format!("bytes {start}-{end}/{length}"),
+    );
+    response(206, &[etag_header, content_range], &body[start..=end])
+}
+
+// Reads one client frame, returning its first byte (FIN and opcode) and unmasked payload.
+fn read_frame(stream: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut head = [0; 2];
+    stream.read_exact(&mut head)?;
+
+    let length = match head[1] & 0x7F {
+        126 => {
+            let mut length = [0; 2];
+            stream.read_exact(&mut length)?;
+            u16::from_be_bytes(length) as usize
+        }
+        127 => {
+            let mut length = [0; 8];
+            stream.read_exact(&mut length)?;
+            u64::from_be_bytes(length) as usize
+        }
+        length => length as usize,
+    };
+
+    let mut mask = [0; 4];
+    stream.read_exact(&mut mask)?;
+    let mut payload = vec![0; length];
+    stream.read_exact(&mut payload)?;
+    payload
+        .iter_mut()
+        .enumerate()
+        .for_each(|(index, byte)| *byte ^= mask[index % 4]);
+
+    Ok((head[0], payload))
+}
+
+fn write_frame(stream: &mut impl Write, first_byte: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![first_byte];
+    match payload.len() {
+        length if length < 126 => frame.push(length as u8),
+        length if length <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+
+    stream.write_all(&frame)?;
+    stream.flush()
+}
+
+fn websocket_echo<S: Read + Write>(stream: &mut S, request: &ReceivedRequest) -> io::Result<()> {
+    let key = request.header("Sec-WebSocket-Key").unwrap_or_default();
+    let accept = base64::encode_block(&sha1(
+        format!("{key}258EAFA5-E914-47DA-95CA-C5AB0DC85B11").as_bytes(),
+    ));
+
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n\r\n"
+    )?;
+    // Check pings are answered before echoing anything.
+    write_frame(stream, 0x89, b"ping")?;
+
+    loop {
+        let (first_byte, payload) = read_frame(stream)?;
+
+        match first_byte & 0x0F {
+            // Close: answer it and hang up.
+            0x8 => return write_frame(stream, first_byte, &payload),
+            // A pong is the answer to our ping; say so.
+            0xA => write_frame(stream, 0x81, &[b"pong: ", &payload[..]].concat())?,
+            // Echo data frames as they came, fragments included.
+            _ => write_frame(stream, first_byte, &payload)?,
+        }
+    }
+}
+
+fn write_reply(stream: &mut (impl Read + Write), request: &ReceivedRequest, handler: &Handler) {
+    let _ = match handler {
+        Handler::Respond {
+            status,
+            headers,
+            body,
+        } => stream.write_all(&response(*status, headers, body)),
+        Handler::Chunked(chunks) => {
+            let mut output =
+                b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n"
+                    .to_vec();
+            for chunk in chunks {
+                output.extend_from_slice(format!("{:x}\r\n", chunk.len()).as_bytes());
+                output.extend_from_slice(chunk);
+                output.extend_from_slice(b"\r\n");
+            }
+            output.extend_from_slice(b"0\r\n\r\n");
+            stream.write_all(&output)
+        }
+        #[cfg(feature = "gzip")]
+        Handler::Gzip(body) => stream.write_all(&response(
+            200,
+            &[(String::from("Content-Encoding"), String::from("gzip"))],
+            &gzip(body),
+        )),
+        Handler::Redirect(status, location) => stream.write_all(&response(
+            *status,
+            &[(String::from("Location"), location.clone())],
+            b"",
+        )),
+        Handler::SlowDrip {
+            response,
+            bytes_per_write,
+            pause,
+        } => {
+            for piece in response.chunks(*bytes_per_write) {
+                if stream
+                    .write_all(piece)
+                    .and_then(|_| stream.flush())
+                    .is_err()
+                {
+                    break;
+                }
+                thread::sleep(*pause);
+            }
+            Ok(())
+        }
+        Handler::AbruptClose(bytes) | Handler::Raw(bytes) => stream.write_all(bytes),
+        Handler::Ranged { body, etag } => stream.write_all(&ranged(request, body, etag)),
+        Handler::WebSocketEcho => websocket_echo(stream, request),
+        Handler::Sequence(_) => unreachable!("sequences are resolved before replying"),
+    };
+    let _ = stream.flush();
+}
+
+fn handle(mut stream: impl Read + Write, routes: &Routes, requests: &Mutex<Vec<ReceivedRequest>>) {
+    let request = match read_request(&mut stream) {
+        Some(request) => request,
+        None => return,
+    };
+    let path = request.path.clone();
+    requests.lock().unwrap().push(request.clone());
+
+    let handler = {
+        let mut routes = routes.lock().unwrap();
+        match routes.get_mut(&path) {
+            Some((Handler::Sequence(handlers), count)) => {
+                let handler = handlers[(*count).min(handlers.len() - 1)].clone();
+                *count += 1;
+                handler
+            }
+            Some((handler, count)) => {
+                *count += 1;
+                handler.clone()
+            }
+            None => Handler::Respond {
+                status: 404,
+                headers: Vec::new(),
+                body: b"not found".to_vec(),
+            },
+        }
+    };
+
+    write_reply(&mut stream, &request, &handler);
+}
+
+fn certificate() -> (X509, PKey<Private>) {
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "localhost").unwrap();
+    let name = name.build();
+
+    let mut serial = BigNum::new().unwrap();
+    serial.rand(64, MsbOption::MAYBE_ZERO, false).unwrap();
+
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder
+        .set_serial_number(&serial.to_asn1_integer().unwrap())
+        .unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(30).unwrap())
+        .unwrap();
+    let alternative_name = SubjectAlternativeName::new()
+        .dns("localhost")
+        .build(&builder.x509v3_context(None, None))
+        .unwrap();
+    builder.append_extension(alternative_name).unwrap();
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+
+    (builder.build(), key)
+}
+
+/// A scratch directory unique to this test process and `name`, emptied first.
+pub fn temporary_directory(name: &str) -> PathBuf {
+    let directory =
+        std::env::temp_dir().join(format!("bored-browser-tests-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+
+    directory
+}
+
+pub struct TestServer {
+    address: SocketAddr,
+    routes: Routes,
+    requests: Arc<Mutex<Vec<ReceivedRequest>>>,
+    stopping: Arc<AtomicBool>,
+    // The self-signed certificate, for TLS servers.
+    certificate: Option<X509>,
+}
+
+impl TestServer {
+    /// Starts a plain HTTP server.
+    pub fn start() -> TestServer {
+        Self::spawn(None)
+    }
+
+    /// Starts an HTTPS server for `localhost`; trust it with `ca_file`.
+    pub fn start_tls() -> TestServer {
+        let (certificate, key) = certificate();
+
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor.set_certificate(&certificate).unwrap();
+        acceptor.set_private_key(&key).unwrap();
+
+        let mut server = Self::spawn(Some(Arc::new(acceptor.build())));
+        server.certificate = Some(certificate);
+
+        server
+    }
+
+    fn spawn(acceptor: Option<Arc<SslAcceptor>>) -> TestServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let routes: Routes = Arc::default();
+        let requests: Arc<Mutex<Vec<ReceivedRequest>>> = Arc::default();
+        let stopping = Arc::new(AtomicBool::new(false));
+
+        let server = TestServer {
+            address,
+            routes: Arc::clone(&routes),
+            requests: Arc::clone(&requests),
+            stopping: Arc::clone(&stopping),
+            certificate: None,
+        };
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stopping.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let routes = Arc::clone(&routes);
+                let requests = Arc::clone(&requests);
+                let acceptor = acceptor.clone();
+
+                thread::spawn(move || match acceptor {
+                    Some(acceptor) => {
+                        if let Ok(stream) = acceptor.accept(stream) {
+                            handle(stream, &routes, &requests);
+                        }
+                    }
+                    None => handle(stream, &routes, &requests),
+                });
+            }
+        });
+
+        server
+    }
+
+    pub fn route(&self, path: &str, handler: Handler) -> &TestServer {
+        self.routes
+            .lock()
+            .unwrap()
+            .insert(String::from(path), (handler, 0));
+
+        self
+    }
+
+    pub fn port(&self) -> u16 {
+        self.address.port()
+    }
+
+    /// The URL of `path` on this server, using `localhost` for TLS servers so the
+    /// certificate matches.
+    pub fn url(&self, path: &str) -> String {
+        match self.certificate {
+            Some(_) => format!("https://localhost:{}{path}", self.port()),
+            None => format!("http://127.0.0.1:{}{path}", self.port()),
+        }
+    }
+
+    /// Requests received so far, in order.
+    pub fn requests(&self) -> Vec<ReceivedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// How many times `path` was requested.
+    pub fn hits(&self, path: &str) -> usize {
+        self.requests()
+            .iter()
+            .filter(|request| request.path == path)
+            .count()
+    }
+
+    /// Writes the server's certificate as a PEM CA file in `directory`.
+    pub fn ca_file(&self, directory: &Path) -> PathBuf {
+        let certificate = self.certificate.as_ref().expect("Not a TLS server");
+
+        let path = directory.join("ca.pem");
+        fs::write(&path, certificate.to_pem().unwrap()).unwrap();
+
+        path
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        // Wake the accept loop so it notices.
+        let _ = TcpStream::connect(self.address);
+    }
+}
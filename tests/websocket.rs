@@ -0,0 +1,96 @@
+mod support;
+
+use bored_browser::cache::Cache;
+use bored_browser::proxy::ProxyConfig;
+use bored_browser::request::{Request, RequestOptions};
+use bored_browser::uri::URI;
+use bored_browser::websocket::{self, Message};
+
+use std::io;
+
+use support::{temporary_directory, Handler, TestServer};
+
+fn request(name: &str) -> Request {
+    Request::init(RequestOptions {
+        proxies: ProxyConfig::default(),
+        ..RequestOptions::new(Cache::initialize_at(&temporary_directory(name), false))
+    })
+}
+
+fn ws_url(server: &TestServer, path: &str) -> URI {
+    URI::parse(&format!("ws://127.0.0.1:{}{path}", server.port()))
+}
+
+#[test]
+fn exchanges_messages_and_closes() {
+    let server = TestServer::start();
+    server.route("/echo", Handler::WebSocketEcho);
+
+    let mut socket = request("ws-echo")
+        .websocket(&ws_url(&server, "/echo"))
+        .unwrap();
+
+    // The server pings first, which is answered automatically.
+    assert_eq!(
+        socket.read().unwrap(),
+        Some(Message::Ping(b"ping".to_vec()))
+    );
+    assert_eq!(
+        socket.read().unwrap(),
+        Some(Message::Text(String::from("pong: ping")))
+    );
+
+    socket.send(&Message::Text(String::from("hello"))).unwrap();
+    assert_eq!(
+        socket.read().unwrap(),
+        Some(Message::Text(String::from("hello")))
+    );
+
+    let large = vec![42; 100_000];
+    socket.send(&Message::Binary(large.clone())).unwrap();
+    assert_eq!(socket.read().unwrap(), Some(Message::Binary(large)));
+
+    socket.close(websocket::NORMAL_CLOSURE, "done").unwrap();
+    assert_eq!(
+        socket.read().unwrap(),
+        Some(Message::Close(Some((1000, String::from("done")))))
+    );
+
+    let handshake = &server.requests()[0];
+    assert_eq!(handshake.header("Upgrade"), Some("websocket"));
+    assert_eq!(handshake.header("Sec-WebSocket-Version"), Some("13"));
+}
+
+#[test]
+fn pipes_lines_until_input_ends() {
+    let server = TestServer::start();
+    server.route("/echo", Handler::WebSocketEcho);
+
+    let mut socket = request("ws-pipe")
+        .websocket(&ws_url(&server, "/echo"))
+        .unwrap();
+    let mut output = Vec::new();
+
+    websocket::pipe(&mut socket, io::Cursor::new("one\ntwo\n"), &mut output).unwrap();
+
+    // Whether the server's ping is answered before input runs out depends on timing.
+    let output = String::from_utf8(output).unwrap();
+    let echoed: Vec<&str> = output
+        .lines()
+        .filter(|line| !line.starts_with("pong: "))
+        .collect();
+    assert_eq!(echoed, vec!["one", "two"]);
+}
+
+#[test]
+fn refuses_servers_that_dont_upgrade() {
+    let server = TestServer::start();
+    server.route("/plain", Handler::ok("not a websocket"));
+
+    let error = request("ws-refused")
+        .websocket(&ws_url(&server, "/plain"))
+        .err()
+        .unwrap();
+
+    assert!(error.to_string().contains("200"), "{error}");
+}